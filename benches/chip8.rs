@@ -0,0 +1,53 @@
+use chip_8_rs::chip8::Chip8;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::fs;
+
+const INSTRUCTIONS_PER_ITERATION: u64 = 10_000;
+
+fn bench_roms(c: &mut Criterion) {
+    let mut group = c.benchmark_group("rom");
+    group.throughput(Throughput::Elements(INSTRUCTIONS_PER_ITERATION));
+    for name in &[
+        "Pong (1 player).ch8",
+        "Space Invaders [David Winter].ch8",
+        "test_opcode.ch8",
+    ] {
+        let rom = fs::read(format!("programs/{}", name)).expect("Read ROM");
+        group.bench_with_input(BenchmarkId::from_parameter(name), &rom, |b, rom| {
+            b.iter(|| {
+                let mut chip8 = Chip8::from_rom(rom).unwrap();
+                for _ in 0..INSTRUCTIONS_PER_ITERATION {
+                    chip8.step_instruction().unwrap();
+                }
+                chip8
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_opcodes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("opcode");
+    let opcodes = [
+        ("7xnn add", 0x7301),
+        ("8xy4 add with carry", 0x8344),
+        ("Dxyn draw 8x15", 0xD12F),
+        ("Dxyn draw 8x5", 0xD125),
+        ("Fx33 BCD", 0xF333),
+        ("Fx65 load", 0xFF65),
+    ];
+    for (name, opcode) in &opcodes {
+        let mut chip8 = Chip8::from_rom(&[]).unwrap();
+        chip8.address_register = 0x300;
+        chip8.registers[1] = 30;
+        chip8.registers[2] = 10;
+        group.bench_function(*name, |b| {
+            b.iter(|| chip8.execute_opcode(black_box(*opcode)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_roms, bench_opcodes);
+criterion_main!(benches);
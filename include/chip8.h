@@ -0,0 +1,69 @@
+/*
+ * The C interface of chip-8-rs, for embedding the interpreter in other frontends. Build the
+ * library with:
+ *
+ *     cargo build --release --no-default-features --features ffi
+ *
+ * and link with target/release/libchip_8_rs.so (or .dylib, or chip_8_rs.dll). The declarations
+ * mirror src/ffi.rs, where the functions are documented in more detail.
+ */
+
+#ifndef CHIP8_H
+#define CHIP8_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct FfiChip8 Chip8;
+
+/* Creates a machine with nothing loaded, which has to be freed with chip8_free */
+Chip8 *chip8_new(void);
+
+/* Replaces the machine with a new one that runs the ROM. Returns 0, or -1 on errors. */
+int chip8_load_rom(Chip8 *machine, const uint8_t *rom, size_t length);
+
+/* Runs as many instructions as fit in the elapsed time (in seconds). Returns the number of
+ * executed instructions, or -1 on errors (like an unknown op-code). */
+int chip8_update(Chip8 *machine, double elapsed_time);
+
+/* Executes one instruction. Returns 0, or -1 on errors. */
+int chip8_step(Chip8 *machine);
+
+/* Presses (if pressed is non-zero) or releases a keypad key, 0 to F */
+void chip8_key(Chip8 *machine, uint8_t key, int pressed);
+
+/* The display, as width times height bytes that are 1 for lit pixels and 0 for the others. The
+ * pointer is valid until the next call that takes the machine. */
+const uint8_t *chip8_display_ptr(const Chip8 *machine);
+int chip8_display_width(const Chip8 *machine);
+/* 32, or 48 or 64 in the hires display modes */
+int chip8_display_height(const Chip8 *machine);
+
+/* The 4096 bytes of memory and the 16 registers (V0 to VF). The pointers are valid until the
+ * next call that takes the machine. */
+const uint8_t *chip8_memory_ptr(const Chip8 *machine);
+const uint8_t *chip8_registers_ptr(const Chip8 *machine);
+uint16_t chip8_program_counter(const Chip8 *machine);
+/* The address register, I */
+uint16_t chip8_address_register(const Chip8 *machine);
+uint8_t chip8_delay_timer(const Chip8 *machine);
+uint8_t chip8_sound_timer(const Chip8 *machine);
+
+/* Whether the beeper is on. Returns 1 or 0. */
+int chip8_sound_playing(const Chip8 *machine);
+
+/* The message of the last error, or NULL if there hasn't been one */
+const char *chip8_last_error(const Chip8 *machine);
+
+/* Frees the machine. NULL is ignored. */
+void chip8_free(Chip8 *machine);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
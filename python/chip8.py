@@ -0,0 +1,145 @@
+"""Python bindings for chip-8-rs, over the C interface in include/chip8.h.
+
+Build the library first:
+
+    cargo build --release --no-default-features --features ffi
+
+The library is looked up in target/release, unless CHIP8_LIBRARY gives its path. The buffers
+are bytes objects, which numpy.frombuffer turns into arrays:
+
+    machine = Machine.from_file("programs/Pong (1 player).ch8")
+    machine.update(1 / 60)
+    screen = numpy.frombuffer(machine.display, numpy.uint8).reshape(machine.height, machine.width)
+"""
+
+import ctypes
+import os
+import sys
+
+_LIBRARY_NAMES = {"darwin": "libchip_8_rs.dylib", "win32": "chip_8_rs.dll"}
+
+
+def _load_library():
+    path = os.environ.get("CHIP8_LIBRARY")
+    if path is None:
+        root = os.path.dirname(os.path.dirname(os.path.abspath(__file__)))
+        name = _LIBRARY_NAMES.get(sys.platform, "libchip_8_rs.so")
+        path = os.path.join(root, "target", "release", name)
+    library = ctypes.CDLL(path)
+    machine = ctypes.c_void_p
+    signatures = {
+        "chip8_new": ([], machine),
+        "chip8_load_rom": ([machine, ctypes.c_char_p, ctypes.c_size_t], ctypes.c_int),
+        "chip8_update": ([machine, ctypes.c_double], ctypes.c_int),
+        "chip8_step": ([machine], ctypes.c_int),
+        "chip8_key": ([machine, ctypes.c_uint8, ctypes.c_int], None),
+        "chip8_display_ptr": ([machine], ctypes.POINTER(ctypes.c_uint8)),
+        "chip8_display_width": ([machine], ctypes.c_int),
+        "chip8_display_height": ([machine], ctypes.c_int),
+        "chip8_memory_ptr": ([machine], ctypes.POINTER(ctypes.c_uint8)),
+        "chip8_registers_ptr": ([machine], ctypes.POINTER(ctypes.c_uint8)),
+        "chip8_program_counter": ([machine], ctypes.c_uint16),
+        "chip8_address_register": ([machine], ctypes.c_uint16),
+        "chip8_delay_timer": ([machine], ctypes.c_uint8),
+        "chip8_sound_timer": ([machine], ctypes.c_uint8),
+        "chip8_sound_playing": ([machine], ctypes.c_int),
+        "chip8_last_error": ([machine], ctypes.c_char_p),
+        "chip8_free": ([machine], None),
+    }
+    for name, (arguments, result) in signatures.items():
+        function = getattr(library, name)
+        function.argtypes = arguments
+        function.restype = result
+    return library
+
+
+_library = None
+
+
+class Chip8Error(Exception):
+    """An error from the machine, like an unknown op-code"""
+
+
+class Machine:
+    """A Chip-8 machine that runs a ROM"""
+
+    def __init__(self, rom):
+        global _library
+        if _library is None:
+            _library = _load_library()
+        self._machine = _library.chip8_new()
+        self._rom = bytes(rom)
+        self.reset()
+
+    @classmethod
+    def from_file(cls, path):
+        with open(path, "rb") as file:
+            return cls(file.read())
+
+    def __del__(self):
+        if getattr(self, "_machine", None) is not None:
+            _library.chip8_free(self._machine)
+            self._machine = None
+
+    def _check(self, result):
+        if result < 0:
+            raise Chip8Error(_library.chip8_last_error(self._machine).decode())
+        return result
+
+    def reset(self):
+        """Starts the ROM over"""
+        self._check(_library.chip8_load_rom(self._machine, self._rom, len(self._rom)))
+
+    def update(self, seconds):
+        """Runs as many instructions as fit in the time, and returns how many ran"""
+        return self._check(_library.chip8_update(self._machine, seconds))
+
+    def step(self):
+        """Executes one instruction"""
+        self._check(_library.chip8_step(self._machine))
+
+    def key(self, key, pressed):
+        """Presses or releases a keypad key, 0 to 15"""
+        _library.chip8_key(self._machine, key, int(pressed))
+
+    @property
+    def width(self):
+        return _library.chip8_display_width(self._machine)
+
+    @property
+    def height(self):
+        return _library.chip8_display_height(self._machine)
+
+    @property
+    def display(self):
+        """One byte per pixel, 1 if it's lit, row by row from the top left"""
+        return ctypes.string_at(_library.chip8_display_ptr(self._machine), self.width * self.height)
+
+    @property
+    def memory(self):
+        return ctypes.string_at(_library.chip8_memory_ptr(self._machine), 0x1000)
+
+    @property
+    def registers(self):
+        """V0 to VF"""
+        return ctypes.string_at(_library.chip8_registers_ptr(self._machine), 16)
+
+    @property
+    def pc(self):
+        return _library.chip8_program_counter(self._machine)
+
+    @property
+    def i(self):
+        return _library.chip8_address_register(self._machine)
+
+    @property
+    def delay_timer(self):
+        return _library.chip8_delay_timer(self._machine)
+
+    @property
+    def sound_timer(self):
+        return _library.chip8_sound_timer(self._machine)
+
+    @property
+    def sound_playing(self):
+        return bool(_library.chip8_sound_playing(self._machine))
@@ -0,0 +1,66 @@
+"""Tests for the Python bindings, which check that the signatures in chip8.py still match the C
+interface. Build the library first (see chip8.py), then run:
+
+    python3 -m unittest discover python
+"""
+
+import unittest
+
+from chip8 import Chip8Error, Machine
+
+# V0 = 5, I = the digit V0, draw it at (V0, V1), skip the next instruction if key V1 (0) is
+# pressed, else loop; V2 = 7, loop
+ROM = bytes([
+    0x60, 0x05,
+    0xF0, 0x29,
+    0xD0, 0x15,
+    0xE1, 0x9E,
+    0x12, 0x06,
+    0x62, 0x07,
+    0x12, 0x0C,
+])
+
+
+class MachineTest(unittest.TestCase):
+    def test_step(self):
+        machine = Machine(ROM)
+        self.assertEqual(machine.pc, 0x200)
+        for _ in range(3):
+            machine.step()
+        self.assertEqual(machine.pc, 0x206)
+        self.assertEqual(machine.registers[0], 5)
+        self.assertEqual(len(machine.registers), 16)
+
+    def test_display(self):
+        machine = Machine(ROM)
+        for _ in range(3):
+            machine.step()
+        self.assertEqual((machine.width, machine.height), (64, 32))
+        self.assertEqual(len(machine.display), 64 * 32)
+        # The top row of the digit 5 is 4 lit pixels
+        self.assertEqual(machine.display[:10], bytes([0, 0, 0, 0, 0, 1, 1, 1, 1, 0]))
+
+    def test_memory(self):
+        machine = Machine(ROM)
+        self.assertEqual(len(machine.memory), 0x1000)
+        self.assertEqual(machine.memory[0x200:0x200 + len(ROM)], ROM)
+
+    def test_update_and_key(self):
+        machine = Machine(ROM)
+        self.assertGreater(machine.update(0.1), 0)
+        self.assertIn(machine.pc, (0x206, 0x208))
+        self.assertEqual(machine.registers[2], 0)
+        machine.key(0, True)
+        machine.update(0.1)
+        self.assertEqual(machine.registers[2], 7)
+        machine.reset()
+        self.assertEqual(machine.pc, 0x200)
+
+    def test_error(self):
+        machine = Machine(bytes([0xFF, 0xFF]))
+        with self.assertRaises(Chip8Error):
+            machine.step()
+
+
+if __name__ == "__main__":
+    unittest.main()
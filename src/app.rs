@@ -1,84 +1,419 @@
-use crate::chip8::Chip8;
+use crate::assembly;
+use crate::autosave;
+use crate::battery::BatteryFile;
+use crate::builtin_roms;
+use crate::cheats::Cheats;
+use crate::chip8::{self, Chip8, StepHistory, Timing};
+use crate::crash_dump;
+use crate::flags_file::FlagsFile;
+use crate::frontend::Frontend;
+use crate::input_script::ScriptEvent;
+use crate::key_commands::KeyInjector;
+use crate::memory_map::{MemoryMap, MemoryRegion};
+use crate::memory_search::MemorySearch;
+use crate::netplay::{Netplay, NetplayEvent};
+use crate::patch;
+use crate::rom_database::RomDatabase;
+use crate::state_file::StateFile;
+use crate::symbols::Symbols;
+use crate::watch::WatchExpression;
 
-use ggez::conf::{WindowMode, WindowSetup};
-use ggez::event::{self, EventHandler, KeyCode, KeyMods};
-use ggez::graphics::{self, Color, DrawParam, FilterMode, Font, Image, Text};
+use ggez::conf::{FullscreenType, WindowMode, WindowSetup};
+use ggez::event::{Button, EventHandler, KeyCode, KeyMods, MouseButton};
+use ggez::graphics::{
+    self, Color, DrawMode, DrawParam, EmptyConst, FilterMode, Font, Image, MeshBuilder, Rect,
+    Scale, Shader, Text, TextFragment,
+};
+use ggez::input::mouse;
 use ggez::timer;
 use ggez::{Context, ContextBuilder, GameError, GameResult};
 use mint::Point2;
+use std::collections::VecDeque;
+use std::fs;
+use std::mem;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Receiver;
+use std::time::Duration;
+use tracing::{info, warn};
+
+mod call_stack_view;
+mod console;
+mod emulation_thread;
+mod event_loop;
+mod gamepad_map;
+mod heat_map;
+mod instruction_listing;
+mod keymap;
+mod keypad_view;
+mod layout;
+mod memory_view;
+mod palette;
+mod recording;
+mod rom_browser;
+mod screenshot;
+mod search_view;
+mod side_by_side;
+mod sprite_view;
+mod state_picker;
+mod stats_overlay;
+mod timeline_view;
+mod value_editor;
+mod watch_view;
+
+use console::Console;
+use instruction_listing::ListingPrompt;
+use recording::GifRecorder;
+use rom_browser::RomBrowser;
+use state_picker::StatePicker;
+use stats_overlay::Stats;
+use timeline_view::Timeline;
+use value_editor::{EditTarget, ValueEditor};
+
+use call_stack_view::StackChange;
+use emulation_thread::{EmulationThread, Stopped};
+use layout::Layout;
+
+pub use gamepad_map::{GamepadMap, DEFAULT_GAMEPAD_MAP};
+pub use keymap::{KeyMap, DEFAULT_KEYMAP};
+pub use palette::{parse_color, Palette, PALETTE_PRESETS};
+pub use side_by_side::SecondMachine;
 
-const COLOR_HIGHLIGHT: Color = Color::new(0.4, 1.0, 0.5, 1.0);
-const COLOR_BG: Color = Color::new(0.2, 0.2, 0.3, 1.0);
-const SCALING: f32 = 8.0;
 const C8_WIDTH: u8 = 64;
-const C8_HEIGHT: u8 = 32;
-const DEBUG_Y_OFFSET: u32 = C8_HEIGHT as u32 * SCALING as u32;
-const DEBUG_HEIGHT: u32 = 255;
-const INSTRUCTION_LISTING_X_OFFSET: u32 = C8_WIDTH as u32 * SCALING as u32;
-const INSTRUCTION_LISTING_WIDTH: u32 = 200;
-const INSTRUCTION_LISTING_LENGTH: u32 = 32;
+/// The tallest display mode (64x64), which the screen buffers are sized for
+const MAX_C8_HEIGHT: usize = chip8::MAX_SCREEN_HEIGHT;
+/// With ghosting enabled, an unlit pixel fades out with this time constant (in seconds)
+const PHOSPHOR_DECAY_TIME: f32 = 0.03;
+/// Fading pixels are turned off below this, where they look the same as the background
+const MIN_PIXEL_INTENSITY: f32 = 1.0 / 512.0;
+const TOAST_DURATION: f32 = 2.0;
+const TURBO_SPEED: f64 = 10.0;
+const SLOW_MOTION_SPEED: f64 = 0.25;
+/// How often the app updates while throttled in the background (in frames per second)
+const BACKGROUND_FRAME_RATE: f64 = 10.0;
+/// How many instructions that were stepped through can be stepped back
+const STEP_HISTORY_LENGTH: usize = 1000;
+
+/// Settings for the app, typically given on the command line
+#[derive(Default)]
+pub struct Options {
+    pub debug: bool,
+    pub keymap: KeyMap,
+    pub gamepad_map: GamepadMap,
+    pub palette: Palette,
+    pub ghosting: bool,
+    pub crt: bool,
+    /// Show the instructions per second and frame times on top of the screen
+    pub stats: bool,
+    /// The initial size of a Chip-8 pixel, in window pixels (8 if not given)
+    pub scale: Option<f32>,
+    /// Used for picking the settings of ROMs that are loaded while running (if not disabled)
+    pub rom_database: Option<RomDatabase>,
+    pub repl: Option<Receiver<String>>,
+    pub watches: Vec<WatchExpression>,
+    /// Print which forms of instructions were executed, when the window is closed
+    pub coverage: bool,
+    /// Run the machine on its own thread instead of in the render loop
+    pub threaded: bool,
+    /// Pause while the window doesn't have focus, and resume when it gets it back
+    pub pause_on_focus_loss: bool,
+    /// Update less often while the window doesn't have focus, and stop drawing while it's
+    /// minimized
+    pub background_throttle: bool,
+    /// Fill the memory after each loaded program with random bytes (see
+    /// `Chip8::randomize_free_memory`)
+    pub random_ram: bool,
+    /// Save the state of each ROM next to it when the window is closed or another ROM is loaded
+    /// (see `autosave`)
+    pub autosave: bool,
+    /// Keep the SUPER-CHIP flags of each ROM in a file next to it
+    pub persist_flags: bool,
+    /// The memory that is kept in a save file next to each ROM (see `battery`), if any
+    pub battery: Option<RangeInclusive<u16>>,
+    /// Where ROMs are loaded (see `Chip8::from_rom_at`)
+    pub start_address: u16,
+    /// The display height that loaded ROMs get, instead of the one they're detected to need
+    pub display_height: Option<u8>,
+    /// The memory that loaded ROMs get at least (see `Chip8::set_memory_size`)
+    pub memory_size: Option<usize>,
+    /// The font that loaded ROMs get instead of the built-in one (see `Chip8::set_font`)
+    pub font: Option<Vec<u8>>,
+    /// Another player's connection. As a client, the local machine isn't run: the keypad is sent
+    /// to the host, and its screen is shown.
+    pub netplay: Option<Netplay>,
+    /// Key commands from stdin or TCP (see `key_commands`)
+    pub key_injector: Option<KeyInjector>,
+    /// The key events of an input script, for the first ROM. They're applied before the frame in
+    /// which their cycle is reached.
+    pub script: Vec<ScriptEvent>,
+    /// A machine to run next to the main one, with the same keys (see `SecondMachine`)
+    pub second_machine: Option<SecondMachine>,
+}
+
+/// The ggez window, with the debugger
+pub struct GgezFrontend {
+    pub disassembled_program: Vec<String>,
+    pub window_title: String,
+    pub options: Options,
+}
+
+impl Frontend for GgezFrontend {
+    fn run(self, chip8: Chip8) -> Result<(), String> {
+        run(
+            chip8,
+            self.disassembled_program,
+            self.window_title,
+            self.options,
+        )
+        .map_err(|err| err.to_string())
+    }
+}
 
 pub fn run(
     chip8: Chip8,
     disassembled_program: Vec<String>,
     window_title: String,
-    debug: bool,
+    options: Options,
 ) -> Result<(), GameError> {
-    let window_width;
-    let window_height;
-    if debug {
-        window_width = C8_WIDTH as f32 * SCALING + INSTRUCTION_LISTING_WIDTH as f32;
-        window_height = C8_HEIGHT as f32 * SCALING + DEBUG_HEIGHT as f32;
-    } else {
-        window_width = C8_WIDTH as f32 * SCALING;
-        window_height = C8_HEIGHT as f32 * SCALING;
-    }
+    let (window_width, window_height) = Layout::default_window_size(
+        options.debug,
+        options.scale,
+        chip8.display_buffer.height(),
+        options.second_machine.is_some(),
+    );
     let (mut ctx, mut event_loop) = ContextBuilder::new("ggez_test", "jm")
         .window_setup(WindowSetup::default().title(&window_title))
-        .window_mode(WindowMode::default().dimensions(window_width, window_height))
+        .window_mode(
+            WindowMode::default()
+                .dimensions(window_width, window_height)
+                .resizable(true),
+        )
         .add_resource_path("resources")
         .build()
         .expect("Creating ggez context");
 
-    let mut app = App::new(&mut ctx, chip8, disassembled_program, debug, window_title)?;
-    event::run(&mut ctx, &mut event_loop, &mut app)
+    let coverage = options.coverage;
+    let mut app = App::new(&mut ctx, chip8, disassembled_program, window_title, options)?;
+    let result = event_loop::run(&mut ctx, &mut event_loop, &mut app);
+    app.stop_emulation_thread();
+    app.write_save_files();
+    if coverage {
+        print!("{}", app.chip8.opcode_coverage().summary());
+    }
+    result
 }
 
 struct App {
     font: Font,
-    c8_screen_buffer: [u8; 4 * C8_WIDTH as usize * C8_HEIGHT as usize],
+    c8_screen_buffer: [u8; 4 * C8_WIDTH as usize * MAX_C8_HEIGHT],
+    pixel_intensities: [f32; C8_WIDTH as usize * MAX_C8_HEIGHT],
+    /// Recreated from `c8_screen_buffer` when a pixel has changed. None means that all pixels
+    /// need to be recolored.
+    c8_screen_image: Option<Image>,
+    ghosting: bool,
+    crt_shader: Option<Shader<EmptyConst>>,
+    crt: bool,
+    stats: Stats,
+    stats_overlay: bool,
+    gif_recorder: Option<GifRecorder>,
     chip8: Chip8,
     disassembled_program: Vec<String>,
+    /// The regions of memory, for the memory view
+    memory_map: MemoryMap,
+    /// Whether the program counter was in the interpreter area at the last update, so that
+    /// entering it is only warned about once
+    pc_in_interpreter: bool,
     debug: bool,
+    fullscreen: bool,
+    layout: Layout,
     paused: bool,
-    instruction_listing: Vec<(usize, String)>,
+    instruction_listing: Vec<(u16, String)>,
+    listing_start: u16,
+    follow_pc: bool,
+    listing_cursor: Option<u16>,
+    listing_prompt: Option<(ListingPrompt, String)>,
+    console: Console,
+    /// The labels of the ROM's symbol file
+    symbols: Symbols,
+    memory_view_address: u16,
+    sprite_view_address: Option<u16>,
+    sprite_view_height: Option<u8>,
     cycles: u32,
+    /// The machine from before each of the last instructions that were stepped through
+    step_history: StepHistory,
     fast_forwarded_cycles: u32,
     window_title: String,
+    keymap: KeyMap,
+    gamepad_map: GamepadMap,
+    palette: Palette,
+    repl: Option<Receiver<String>>,
+    toast: Option<(String, f32)>,
+    rom_browser: Option<RomBrowser>,
+    state_picker: Option<StatePicker>,
+    value_editor: Option<ValueEditor>,
+    error: Option<String>,
+    base_clock_frequency: f64,
+    rom_database: Option<RomDatabase>,
+    turbo: bool,
+    slow_motion: bool,
+    previous_stack_pointer: u8,
+    stack_change: Option<StackChange>,
+    heat_map: bool,
+    timeline: Timeline,
+    timeline_view: bool,
+    watches: Vec<WatchExpression>,
+    /// The frozen values, which are written again after every update
+    cheats: Cheats,
+    memory_search: Option<MemorySearch>,
+    autosave: bool,
+    persist_flags: bool,
+    /// Where the flags of the running ROM are saved, if they are
+    flags_file: Option<FlagsFile>,
+    battery: Option<RangeInclusive<u16>>,
+    battery_file: Option<BatteryFile>,
+    /// Where ROMs are loaded
+    start_address: u16,
+    display_height: Option<u8>,
+    memory_size: Option<usize>,
+    /// The font that loaded ROMs get, if not the built-in one
+    chip8_font: Option<Vec<u8>>,
+    threaded: bool,
+    pause_on_focus_loss: bool,
+    /// Whether the app was paused because the window lost focus, so that it's only resumed when
+    /// it gets focus back if it was
+    paused_on_focus_loss: bool,
+    background_throttle: bool,
+    focused: bool,
+    minimized: bool,
+    random_ram: bool,
+    netplay: Option<Netplay>,
+    key_injector: Option<KeyInjector>,
+    /// The events of the input script that haven't happened yet
+    script: VecDeque<ScriptEvent>,
+    second_machine: Option<SecondMachine>,
+    /// While this is running, it owns the machine, and `chip8` is a copy that is updated once per
+    /// frame
+    emulation_thread: Option<EmulationThread>,
 }
 
 impl App {
     pub fn new(
         ctx: &mut Context,
         chip8: Chip8,
-        disassembled_program: Vec<String>,
-        debug: bool,
+        mut disassembled_program: Vec<String>,
         window_title: String,
+        options: Options,
     ) -> GameResult<App> {
+        disassembled_program.resize(chip8.memory().len(), String::new());
         let font = Font::new(ctx, "/fonts/Merchant Copy.ttf")?;
-        let c8_screen_buffer = [255; 4 * C8_WIDTH as usize * C8_HEIGHT as usize];
-        let app = App {
-            font: font,
-            c8_screen_buffer: c8_screen_buffer,
-            chip8: chip8,
-            disassembled_program: disassembled_program,
-            debug: debug,
+        let c8_screen_buffer = [255; 4 * C8_WIDTH as usize * MAX_C8_HEIGHT];
+        let crt_shader = match Shader::new(
+            ctx,
+            "/shaders/basic_150.glslv",
+            "/shaders/crt_150.glslf",
+            EmptyConst,
+            "CRT",
+            None,
+        ) {
+            Ok(shader) => Some(shader),
+            Err(err) => {
+                warn!("Couldn't load CRT shader: {}", err);
+                None
+            }
+        };
+        let (window_width, window_height) = graphics::drawable_size(ctx);
+        let c8_height = chip8.display_buffer.height();
+        let base_clock_frequency = chip8.clock_frequency();
+        let memory_map = MemoryMap::new(
+            &disassembled_program,
+            options.start_address as usize..chip8.program_end(),
+        );
+        let mut app = App {
+            font,
+            c8_screen_buffer,
+            pixel_intensities: [0.0; C8_WIDTH as usize * MAX_C8_HEIGHT],
+            c8_screen_image: None,
+            ghosting: options.ghosting,
+            crt_shader,
+            crt: options.crt,
+            stats: Stats::default(),
+            stats_overlay: options.stats,
+            gif_recorder: None,
+            chip8,
+            disassembled_program,
+            memory_map,
+            pc_in_interpreter: false,
+            debug: options.debug,
+            fullscreen: false,
+            layout: Layout::new(
+                window_width,
+                window_height,
+                options.debug,
+                c8_height,
+                options.second_machine.is_some(),
+            ),
             paused: false,
-            instruction_listing: vec![(0, String::new()); INSTRUCTION_LISTING_LENGTH as usize],
+            instruction_listing: Vec::new(),
+            listing_start: options.start_address,
+            follow_pc: true,
+            listing_cursor: None,
+            listing_prompt: None,
+            console: Console::default(),
+            symbols: Symbols::default(),
+            memory_view_address: options.start_address,
+            sprite_view_address: None,
+            sprite_view_height: None,
             cycles: 0,
+            step_history: StepHistory::new(STEP_HISTORY_LENGTH),
             fast_forwarded_cycles: 0,
-            window_title: window_title,
+            window_title,
+            keymap: options.keymap,
+            gamepad_map: options.gamepad_map,
+            palette: options.palette,
+            repl: options.repl,
+            toast: None,
+            rom_browser: None,
+            state_picker: None,
+            value_editor: None,
+            error: None,
+            base_clock_frequency,
+            turbo: false,
+            slow_motion: false,
+            previous_stack_pointer: 0,
+            stack_change: None,
+            heat_map: false,
+            timeline: Timeline::default(),
+            timeline_view: false,
+            watches: options.watches,
+            cheats: Cheats::default(),
+            memory_search: None,
+            autosave: options.autosave,
+            persist_flags: options.persist_flags,
+            flags_file: None,
+            battery: options.battery,
+            battery_file: None,
+            start_address: options.start_address,
+            display_height: options.display_height,
+            memory_size: options.memory_size,
+            chip8_font: options.font,
+            threaded: options.threaded,
+            pause_on_focus_loss: options.pause_on_focus_loss,
+            paused_on_focus_loss: false,
+            background_throttle: options.background_throttle,
+            focused: true,
+            minimized: false,
+            random_ram: options.random_ram,
+            netplay: options.netplay,
+            key_injector: options.key_injector,
+            script: options.script.into(),
+            second_machine: options.second_machine,
+            emulation_thread: None,
+            rom_database: options.rom_database,
         };
+        let rom_path = PathBuf::from(&app.window_title);
+        app.load_cheats(&rom_path);
+        app.load_symbols(&rom_path);
+        app.open_save_files(&rom_path);
         Ok(app)
     }
 
@@ -87,30 +422,25 @@ impl App {
         let margin = 10.0;
 
         for (i, register_value) in self.chip8.registers.iter().enumerate() {
-            self.draw_text(
+            let value = format!("{:02X}", register_value);
+            self.draw_editable_value(
                 ctx,
-                &format!("V{:X}: {:02X}", i, register_value),
+                &format!("V{:X}: ", i),
+                value,
+                EditTarget::Register(i as u8),
                 margin,
-                DEBUG_Y_OFFSET as f32 + margin + i as f32 * line_height,
+                self.layout.debug_y + margin + i as f32 * line_height,
             )?;
         }
 
         let x = 80.0;
-        let mut y = DEBUG_Y_OFFSET as f32 + margin;
-        self.draw_text(
-            ctx,
-            &format!("I: {:04X}", self.chip8.address_register),
-            x,
-            y,
-        )?;
+        let mut y = self.layout.debug_y + margin;
+        let value = format!("{:04X}", self.chip8.address_register);
+        self.draw_editable_value(ctx, "I: ", value, EditTarget::AddressRegister, x, y)?;
 
         y += line_height;
-        self.draw_text(
-            ctx,
-            &format!("PC: {:03X}", self.chip8.program_counter),
-            x,
-            y,
-        )?;
+        let value = format!("{:03X}", self.chip8.program_counter);
+        self.draw_editable_value(ctx, "PC: ", value, EditTarget::ProgramCounter, x, y)?;
 
         y += line_height;
         self.draw_text(
@@ -129,34 +459,40 @@ impl App {
         )?;
 
         y += line_height * 2.0;
-        self.draw_text(ctx, &"Stack:", x, y)?;
-        for i in 0..self.chip8.stack_pointer {
-            self.draw_text(
-                ctx,
-                &format!("{:03X}", self.chip8.stack[i as usize]),
-                x + 50.0 + i as f32 * 28.0,
-                y,
-            )?;
-        }
+        self.draw_text(
+            ctx,
+            &format!(
+                "Stack depth: {}/{}",
+                self.chip8.stack_pointer,
+                self.chip8.stack.len()
+            ),
+            x,
+            y,
+        )?;
 
         y += line_height * 2.0;
         self.draw_text(ctx, "Next instruction:", x, y)?;
-        let text = format!(
-            "{}",
-            match self
-                .disassembled_program
-                .get(self.chip8.program_counter as usize)
-            {
-                Some(s) => s,
-                None => "?",
+        let pc = self.chip8.program_counter;
+        let text = match self.disassembled_program.get(pc as usize) {
+            Some(text) => {
+                let text = self.with_target_label(pc, text);
+                match self.symbols.name(pc) {
+                    Some(name) => format!("<{}> {}", name, text),
+                    None => text,
+                }
             }
-        );
-        self.draw_text_with_color(ctx, &text, x + 120.0, y, COLOR_HIGHLIGHT)?;
+            None => "?".to_owned(),
+        };
+        self.draw_text_with_color(ctx, &text, x + 120.0, y, self.palette.highlight)?;
 
         y += line_height * 2.0;
+        let speed = match self.chip8.timing() {
+            Timing::Clock => format!("Clock frequency: {:.0}", self.chip8.clock_frequency()),
+            Timing::Tickrate => format!("Tickrate: {}", self.chip8.tickrate()),
+        };
         self.draw_text(
             ctx,
-            &format!("Clock frequency: {}", self.chip8.clock_frequency()),
+            &format!("{} ({:.0}%)", speed, self.relative_speed() * 100.0),
             x,
             y,
         )?;
@@ -164,7 +500,14 @@ impl App {
         y += line_height * 2.0;
         self.draw_text(
             ctx,
-            &format!("Status: {}", if self.paused { "PAUSED" } else { "RUNNING" }),
+            &format!(
+                "Status: {}",
+                match (self.paused, self.chip8.stopped_at_breakpoint()) {
+                    (true, Some(_)) => "BREAKPOINT",
+                    (true, None) => "PAUSED",
+                    (false, _) => "RUNNING",
+                }
+            ),
             x,
             y,
         )?;
@@ -182,59 +525,710 @@ impl App {
         Ok(())
     }
 
-    fn draw_instruction_listing(&mut self, ctx: &mut Context) -> GameResult<()> {
-        let line_height = 15.0;
-        let margin = 15.0;
-        let pc = self.chip8.program_counter as usize;
-        if pc < self.instruction_listing[0].0
-            || pc > self.instruction_listing[INSTRUCTION_LISTING_LENGTH as usize - 1].0
-        {
-            let mut address = (pc as f32 / INSTRUCTION_LISTING_LENGTH as f32) as usize
-                * INSTRUCTION_LISTING_LENGTH as usize;
-            let mut i = 0;
-            while address < 0x1000 && i < INSTRUCTION_LISTING_LENGTH as usize {
-                let text = self
-                    .disassembled_program
-                    .get(address)
-                    .expect("Get disassembled");
-                if !text.is_empty() {
-                    self.instruction_listing[i] = (address, text.clone());
-                    i += 1;
+    /// Draws a label and a value, or the input so far if the value is being edited
+    fn draw_editable_value(
+        &self,
+        ctx: &mut Context,
+        label: &str,
+        value: String,
+        target: EditTarget,
+        x: f32,
+        y: f32,
+    ) -> GameResult<()> {
+        let value = match self.edited_text(target) {
+            Some(text) => TextFragment::new(text).color(self.palette.secondary_highlight),
+            None => TextFragment::new(value).color(self.palette.text),
+        };
+        let label = TextFragment::new(label).color(self.palette.text);
+        self.draw_fragments(ctx, vec![label, value], x, y)
+    }
+
+    fn toggle_breakpoint(&mut self, address: u16) {
+        self.stop_emulation_thread();
+        if self.chip8.toggle_breakpoint(address) {
+            info!("Added breakpoint at {:03X}", address);
+        } else {
+            info!("Removed breakpoint at {:03X}", address);
+        }
+    }
+
+    fn step_instruction(&mut self) {
+        self.stop_emulation_thread();
+        match self.step_history.step(&mut self.chip8) {
+            Ok(_) => self.cycles += 1,
+            Err(err) => warn!("{}", err),
+        }
+        if let Some(second_machine) = &mut self.second_machine {
+            second_machine.step_instruction();
+        }
+    }
+
+    fn step_back(&mut self) {
+        self.stop_emulation_thread();
+        match self.step_history.step_back(&mut self.chip8) {
+            Ok(true) => {
+                self.cycles = self.cycles.saturating_sub(1);
+                self.error = None;
+            }
+            Ok(false) => self.show_toast("No steps to go back to".to_owned()),
+            Err(err) => warn!("{}", err),
+        }
+        if let Some(second_machine) = &mut self.second_machine {
+            second_machine.step_back();
+        }
+    }
+
+    fn advance_frame(&mut self) {
+        self.stop_emulation_thread();
+        match self.chip8.advance_frame() {
+            Ok(report) => self.cycles += report.cycles,
+            Err(err) => warn!("{}", err),
+        }
+        if let Some(second_machine) = &mut self.second_machine {
+            second_machine.advance_frame();
+        }
+    }
+
+    fn multiply_clock_frequency(&mut self, multiplier: f64) {
+        self.stop_emulation_thread();
+        self.chip8.multiply_clock_frequency(multiplier);
+    }
+
+    fn run_to_cursor(&mut self) {
+        if let Some(address) = self.listing_cursor {
+            self.stop_emulation_thread();
+            self.chip8.run_to(address);
+            self.paused = false;
+        }
+    }
+
+    fn update_layout(&mut self, ctx: &Context) {
+        let (width, height) = graphics::drawable_size(ctx);
+        let c8_height = self.chip8.display_buffer.height();
+        self.layout = Layout::new(
+            width,
+            height,
+            self.debug,
+            c8_height,
+            self.second_machine.is_some(),
+        );
+    }
+
+    fn toggle_gif_recording(&mut self) {
+        match self.gif_recorder.take() {
+            Some(gif_recorder) => match gif_recorder.stop() {
+                Ok(path) => info!("Saved recording to {}", path.display()),
+                Err(err) => warn!("{}", err),
+            },
+            None => match GifRecorder::start(
+                &self.palette,
+                &self.window_title,
+                self.chip8.display_buffer.height(),
+            ) {
+                Ok(gif_recorder) => {
+                    info!("Started recording");
+                    self.gif_recorder = Some(gif_recorder);
                 }
-                address += 1;
+                Err(err) => warn!("{}", err),
+            },
+        }
+    }
+
+    /// Replaces the running program with the one in the given file, resetting the machine
+    fn load_rom(&mut self, path: &Path) {
+        self.stop_emulation_thread();
+        let rom = match builtin_roms::read_rom(path) {
+            Ok(rom) => rom,
+            Err(err) => {
+                self.show_toast(err);
+                return;
+            }
+        };
+        let rom_info = self
+            .rom_database
+            .as_ref()
+            .and_then(|database| database.lookup(&rom));
+        let patched = match patch::sidecar(path) {
+            Some(patch_file) => match patch::apply_file(&rom, &patch_file) {
+                Ok(patched) => {
+                    info!("Applied patch {}", patch_file.display());
+                    Some(patched)
+                }
+                Err(err) => {
+                    self.show_toast(err);
+                    return;
+                }
+            },
+            None => None,
+        };
+        let rom = patched.unwrap_or(rom);
+        let mut chip8 = match Chip8::from_rom_at(&rom, self.start_address) {
+            Ok(chip8) => chip8,
+            Err(err) => {
+                self.show_toast(format!("{}: {}", path.display(), err));
+                return;
+            }
+        };
+        if let Some(height) = self.display_height {
+            chip8.set_display_height(height).unwrap();
+        }
+        if let Some(size) = self.memory_size {
+            chip8
+                .set_memory_size(size.max(chip8.memory().len()))
+                .unwrap();
+        }
+        if let Some(font) = &self.chip8_font {
+            chip8.set_font(font).unwrap();
+        }
+        if self.random_ram {
+            chip8.randomize_free_memory();
+        }
+        chip8.set_clock_frequency(self.chip8.clock_frequency());
+        chip8.set_timing(self.chip8.timing());
+        chip8.set_cycle_costs(self.chip8.cycle_costs());
+        chip8.set_sticky_keys(self.chip8.sticky_keys());
+        chip8.set_quirks(self.chip8.quirks());
+        chip8.set_font_protection(self.chip8.font_protection());
+        chip8.set_tracer(self.chip8.take_tracer());
+        let message = match rom_info {
+            Some(info) => {
+                if let Some(frequency) = info.clock {
+                    chip8.set_clock_frequency(frequency as f64);
+                    self.base_clock_frequency = frequency as f64;
+                }
+                if let Some(quirks) = info.recommended_quirks() {
+                    chip8.set_quirks(quirks);
+                }
+                format!("Loaded {}", info.name())
+            }
+            None => format!("Loaded {}", path.display()),
+        };
+        let message = if self.autosave && autosave::exists(path) {
+            format!("{} (F4 picks the autosave)", message)
+        } else {
+            message
+        };
+        self.write_save_files();
+        self.chip8 = chip8;
+        self.disassembled_program = assembly::disassemble_rom_at(rom, self.start_address);
+        self.disassembled_program
+            .resize(self.chip8.memory().len(), String::new());
+        self.memory_map = MemoryMap::new(
+            &self.disassembled_program,
+            self.start_address as usize..self.chip8.program_end(),
+        );
+        self.pc_in_interpreter = false;
+        self.window_title = path.display().to_string();
+        self.pixel_intensities = [0.0; C8_WIDTH as usize * MAX_C8_HEIGHT];
+        self.c8_screen_image = None;
+        self.follow_pc = true;
+        self.listing_cursor = None;
+        self.memory_view_address = self.start_address;
+        self.sprite_view_address = None;
+        self.sprite_view_height = None;
+        self.cycles = 0;
+        self.fast_forwarded_cycles = 0;
+        self.stats = Stats::default();
+        self.script.clear();
+        self.paused = false;
+        self.error = None;
+        self.previous_stack_pointer = 0;
+        self.stack_change = None;
+        self.timeline = Timeline::default();
+        self.memory_search = None;
+        self.load_cheats(path);
+        self.load_symbols(path);
+        self.open_save_files(path);
+        self.show_toast(message);
+    }
+
+    /// Loads the ROM's flags file and save file (the ones that are enabled), and keeps saving
+    /// to them
+    fn open_save_files(&mut self, rom_path: &Path) {
+        self.flags_file = None;
+        self.battery_file = None;
+        if builtin_roms::is_builtin(rom_path) {
+            return;
+        }
+        if self.persist_flags {
+            match FlagsFile::open(rom_path, &mut self.chip8) {
+                Ok(flags_file) => self.flags_file = Some(flags_file),
+                Err(err) => warn!("{}", err),
+            }
+        }
+        if let Some(range) = self.battery.clone() {
+            match BatteryFile::open(rom_path, range, &mut self.chip8) {
+                Ok(battery_file) => self.battery_file = Some(battery_file),
+                Err(err) => warn!("{}", err),
+            }
+        }
+    }
+
+    /// Writes the flags and the battery-backed memory to their files, if they have changed, and
+    /// the autosave
+    fn write_save_files(&mut self) {
+        let rom_path = Path::new(&self.window_title);
+        if self.autosave && !builtin_roms::is_builtin(rom_path) {
+            match autosave::save(rom_path, &self.chip8) {
+                Ok(()) => info!("Saved the state to {}", autosave::path(rom_path).display()),
+                Err(err) => warn!("{}", err),
+            }
+        }
+        if let Some(flags_file) = &mut self.flags_file {
+            match flags_file.save(&self.chip8) {
+                Ok(true) => info!("Saved the flags to {}", flags_file.file_path().display()),
+                Ok(false) => {}
+                Err(err) => warn!("{}", err),
+            }
+        }
+        if let Some(battery_file) = &mut self.battery_file {
+            match battery_file.save(&self.chip8) {
+                Ok(true) => info!("Saved memory to {}", battery_file.file_path().display()),
+                Ok(false) => {}
+                Err(err) => warn!("{}", err),
             }
-            while i < INSTRUCTION_LISTING_LENGTH as usize {
-                self.instruction_listing[i] = (usize::MAX, String::new());
-                i += 1;
+        }
+    }
+
+    fn toggle_rom_browser(&mut self) {
+        if self.rom_browser.take().is_none() {
+            match RomBrowser::open(rom_browser::ROM_DIRECTORY) {
+                Ok(rom_browser) => self.rom_browser = Some(rom_browser),
+                Err(err) => self.show_toast(err),
             }
         }
+    }
 
-        let x = INSTRUCTION_LISTING_X_OFFSET as f32 + margin;
-        for (i, (address, text)) in self.instruction_listing.iter().enumerate() {
-            if address != &usize::MAX {
-                let y = margin + i as f32 * line_height;
-                let line = format!("{:03X}: {}", address, text);
-                if &pc == address {
-                    self.draw_text_with_color(ctx, &line, x, y, COLOR_HIGHLIGHT)?;
-                } else {
-                    self.draw_text(ctx, &line, x, y)?;
+    /// Handles a key press while the ROM browser is open
+    fn handle_rom_browser_key(&mut self, keycode: KeyCode) {
+        match keycode {
+            KeyCode::Up => self.move_rom_browser_selection(-1),
+            KeyCode::Down => self.move_rom_browser_selection(1),
+            KeyCode::PageUp => self.move_rom_browser_selection(-10),
+            KeyCode::PageDown => self.move_rom_browser_selection(10),
+            KeyCode::Return => {
+                if let Some(rom_browser) = self.rom_browser.take() {
+                    self.load_rom(rom_browser.selected());
                 }
             }
+            KeyCode::Tab | KeyCode::Escape => self.rom_browser = None,
+            _ => {}
+        }
+    }
+
+    fn move_rom_browser_selection(&mut self, delta: i32) {
+        if let Some(rom_browser) = &mut self.rom_browser {
+            rom_browser.move_selection(delta);
+        }
+    }
+
+    /// Reports the error that paused the emulator, along with the instructions leading up to it
+    fn draw_error(&self, ctx: &mut Context) -> GameResult<()> {
+        let error = match &self.error {
+            Some(error) => error,
+            None => return Ok(()),
+        };
+        let history: Vec<String> = self
+            .chip8
+            .history()
+            .iter()
+            .map(|address| format!("{:03X}", address))
+            .collect();
+        let lines = [
+            format!("Error: {}", error),
+            format!("Recent: {}", history.join(" ")),
+            "F1: skip the instruction    F2: reset".to_owned(),
+        ];
+        let line_height = 15.0;
+        let x = self.layout.screen_x + 10.0;
+        let y = self.layout.screen_y + self.layout.c8_height as f32 * self.layout.scaling
+            - 10.0
+            - lines.len() as f32 * line_height;
+        let background = MeshBuilder::new()
+            .rectangle(
+                DrawMode::fill(),
+                Rect::new(
+                    x - 5.0,
+                    y - 5.0,
+                    C8_WIDTH as f32 * self.layout.scaling - 10.0,
+                    lines.len() as f32 * line_height + 10.0,
+                ),
+                self.palette.debug_background,
+            )
+            .build(ctx)?;
+        graphics::draw(ctx, &background, DrawParam::default())?;
+        for (i, line) in lines.iter().enumerate() {
+            let color = if i == 0 {
+                self.palette.secondary_highlight
+            } else {
+                self.palette.text
+            };
+            self.draw_text_with_color(ctx, line, x, y + i as f32 * line_height, color)?;
+        }
+        Ok(())
+    }
+
+    fn pause_on_error(&mut self, err: String) {
+        warn!("Paused on error: {}", err);
+        match crash_dump::write(&self.chip8, Path::new(&self.window_title), &err) {
+            Ok(dump) => info!("Wrote a crash dump to {}", dump.report_path.display()),
+            Err(dump_err) => warn!("{}", dump_err),
+        }
+        self.error = Some(err);
+        self.paused = true;
+    }
+
+    fn check_breakpoint(&mut self) {
+        if let Some(address) = self.chip8.stopped_at_breakpoint() {
+            info!("Hit breakpoint at {:03X}", address);
+            self.paused = true;
+        }
+    }
+
+    /// Starts the emulation thread if it isn't running, and otherwise updates the copy of the
+    /// machine with the latest frame
+    fn run_emulation_thread(&mut self) {
+        let time_scale = self.time_scale();
+        let emulation_thread = match &mut self.emulation_thread {
+            Some(emulation_thread) => emulation_thread,
+            None => {
+                let mirror = emulation_thread::mirror(&self.chip8);
+                let chip8 = mem::replace(&mut self.chip8, mirror);
+                self.emulation_thread = Some(EmulationThread::spawn(
+                    chip8,
+                    time_scale,
+                    self.cheats.clone(),
+                ));
+                return;
+            }
+        };
+        emulation_thread.set_time_scale(time_scale);
+        let mut state = None;
+        for frame in emulation_thread.frames() {
+            self.cycles += frame.cycles;
+            self.fast_forwarded_cycles += frame.fast_forwarded_cycles;
+            self.stats.clamped_updates += frame.clamped_updates;
+            if state.replace(frame.state).is_some() {
+                self.stats.dropped_frames += 1;
+            }
+        }
+        if let Some(state) = state {
+            self.chip8
+                .load_state(&state)
+                .expect("The emulation thread sent an invalid state");
+        }
+        if emulation_thread.is_finished() {
+            self.stop_emulation_thread();
+        }
+    }
+
+    /// Takes the machine back from the emulation thread (if it's running), so that it can be
+    /// inspected and changed
+    fn stop_emulation_thread(&mut self) {
+        let Stopped {
+            mut chip8,
+            error,
+            cycles,
+            fast_forwarded_cycles,
+            clamped_updates,
+        } = match self.emulation_thread.take() {
+            Some(emulation_thread) => emulation_thread.stop(),
+            None => return,
+        };
+        // Keys that changed after the thread had stopped by itself didn't reach the machine
+        for (key, &pressed) in self.chip8.pressed_keys().iter().enumerate() {
+            if chip8.pressed_keys()[key] != pressed {
+                chip8.handle_key_event(key as u8, pressed);
+            }
+        }
+        self.chip8 = chip8;
+        self.cycles += cycles;
+        self.fast_forwarded_cycles += fast_forwarded_cycles;
+        self.stats.clamped_updates += clamped_updates;
+        if let Some(err) = error {
+            self.pause_on_error(err);
+        }
+        self.check_breakpoint();
+    }
+
+    /// Warns when the program jumps into the interpreter area, which holds the font rather than
+    /// code (unless the interpreter was patched)
+    fn check_pc_region(&mut self) {
+        let pc = self.chip8.program_counter;
+        let in_interpreter = self.memory_map.region(pc) == MemoryRegion::Interpreter;
+        if in_interpreter && !self.pc_in_interpreter {
+            warn!("PC entered the interpreter and font area at {:03X}", pc);
+            self.show_toast(format!("PC is in the font area: {:03X}", pc));
+        }
+        self.pc_in_interpreter = in_interpreter;
+    }
+
+    fn skip_failing_instruction(&mut self) {
+        if self.error.is_none() {
+            return;
+        }
+        match self.chip8.program_counter.checked_add(2) {
+            Some(next) => {
+                self.chip8.program_counter = next;
+                self.error = None;
+                self.paused = false;
+            }
+            None => self.show_toast("Can't skip the last instruction of memory".to_owned()),
+        }
+    }
+
+    fn reset(&mut self) {
+        let path = self.window_title.clone();
+        self.load_rom(Path::new(&path));
+        if let Some(second_machine) = &mut self.second_machine {
+            second_machine.reset();
+        }
+    }
+
+    /// How long to wait before the next update, while the window is in the background and the
+    /// app is throttled
+    fn background_delay(&self) -> Option<Duration> {
+        if self.background_throttle && (!self.focused || self.minimized) {
+            Some(Duration::from_secs_f64(1.0 / BACKGROUND_FRAME_RATE))
+        } else {
+            None
+        }
+    }
+
+    /// How fast the emulation runs, relative to real time
+    fn time_scale(&self) -> f64 {
+        if self.turbo {
+            TURBO_SPEED
+        } else if self.slow_motion {
+            SLOW_MOTION_SPEED
+        } else {
+            1.0
+        }
+    }
+
+    /// The speed relative to the clock frequency that the emulator was started with
+    fn relative_speed(&self) -> f64 {
+        self.time_scale() * self.chip8.clock_frequency() / self.base_clock_frequency
+    }
+
+    fn draw_speed_indicator(&self, ctx: &mut Context) -> GameResult<()> {
+        let speed = self.relative_speed();
+        if (speed - 1.0).abs() < 0.005 {
+            return Ok(());
+        }
+        let text = if self.turbo {
+            format!(">> {:.0}%", speed * 100.0)
+        } else {
+            format!("{:.0}%", speed * 100.0)
+        };
+        let x = self.layout.screen_x + C8_WIDTH as f32 * self.layout.scaling
+            - 10.0
+            - self.text_width(ctx, &text);
+        let y = self.layout.screen_y + 10.0;
+        self.draw_text_with_color(ctx, &text, x, y, self.palette.highlight)
+    }
+
+    /// Shows a message on top of the screen for a short while
+    fn show_toast(&mut self, message: String) {
+        info!("{}", message);
+        self.toast = Some((message, TOAST_DURATION));
+    }
+
+    fn draw_toast(&self, ctx: &mut Context) -> GameResult<()> {
+        if let Some((message, _)) = &self.toast {
+            let x = self.layout.screen_x + 10.0;
+            let y = self.layout.screen_y + 10.0;
+            self.draw_text_with_color(ctx, message, x, y, self.palette.highlight)?;
         }
         Ok(())
     }
 
+    /// Saves the state to the slot, and returns what happened
+    fn save_state(&mut self, slot: u8) -> String {
+        if builtin_roms::is_builtin(Path::new(&self.window_title)) {
+            return "Built-in ROMs have no save state slots".to_owned();
+        }
+        self.stop_emulation_thread();
+        let path = state_picker::state_path(Path::new(&self.window_title), slot);
+        match fs::write(&path, StateFile::new(&self.chip8).to_bytes(true)) {
+            Ok(()) => format!("Saved state to slot {}", slot),
+            Err(err) => format!("Couldn't write {}: {}", path.display(), err),
+        }
+    }
+
+    /// Loads the state in the slot, unless it was saved from another ROM, and returns what
+    /// happened
+    fn load_state(&mut self, slot: u8) -> String {
+        if builtin_roms::is_builtin(Path::new(&self.window_title)) {
+            return "Built-in ROMs have no save state slots".to_owned();
+        }
+        self.stop_emulation_thread();
+        let path = state_picker::state_path(Path::new(&self.window_title), slot);
+        let result = fs::read(&path)
+            .map_err(|err| format!("Couldn't read {}: {}", path.display(), err))
+            .and_then(|bytes| StateFile::parse(&bytes))
+            .and_then(|file| file.load_into(&mut self.chip8));
+        match result {
+            Ok(()) => format!("Loaded state from slot {}", slot),
+            Err(err) => err,
+        }
+    }
+
+    fn handle_c8_key(&mut self, keycode: KeyCode, pressed: bool) {
+        if let Some(keypad_value) = self.keymap.keypad_value(keycode) {
+            self.handle_key_event(keypad_value, pressed);
+        }
+    }
+
+    fn handle_gamepad_button(&mut self, button: Button, pressed: bool) {
+        if let Some(keypad_value) = self.gamepad_map.keypad_value(button) {
+            self.handle_key_event(keypad_value, pressed);
+        }
+    }
+
+    fn handle_key_event(&mut self, key: u8, pressed: bool) {
+        if let Some(Netplay::Client(client)) = &mut self.netplay {
+            if let Err(err) = client.send_key(key, pressed) {
+                warn!("{}", err);
+                self.show_toast(err);
+            }
+            return;
+        }
+        // With the emulation thread running, this keeps the keypad view up to date
+        self.chip8.handle_key_event(key, pressed);
+        self.timeline.handle_key_event(key, pressed);
+        if let Some(second_machine) = &mut self.second_machine {
+            second_machine.handle_key_event(key, pressed);
+        }
+        if let Some(emulation_thread) = &self.emulation_thread {
+            emulation_thread.handle_key_event(key, pressed);
+        }
+    }
+
+    /// Presses the other player's keys, and sends them the screen
+    fn update_netplay_host(&mut self) {
+        let events = match &mut self.netplay {
+            Some(Netplay::Host(host)) => host.poll(),
+            _ => return,
+        };
+        for event in events {
+            match event {
+                NetplayEvent::Connected(address) => {
+                    info!("Player 2 connected from {}", address);
+                    self.show_toast("Player 2 connected".to_owned());
+                }
+                NetplayEvent::Key(key, pressed) => self.handle_key_event(key, pressed),
+                NetplayEvent::Disconnected => {
+                    info!("Player 2 disconnected");
+                    self.show_toast("Player 2 disconnected".to_owned());
+                }
+            }
+        }
+        if let Some(Netplay::Host(host)) = &mut self.netplay {
+            host.send_frame(&self.chip8.display_buffer);
+        }
+    }
+
+    fn handle_repl_input(&mut self) {
+        let lines: Vec<String> = match &self.repl {
+            Some(repl) => repl.try_iter().collect(),
+            None => return,
+        };
+        for line in lines {
+            let output = self.run_command(&line);
+            if !output.is_empty() {
+                println!("{}", output);
+            }
+        }
+    }
+
+    /// Prints the screen as text to stdout, and saves it to `screenshots/`
+    fn save_text_screen(&self) -> Result<String, String> {
+        print!("{}", self.chip8.display_buffer.to_text_grid());
+        screenshot::save_text_screenshot(&self.chip8.display_buffer, &self.window_title)
+            .map(|path| format!("Saved the screen as text to {}", path.display()))
+    }
+
+    fn execute_opcode(&mut self, opcode: u16) -> String {
+        self.stop_emulation_thread();
+        match self.chip8.execute_opcode(opcode) {
+            Ok(()) => format!("Executed {:04X}", opcode),
+            Err(err) => err,
+        }
+    }
+
+    /// Updates the disassembly of any instruction that overlaps the given (modified) address
+    fn refresh_disassembly(&mut self, address: u16) {
+        let memory = self.chip8.memory();
+        let start = address.saturating_sub(1) as usize;
+        for instruction_address in start..=address as usize {
+            if instruction_address + 1 >= memory.len()
+                || self
+                    .disassembled_program
+                    .get(instruction_address)
+                    .is_none_or(String::is_empty)
+            {
+                continue;
+            }
+            let opcode =
+                u16::from_be_bytes([memory[instruction_address], memory[instruction_address + 1]]);
+            self.disassembled_program[instruction_address] =
+                match assembly::disassemble_opcode(opcode) {
+                    Ok(text) => text,
+                    Err(_) => format!("DATA[{:#06X}]", opcode),
+                };
+        }
+    }
+
+    fn patch_instruction(&mut self, address: u16, opcode: u16) -> String {
+        self.stop_emulation_thread();
+        if let Err(err) = self.chip8.write_memory(address, &opcode.to_be_bytes()) {
+            return err;
+        }
+        // The memory may have grown since the ROM was disassembled, by loading a state
+        if self.disassembled_program.len() < self.chip8.memory().len() {
+            self.disassembled_program
+                .resize(self.chip8.memory().len(), String::new());
+        }
+        self.disassembled_program[address as usize] = match assembly::disassemble_opcode(opcode) {
+            Ok(text) => text,
+            Err(_) => format!("DATA[{:#06X}]", opcode),
+        };
+        format!("Patched {:03X}: {:04X}", address, opcode)
+    }
+
     fn draw_text(&self, ctx: &mut Context, s: &str, x: f32, y: f32) -> GameResult<()> {
-        let text = Text::new((s, self.font, 25.0));
-        graphics::draw(
+        self.draw_text_with_color(ctx, s, x, y, self.palette.text)
+    }
+
+    fn text_width(&self, ctx: &mut Context, s: &str) -> f32 {
+        Text::new((s, self.font, 25.0)).width(ctx) as f32 * 0.5
+    }
+
+    fn draw_fragments(
+        &self,
+        ctx: &mut Context,
+        fragments: Vec<TextFragment>,
+        x: f32,
+        y: f32,
+    ) -> GameResult<()> {
+        let mut text = Text::default();
+        for fragment in fragments {
+            text.add(fragment.font(self.font).scale(Scale::uniform(25.0)));
+        }
+        graphics::queue_text(
             ctx,
             &text,
-            DrawParam::default()
-                .scale([0.5, 0.5])
-                .dest(Point2 { x: x, y: y }),
-        )
+            Point2 {
+                x: 2.0 * x,
+                y: 2.0 * y,
+            },
+            None,
+        );
+        Ok(())
     }
 
+    /// Queues the text, which is drawn by the next `draw_queued_text`
     fn draw_text_with_color(
         &self,
         ctx: &mut Context,
@@ -244,45 +1238,102 @@ impl App {
         color: Color,
     ) -> GameResult<()> {
         let text = Text::new((s, self.font, 25.0));
-        graphics::draw(
+        graphics::queue_text(
             ctx,
             &text,
-            DrawParam::default()
-                .scale([0.5, 0.5])
-                .dest(Point2 { x: x, y: y })
-                .color(color),
+            Point2 {
+                x: 2.0 * x,
+                y: 2.0 * y,
+            },
+            Some(color),
+        );
+        Ok(())
+    }
+
+    /// Draws all the queued text in one go. Text is queued at twice its coordinates, since it's
+    /// rendered at twice its size and scaled down.
+    fn draw_queued_text(&self, ctx: &mut Context) -> GameResult<()> {
+        graphics::draw_queued_text(
+            ctx,
+            DrawParam::default().scale([0.5, 0.5]),
+            None,
+            FilterMode::Linear,
         )
     }
 
-    fn update_c8_screen_buffer(&mut self) {
-        for y in 0..C8_HEIGHT {
-            for x in 0..C8_WIDTH {
-                let offset = 4 * (y as usize * C8_WIDTH as usize + x as usize);
-                if self.chip8.display_buffer.get_pixel(x, y) {
-                    self.c8_screen_buffer[offset] = 255;
-                    self.c8_screen_buffer[offset + 1] = 255;
-                    self.c8_screen_buffer[offset + 2] = 255;
-                } else {
-                    self.c8_screen_buffer[offset] = 0;
-                    self.c8_screen_buffer[offset + 1] = 0;
-                    self.c8_screen_buffer[offset + 2] = 0;
-                }
+    /// Recolors the pixels whose intensity changed, and discards the screen image if any did.
+    /// With CHIP-8X colors, every pixel is recolored, since the colors may have changed.
+    fn update_c8_screen_buffer(&mut self, dt: f32) {
+        let fade = (-dt / PHOSPHOR_DECAY_TIME).exp();
+        let colors = self.chip8.display_buffer.colors();
+        let recolor_all = self.c8_screen_image.is_none() || colors.is_some();
+        let mut changed = false;
+        let pixels = self
+            .chip8
+            .display_buffer
+            .pixels()
+            .zip(self.pixel_intensities.iter_mut())
+            .zip(self.c8_screen_buffer.chunks_exact_mut(4));
+        for (i, ((lit, previous_intensity), rgba)) in pixels.enumerate() {
+            let intensity = if lit {
+                1.0
+            } else if self.ghosting && *previous_intensity > MIN_PIXEL_INTENSITY {
+                *previous_intensity * fade
+            } else {
+                0.0
+            };
+            if !recolor_all && intensity == *previous_intensity {
+                continue;
             }
+            changed = true;
+            *previous_intensity = intensity;
+            let color = match colors {
+                Some(colors) => {
+                    let (x, y) = (i % C8_WIDTH as usize, i / C8_WIDTH as usize);
+                    let [fg_r, fg_g, fg_b] = colors.foreground(x as u8, y as u8);
+                    let [bg_r, bg_g, bg_b] = colors.background();
+                    palette::mix(
+                        Color::from_rgb(bg_r, bg_g, bg_b),
+                        Color::from_rgb(fg_r, fg_g, fg_b),
+                        intensity,
+                    )
+                }
+                None => self.palette.pixel_color(intensity),
+            };
+            let (r, g, b) = color.to_rgb();
+            rgba[..3].copy_from_slice(&[r, g, b]);
+        }
+        if changed {
+            self.c8_screen_image = None;
         }
     }
 
-    fn draw_c8_screen(&self, ctx: &mut Context) -> GameResult<()> {
-        let mut c8_screen_image = Image::from_rgba8(
-            ctx,
-            C8_WIDTH as u16,
-            C8_HEIGHT as u16,
-            &self.c8_screen_buffer,
-        )?;
-        c8_screen_image.set_filter(FilterMode::Nearest);
+    fn draw_c8_screen(&mut self, ctx: &mut Context) -> GameResult<()> {
+        if self.c8_screen_image.is_none() {
+            let height = self.chip8.display_buffer.height() as usize;
+            let mut image = Image::from_rgba8(
+                ctx,
+                C8_WIDTH as u16,
+                height as u16,
+                &self.c8_screen_buffer[..4 * C8_WIDTH as usize * height],
+            )?;
+            image.set_filter(FilterMode::Nearest);
+            self.c8_screen_image = Some(image);
+        }
+        let c8_screen_image = self.c8_screen_image.as_ref().unwrap();
+        let _shader_lock = match (&self.crt_shader, self.crt) {
+            (Some(shader), true) => Some(graphics::use_shader(ctx, shader)),
+            _ => None,
+        };
         graphics::draw(
             ctx,
-            &c8_screen_image,
-            DrawParam::default().scale([SCALING as f32, SCALING as f32]),
+            c8_screen_image,
+            DrawParam::default()
+                .dest(Point2 {
+                    x: self.layout.screen_x,
+                    y: self.layout.screen_y,
+                })
+                .scale([self.layout.scaling, self.layout.scaling]),
         )?;
         Ok(())
     }
@@ -290,29 +1341,130 @@ impl App {
 
 impl EventHandler for App {
     fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
-        if !self.paused {
-            let dt = timer::delta(ctx).as_secs_f64();
-            let cycles = self.chip8.update(dt).expect("chip8 update");
-            self.cycles += cycles;
-            if cycles > 1 {
-                self.fast_forwarded_cycles += cycles - 1;
+        let cycles_before = self.cycles;
+        self.handle_repl_input();
+        while let Some(event) = self.script.front().copied() {
+            if event.cycle > self.cycles as u64 {
+                break;
+            }
+            self.script.pop_front();
+            self.handle_key_event(event.key, event.pressed);
+        }
+
+        if let Some(Netplay::Client(client)) = &mut self.netplay {
+            match client.latest_frame() {
+                Ok(Some(frame)) => self.chip8.display_buffer = frame,
+                Ok(None) => {}
+                Err(err) => {
+                    warn!("{}", err);
+                    ggez::event::quit(ctx);
+                }
+            }
+        } else if self.paused || self.rom_browser.is_some() || self.state_picker.is_some() {
+            self.stop_emulation_thread();
+        } else if self.threaded {
+            self.run_emulation_thread();
+        } else {
+            let dt = timer::delta(ctx).as_secs_f64() * self.time_scale();
+            match self.chip8.update(dt) {
+                Ok(report) => {
+                    self.cycles += report.cycles;
+                    if report.cycles > 1 {
+                        self.fast_forwarded_cycles += report.cycles - 1;
+                    }
+                    if report.clamped {
+                        self.stats.clamped_updates += 1;
+                    }
+                }
+                Err(err) => self.pause_on_error(err),
+            }
+            self.cheats.apply(&mut self.chip8);
+            self.check_breakpoint();
+        }
+        if !self.paused && self.rom_browser.is_none() && self.state_picker.is_none() {
+            let dt = timer::delta(ctx).as_secs_f64() * self.time_scale();
+            if let Some(second_machine) = &mut self.second_machine {
+                second_machine.update(dt);
+            }
+        }
+        self.update_netplay_host();
+        if let Some(injector) = &mut self.key_injector {
+            let events = injector.update(timer::delta(ctx).as_secs_f64());
+            for (key, pressed) in events {
+                self.handle_key_event(key, pressed);
+            }
+        }
+
+        if let Some((_, remaining)) = &mut self.toast {
+            *remaining -= timer::delta(ctx).as_secs_f32();
+            if *remaining <= 0.0 {
+                self.toast = None;
             }
         }
 
-        let fps = timer::fps(ctx) as u32;
-        graphics::set_window_title(ctx, &format!("[{}]    (FPS: {})", self.window_title, fps));
+        self.update_call_stack_view(timer::delta(ctx).as_secs_f32());
+        self.check_pc_region();
+        if !self.paused {
+            let dt = timer::delta(ctx).as_secs_f64() * self.time_scale();
+            let (delay_timer, sound_timer) = (self.chip8.delay_timer, self.chip8.sound_timer);
+            self.timeline.update(dt, delay_timer, sound_timer);
+        }
+
+        if let Some(gif_recorder) = &mut self.gif_recorder {
+            gif_recorder.update(&self.chip8.display_buffer, timer::delta(ctx).as_secs_f64());
+        }
+
+        self.stats.update(
+            timer::delta(ctx).as_secs_f32(),
+            self.cycles.wrapping_sub(cycles_before),
+        );
+        let recording = if self.gif_recorder.is_some() {
+            "    (REC)"
+        } else {
+            ""
+        };
+        graphics::set_window_title(ctx, &format!("[{}]{}", self.window_title, recording));
 
         Ok(())
     }
 
     fn draw(&mut self, ctx: &mut Context) -> GameResult<()> {
-        graphics::clear(ctx, COLOR_BG);
-        self.update_c8_screen_buffer();
-        self.draw_c8_screen(ctx)?;
+        graphics::clear(ctx, self.palette.debug_background);
+        if self.layout.c8_height != self.chip8.display_buffer.height() {
+            // The program (or a loaded state) switched to another display mode
+            self.update_layout(ctx);
+            self.c8_screen_image = None;
+        }
+        self.update_c8_screen_buffer(timer::delta(ctx).as_secs_f32());
+        if self.heat_map {
+            self.draw_heat_map(ctx)?;
+        } else if self.timeline_view {
+            self.draw_timeline_view(ctx)?;
+        } else {
+            self.draw_c8_screen(ctx)?;
+        }
+        self.draw_second_machine(ctx)?;
+        self.draw_speed_indicator(ctx)?;
+        if self.stats_overlay {
+            self.draw_stats_overlay(ctx)?;
+        }
+        self.draw_error(ctx)?;
+        self.draw_toast(ctx)?;
         if self.debug {
             self.draw_debug_area(ctx)?;
             self.draw_instruction_listing(ctx)?;
+            self.draw_memory_view(ctx)?;
+            self.draw_sprite_view(ctx)?;
+            self.draw_keypad_view(ctx)?;
+            self.draw_call_stack_view(ctx)?;
+            self.draw_watch_view(ctx)?;
         }
+        // The ROM browser covers everything, so the text so far has to be drawn before it
+        self.draw_queued_text(ctx)?;
+        self.draw_console(ctx)?;
+        self.draw_rom_browser(ctx)?;
+        self.draw_state_picker(ctx)?;
+        self.draw_queued_text(ctx)?;
         graphics::present(ctx)
     }
 
@@ -320,46 +1472,202 @@ impl EventHandler for App {
         &mut self,
         ctx: &mut Context,
         keycode: KeyCode,
-        _keymod: KeyMods,
+        keymod: KeyMods,
         repeat: bool,
     ) {
+        if self.rom_browser.is_some() {
+            self.handle_rom_browser_key(keycode);
+            return;
+        }
+        if self.state_picker.is_some() {
+            self.handle_state_picker_key(keycode);
+            return;
+        }
+        if self.value_editor.is_some() {
+            self.handle_value_editor_key(keycode);
+            return;
+        }
+        if self.listing_prompt.is_some() {
+            self.handle_listing_prompt_key(keycode);
+            return;
+        }
+        if self.console.open {
+            self.handle_console_key(keycode);
+            return;
+        }
         if !repeat {
-            c8_handle_key(&mut self.chip8, keycode, true);
+            // Keys that are mapped to the keypad only reach the app while Ctrl is held
+            if !keymod.contains(KeyMods::CTRL) && self.keymap.keypad_value(keycode).is_some() {
+                self.handle_c8_key(keycode, true);
+                return;
+            }
 
             match keycode {
                 KeyCode::Escape => ggez::event::quit(ctx),
-                KeyCode::P => self.chip8.multiply_clock_frequency(1.25),
-                KeyCode::O => self.chip8.multiply_clock_frequency(0.8),
-                KeyCode::Return => self.paused = !self.paused,
-                KeyCode::L => self.debug = !self.debug,
+                KeyCode::Tab => self.toggle_rom_browser(),
+                KeyCode::Grave => self.toggle_console(),
+                KeyCode::Space => self.turbo = true,
+                KeyCode::Backslash => self.slow_motion = !self.slow_motion,
+                KeyCode::F1 => self.skip_failing_instruction(),
+                KeyCode::F2 => self.reset(),
+                KeyCode::F3 => self.stats_overlay = !self.stats_overlay,
+                KeyCode::P => self.multiply_clock_frequency(1.25),
+                KeyCode::O => self.multiply_clock_frequency(0.8),
+                KeyCode::Return => {
+                    self.paused = !self.paused;
+                    self.error = None;
+                }
+                KeyCode::N if self.paused => self.step_instruction(),
+                KeyCode::B if self.paused && !keymod.contains(KeyMods::CTRL) => self.step_back(),
+                KeyCode::M if self.paused => self.advance_frame(),
+                KeyCode::J if self.paused => self.run_to_cursor(),
+                KeyCode::L => {
+                    self.debug = !self.debug;
+                    self.update_layout(ctx);
+                }
+                KeyCode::Up => self.scroll_memory_view(-1),
+                KeyCode::Down => self.scroll_memory_view(1),
+                KeyCode::PageUp | KeyCode::PageDown => {
+                    let pages = if keycode == KeyCode::PageUp { -1 } else { 1 };
+                    if self.is_over_instruction_listing(mouse::position(ctx).x) {
+                        self.scroll_instruction_listing_page(pages);
+                    } else {
+                        self.scroll_memory_view_page(pages);
+                    }
+                }
+                KeyCode::Home => self.follow_pc(),
+                KeyCode::F if keymod.contains(KeyMods::CTRL) => {
+                    self.open_listing_prompt(ListingPrompt::Find)
+                }
+                KeyCode::B if keymod.contains(KeyMods::CTRL) => {
+                    self.open_listing_prompt(ListingPrompt::Break)
+                }
+                KeyCode::Minus => self.change_sprite_view_height(-1),
+                KeyCode::Equals => self.change_sprite_view_height(1),
+                KeyCode::Comma => self.move_sprite_view(-1),
+                KeyCode::Period => self.move_sprite_view(1),
+                KeyCode::Slash => self.reset_sprite_view(),
+                KeyCode::F4 => self.toggle_state_picker(),
+                KeyCode::F5 | KeyCode::F6 | KeyCode::F7 | KeyCode::F8 => {
+                    let slot = match keycode {
+                        KeyCode::F5 => 1,
+                        KeyCode::F6 => 2,
+                        KeyCode::F7 => 3,
+                        _ => 4,
+                    };
+                    let message = if keymod.contains(KeyMods::SHIFT) {
+                        self.load_state(slot)
+                    } else {
+                        self.save_state(slot)
+                    };
+                    self.show_toast(message);
+                }
+                KeyCode::G => self.ghosting = !self.ghosting,
+                KeyCode::H if keymod.contains(KeyMods::SHIFT) => {
+                    self.stop_emulation_thread();
+                    self.chip8.reset_execution_counts();
+                    self.show_toast("Reset the heat map".to_owned());
+                }
+                KeyCode::H => self.heat_map = !self.heat_map,
+                KeyCode::T => self.timeline_view = !self.timeline_view,
+                KeyCode::F9 => self.crt = !self.crt,
+                KeyCode::F12 if keymod.contains(KeyMods::SHIFT) => match self.save_text_screen() {
+                    Ok(message) => info!("{}", message),
+                    Err(err) => warn!("{}", err),
+                },
+                KeyCode::F12 => {
+                    match screenshot::save_screenshot(
+                        &self.chip8.display_buffer,
+                        &self.palette,
+                        &self.window_title,
+                    ) {
+                        Ok(path) => info!("Saved screenshot to {}", path.display()),
+                        Err(err) => warn!("{}", err),
+                    }
+                }
+                KeyCode::F10 => self.toggle_gif_recording(),
+                KeyCode::F11 => {
+                    self.fullscreen = !self.fullscreen;
+                    let fullscreen_type = if self.fullscreen {
+                        FullscreenType::Desktop
+                    } else {
+                        FullscreenType::Windowed
+                    };
+                    if let Err(err) = graphics::set_fullscreen(ctx, fullscreen_type) {
+                        warn!("Couldn't toggle fullscreen: {}", err);
+                    }
+                }
                 _ => {}
             }
         }
     }
 
-    fn key_up_event(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods) {
-        c8_handle_key(&mut self.chip8, keycode, false);
+    fn mouse_wheel_event(&mut self, ctx: &mut Context, _x: f32, y: f32) {
+        let mouse = mouse::position(ctx);
+        if self.debug
+            && mouse.x >= memory_view::MEMORY_VIEW_X
+            && mouse.x < self.layout.listing_x
+            && mouse.y >= self.layout.debug_y
+        {
+            self.scroll_memory_view(-y.signum() as i32);
+        } else if self.debug && self.is_over_instruction_listing(mouse.x) {
+            self.scroll_instruction_listing(-y.signum() as i32 * 3);
+        }
+    }
+
+    fn mouse_button_down_event(&mut self, ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
+        if !self.debug {
+            return;
+        }
+        self.value_editor = None;
+        if button == MouseButton::Left && self.begin_edit_at(ctx, x, y) {
+            return;
+        }
+        if let Some(address) = self.instruction_listing_address_at(x, y) {
+            match button {
+                MouseButton::Left => self.toggle_breakpoint(address),
+                MouseButton::Right => self.listing_cursor = Some(address),
+                _ => {}
+            }
+        }
     }
-}
 
-fn c8_handle_key(chip8: &mut Chip8, keycode: KeyCode, pressed: bool) {
-    match keycode {
-        KeyCode::Key0 => chip8.handle_key_event(0x0, pressed),
-        KeyCode::Key1 => chip8.handle_key_event(0x1, pressed),
-        KeyCode::Key2 => chip8.handle_key_event(0x2, pressed),
-        KeyCode::Key3 => chip8.handle_key_event(0x3, pressed),
-        KeyCode::Key4 => chip8.handle_key_event(0x4, pressed),
-        KeyCode::Key5 => chip8.handle_key_event(0x5, pressed),
-        KeyCode::Key6 => chip8.handle_key_event(0x6, pressed),
-        KeyCode::Key7 => chip8.handle_key_event(0x7, pressed),
-        KeyCode::Key8 => chip8.handle_key_event(0x8, pressed),
-        KeyCode::Key9 => chip8.handle_key_event(0x9, pressed),
-        KeyCode::A => chip8.handle_key_event(0xA, pressed),
-        KeyCode::B => chip8.handle_key_event(0xB, pressed),
-        KeyCode::C => chip8.handle_key_event(0xC, pressed),
-        KeyCode::D => chip8.handle_key_event(0xD, pressed),
-        KeyCode::E => chip8.handle_key_event(0xE, pressed),
-        KeyCode::F => chip8.handle_key_event(0xF, pressed),
-        _ => {}
+    fn focus_event(&mut self, _ctx: &mut Context, gained: bool) {
+        self.focused = gained;
+        if !self.pause_on_focus_loss {
+            return;
+        }
+        if !gained && !self.paused {
+            info!("Paused while the window is in the background");
+            self.paused = true;
+            self.paused_on_focus_loss = true;
+        } else if gained && self.paused_on_focus_loss {
+            self.paused = false;
+            self.paused_on_focus_loss = false;
+        }
+    }
+
+    fn resize_event(&mut self, ctx: &mut Context, width: f32, height: f32) {
+        // Some platforms report a minimized window as one without a size
+        self.minimized = width == 0.0 || height == 0.0;
+        if self.minimized {
+            return;
+        }
+        let screen_coordinates = Rect::new(0.0, 0.0, width, height);
+        graphics::set_screen_coordinates(ctx, screen_coordinates).expect("Set screen coordinates");
+        self.update_layout(ctx);
+    }
+
+    fn text_input_event(&mut self, _ctx: &mut Context, character: char) {
+        self.handle_value_editor_input(character);
+        self.handle_listing_prompt_input(character);
+        self.handle_console_input(character);
+    }
+
+    fn key_up_event(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods) {
+        if keycode == KeyCode::Space {
+            self.turbo = false;
+        }
+        self.handle_c8_key(keycode, false);
     }
 }
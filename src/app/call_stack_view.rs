@@ -0,0 +1,91 @@
+use super::App;
+
+use ggez::{Context, GameResult};
+
+const LINE_HEIGHT: f32 = 15.0;
+const MARGIN: f32 = 15.0;
+/// How long a frame stays highlighted after it's been pushed or popped (in seconds)
+const CHANGE_HIGHLIGHT_TIME: f32 = 0.5;
+
+/// A push or pop of the stack that was seen recently
+pub(super) enum StackChange {
+    Pushed(f32),
+    Popped(u16, f32),
+}
+
+impl App {
+    /// The call stack, innermost frame first. Each frame shows the subroutine that was called
+    /// and the address that it returns to.
+    pub(super) fn draw_call_stack_view(&self, ctx: &mut Context) -> GameResult<()> {
+        let x = self.layout.call_stack_x + MARGIN;
+        let mut y = MARGIN;
+        self.draw_text(ctx, "Call stack:", x, y)?;
+
+        y += LINE_HEIGHT;
+        let pc = self.chip8.program_counter;
+        let current = match self.chip8.stack_pointer {
+            0 => "main".to_owned(),
+            depth => self.called_subroutine_label(self.chip8.stack[depth as usize - 1]),
+        };
+        let line = format!("{:03X} in {}", pc, current);
+        self.draw_text_with_color(ctx, &line, x, y, self.palette.highlight)?;
+
+        for depth in (0..self.chip8.stack_pointer as usize).rev() {
+            y += LINE_HEIGHT;
+            let return_address = self.chip8.stack[depth];
+            let caller = match depth {
+                0 => "main".to_owned(),
+                _ => self.called_subroutine_label(self.chip8.stack[depth - 1]),
+            };
+            let line = format!("{:03X} in {}", return_address, caller);
+            let just_pushed = depth + 1 == self.chip8.stack_pointer as usize
+                && matches!(self.stack_change, Some(StackChange::Pushed(_)));
+            let color = if just_pushed {
+                self.palette.secondary_highlight
+            } else {
+                self.palette.text
+            };
+            self.draw_text_with_color(ctx, &line, x, y, color)?;
+        }
+
+        if let Some(StackChange::Popped(return_address, _)) = self.stack_change {
+            y += LINE_HEIGHT;
+            let line = format!("(returned to {:03X})", return_address);
+            self.draw_text_with_color(ctx, &line, x, y, self.palette.secondary_highlight)?;
+        }
+        Ok(())
+    }
+
+    /// Keeps track of pushes and pops, so that they can be highlighted for a short while
+    pub(super) fn update_call_stack_view(&mut self, dt: f32) {
+        let stack_pointer = self.chip8.stack_pointer;
+        if stack_pointer > self.previous_stack_pointer {
+            self.stack_change = Some(StackChange::Pushed(CHANGE_HIGHLIGHT_TIME));
+        } else if stack_pointer < self.previous_stack_pointer {
+            let return_address = self.chip8.stack[stack_pointer as usize];
+            self.stack_change = Some(StackChange::Popped(return_address, CHANGE_HIGHLIGHT_TIME));
+        } else if let Some(StackChange::Pushed(remaining) | StackChange::Popped(_, remaining)) =
+            &mut self.stack_change
+        {
+            *remaining -= dt;
+            if *remaining <= 0.0 {
+                self.stack_change = None;
+            }
+        }
+        self.previous_stack_pointer = stack_pointer;
+    }
+
+    /// A label for the subroutine that was called by the instruction just before the given
+    /// return address
+    fn called_subroutine_label(&self, return_address: u16) -> String {
+        let memory = self.chip8.memory();
+        let call_address = return_address.wrapping_sub(2) as usize;
+        match memory.get(call_address..call_address + 2) {
+            Some(&[high, low]) if high & 0xF0 == 0x20 => {
+                let target = u16::from_be_bytes([high, low]) & 0x0FFF;
+                format!("sub_{:03X}", target)
+            }
+            _ => "?".to_owned(),
+        }
+    }
+}
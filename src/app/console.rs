@@ -0,0 +1,312 @@
+use super::state_picker::SLOTS;
+use super::{App, STEP_HISTORY_LENGTH};
+use crate::assembly;
+use crate::chip8::{MAX_CLOCK_FREQUENCY, MIN_CLOCK_FREQUENCY};
+
+use ggez::event::KeyCode;
+use ggez::graphics::{self, DrawMode, DrawParam, MeshBuilder, Rect};
+use ggez::{Context, GameResult};
+use std::collections::VecDeque;
+
+const LINE_HEIGHT: f32 = 15.0;
+const MARGIN: f32 = 10.0;
+/// The lines of output that are kept and shown above the input
+const OUTPUT_LINES: usize = 12;
+const HISTORY_LENGTH: usize = 100;
+/// The most instructions that `step N` executes, as many as can be stepped back over
+const MAX_STEPS: u32 = STEP_HISTORY_LENGTH as u32;
+
+/// The commands that are completed with Tab (the short forms aren't)
+const COMMANDS: [&str; 15] = [
+    "break",
+    "continue",
+    "freeze",
+    "help",
+    "load-state",
+    "pause",
+    "poke",
+    "save-state",
+    "screen",
+    "search",
+    "speed",
+    "step",
+    "unfreeze",
+    "unwatch",
+    "watch",
+];
+
+const HELP: &str = "\
+break LABEL|ADDR (b)   Toggle a breakpoint
+step [N] (s)           Execute one (or N) instructions, and pause
+continue (c), pause    Resume or pause the emulation
+speed [HZ]             Show or set the clock frequency
+save-state N           Save to slot N (1-4), like F5-F8
+load-state N           Load from slot N (1-4)
+watch EXPR, unwatch EXPR
+freeze|poke TARGET = VALUE, unfreeze TARGET
+search FILTER
+screen                 Print the screen as text to stdout and screenshots/
+Anything else is assembled, like V3 = 0x10 or 2A0: jump: 0x200";
+
+/// A text box over the top of the window, opened with the backtick key, for commands that don't
+/// have a hotkey. It takes the same commands as the REPL on stdin.
+#[derive(Default)]
+pub(super) struct Console {
+    pub open: bool,
+    input: String,
+    output: VecDeque<String>,
+    /// The commands that were entered, oldest first
+    history: Vec<String>,
+    /// The entry of the history that is in the input, while browsing it with Up and Down
+    history_index: Option<usize>,
+}
+
+impl Console {
+    fn print(&mut self, text: &str) {
+        for line in text.lines() {
+            if self.output.len() == OUTPUT_LINES {
+                self.output.pop_front();
+            }
+            self.output.push_back(line.to_owned());
+        }
+    }
+
+    fn browse_history(&mut self, delta: i32) {
+        if self.history.is_empty() {
+            return;
+        }
+        let last = self.history.len() as i32 - 1;
+        let index = match self.history_index {
+            Some(index) => index as i32 + delta,
+            None if delta < 0 => last,
+            None => return,
+        };
+        if index > last {
+            self.history_index = None;
+            self.input.clear();
+        } else {
+            let index = index.max(0) as usize;
+            self.history_index = Some(index);
+            self.input = self.history[index].clone();
+        }
+    }
+
+    /// Completes the word at the end of the input with a command (if it's the first word) or
+    /// one of the labels. With several candidates, the word is completed as far as they agree,
+    /// and they are listed.
+    fn complete<'a>(&mut self, labels: impl Iterator<Item = &'a str>) {
+        let start = self.input.rfind(' ').map_or(0, |space| space + 1);
+        let word = self.input[start..].to_lowercase();
+        let candidates: Vec<String> = if start == 0 {
+            COMMANDS
+                .iter()
+                .filter(|command| command.starts_with(&word))
+                .map(|&command| command.to_owned())
+                .collect()
+        } else {
+            labels
+                .filter(|label| label.to_lowercase().starts_with(&word))
+                .map(str::to_owned)
+                .collect()
+        };
+        match candidates.as_slice() {
+            [] => {}
+            [candidate] => {
+                self.input.truncate(start);
+                self.input.push_str(candidate);
+                self.input.push(' ');
+            }
+            [first, rest @ ..] => {
+                let common = rest.iter().fold(first.len(), |common, candidate| {
+                    common_prefix_len(&first[..common], candidate)
+                });
+                self.input.truncate(start);
+                self.input.push_str(&first[..common]);
+                self.print(&candidates.join("  "));
+            }
+        }
+    }
+}
+
+/// The length in bytes of the start of `a` that `b` starts with too (ignoring ASCII case)
+fn common_prefix_len(a: &str, b: &str) -> usize {
+    let mut len = 0;
+    for ((i, a), b) in a.char_indices().zip(b.chars()) {
+        if !a.eq_ignore_ascii_case(&b) {
+            return i;
+        }
+        len = i + a.len_utf8();
+    }
+    len
+}
+
+impl App {
+    pub(super) fn toggle_console(&mut self) {
+        self.console.open = !self.console.open;
+    }
+
+    /// Handles a key press while the console is open
+    pub(super) fn handle_console_key(&mut self, keycode: KeyCode) {
+        match keycode {
+            KeyCode::Return => {
+                let line = self.console.input.trim().to_owned();
+                self.console.input.clear();
+                self.console.history_index = None;
+                if line.is_empty() {
+                    return;
+                }
+                self.console.print(&format!("> {}", line));
+                if self.console.history.last() != Some(&line) {
+                    if self.console.history.len() == HISTORY_LENGTH {
+                        self.console.history.remove(0);
+                    }
+                    self.console.history.push(line.clone());
+                }
+                let output = self.run_command(&line);
+                self.console.print(&output);
+            }
+            KeyCode::Tab => self
+                .console
+                .complete(self.symbols.iter().map(|(_, name)| name)),
+            KeyCode::Up => self.console.browse_history(-1),
+            KeyCode::Down => self.console.browse_history(1),
+            KeyCode::Back => {
+                self.console.input.pop();
+            }
+            KeyCode::Grave | KeyCode::Escape => self.console.open = false,
+            _ => {}
+        }
+    }
+
+    pub(super) fn handle_console_input(&mut self, character: char) {
+        // The backtick that opened the console comes after it was opened
+        if self.console.open && !character.is_control() && character != '`' {
+            self.console.input.push(character);
+        }
+    }
+
+    /// Runs a command from the console or the REPL, and returns its output
+    pub(super) fn run_command(&mut self, line: &str) -> String {
+        let line = line.trim();
+        let (command, args) = match line.split_once(char::is_whitespace) {
+            Some((command, args)) => (command, args.trim()),
+            None => (line, ""),
+        };
+        match command {
+            "" => String::new(),
+            "help" | "h" => HELP.to_owned(),
+            "break" | "b" => self.toggle_breakpoint_at(args),
+            "step" | "s" => self.step_command(args),
+            "continue" | "c" => {
+                self.paused = false;
+                self.error = None;
+                "Running".to_owned()
+            }
+            "pause" => {
+                self.paused = true;
+                "Paused".to_owned()
+            }
+            "speed" => self.speed_command(args),
+            "save-state" | "load-state" => match args.parse::<u8>() {
+                Ok(slot) if (1..=SLOTS).contains(&slot) => {
+                    if command == "save-state" {
+                        self.save_state(slot)
+                    } else {
+                        self.load_state(slot)
+                    }
+                }
+                _ => format!("Expected a slot from 1 to {}: {}", SLOTS, args),
+            },
+            "watch" => self.add_watch(args),
+            "unwatch" => self.remove_watch(args),
+            "freeze" | "poke" => self.add_cheat(line),
+            "unfreeze" => self.unfreeze(args),
+            "search" => self.search_memory(args),
+            "screen" => self.save_text_screen().unwrap_or_else(|err| err),
+            _ => match assembly::assemble_line(line) {
+                Ok((Some(address), opcode)) => self.patch_instruction(address, opcode),
+                Ok((None, opcode)) => self.execute_opcode(opcode),
+                Err(err) => err,
+            },
+        }
+    }
+
+    fn step_command(&mut self, args: &str) -> String {
+        let steps = if args.is_empty() {
+            1
+        } else {
+            match args.parse::<u32>() {
+                Ok(steps) if steps <= MAX_STEPS => steps,
+                Ok(_) => return format!("At most {} steps at a time: {}", MAX_STEPS, args),
+                Err(_) => return format!("Invalid number of steps: {}", args),
+            }
+        };
+        self.paused = true;
+        for _ in 0..steps {
+            self.step_instruction();
+        }
+        let pc = self.chip8.program_counter;
+        let text = self
+            .disassembled_program
+            .get(pc as usize)
+            .map_or("?", String::as_str);
+        format!("{:03X}: {}", pc, self.with_target_label(pc, text))
+    }
+
+    fn speed_command(&mut self, args: &str) -> String {
+        if !args.is_empty() {
+            match args.trim_end_matches("Hz").trim().parse::<f64>() {
+                Ok(frequency) if frequency.is_finite() => {
+                    self.stop_emulation_thread();
+                    self.chip8.set_clock_frequency(frequency);
+                }
+                _ => return format!("Invalid clock frequency: {}", args),
+            }
+        }
+        format!(
+            "{:.0} Hz ({:.0} to {:.0})",
+            self.chip8.clock_frequency(),
+            MIN_CLOCK_FREQUENCY,
+            MAX_CLOCK_FREQUENCY
+        )
+    }
+
+    pub(super) fn draw_console(&self, ctx: &mut Context) -> GameResult<()> {
+        if !self.console.open {
+            return Ok(());
+        }
+        let (width, _) = graphics::drawable_size(ctx);
+        let height = (OUTPUT_LINES + 1) as f32 * LINE_HEIGHT + 2.0 * MARGIN;
+        let background = MeshBuilder::new()
+            .rectangle(
+                DrawMode::fill(),
+                Rect::new(0.0, 0.0, width, height),
+                self.palette.debug_background,
+            )
+            .rectangle(
+                DrawMode::stroke(1.0),
+                Rect::new(0.0, 0.0, width, height),
+                self.palette.text,
+            )
+            .build(ctx)?;
+        graphics::draw(ctx, &background, DrawParam::default())?;
+
+        let first_line = OUTPUT_LINES - self.console.output.len();
+        for (i, line) in self.console.output.iter().enumerate() {
+            let y = MARGIN + (first_line + i) as f32 * LINE_HEIGHT;
+            self.draw_text(ctx, line, MARGIN, y)?;
+        }
+        let y = MARGIN + OUTPUT_LINES as f32 * LINE_HEIGHT;
+        let input = format!("> {}_", self.console.input);
+        self.draw_text_with_color(ctx, &input, MARGIN, y, self.palette.highlight)
+    }
+}
+
+#[test]
+fn test_common_prefix_len() {
+    assert_eq!(common_prefix_len("load-state", "LOOP"), 2);
+    assert_eq!(common_prefix_len("éa", "éb"), 2);
+    assert_eq!(common_prefix_len("éa", "é"), 2);
+    assert_eq!(common_prefix_len("é", "e"), 0);
+    assert_eq!(common_prefix_len("", "abc"), 0);
+}
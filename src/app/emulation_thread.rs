@@ -0,0 +1,195 @@
+use crate::cheats::Cheats;
+use crate::chip8::Chip8;
+
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How long the thread sleeps between updates of the machine
+const UPDATE_INTERVAL: Duration = Duration::from_millis(1);
+/// How often the thread sends the state of the machine to the GUI
+const FRAME_INTERVAL: Duration = Duration::from_micros(16_667);
+
+enum Command {
+    KeyEvent(u8, bool),
+    TimeScale(f64),
+    Stop,
+}
+
+/// What the machine has done since the previous frame
+pub(super) struct Frame {
+    /// A save state, for showing the display and the debug views
+    pub(super) state: Vec<u8>,
+    pub(super) cycles: u32,
+    pub(super) fast_forwarded_cycles: u32,
+    /// Updates that were cut short after a stall (see `Chip8::update`)
+    pub(super) clamped_updates: u32,
+}
+
+/// The machine that is handed back when the thread stops
+pub(super) struct Stopped {
+    pub(super) chip8: Chip8,
+    /// The error that stopped the thread, if it didn't stop because of a command or a breakpoint
+    pub(super) error: Option<String>,
+    /// The cycles since the last frame that was received
+    pub(super) cycles: u32,
+    pub(super) fast_forwarded_cycles: u32,
+    pub(super) clamped_updates: u32,
+}
+
+/// Runs the machine on its own thread, so that its timing doesn't depend on the frame rate of the
+/// GUI. The thread stops by itself when the program fails or a breakpoint is reached.
+pub(super) struct EmulationThread {
+    commands: Sender<Command>,
+    frames: Receiver<Frame>,
+    handle: JoinHandle<Stopped>,
+    time_scale: f64,
+}
+
+impl EmulationThread {
+    /// The frozen cheats are applied after every update, like they are on the GUI thread
+    pub(super) fn spawn(chip8: Chip8, time_scale: f64, cheats: Cheats) -> EmulationThread {
+        let (commands, command_receiver) = mpsc::channel();
+        // If the GUI falls behind, frames are dropped rather than queued up
+        let (frame_sender, frames) = mpsc::sync_channel(2);
+        let handle = thread::Builder::new()
+            .name("emulation".to_owned())
+            .spawn(move || {
+                let mut stopped = Stopped {
+                    chip8,
+                    error: None,
+                    cycles: 0,
+                    fast_forwarded_cycles: 0,
+                    clamped_updates: 0,
+                };
+                let result = run(
+                    &mut stopped,
+                    &cheats,
+                    command_receiver,
+                    frame_sender,
+                    time_scale,
+                );
+                stopped.error = result.err();
+                stopped
+            })
+            .expect("Spawning the emulation thread");
+        EmulationThread {
+            commands,
+            frames,
+            handle,
+            time_scale,
+        }
+    }
+
+    pub(super) fn handle_key_event(&self, key: u8, pressed: bool) {
+        // Sending fails if the thread has stopped by itself, and then the key is handled by `stop`
+        let _ = self.commands.send(Command::KeyEvent(key, pressed));
+    }
+
+    pub(super) fn set_time_scale(&mut self, time_scale: f64) {
+        if time_scale != self.time_scale {
+            self.time_scale = time_scale;
+            let _ = self.commands.send(Command::TimeScale(time_scale));
+        }
+    }
+
+    /// The frames that have been sent since the last call
+    pub(super) fn frames(&self) -> impl Iterator<Item = Frame> + '_ {
+        self.frames.try_iter()
+    }
+
+    /// Whether the thread has stopped by itself, so that `stop` won't block
+    pub(super) fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Stops the thread (unless it has stopped by itself) and hands back the machine
+    pub(super) fn stop(self) -> Stopped {
+        let _ = self.commands.send(Command::Stop);
+        let mut stopped = self.handle.join().expect("The emulation thread panicked");
+        for frame in self.frames.try_iter() {
+            stopped.cycles += frame.cycles;
+            stopped.fast_forwarded_cycles += frame.fast_forwarded_cycles;
+            stopped.clamped_updates += frame.clamped_updates;
+        }
+        stopped
+    }
+}
+
+/// Updates the machine until it's told to stop, and counts the cycles since the last frame
+fn run(
+    stopped: &mut Stopped,
+    cheats: &Cheats,
+    commands: Receiver<Command>,
+    frames: SyncSender<Frame>,
+    mut time_scale: f64,
+) -> Result<(), String> {
+    let chip8 = &mut stopped.chip8;
+    let mut last_update = Instant::now();
+    let mut next_frame = last_update + FRAME_INTERVAL;
+    loop {
+        for command in commands.try_iter() {
+            match command {
+                Command::KeyEvent(key, pressed) => chip8.handle_key_event(key, pressed),
+                Command::TimeScale(scale) => time_scale = scale,
+                Command::Stop => return Ok(()),
+            }
+        }
+
+        let now = Instant::now();
+        let dt = (now - last_update).as_secs_f64() * time_scale;
+        last_update = now;
+        let report = chip8.update(dt)?;
+        cheats.apply(chip8);
+        stopped.cycles += report.cycles;
+        if report.cycles > 1 {
+            stopped.fast_forwarded_cycles += report.cycles - 1;
+        }
+        if report.clamped {
+            stopped.clamped_updates += 1;
+        }
+        if chip8.stopped_at_breakpoint().is_some() {
+            return Ok(());
+        }
+
+        if now >= next_frame {
+            next_frame = now + FRAME_INTERVAL;
+            let frame = Frame {
+                state: chip8.save_state(),
+                cycles: stopped.cycles,
+                fast_forwarded_cycles: stopped.fast_forwarded_cycles,
+                clamped_updates: stopped.clamped_updates,
+            };
+            match frames.try_send(frame) {
+                Ok(()) => {
+                    stopped.cycles = 0;
+                    stopped.fast_forwarded_cycles = 0;
+                    stopped.clamped_updates = 0;
+                }
+                Err(TrySendError::Full(_)) => {}
+                Err(TrySendError::Disconnected(_)) => return Ok(()),
+            }
+        }
+        thread::sleep(UPDATE_INTERVAL);
+    }
+}
+
+/// A copy of the machine's state, settings, breakpoints and pressed keys, for the GUI to show while the
+/// machine itself runs on the thread
+pub(super) fn mirror(chip8: &Chip8) -> Chip8 {
+    let mut mirror = Chip8::new([0; 0x1000]);
+    mirror
+        .load_state(&chip8.save_state())
+        .expect("Copying the machine");
+    mirror.set_quirks(chip8.quirks());
+    mirror.set_timing(chip8.timing());
+    mirror.set_cycle_costs(chip8.cycle_costs());
+    mirror.set_sticky_keys(chip8.sticky_keys());
+    for &address in chip8.breakpoints() {
+        mirror.toggle_breakpoint(address);
+    }
+    for (key, &pressed) in chip8.pressed_keys().iter().enumerate() {
+        mirror.handle_key_event(key as u8, pressed);
+    }
+    mirror
+}
@@ -0,0 +1,88 @@
+use super::App;
+
+use ggez::event::winit_event::{ElementState, Event, KeyboardInput, MouseScrollDelta, WindowEvent};
+use ggez::event::{self, EventHandler, EventsLoop};
+use ggez::input::gamepad::gilrs::EventType;
+use ggez::input::{keyboard, mouse};
+use ggez::{timer, Context, GameResult};
+
+/// The main loop of the app.
+///
+/// This does the same as `ggez::event::run`, except that it also lets the app handle files that
+/// are dropped onto the window and gamepad buttons (which ggez doesn't expose through
+/// `EventHandler` in a way that the app can use), and that it can throttle the app while the
+/// window is in the background.
+pub(super) fn run(ctx: &mut Context, events_loop: &mut EventsLoop, app: &mut App) -> GameResult {
+    while ctx.continuing {
+        ctx.timer_context.tick();
+        events_loop.poll_events(|event| {
+            ctx.process_event(&event);
+            if let Event::WindowEvent { event, .. } = event {
+                match event {
+                    WindowEvent::Resized(logical_size) => {
+                        app.resize_event(
+                            ctx,
+                            logical_size.width as f32,
+                            logical_size.height as f32,
+                        );
+                    }
+                    WindowEvent::CloseRequested if !app.quit_event(ctx) => event::quit(ctx),
+                    WindowEvent::Focused(gained) => app.focus_event(ctx, gained),
+                    WindowEvent::DroppedFile(path) => app.load_rom(&path),
+                    WindowEvent::ReceivedCharacter(character) => {
+                        app.text_input_event(ctx, character)
+                    }
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state,
+                                virtual_keycode: Some(keycode),
+                                modifiers,
+                                ..
+                            },
+                        ..
+                    } => match state {
+                        ElementState::Pressed => {
+                            let repeat = keyboard::is_key_repeated(ctx);
+                            app.key_down_event(ctx, keycode, modifiers.into(), repeat);
+                        }
+                        ElementState::Released => app.key_up_event(ctx, keycode, modifiers.into()),
+                    },
+                    WindowEvent::MouseWheel { delta, .. } => {
+                        let (x, y) = match delta {
+                            MouseScrollDelta::LineDelta(x, y) => (x, y),
+                            MouseScrollDelta::PixelDelta(position) => {
+                                (position.x as f32, position.y as f32)
+                            }
+                        };
+                        app.mouse_wheel_event(ctx, x, y);
+                    }
+                    WindowEvent::MouseInput {
+                        state: ElementState::Pressed,
+                        button,
+                        ..
+                    } => {
+                        let position = mouse::position(ctx);
+                        app.mouse_button_down_event(ctx, button, position.x, position.y);
+                    }
+                    _ => {}
+                }
+            }
+        });
+        while let Some(event) = ctx.gamepad_context.next_event() {
+            match event.event {
+                EventType::ButtonPressed(button, _) => app.handle_gamepad_button(button, true),
+                EventType::ButtonReleased(button, _) => app.handle_gamepad_button(button, false),
+                _ => {}
+            }
+        }
+        app.update(ctx)?;
+        if !app.minimized {
+            app.draw(ctx)?;
+        }
+        if let Some(delay) = app.background_delay() {
+            timer::sleep(delay);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,71 @@
+use ggez::event::Button;
+use std::collections::HashMap;
+
+/// D-pad to 2/8/4/6 (the usual directions), and the two main buttons to 5 and 0
+pub const DEFAULT_GAMEPAD_MAP: &str = "up=2,down=8,left=4,right=6,south=5,east=0";
+
+/// Maps gamepad buttons to Chip-8 keypad values.
+pub struct GamepadMap {
+    buttons: HashMap<Button, u8>,
+}
+
+impl GamepadMap {
+    /// Parses a mapping given as comma-separated `button=value` pairs, such as "south=5,up=2".
+    ///
+    /// Buttons are named after their position: up, down, left, right (the d-pad), south, east,
+    /// west, north (the action buttons), select, start, lt, lt2, rt and rt2 (the triggers).
+    pub fn parse(mapping: &str) -> Result<GamepadMap, String> {
+        let mut buttons = HashMap::new();
+        for pair in mapping
+            .split(',')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+        {
+            let mut parts = pair.splitn(2, '=');
+            let name = parts.next().unwrap_or_default().trim();
+            let value = parts
+                .next()
+                .ok_or_else(|| format!("Expected button=value in gamepad map: {}", pair))?
+                .trim();
+            let button = name_to_button(name)
+                .ok_or_else(|| format!("Unsupported button in gamepad map: {}", name))?;
+            let keypad_value = u8::from_str_radix(value, 16)
+                .ok()
+                .filter(|&value| value < 16)
+                .ok_or_else(|| format!("Expected a keypad value 0-F in gamepad map: {}", pair))?;
+            buttons.insert(button, keypad_value);
+        }
+        Ok(GamepadMap { buttons })
+    }
+
+    pub(super) fn keypad_value(&self, button: Button) -> Option<u8> {
+        self.buttons.get(&button).copied()
+    }
+}
+
+impl Default for GamepadMap {
+    fn default() -> GamepadMap {
+        GamepadMap::parse(DEFAULT_GAMEPAD_MAP).expect("Parse default gamepad map")
+    }
+}
+
+fn name_to_button(name: &str) -> Option<Button> {
+    let button = match name.to_ascii_lowercase().as_str() {
+        "up" => Button::DPadUp,
+        "down" => Button::DPadDown,
+        "left" => Button::DPadLeft,
+        "right" => Button::DPadRight,
+        "south" => Button::South,
+        "east" => Button::East,
+        "west" => Button::West,
+        "north" => Button::North,
+        "select" => Button::Select,
+        "start" => Button::Start,
+        "lt" => Button::LeftTrigger,
+        "lt2" => Button::LeftTrigger2,
+        "rt" => Button::RightTrigger,
+        "rt2" => Button::RightTrigger2,
+        _ => return None,
+    };
+    Some(button)
+}
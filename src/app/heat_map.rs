@@ -0,0 +1,66 @@
+use super::{App, C8_WIDTH};
+
+use ggez::graphics::{self, Color, DrawParam, FilterMode, Image};
+use ggez::{Context, GameResult};
+use mint::Point2;
+
+const ADDRESSES_PER_ROW: usize = 64;
+const COLD: (f32, f32, f32) = (0.4, 0.0, 0.0);
+const WARM: (f32, f32, f32) = (1.0, 0.5, 0.0);
+const HOT: (f32, f32, f32) = (1.0, 1.0, 0.6);
+
+impl App {
+    /// Shows how often each address in memory has been executed, in place of the screen. Each
+    /// row covers 64 bytes, and executed addresses go from dark red to light yellow (the most
+    /// executed address) on a log scale.
+    pub(super) fn draw_heat_map(&self, ctx: &mut Context) -> GameResult<()> {
+        let counts = self.chip8.execution_counts();
+        let max = counts.iter().copied().max().unwrap_or(0);
+        let log_max = (1.0 + max as f32).ln();
+        let mut rgba = Vec::with_capacity(4 * counts.len());
+        for &count in counts {
+            let color = if count == 0 {
+                self.palette.background
+            } else {
+                heat_color((1.0 + count as f32).ln() / log_max)
+            };
+            let (r, g, b) = color.to_rgb();
+            rgba.extend_from_slice(&[r, g, b, 255]);
+        }
+        let rows = counts.len() / ADDRESSES_PER_ROW;
+        let mut image = Image::from_rgba8(ctx, ADDRESSES_PER_ROW as u16, rows as u16, &rgba)?;
+        image.set_filter(FilterMode::Nearest);
+
+        let scaling = self.layout.scaling * self.layout.c8_height as f32 / rows as f32;
+        let x = self.layout.screen_x
+            + (C8_WIDTH as f32 * self.layout.scaling - ADDRESSES_PER_ROW as f32 * scaling) / 2.0;
+        let y = self.layout.screen_y;
+        graphics::draw(
+            ctx,
+            &image,
+            DrawParam::default()
+                .dest(Point2 { x, y })
+                .scale([scaling, scaling]),
+        )?;
+        self.draw_text(
+            ctx,
+            &format!("Heat map ({:X} bytes/row)", ADDRESSES_PER_ROW),
+            self.layout.screen_x + 10.0,
+            y + 10.0,
+        )
+    }
+}
+
+fn heat_color(heat: f32) -> Color {
+    let (from, to, t) = if heat < 0.5 {
+        (COLD, WARM, heat * 2.0)
+    } else {
+        (WARM, HOT, heat * 2.0 - 1.0)
+    };
+    Color::new(
+        from.0 + (to.0 - from.0) * t,
+        from.1 + (to.1 - from.1) * t,
+        from.2 + (to.2 - from.2) * t,
+        1.0,
+    )
+}
@@ -0,0 +1,310 @@
+use super::App;
+use crate::assembly;
+use crate::chip8::Instruction;
+use crate::symbols::Symbols;
+
+use ggez::event::KeyCode;
+use ggez::{Context, GameResult};
+use std::path::Path;
+use tracing::{info, warn};
+
+const INSTRUCTION_LISTING_LENGTH: usize = 32;
+const LINE_HEIGHT: f32 = 15.0;
+const MARGIN: f32 = 15.0;
+/// While following PC, this many instructions before it are kept in view
+const LINES_BEFORE_PC: usize = 4;
+
+/// What the text box under the listing is for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) enum ListingPrompt {
+    /// Finding an address, a label or an instruction
+    Find,
+    /// Toggling a breakpoint at an address or a label
+    Break,
+}
+
+impl App {
+    /// The disassembled instructions, starting at `listing_start` or around PC (if following it)
+    pub(super) fn draw_instruction_listing(&mut self, ctx: &mut Context) -> GameResult<()> {
+        let pc = self.chip8.program_counter;
+        self.rebuild_instruction_listing();
+        if self.follow_pc && !self.instruction_listing.iter().any(|(a, _)| *a == pc) {
+            self.listing_start = self.instruction_address_before(pc, LINES_BEFORE_PC);
+            self.rebuild_instruction_listing();
+        }
+
+        let x = self.layout.listing_x + MARGIN;
+        for (i, (address, text)) in self.instruction_listing.iter().enumerate() {
+            let y = MARGIN + i as f32 * LINE_HEIGHT;
+            let marker = if self.chip8.breakpoints().contains(address) {
+                "*"
+            } else if self.chip8.modified_code().contains_key(address) {
+                "!"
+            } else {
+                " "
+            };
+            let line = match self.symbols.name(*address) {
+                Some(name) => format!("{}{:03X} <{}>: {}", marker, address, name, text),
+                None => format!("{}{:03X}: {}", marker, address, text),
+            };
+            if *address == pc {
+                self.draw_text_with_color(ctx, &line, x, y, self.palette.highlight)?;
+            } else if self.listing_cursor == Some(*address) {
+                let color = self.palette.secondary_highlight;
+                self.draw_text_with_color(ctx, &line, x, y, color)?;
+            } else {
+                self.draw_text(ctx, &line, x, y)?;
+            }
+        }
+
+        let y = MARGIN + INSTRUCTION_LISTING_LENGTH as f32 * LINE_HEIGHT;
+        if let Some((prompt, input)) = &self.listing_prompt {
+            let line = match prompt {
+                ListingPrompt::Find => format!("Find: {}_", input),
+                ListingPrompt::Break => format!("Break at: {}_", input),
+            };
+            self.draw_text_with_color(ctx, &line, x, y, self.palette.secondary_highlight)?;
+        } else if !self.follow_pc {
+            self.draw_text(ctx, "(Home: follow PC)", x, y)?;
+        }
+        Ok(())
+    }
+
+    /// Instructions that the program has modified are disassembled again, since the listing of
+    /// the ROM no longer matches what they do
+    fn rebuild_instruction_listing(&mut self) {
+        let start = self.listing_start as usize;
+        let memory = self.chip8.memory();
+        let modified_code = self.chip8.modified_code();
+        self.instruction_listing = self
+            .disassembled_program
+            .iter()
+            .enumerate()
+            .skip(start)
+            .filter(|(_, text)| !text.is_empty())
+            .take(INSTRUCTION_LISTING_LENGTH)
+            .map(|(address, text)| {
+                let text = if modified_code.contains_key(&(address as u16)) {
+                    let opcode = u16::from_be_bytes([memory[address], memory[address + 1]]);
+                    assembly::disassemble_opcode(opcode)
+                        .unwrap_or_else(|_| format!("DATA[{:#06X}]", opcode))
+                } else {
+                    text.clone()
+                };
+                (
+                    address as u16,
+                    self.with_target_label(address as u16, &text),
+                )
+            })
+            .collect();
+    }
+
+    /// Replaces the labels of the ROM's symbol file with the ones in the file next to the new
+    /// ROM, if it has one
+    pub(super) fn load_symbols(&mut self, rom_path: &Path) {
+        self.symbols = match Symbols::load(rom_path) {
+            Ok(symbols) => {
+                if !symbols.is_empty() {
+                    info!(
+                        "Loaded {} labels from {}",
+                        symbols.len(),
+                        Symbols::path(rom_path).display()
+                    );
+                }
+                symbols
+            }
+            Err(err) => {
+                warn!("{}", err);
+                Symbols::default()
+            }
+        };
+    }
+
+    /// The disassembled instruction at the address, followed by the label of the address that it
+    /// jumps to, calls or points I at (if there is one)
+    pub(super) fn with_target_label(&self, address: u16, text: &str) -> String {
+        let memory = self.chip8.memory();
+        let opcode = match memory.get(address as usize..address as usize + 2) {
+            Some(bytes) => u16::from_be_bytes([bytes[0], bytes[1]]),
+            None => return text.to_owned(),
+        };
+        let target = match self.chip8.decode(opcode) {
+            Ok(Instruction::Jump(target))
+            | Ok(Instruction::Call(target))
+            | Ok(Instruction::SetI(target))
+            | Ok(Instruction::JumpWithOffset(_, target)) => target,
+            _ => return text.to_owned(),
+        };
+        match self.symbols.name(target) {
+            Some(name) => format!("{} <{}>", text, name),
+            None => text.to_owned(),
+        }
+    }
+
+    /// The address of the instruction that is the given number of lines before the given address
+    /// in the listing (or the first instruction, if there aren't that many)
+    fn instruction_address_before(&self, address: u16, lines: usize) -> u16 {
+        self.disassembled_program[..address as usize]
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, text)| !text.is_empty())
+            .take(lines)
+            .last()
+            .map_or(address, |(address, _)| address as u16)
+    }
+
+    /// The address of the instruction that is the given number of lines after the given address
+    /// in the listing (or the last instruction, if there aren't that many)
+    fn instruction_address_after(&self, address: u16, lines: usize) -> u16 {
+        self.disassembled_program
+            .iter()
+            .enumerate()
+            .skip(address as usize + 1)
+            .filter(|(_, text)| !text.is_empty())
+            .take(lines)
+            .last()
+            .map_or(address, |(address, _)| address as u16)
+    }
+
+    /// The address of the listing line at the given window position, if any
+    pub(super) fn instruction_listing_address_at(&self, x: f32, y: f32) -> Option<u16> {
+        if !self.is_over_instruction_listing(x) || y < MARGIN {
+            return None;
+        }
+        let line = ((y - MARGIN) / LINE_HEIGHT) as usize;
+        self.instruction_listing
+            .get(line)
+            .map(|(address, _)| *address)
+    }
+
+    pub(super) fn is_over_instruction_listing(&self, x: f32) -> bool {
+        x >= self.layout.listing_x && x < self.layout.call_stack_x
+    }
+
+    /// Scrolls the listing by the given number of lines, which stops it from following PC
+    pub(super) fn scroll_instruction_listing(&mut self, lines: i32) {
+        self.follow_pc = false;
+        self.listing_start = if lines < 0 {
+            self.instruction_address_before(self.listing_start, -lines as usize)
+        } else {
+            self.instruction_address_after(self.listing_start, lines as usize)
+        };
+    }
+
+    pub(super) fn scroll_instruction_listing_page(&mut self, pages: i32) {
+        self.scroll_instruction_listing(pages * INSTRUCTION_LISTING_LENGTH as i32);
+    }
+
+    pub(super) fn follow_pc(&mut self) {
+        self.follow_pc = true;
+    }
+
+    pub(super) fn open_listing_prompt(&mut self, prompt: ListingPrompt) {
+        self.listing_prompt = Some((prompt, String::new()));
+    }
+
+    /// Handles a key press while the text box under the listing is open
+    pub(super) fn handle_listing_prompt_key(&mut self, keycode: KeyCode) {
+        match keycode {
+            KeyCode::Return => match self.listing_prompt.take() {
+                Some((ListingPrompt::Find, query)) => self.search_instruction_listing(&query),
+                Some((ListingPrompt::Break, target)) => {
+                    let message = self.toggle_breakpoint_at(&target);
+                    self.show_toast(message);
+                }
+                None => {}
+            },
+            KeyCode::Escape => self.listing_prompt = None,
+            KeyCode::Back => {
+                if let Some((_, input)) = &mut self.listing_prompt {
+                    input.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub(super) fn handle_listing_prompt_input(&mut self, character: char) {
+        if let Some((_, input)) = &mut self.listing_prompt {
+            if !character.is_control() {
+                input.push(character);
+            }
+        }
+    }
+
+    /// The address of a label, or of an address in hex (like `2A0` or `0x2A0`)
+    fn parse_listing_address(&self, target: &str) -> Option<u16> {
+        let target = target.trim();
+        if let Some(address) = self.symbols.address(target) {
+            return Some(address);
+        }
+        let hex = target.trim_start_matches("0x");
+        if (1..=3).contains(&hex.len()) && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return u16::from_str_radix(hex, 16).ok();
+        }
+        None
+    }
+
+    /// Toggles the breakpoint at a label or an address, and returns what it did
+    pub(super) fn toggle_breakpoint_at(&mut self, target: &str) -> String {
+        let address = match self.parse_listing_address(target) {
+            Some(address) => address,
+            None => return format!("No label or address: {}", target.trim()),
+        };
+        self.stop_emulation_thread();
+        let name = match self.symbols.name(address) {
+            Some(name) => format!("{:03X} <{}>", address, name),
+            None => format!("{:03X}", address),
+        };
+        if self.chip8.toggle_breakpoint(address) {
+            format!("Breakpoint at {}", name)
+        } else {
+            format!("Removed the breakpoint at {}", name)
+        }
+    }
+
+    /// Selects the instruction at the given address (as 1-3 hex digits) or label, or else the
+    /// next instruction that contains the query, and scrolls to it
+    fn search_instruction_listing(&mut self, query: &str) {
+        if let Some(address) = self.symbols.address(query.trim()) {
+            self.select_listing_address(address);
+            return;
+        }
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return;
+        }
+        let hex = query.trim_start_matches("0x");
+        let found = if hex.len() <= 3 && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            let address = u16::from_str_radix(hex, 16).unwrap_or(0);
+            self.disassembled_program
+                .iter()
+                .enumerate()
+                .skip(address as usize)
+                .find(|(_, text)| !text.is_empty())
+                .map(|(address, _)| address as u16)
+        } else {
+            let from = self.listing_cursor.unwrap_or(self.listing_start) as usize + 1;
+            let len = self.disassembled_program.len();
+            (0..len)
+                .map(|offset| (from + offset) % len)
+                .find(|&address| {
+                    self.disassembled_program[address]
+                        .to_lowercase()
+                        .contains(&query)
+                })
+                .map(|address| address as u16)
+        };
+        match found {
+            Some(address) => self.select_listing_address(address),
+            None => self.show_toast(format!("Found no instruction matching {}", query)),
+        }
+    }
+
+    fn select_listing_address(&mut self, address: u16) {
+        self.listing_cursor = Some(address);
+        self.follow_pc = false;
+        self.listing_start = self.instruction_address_before(address, LINES_BEFORE_PC);
+    }
+}
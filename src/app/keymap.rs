@@ -0,0 +1,134 @@
+use ggez::event::KeyCode;
+use std::collections::HashMap;
+
+use crate::frontend::keymap_keys;
+pub use crate::frontend::DEFAULT_KEYMAP;
+
+/// Maps keyboard keys to Chip-8 keypad values.
+pub struct KeyMap {
+    keys: HashMap<KeyCode, u8>,
+}
+
+impl KeyMap {
+    /// Parses a keymap given as 16 characters, where the first character is the key that maps
+    /// to keypad value 0x0, the second one maps to 0x1, and so on, or as the name of a keyboard
+    /// layout (see `keymap_keys`).
+    pub fn parse(keys: &str) -> Result<KeyMap, String> {
+        let keys = keymap_keys(keys);
+        let chars: Vec<char> = keys.chars().collect();
+        if chars.len() != 16 {
+            return Err(format!(
+                "Expected 16 keys in keymap, but got {}: {}",
+                chars.len(),
+                keys
+            ));
+        }
+        let mut keymap = HashMap::new();
+        for (keypad_value, c) in chars.into_iter().enumerate() {
+            let keycode =
+                char_to_keycode(c).ok_or_else(|| format!("Unsupported key in keymap: {}", c))?;
+            if keymap.insert(keycode, keypad_value as u8).is_some() {
+                return Err(format!("Duplicate key in keymap: {}", c));
+            }
+        }
+        Ok(KeyMap { keys: keymap })
+    }
+
+    pub(super) fn keypad_value(&self, keycode: KeyCode) -> Option<u8> {
+        self.keys.get(&keycode).copied()
+    }
+}
+
+impl Default for KeyMap {
+    fn default() -> KeyMap {
+        KeyMap::parse(DEFAULT_KEYMAP).expect("Parse default keymap")
+    }
+}
+
+fn char_to_keycode(c: char) -> Option<KeyCode> {
+    let keycode = match c.to_ascii_lowercase() {
+        '0' => KeyCode::Key0,
+        '1' => KeyCode::Key1,
+        '2' => KeyCode::Key2,
+        '3' => KeyCode::Key3,
+        '4' => KeyCode::Key4,
+        '5' => KeyCode::Key5,
+        '6' => KeyCode::Key6,
+        '7' => KeyCode::Key7,
+        '8' => KeyCode::Key8,
+        '9' => KeyCode::Key9,
+        'a' => KeyCode::A,
+        'b' => KeyCode::B,
+        'c' => KeyCode::C,
+        'd' => KeyCode::D,
+        'e' => KeyCode::E,
+        'f' => KeyCode::F,
+        'g' => KeyCode::G,
+        'h' => KeyCode::H,
+        'i' => KeyCode::I,
+        'j' => KeyCode::J,
+        'k' => KeyCode::K,
+        'l' => KeyCode::L,
+        'm' => KeyCode::M,
+        'n' => KeyCode::N,
+        'o' => KeyCode::O,
+        'p' => KeyCode::P,
+        'q' => KeyCode::Q,
+        'r' => KeyCode::R,
+        's' => KeyCode::S,
+        't' => KeyCode::T,
+        'u' => KeyCode::U,
+        'v' => KeyCode::V,
+        'w' => KeyCode::W,
+        'x' => KeyCode::X,
+        'y' => KeyCode::Y,
+        'z' => KeyCode::Z,
+        ',' => KeyCode::Comma,
+        '.' => KeyCode::Period,
+        ';' => KeyCode::Semicolon,
+        '/' => KeyCode::Slash,
+        '-' => KeyCode::Minus,
+        '[' => KeyCode::LBracket,
+        ']' => KeyCode::RBracket,
+        '\'' => KeyCode::Apostrophe,
+        _ => return None,
+    };
+    Some(keycode)
+}
+
+#[test]
+fn test_parse_keymap() {
+    let keymap = KeyMap::parse("0123456789ABCDEF").unwrap();
+    assert_eq!(keymap.keypad_value(KeyCode::Key0), Some(0x0));
+    assert_eq!(keymap.keypad_value(KeyCode::F), Some(0xF));
+    assert_eq!(keymap.keypad_value(KeyCode::G), None);
+
+    let default = KeyMap::default();
+    assert_eq!(default.keypad_value(KeyCode::X), Some(0x0));
+    assert_eq!(default.keypad_value(KeyCode::V), Some(0xF));
+
+    assert!(KeyMap::parse("0123456789abcde")
+        .err()
+        .unwrap()
+        .starts_with("Expected 16 keys"));
+    assert_eq!(
+        KeyMap::parse("0123456789abcdea").err(),
+        Some("Duplicate key in keymap: a".to_owned())
+    );
+    assert_eq!(
+        KeyMap::parse("0123456789abcde!").err(),
+        Some("Unsupported key in keymap: !".to_owned())
+    );
+}
+
+#[test]
+fn test_parse_keymap_presets() {
+    for preset in crate::frontend::KEYMAP_PRESETS {
+        assert!(KeyMap::parse(preset).is_ok(), "{}", preset);
+    }
+    let azerty = KeyMap::parse("AZERTY").unwrap();
+    assert_eq!(azerty.keypad_value(KeyCode::A), Some(0x4));
+    assert_eq!(azerty.keypad_value(KeyCode::W), Some(0xA));
+    let dvorak = KeyMap::parse("dvorak").unwrap();
+    assert_eq!(dvorak.keypad_value(KeyCode::Apostrophe), Some(0x4));
+}
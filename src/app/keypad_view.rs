@@ -0,0 +1,77 @@
+use super::App;
+
+use ggez::graphics::{self, DrawMode, DrawParam, MeshBuilder, Rect};
+use ggez::{Context, GameResult};
+
+const KEYPAD_VIEW_X: f32 = 260.0;
+const KEYPAD_VIEW_Y: f32 = 135.0;
+const CELL_SIZE: f32 = 14.0;
+const CELL_STEP: f32 = 16.0;
+
+/// The layout of the original COSMAC VIP keypad
+const KEYPAD_LAYOUT: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+
+impl App {
+    /// The keypad, with pressed keys highlighted. While the machine is blocked on Fx0A, all
+    /// keys are outlined and the register that will receive the key is shown.
+    pub(super) fn draw_keypad_view(&self, ctx: &mut Context) -> GameResult<()> {
+        let x = KEYPAD_VIEW_X;
+        let y = self.layout.debug_y + KEYPAD_VIEW_Y;
+        let pressed_keys = self.chip8.pressed_keys();
+        let waiting_for_key = self.chip8.waiting_for_key();
+
+        let mut mesh = MeshBuilder::new();
+        for (row, keys) in KEYPAD_LAYOUT.iter().enumerate() {
+            for (column, &key) in keys.iter().enumerate() {
+                let bounds = Rect::new(
+                    x + column as f32 * CELL_STEP,
+                    y + row as f32 * CELL_STEP,
+                    CELL_SIZE,
+                    CELL_SIZE,
+                );
+                let color = if pressed_keys[key as usize] {
+                    self.palette.highlight
+                } else {
+                    self.palette.background
+                };
+                mesh.rectangle(DrawMode::fill(), bounds, color);
+                if waiting_for_key.is_some() {
+                    mesh.rectangle(
+                        DrawMode::stroke(1.0),
+                        bounds,
+                        self.palette.secondary_highlight,
+                    );
+                }
+            }
+        }
+        let mesh = mesh.build(ctx)?;
+        graphics::draw(ctx, &mesh, DrawParam::default())?;
+
+        for (row, keys) in KEYPAD_LAYOUT.iter().enumerate() {
+            for (column, &key) in keys.iter().enumerate() {
+                self.draw_text(
+                    ctx,
+                    &format!("{:X}", key),
+                    x + column as f32 * CELL_STEP + 4.0,
+                    y + row as f32 * CELL_STEP + 1.0,
+                )?;
+            }
+        }
+
+        if let Some(register) = waiting_for_key {
+            self.draw_text_with_color(
+                ctx,
+                &format!("Wait: V{:X}", register),
+                x,
+                y + 4.0 * CELL_STEP,
+                self.palette.secondary_highlight,
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+use super::C8_WIDTH;
+
+const DEFAULT_SCALING: f32 = 8.0;
+const DEBUG_HEIGHT: f32 = 255.0;
+const INSTRUCTION_LISTING_WIDTH: f32 = 200.0;
+const CALL_STACK_WIDTH: f32 = 150.0;
+/// The width of the panels to the right of the screen
+const SIDE_PANELS_WIDTH: f32 = INSTRUCTION_LISTING_WIDTH + CALL_STACK_WIDTH;
+/// The space between the two screens, with a second machine
+const SCREEN_GAP: f32 = 10.0;
+/// The space above the screens for their labels, with a second machine
+const SCREEN_LABEL_HEIGHT: f32 = 20.0;
+
+/// Positions of the Chip-8 screen and the debug panels, for a given window size.
+///
+/// The debug panels have a fixed size, and the screen is scaled to fit the remaining space
+/// while preserving its aspect ratio. With a second machine, the two screens share that space,
+/// with the main one on the left.
+pub(super) struct Layout {
+    pub screen_x: f32,
+    pub screen_y: f32,
+    /// Where the second machine's screen is, if there is one
+    pub second_screen_x: Option<f32>,
+    /// The height of the labels above the screens (0 with a single screen)
+    pub label_height: f32,
+    pub scaling: f32,
+    pub debug_y: f32,
+    pub listing_x: f32,
+    pub call_stack_x: f32,
+    /// The height of the Chip-8 display (32, 48 or 64) that the layout was made for
+    pub c8_height: u8,
+}
+
+impl Layout {
+    pub fn new(
+        window_width: f32,
+        window_height: f32,
+        debug: bool,
+        c8_height: u8,
+        side_by_side: bool,
+    ) -> Layout {
+        let (area_width, area_height) = if debug {
+            (
+                window_width - SIDE_PANELS_WIDTH,
+                window_height - DEBUG_HEIGHT,
+            )
+        } else {
+            (window_width, window_height)
+        };
+        let (screens, gaps, label_height) = if side_by_side {
+            (2.0, SCREEN_GAP, SCREEN_LABEL_HEIGHT)
+        } else {
+            (1.0, 0.0, 0.0)
+        };
+        let scaling = ((area_width - gaps) / (screens * C8_WIDTH as f32))
+            .min((area_height - label_height) / c8_height as f32)
+            .max(1.0);
+        let screen_width = C8_WIDTH as f32 * scaling;
+        let screens_width = screens * screen_width + gaps;
+        let screens_height = c8_height as f32 * scaling + label_height;
+        let screen_x = ((area_width - screens_width) / 2.0).max(0.0);
+        let listing_x = area_width.max(screens_width);
+        Layout {
+            screen_x,
+            screen_y: ((area_height - screens_height) / 2.0).max(0.0) + label_height,
+            second_screen_x: if side_by_side {
+                Some(screen_x + screen_width + SCREEN_GAP)
+            } else {
+                None
+            },
+            label_height,
+            scaling,
+            debug_y: area_height.max(screens_height),
+            listing_x,
+            call_stack_x: listing_x + INSTRUCTION_LISTING_WIDTH,
+            c8_height,
+        }
+    }
+
+    /// The initial window size, for the given size of a Chip-8 pixel and display height
+    pub fn default_window_size(
+        debug: bool,
+        scaling: Option<f32>,
+        c8_height: u8,
+        side_by_side: bool,
+    ) -> (f32, f32) {
+        let scaling = scaling.unwrap_or(DEFAULT_SCALING);
+        let (width, height) = (C8_WIDTH as f32 * scaling, c8_height as f32 * scaling);
+        let (width, height) = if side_by_side {
+            (2.0 * width + SCREEN_GAP, height + SCREEN_LABEL_HEIGHT)
+        } else {
+            (width, height)
+        };
+        if debug {
+            (width + SIDE_PANELS_WIDTH, height + DEBUG_HEIGHT)
+        } else {
+            (width, height)
+        }
+    }
+}
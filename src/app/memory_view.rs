@@ -0,0 +1,77 @@
+use super::value_editor::EditTarget;
+use super::App;
+use crate::memory_map::MemoryRegion;
+
+use ggez::graphics::{Color, TextFragment};
+use ggez::{Context, GameResult};
+
+pub(super) const MEMORY_VIEW_X: f32 = 330.0;
+pub(super) const MEMORY_VIEW_ROWS: u16 = 16;
+pub(super) const BYTES_PER_ROW: u16 = 8;
+
+impl App {
+    /// A hex dump of memory, with the bytes at PC and I highlighted. The other bytes are colored
+    /// by their region: code like text, data like lit pixels, and the interpreter area, free
+    /// memory and the work area at the top dimmed.
+    pub(super) fn draw_memory_view(&self, ctx: &mut Context) -> GameResult<()> {
+        let line_height = 15.0;
+        let margin = 10.0;
+        let pc = self.chip8.program_counter as usize;
+        let i = self.chip8.address_register as usize;
+        let memory = self.chip8.memory();
+
+        for row in 0..MEMORY_VIEW_ROWS {
+            let row_address = (self.memory_view_address + row * BYTES_PER_ROW) as usize;
+            if row_address >= memory.len() {
+                break;
+            }
+            let mut fragments =
+                vec![TextFragment::new(format!("{:03X}:", row_address)).color(self.palette.text)];
+            let row_end = (row_address + BYTES_PER_ROW as usize).min(memory.len());
+            for (address, byte) in memory.iter().enumerate().take(row_end).skip(row_address) {
+                let color = if address == pc || address == pc + 1 {
+                    self.palette.highlight
+                } else if address == i {
+                    self.palette.secondary_highlight
+                } else {
+                    self.region_color(self.memory_map.region(address as u16))
+                };
+                let text = match self.edited_text(EditTarget::Memory(address as u16)) {
+                    Some(text) => TextFragment::new(format!(" {}", text))
+                        .color(self.palette.secondary_highlight),
+                    None => TextFragment::new(format!(" {:02X}", byte)).color(color),
+                };
+                fragments.push(text);
+            }
+            self.draw_fragments(
+                ctx,
+                fragments,
+                MEMORY_VIEW_X,
+                self.layout.debug_y + margin + row as f32 * line_height,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn region_color(&self, region: MemoryRegion) -> Color {
+        let dimmed = |color: Color| Color { a: 0.5, ..color };
+        match region {
+            MemoryRegion::Code => self.palette.text,
+            MemoryRegion::Data => self.palette.foreground,
+            MemoryRegion::Interpreter | MemoryRegion::Free => dimmed(self.palette.text),
+            MemoryRegion::WorkArea => dimmed(self.palette.secondary_highlight),
+        }
+    }
+
+    /// Scrolls the memory view by the given number of rows
+    pub(super) fn scroll_memory_view(&mut self, rows: i32) {
+        let max_address =
+            (self.chip8.memory().len() as i32 - (MEMORY_VIEW_ROWS * BYTES_PER_ROW) as i32).max(0);
+        let address = self.memory_view_address as i32 + rows * BYTES_PER_ROW as i32;
+        self.memory_view_address = address.max(0).min(max_address) as u16;
+    }
+
+    pub(super) fn scroll_memory_view_page(&mut self, pages: i32) {
+        self.scroll_memory_view(pages * MEMORY_VIEW_ROWS as i32);
+    }
+}
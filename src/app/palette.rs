@@ -0,0 +1,114 @@
+use ggez::graphics::Color;
+
+pub const PALETTE_PRESETS: [&str; 5] = ["default", "green", "amber", "lcd", "high-contrast"];
+
+/// The colors used for the Chip-8 screen and the debug panels.
+#[derive(Clone, Copy, Debug)]
+pub struct Palette {
+    pub foreground: Color,
+    pub background: Color,
+    pub debug_background: Color,
+    pub text: Color,
+    pub highlight: Color,
+    pub secondary_highlight: Color,
+}
+
+impl Palette {
+    /// Returns one of the built-in palettes (see `PALETTE_PRESETS`)
+    pub fn preset(name: &str) -> Option<Palette> {
+        let palette = match name {
+            "default" => Palette::default(),
+            "green" => Palette {
+                foreground: Color::from_rgb_u32(0x33FF66),
+                background: Color::from_rgb_u32(0x0A1A0F),
+                debug_background: Color::from_rgb_u32(0x102014),
+                text: Color::from_rgb_u32(0x88CC99),
+                highlight: Color::from_rgb_u32(0x33FF66),
+                secondary_highlight: Color::from_rgb_u32(0xCCFF33),
+            },
+            "amber" => Palette {
+                foreground: Color::from_rgb_u32(0xFFB000),
+                background: Color::from_rgb_u32(0x1A1000),
+                debug_background: Color::from_rgb_u32(0x2A1C08),
+                text: Color::from_rgb_u32(0xCC9955),
+                highlight: Color::from_rgb_u32(0xFFD060),
+                secondary_highlight: Color::from_rgb_u32(0xFF6600),
+            },
+            "lcd" => Palette {
+                foreground: Color::from_rgb_u32(0x0F380F),
+                background: Color::from_rgb_u32(0x9BBC0F),
+                debug_background: Color::from_rgb_u32(0x306230),
+                text: Color::from_rgb_u32(0xCADC9F),
+                highlight: Color::from_rgb_u32(0x9BBC0F),
+                secondary_highlight: Color::from_rgb_u32(0x0F380F),
+            },
+            "high-contrast" => Palette {
+                foreground: Color::from_rgb_u32(0xFFFFFF),
+                background: Color::from_rgb_u32(0x000000),
+                debug_background: Color::from_rgb_u32(0x000000),
+                text: Color::from_rgb_u32(0xFFFFFF),
+                highlight: Color::from_rgb_u32(0xFFFF00),
+                secondary_highlight: Color::from_rgb_u32(0x00FFFF),
+            },
+            _ => return None,
+        };
+        Some(palette)
+    }
+
+    /// The color of a pixel that is lit with the given intensity (from 0.0 to 1.0)
+    pub fn pixel_color(&self, intensity: f32) -> Color {
+        mix(self.background, self.foreground, intensity)
+    }
+}
+
+/// The color between the two, at the given intensity (from 0.0 for `bg` to 1.0 for `fg`)
+pub fn mix(bg: Color, fg: Color, intensity: f32) -> Color {
+    Color::new(
+        bg.r + (fg.r - bg.r) * intensity,
+        bg.g + (fg.g - bg.g) * intensity,
+        bg.b + (fg.b - bg.b) * intensity,
+        1.0,
+    )
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette {
+            foreground: Color::from_rgb_u32(0xFFFFFF),
+            background: Color::from_rgb_u32(0x000000),
+            debug_background: Color::new(0.2, 0.2, 0.3, 1.0),
+            text: Color::from_rgb_u32(0xFFFFFF),
+            highlight: Color::new(0.4, 1.0, 0.5, 1.0),
+            secondary_highlight: Color::new(1.0, 0.8, 0.3, 1.0),
+        }
+    }
+}
+
+/// Parses a color given as "RRGGBB" or "#RRGGBB"
+pub fn parse_color(s: &str) -> Result<Color, String> {
+    let hex = s.trim_start_matches('#');
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("Expected a color on the form RRGGBB: {}", s));
+    }
+    u32::from_str_radix(hex, 16)
+        .map(Color::from_rgb_u32)
+        .map_err(|_| format!("Expected a color on the form RRGGBB: {}", s))
+}
+
+#[test]
+fn test_parse_color() {
+    let expected = Color::from_rgb_u32(0x12AB3C);
+    assert_eq!(parse_color("12AB3C"), Ok(expected));
+    assert_eq!(parse_color("#12ab3c"), Ok(expected));
+    for invalid in ["", "#", "12AB3", "12AB3C4", "#12AB3G", "+2AB3C", "0x12AB3C"] {
+        assert!(parse_color(invalid).is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn test_palette_presets() {
+    for name in PALETTE_PRESETS {
+        assert!(Palette::preset(name).is_some(), "{}", name);
+    }
+    assert!(Palette::preset("purple").is_none());
+}
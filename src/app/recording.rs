@@ -0,0 +1,121 @@
+use super::screenshot::timestamped_path;
+use super::{Palette, C8_WIDTH};
+use crate::chip8::DisplayBuffer;
+
+use gif::{Encoder, Frame, Repeat, SetParameter};
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::PathBuf;
+use tracing::warn;
+
+const RECORDING_DIRECTORY: &str = "recordings";
+const RECORDING_SCALING: u16 = 4;
+const FRAME_INTERVAL: f64 = 1.0 / 60.0;
+
+/// Records the display to an animated GIF, capturing it at 60Hz.
+///
+/// GIF frame delays are given in centiseconds, so identical consecutive frames are merged and
+/// each frame's delay is rounded such that the total duration stays accurate.
+pub(super) struct GifRecorder {
+    encoder: Encoder<BufWriter<File>>,
+    path: PathBuf,
+    frame_cooldown: f64,
+    elapsed_time: f64,
+    pending_frame: Option<Vec<u8>>,
+    written_centiseconds: u64,
+    /// The display height when the recording started, which all frames have
+    height: u8,
+}
+
+impl GifRecorder {
+    pub fn start(palette: &Palette, rom_filename: &str, height: u8) -> Result<GifRecorder, String> {
+        fs::create_dir_all(RECORDING_DIRECTORY)
+            .map_err(|err| format!("Couldn't create {}: {}", RECORDING_DIRECTORY, err))?;
+        let path = timestamped_path(RECORDING_DIRECTORY, rom_filename, "gif");
+        let file = File::create(&path)
+            .map_err(|err| format!("Couldn't create {}: {}", path.display(), err))?;
+        let (bg_r, bg_g, bg_b) = palette.background.to_rgb();
+        let (fg_r, fg_g, fg_b) = palette.foreground.to_rgb();
+        let global_palette = [bg_r, bg_g, bg_b, fg_r, fg_g, fg_b];
+        let mut encoder = Encoder::new(
+            BufWriter::new(file),
+            C8_WIDTH as u16 * RECORDING_SCALING,
+            height as u16 * RECORDING_SCALING,
+            &global_palette,
+        )
+        .map_err(|err| format!("Couldn't start recording: {}", err))?;
+        encoder
+            .set(Repeat::Infinite)
+            .map_err(|err| format!("Couldn't start recording: {}", err))?;
+        Ok(GifRecorder {
+            encoder,
+            path,
+            frame_cooldown: 0.0,
+            elapsed_time: 0.0,
+            pending_frame: None,
+            written_centiseconds: 0,
+            height,
+        })
+    }
+
+    pub fn update(&mut self, display_buffer: &DisplayBuffer, elapsed_time: f64) {
+        self.elapsed_time += elapsed_time;
+        self.frame_cooldown -= elapsed_time;
+        while self.frame_cooldown <= 0.0 {
+            self.frame_cooldown += FRAME_INTERVAL;
+            self.capture(display_buffer);
+        }
+    }
+
+    /// Writes the last frame and finishes the file
+    pub fn stop(mut self) -> Result<PathBuf, String> {
+        let end_time = self.elapsed_time;
+        self.write_pending_frame(end_time)?;
+        Ok(self.path.clone())
+    }
+
+    fn capture(&mut self, display_buffer: &DisplayBuffer) {
+        let pixels = scaled_indexed_pixels(display_buffer, self.height);
+        let unchanged = self.pending_frame.as_ref() == Some(&pixels);
+        if !unchanged {
+            let now = self.elapsed_time;
+            if let Err(err) = self.write_pending_frame(now) {
+                warn!("{}", err);
+            }
+            self.pending_frame = Some(pixels);
+        }
+    }
+
+    fn write_pending_frame(&mut self, end_time: f64) -> Result<(), String> {
+        if let Some(pixels) = self.pending_frame.take() {
+            let end_centiseconds = (end_time * 100.0).round() as u64;
+            let delay = end_centiseconds.saturating_sub(self.written_centiseconds);
+            self.written_centiseconds = end_centiseconds;
+            let mut frame = Frame::from_indexed_pixels(
+                C8_WIDTH as u16 * RECORDING_SCALING,
+                self.height as u16 * RECORDING_SCALING,
+                &pixels,
+                None,
+            );
+            frame.delay = delay.min(u16::MAX as u64) as u16;
+            self.encoder
+                .write_frame(&frame)
+                .map_err(|err| format!("Couldn't write to {}: {}", self.path.display(), err))?;
+        }
+        Ok(())
+    }
+}
+
+fn scaled_indexed_pixels(display_buffer: &DisplayBuffer, height: u8) -> Vec<u8> {
+    let scaling = RECORDING_SCALING as usize;
+    let width = C8_WIDTH as usize * scaling;
+    let height = height as usize * scaling;
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let lit = display_buffer.get_pixel((x / scaling) as u8, (y / scaling) as u8);
+            pixels.push(lit as u8);
+        }
+    }
+    pixels
+}
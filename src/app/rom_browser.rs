@@ -0,0 +1,93 @@
+use super::App;
+
+use ggez::graphics::{self, DrawMode, MeshBuilder, Rect};
+use ggez::{Context, GameResult};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub(super) const ROM_DIRECTORY: &str = "programs";
+const ROM_EXTENSIONS: [&str; 2] = ["ch8", "c8"];
+const LINE_HEIGHT: f32 = 15.0;
+const MARGIN: f32 = 15.0;
+
+/// A list of the ROMs in a directory, from which one can be picked to run
+pub(super) struct RomBrowser {
+    roms: Vec<PathBuf>,
+    selected: usize,
+}
+
+impl RomBrowser {
+    pub fn open(directory: &str) -> Result<RomBrowser, String> {
+        let entries = fs::read_dir(directory)
+            .map_err(|err| format!("Couldn't read {}: {}", directory, err))?;
+        let mut roms: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.extension()
+                    .and_then(|extension| extension.to_str())
+                    .is_some_and(|extension| ROM_EXTENSIONS.contains(&extension))
+            })
+            .collect();
+        if roms.is_empty() {
+            return Err(format!("Found no ROMs in {}", directory));
+        }
+        roms.sort();
+        Ok(RomBrowser { roms, selected: 0 })
+    }
+
+    pub fn move_selection(&mut self, delta: i32) {
+        let last = self.roms.len() as i32 - 1;
+        self.selected = (self.selected as i32 + delta).max(0).min(last) as usize;
+    }
+
+    pub fn selected(&self) -> &Path {
+        &self.roms[self.selected]
+    }
+}
+
+impl App {
+    pub(super) fn draw_rom_browser(&self, ctx: &mut Context) -> GameResult<()> {
+        let browser = match &self.rom_browser {
+            Some(browser) => browser,
+            None => return Ok(()),
+        };
+        let (width, height) = graphics::drawable_size(ctx);
+        let background = MeshBuilder::new()
+            .rectangle(
+                DrawMode::fill(),
+                Rect::new(0.0, 0.0, width, height),
+                self.palette.debug_background,
+            )
+            .build(ctx)?;
+        graphics::draw(ctx, &background, graphics::DrawParam::default())?;
+
+        self.draw_text(
+            ctx,
+            "Select a ROM (Up/Down, Enter to run, Tab to cancel):",
+            MARGIN,
+            MARGIN,
+        )?;
+        let visible_lines = (((height - 3.0 * MARGIN) / LINE_HEIGHT) as usize).max(1);
+        let first = (browser.selected + 1).saturating_sub(visible_lines);
+        for (i, rom) in browser
+            .roms
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(visible_lines)
+        {
+            let name = rom
+                .file_name()
+                .map(|name| name.to_string_lossy())
+                .unwrap_or_default();
+            let y = 2.0 * MARGIN + (i - first) as f32 * LINE_HEIGHT;
+            if i == browser.selected {
+                let line = format!("> {}", name);
+                self.draw_text_with_color(ctx, &line, MARGIN, y, self.palette.highlight)?;
+            } else {
+                self.draw_text(ctx, &format!("  {}", name), MARGIN, y)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,67 @@
+use super::{Palette, C8_WIDTH};
+use crate::chip8::DisplayBuffer;
+
+use image::imageops::{self, FilterType};
+use image::RgbaImage;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SCREENSHOT_DIRECTORY: &str = "screenshots";
+const SCREENSHOT_SCALING: u32 = 8;
+
+/// Saves the display as a PNG in the screenshots directory, named after the ROM and the
+/// current time. Returns the path of the file.
+pub(super) fn save_screenshot(
+    display_buffer: &DisplayBuffer,
+    palette: &Palette,
+    rom_filename: &str,
+) -> Result<PathBuf, String> {
+    let rgba = display_buffer.to_rgba(
+        palette.foreground.to_rgba().into(),
+        palette.background.to_rgba().into(),
+    );
+    let height = display_buffer.height() as u32;
+    let image = RgbaImage::from_raw(C8_WIDTH as u32, height, rgba).expect("Display buffer size");
+    let image = imageops::resize(
+        &image,
+        C8_WIDTH as u32 * SCREENSHOT_SCALING,
+        height * SCREENSHOT_SCALING,
+        FilterType::Nearest,
+    );
+
+    fs::create_dir_all(SCREENSHOT_DIRECTORY)
+        .map_err(|err| format!("Couldn't create {}: {}", SCREENSHOT_DIRECTORY, err))?;
+    let path = timestamped_path(SCREENSHOT_DIRECTORY, rom_filename, "png");
+    image
+        .save(&path)
+        .map_err(|err| format!("Couldn't save {}: {}", path.display(), err))?;
+    Ok(path)
+}
+
+/// Saves the display as text (see `DisplayBuffer::to_text_grid`) in the screenshots directory,
+/// like `save_screenshot`. Returns the path of the file.
+pub(super) fn save_text_screenshot(
+    display_buffer: &DisplayBuffer,
+    rom_filename: &str,
+) -> Result<PathBuf, String> {
+    fs::create_dir_all(SCREENSHOT_DIRECTORY)
+        .map_err(|err| format!("Couldn't create {}: {}", SCREENSHOT_DIRECTORY, err))?;
+    let path = timestamped_path(SCREENSHOT_DIRECTORY, rom_filename, "txt");
+    fs::write(&path, display_buffer.to_text_grid())
+        .map_err(|err| format!("Couldn't save {}: {}", path.display(), err))?;
+    Ok(path)
+}
+
+/// A path like "directory/pong_1612345678901.extension"
+pub(super) fn timestamped_path(directory: &str, rom_filename: &str, extension: &str) -> PathBuf {
+    let rom_name = Path::new(rom_filename)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "chip8".to_owned());
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis())
+        .unwrap_or(0);
+    Path::new(directory).join(format!("{}_{}.{}", rom_name, millis, extension))
+}
@@ -0,0 +1,61 @@
+use super::App;
+use crate::memory_search::{Filter, MemorySearch};
+
+use ggez::graphics::TextFragment;
+use ggez::{Context, GameResult};
+
+const LINE_HEIGHT: f32 = 15.0;
+/// How many of the matches are listed, since there's only room for a few
+const MAX_LISTED_MATCHES: usize = 5;
+
+impl App {
+    /// The number of matches of the memory search, and the first few of them with their values
+    pub(super) fn draw_search_view(&self, ctx: &mut Context, x: f32, y: f32) -> GameResult<()> {
+        let search = match &self.memory_search {
+            Some(search) => search,
+            None => return Ok(()),
+        };
+        let matches = search.matches();
+        self.draw_text(ctx, &format!("Search: {} matches", matches.len()), x, y)?;
+        let memory = self.chip8.memory();
+        for (i, &address) in matches.iter().take(MAX_LISTED_MATCHES).enumerate() {
+            let fragments = vec![
+                TextFragment::new(format!("{:03X}: ", address)).color(self.palette.text),
+                TextFragment::new(format!("{:#04X}", memory[address as usize]))
+                    .color(self.palette.highlight),
+                TextFragment::new(format!(" (was {:#04X})", search.previous_value(address)))
+                    .color(self.palette.text),
+            ];
+            self.draw_fragments(ctx, fragments, x, y + (i + 1) as f32 * LINE_HEIGHT)?;
+        }
+        Ok(())
+    }
+
+    /// Narrows down the memory search (starting one if needed) with a filter like `3` or
+    /// `increased`, or forgets it with `reset`
+    pub(super) fn search_memory(&mut self, source: &str) -> String {
+        if source.trim() == "reset" {
+            self.memory_search = None;
+            return "Reset the search".to_owned();
+        }
+        let filter = match Filter::parse(source) {
+            Ok(filter) => filter,
+            Err(err) => return err,
+        };
+        let search = match &mut self.memory_search {
+            Some(search) => search,
+            None if !matches!(filter, Filter::Equal(_)) => {
+                // There's nothing to compare with yet
+                self.memory_search = Some(MemorySearch::new(&self.chip8));
+                return "Started a search: change the value in the game, then search again"
+                    .to_owned();
+            }
+            None => self.memory_search.insert(MemorySearch::new(&self.chip8)),
+        };
+        let count = search.filter(&self.chip8, filter);
+        match search.matches() {
+            [address] => format!("Found it at {:03X}", address),
+            _ => format!("{} matches", count),
+        }
+    }
+}
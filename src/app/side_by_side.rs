@@ -0,0 +1,130 @@
+use super::{App, STEP_HISTORY_LENGTH};
+use crate::chip8::{Chip8, StepHistory};
+
+use ggez::graphics::{self, Color, DrawParam, FilterMode, Image};
+use ggez::{Context, GameResult};
+use mint::Point2;
+use tracing::warn;
+
+/// A machine that runs next to the main one and gets the same keys, for comparing two ROMs (like
+/// a patched one and the original) or one ROM under two sets of quirks
+pub struct SecondMachine {
+    chip8: Chip8,
+    /// The labels of the main machine and this one, which are shown above their screens
+    labels: [String; 2],
+    /// The state that the machine started in, which it's reset to along with the main machine
+    initial_state: Vec<u8>,
+    /// The error that stopped this machine, which doesn't stop the main one
+    error: Option<String>,
+    step_history: StepHistory,
+}
+
+impl SecondMachine {
+    pub fn new(chip8: Chip8, labels: [String; 2]) -> SecondMachine {
+        let initial_state = chip8.save_state();
+        SecondMachine {
+            chip8,
+            labels,
+            initial_state,
+            error: None,
+            step_history: StepHistory::new(STEP_HISTORY_LENGTH),
+        }
+    }
+
+    pub(super) fn update(&mut self, dt: f64) {
+        if self.error.is_none() {
+            if let Err(err) = self.chip8.update(dt) {
+                self.stop_on_error(err);
+            }
+        }
+    }
+
+    pub(super) fn step_instruction(&mut self) {
+        if self.error.is_none() {
+            if let Err(err) = self.step_history.step(&mut self.chip8) {
+                self.stop_on_error(err);
+            }
+        }
+    }
+
+    pub(super) fn step_back(&mut self) {
+        match self.step_history.step_back(&mut self.chip8) {
+            Ok(true) => self.error = None,
+            Ok(false) => {}
+            Err(err) => self.stop_on_error(err),
+        }
+    }
+
+    pub(super) fn advance_frame(&mut self) {
+        if self.error.is_none() {
+            if let Err(err) = self.chip8.advance_frame() {
+                self.stop_on_error(err);
+            }
+        }
+    }
+
+    pub(super) fn handle_key_event(&mut self, key: u8, pressed: bool) {
+        self.chip8.handle_key_event(key, pressed);
+    }
+
+    pub(super) fn reset(&mut self) {
+        self.chip8
+            .load_state(&self.initial_state)
+            .expect("The initial state of the second machine is invalid");
+        self.error = None;
+    }
+
+    fn stop_on_error(&mut self, err: String) {
+        warn!("{} stopped on error: {}", self.labels[1], err);
+        self.error = Some(err);
+    }
+}
+
+impl App {
+    /// Draws the second machine's screen to the right of the main one, and the labels of both.
+    /// A display that is taller than the main one is scaled down to the same height.
+    pub(super) fn draw_second_machine(&self, ctx: &mut Context) -> GameResult<()> {
+        let (second_machine, x) = match (&self.second_machine, self.layout.second_screen_x) {
+            (Some(second_machine), Some(x)) => (second_machine, x),
+            _ => return Ok(()),
+        };
+        let display = &second_machine.chip8.display_buffer;
+        let color = |color: Color| {
+            let (r, g, b) = color.to_rgb();
+            [r, g, b, 255]
+        };
+        let rgba = display.to_rgba(
+            color(self.palette.foreground),
+            color(self.palette.background),
+        );
+        let mut image =
+            Image::from_rgba8(ctx, display.width() as u16, display.height() as u16, &rgba)?;
+        image.set_filter(FilterMode::Nearest);
+        let scaling =
+            self.layout.scaling * (self.layout.c8_height as f32 / display.height() as f32).min(1.0);
+        graphics::draw(
+            ctx,
+            &image,
+            DrawParam::default()
+                .dest(Point2 {
+                    x,
+                    y: self.layout.screen_y,
+                })
+                .scale([scaling, scaling]),
+        )?;
+
+        let label_y = self.layout.screen_y - self.layout.label_height + 3.0;
+        let [main_label, second_label] = &second_machine.labels;
+        self.draw_text(ctx, main_label, self.layout.screen_x, label_y)?;
+        match &second_machine.error {
+            Some(err) => self.draw_text_with_color(
+                ctx,
+                &format!("{} (stopped: {})", second_label, err),
+                x,
+                label_y,
+                self.palette.secondary_highlight,
+            ),
+            None => self.draw_text(ctx, second_label, x, label_y),
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use super::App;
+
+use ggez::graphics::{self, DrawParam, FilterMode, Image};
+use ggez::{Context, GameResult};
+use mint::Point2;
+
+const SPRITE_VIEW_X: f32 = 250.0;
+const SPRITE_VIEW_SCALING: f32 = 4.0;
+const DEFAULT_SPRITE_HEIGHT: u8 = 8;
+const MAX_SPRITE_HEIGHT: u8 = 15;
+
+impl App {
+    /// A zoomed preview of the sprite at I (or at an address chosen by the user). Unless the
+    /// user has picked a height, it's taken from the next instruction if that's a Dxyn.
+    pub(super) fn draw_sprite_view(&self, ctx: &mut Context) -> GameResult<()> {
+        let margin = 10.0;
+        let address = self.sprite_view_address();
+        let height = self.sprite_view_height();
+        let memory = self.chip8.memory();
+
+        let (fg_r, fg_g, fg_b, _) = self.palette.foreground.to_rgba();
+        let (bg_r, bg_g, bg_b, _) = self.palette.background.to_rgba();
+        let mut rgba = Vec::with_capacity(4 * 8 * height as usize);
+        for row in 0..height as usize {
+            let byte = memory.get(address as usize + row).copied().unwrap_or(0);
+            for bit in 0..8 {
+                if byte & (0x80 >> bit) != 0 {
+                    rgba.extend_from_slice(&[fg_r, fg_g, fg_b, 255]);
+                } else {
+                    rgba.extend_from_slice(&[bg_r, bg_g, bg_b, 255]);
+                }
+            }
+        }
+
+        let y = self.layout.debug_y + margin;
+        self.draw_text(ctx, &format!("{:03X}", address), SPRITE_VIEW_X, y)?;
+        let mut image = Image::from_rgba8(ctx, 8, height as u16, &rgba)?;
+        image.set_filter(FilterMode::Nearest);
+        graphics::draw(
+            ctx,
+            &image,
+            DrawParam::default()
+                .dest(Point2 {
+                    x: SPRITE_VIEW_X + 30.0,
+                    y,
+                })
+                .scale([SPRITE_VIEW_SCALING, SPRITE_VIEW_SCALING]),
+        )
+    }
+
+    fn sprite_view_address(&self) -> u16 {
+        self.sprite_view_address
+            .unwrap_or(self.chip8.address_register)
+    }
+
+    fn sprite_view_height(&self) -> u8 {
+        if let Some(height) = self.sprite_view_height {
+            return height;
+        }
+        let pc = self.chip8.program_counter as usize;
+        let memory = self.chip8.memory();
+        if pc + 1 < memory.len() && memory[pc] & 0xF0 == 0xD0 && memory[pc + 1] & 0x0F != 0 {
+            memory[pc + 1] & 0x0F
+        } else {
+            DEFAULT_SPRITE_HEIGHT
+        }
+    }
+
+    pub(super) fn change_sprite_view_height(&mut self, delta: i8) {
+        let height = self.sprite_view_height() as i8 + delta;
+        self.sprite_view_height = Some(height.max(1).min(MAX_SPRITE_HEIGHT as i8) as u8);
+    }
+
+    /// Moves the sprite view away from I, to browse memory
+    pub(super) fn move_sprite_view(&mut self, delta: i32) {
+        let max_address = self.chip8.memory().len() as i32 - 1;
+        let address = (self.sprite_view_address() as i32 + delta)
+            .max(0)
+            .min(max_address);
+        self.sprite_view_address = Some(address as u16);
+    }
+
+    /// Makes the sprite view follow I again, with the height of the next Dxyn
+    pub(super) fn reset_sprite_view(&mut self) {
+        self.sprite_view_address = None;
+        self.sprite_view_height = None;
+    }
+}
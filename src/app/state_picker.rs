@@ -0,0 +1,208 @@
+use super::App;
+use crate::autosave;
+use crate::builtin_roms;
+use crate::chip8::Chip8;
+use crate::state_file::StateFile;
+
+use ggez::event::KeyCode;
+use ggez::graphics::{self, Color, DrawMode, DrawParam, FilterMode, Image, MeshBuilder, Rect};
+use ggez::{Context, GameResult};
+use mint::Point2;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+const MARGIN: f32 = 15.0;
+const THUMBNAIL_HEIGHT: f32 = 64.0;
+/// The slots of F5 to F8
+pub(super) const SLOTS: u8 = 4;
+
+/// A save state of the running ROM, and whether it can be loaded
+struct Entry {
+    label: String,
+    path: PathBuf,
+    /// None if there's no such file
+    file: Option<Result<StateFile, String>>,
+    /// Whether it was saved from the running ROM
+    matches: bool,
+}
+
+/// The save states of the running ROM (its slots and its autosave) with thumbnails, from which
+/// one can be picked to load
+pub(super) struct StatePicker {
+    entries: Vec<Entry>,
+    selected: usize,
+}
+
+impl StatePicker {
+    pub fn open(rom_path: &Path, chip8: &Chip8) -> StatePicker {
+        let mut paths: Vec<(String, PathBuf)> = (1..=SLOTS)
+            .map(|slot| (format!("Slot {}", slot), state_path(rom_path, slot)))
+            .collect();
+        paths.push(("Autosave".to_owned(), autosave::path(rom_path)));
+        let entries = paths
+            .into_iter()
+            .map(|(label, path)| {
+                let file = match fs::read(&path) {
+                    Ok(bytes) => Some(StateFile::parse(&bytes)),
+                    Err(err) if err.kind() == ErrorKind::NotFound => None,
+                    Err(err) => Some(Err(format!("Couldn't read {}: {}", path.display(), err))),
+                };
+                let matches = matches!(&file, Some(Ok(file)) if file.matches(chip8));
+                Entry {
+                    label,
+                    path,
+                    file,
+                    matches,
+                }
+            })
+            .collect();
+        StatePicker {
+            entries,
+            selected: 0,
+        }
+    }
+
+    pub fn move_selection(&mut self, delta: i32) {
+        let last = self.entries.len() as i32 - 1;
+        self.selected = (self.selected as i32 + delta).max(0).min(last) as usize;
+    }
+}
+
+/// `ROM.stateN`, next to the ROM
+pub(super) fn state_path(rom_path: &Path, slot: u8) -> PathBuf {
+    PathBuf::from(format!("{}.state{}", rom_path.display(), slot))
+}
+
+impl App {
+    pub(super) fn toggle_state_picker(&mut self) {
+        if self.state_picker.take().is_none() {
+            if builtin_roms::is_builtin(Path::new(&self.window_title)) {
+                self.show_toast("Built-in ROMs have no save states".to_owned());
+                return;
+            }
+            self.stop_emulation_thread();
+            let picker = StatePicker::open(Path::new(&self.window_title), &self.chip8);
+            self.state_picker = Some(picker);
+        }
+    }
+
+    /// Handles a key press while the save state picker is open
+    pub(super) fn handle_state_picker_key(&mut self, keycode: KeyCode) {
+        let picker = match &mut self.state_picker {
+            Some(picker) => picker,
+            None => return,
+        };
+        match keycode {
+            KeyCode::Up => picker.move_selection(-1),
+            KeyCode::Down => picker.move_selection(1),
+            KeyCode::Return => {
+                let entry = &picker.entries[picker.selected];
+                let result = match &entry.file {
+                    Some(Ok(file)) => file.load_into(&mut self.chip8),
+                    Some(Err(err)) => Err(err.clone()),
+                    None => Err(format!("{} is empty", entry.label)),
+                };
+                let message = match result {
+                    Ok(()) => format!("Loaded {}", entry.path.display()),
+                    Err(err) => err,
+                };
+                self.state_picker = None;
+                self.show_toast(message);
+            }
+            KeyCode::F4 | KeyCode::Escape => self.state_picker = None,
+            _ => {}
+        }
+    }
+
+    pub(super) fn draw_state_picker(&self, ctx: &mut Context) -> GameResult<()> {
+        let picker = match &self.state_picker {
+            Some(picker) => picker,
+            None => return Ok(()),
+        };
+        let (width, height) = graphics::drawable_size(ctx);
+        let background = MeshBuilder::new()
+            .rectangle(
+                DrawMode::fill(),
+                Rect::new(0.0, 0.0, width, height),
+                self.palette.debug_background,
+            )
+            .build(ctx)?;
+        graphics::draw(ctx, &background, DrawParam::default())?;
+
+        self.draw_text(
+            ctx,
+            "Load a save state (Up/Down, Enter to load, F4 to cancel):",
+            MARGIN,
+            MARGIN,
+        )?;
+        let color = |color: Color| {
+            let (r, g, b) = color.to_rgb();
+            [r, g, b, 255]
+        };
+        for (i, entry) in picker.entries.iter().enumerate() {
+            let y = 2.0 * MARGIN + i as f32 * (THUMBNAIL_HEIGHT + MARGIN);
+            let thumbnail = match &entry.file {
+                Some(Ok(file)) => file.thumbnail.as_ref(),
+                _ => None,
+            };
+            let thumbnail_width = 2.0 * THUMBNAIL_HEIGHT;
+            match thumbnail {
+                Some(display) => {
+                    let rgba = display.to_rgba(
+                        color(self.palette.foreground),
+                        color(self.palette.background),
+                    );
+                    let mut image = Image::from_rgba8(
+                        ctx,
+                        display.width() as u16,
+                        display.height() as u16,
+                        &rgba,
+                    )?;
+                    image.set_filter(FilterMode::Nearest);
+                    let scaling = (THUMBNAIL_HEIGHT / display.height() as f32)
+                        .min(thumbnail_width / display.width() as f32);
+                    graphics::draw(
+                        ctx,
+                        &image,
+                        DrawParam::default()
+                            .dest(Point2 { x: MARGIN, y })
+                            .scale([scaling, scaling]),
+                    )?;
+                }
+                None => {
+                    let frame = MeshBuilder::new()
+                        .rectangle(
+                            DrawMode::stroke(1.0),
+                            Rect::new(MARGIN, y, thumbnail_width, THUMBNAIL_HEIGHT),
+                            self.palette.text,
+                        )
+                        .build(ctx)?;
+                    graphics::draw(ctx, &frame, DrawParam::default())?;
+                }
+            }
+
+            let status = match &entry.file {
+                None => "empty".to_owned(),
+                Some(Err(err)) => err.clone(),
+                Some(Ok(_)) if !entry.matches => "from another ROM".to_owned(),
+                Some(Ok(_)) => entry.path.display().to_string(),
+            };
+            let x = 2.0 * MARGIN + thumbnail_width;
+            let line = format!("{} ({})", entry.label, status);
+            if i == picker.selected {
+                let line = format!("> {}", line);
+                self.draw_text_with_color(ctx, &line, x, y, self.palette.highlight)?;
+            } else if entry.matches {
+                self.draw_text(ctx, &format!("  {}", line), x, y)?;
+            } else {
+                let dimmed = Color {
+                    a: 0.5,
+                    ..self.palette.text
+                };
+                self.draw_text_with_color(ctx, &format!("  {}", line), x, y, dimmed)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+use super::App;
+use crate::chip8::Timing;
+
+use ggez::graphics::{self, Color, DrawMode, DrawParam, MeshBuilder, Rect};
+use ggez::{Context, GameResult};
+use mint::Point2;
+use std::collections::VecDeque;
+
+const MARGIN: f32 = 10.0;
+const LINE_HEIGHT: f32 = 15.0;
+/// How many of the latest frames the graph shows
+const FRAME_TIME_HISTORY: usize = 120;
+const BAR_WIDTH: f32 = 2.0;
+const GRAPH_HEIGHT: f32 = 40.0;
+/// Frame times above this are cut off at the top of the graph (in seconds)
+const GRAPH_MAX_FRAME_TIME: f32 = 0.05;
+const TARGET_FRAME_TIME: f32 = 1.0 / 60.0;
+/// How often the instructions per second are measured (in seconds)
+const IPS_INTERVAL: f64 = 1.0;
+
+/// How fast the emulation actually runs, measured over the latest frames
+#[derive(Default)]
+pub(super) struct Stats {
+    /// The durations of the latest frames (in seconds), oldest first
+    frame_times: VecDeque<f32>,
+    /// The time and cycles since the instructions per second were last measured
+    elapsed_time: f64,
+    elapsed_cycles: u32,
+    instructions_per_second: Option<f64>,
+    /// States that the emulation thread sent, but that were replaced by a newer one before they
+    /// were shown
+    pub dropped_frames: u32,
+    /// Updates of the machine that were cut short after a stall (see `Chip8::update`)
+    pub clamped_updates: u32,
+}
+
+impl Stats {
+    pub fn update(&mut self, frame_time: f32, cycles: u32) {
+        if self.frame_times.len() == FRAME_TIME_HISTORY {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(frame_time);
+        self.elapsed_time += frame_time as f64;
+        self.elapsed_cycles += cycles;
+        if self.elapsed_time >= IPS_INTERVAL {
+            self.instructions_per_second = Some(self.elapsed_cycles as f64 / self.elapsed_time);
+            self.elapsed_time = 0.0;
+            self.elapsed_cycles = 0;
+        }
+    }
+}
+
+impl App {
+    /// The measured instructions per second against the configured speed, a graph of the latest
+    /// frame times and the cycles that didn't run at an even pace, in the bottom left corner of
+    /// the screen
+    pub(super) fn draw_stats_overlay(&self, ctx: &mut Context) -> GameResult<()> {
+        let x = self.layout.screen_x + MARGIN;
+        let bottom =
+            self.layout.screen_y + self.layout.c8_height as f32 * self.layout.scaling - MARGIN;
+        let graph_top = bottom - GRAPH_HEIGHT;
+        let text_top = graph_top - 3.0 * LINE_HEIGHT - 5.0;
+        let frame_times = &self.stats.frame_times;
+
+        let mut mesh = MeshBuilder::new();
+        let (r, g, b) = self.palette.debug_background.to_rgb();
+        mesh.rectangle(
+            DrawMode::fill(),
+            Rect::new(
+                x - 5.0,
+                text_top - 5.0,
+                FRAME_TIME_HISTORY as f32 * BAR_WIDTH + 10.0,
+                bottom - text_top + 10.0,
+            ),
+            Color::from_rgba(r, g, b, 200),
+        );
+        for (i, &frame_time) in frame_times.iter().enumerate() {
+            let height = (frame_time / GRAPH_MAX_FRAME_TIME).min(1.0) * GRAPH_HEIGHT;
+            // Frames that took half a frame longer than they should have stutter visibly
+            let color = if frame_time > 1.5 * TARGET_FRAME_TIME {
+                self.palette.secondary_highlight
+            } else {
+                self.palette.highlight
+            };
+            let bounds = Rect::new(
+                x + i as f32 * BAR_WIDTH,
+                bottom - height,
+                BAR_WIDTH,
+                height.max(1.0),
+            );
+            mesh.rectangle(DrawMode::fill(), bounds, color);
+        }
+        let target_y = bottom - TARGET_FRAME_TIME / GRAPH_MAX_FRAME_TIME * GRAPH_HEIGHT;
+        mesh.line(
+            &[
+                Point2 { x, y: target_y },
+                Point2 {
+                    x: x + FRAME_TIME_HISTORY as f32 * BAR_WIDTH,
+                    y: target_y,
+                },
+            ],
+            1.0,
+            self.palette.text,
+        )?;
+        let mesh = mesh.build(ctx)?;
+        graphics::draw(ctx, &mesh, DrawParam::default())?;
+
+        let configured = match self.chip8.timing() {
+            Timing::Clock => self.chip8.clock_frequency(),
+            Timing::Tickrate => (self.chip8.tickrate() * 60) as f64,
+        } * self.time_scale();
+        let measured = match self.stats.instructions_per_second {
+            Some(ips) => format!("{:.0}", ips),
+            None => "-".to_owned(),
+        };
+        let latest = frame_times.back().copied().unwrap_or(0.0);
+        let slowest = frame_times.iter().copied().fold(0.0, f32::max);
+        let lines = [
+            format!("IPS: {} / {:.0}", measured, configured),
+            format!(
+                "Frame time: {:.1} ms (max {:.1} ms)",
+                latest * 1000.0,
+                slowest * 1000.0
+            ),
+            format!(
+                "Fast-forwarded cycles: {}, dropped frames: {}, clamped updates: {}",
+                self.fast_forwarded_cycles, self.stats.dropped_frames, self.stats.clamped_updates
+            ),
+        ];
+        for (i, line) in lines.iter().enumerate() {
+            self.draw_text(ctx, line, x, text_top + i as f32 * LINE_HEIGHT)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+use super::{App, C8_WIDTH};
+
+use ggez::graphics::{self, DrawMode, DrawParam, MeshBuilder, Rect};
+use ggez::{Context, GameResult};
+use mint::Point2;
+use std::collections::VecDeque;
+
+/// How far back the timeline goes (in emulated seconds)
+const TIMELINE_SECONDS: f64 = 5.0;
+const MARGIN: f32 = 10.0;
+const LABEL_HEIGHT: f32 = 20.0;
+
+/// The timers and the keypad over the last few seconds, so that timing bugs can be seen as they
+/// play out rather than from the current values
+#[derive(Default)]
+pub(super) struct Timeline {
+    /// Emulated seconds since the timeline was started, which stands still while paused
+    time: f64,
+    /// The time, delay timer and sound timer after each update
+    timers: VecDeque<(f64, u8, u8)>,
+    /// The key, and when it was pressed and released (None while it's still held)
+    key_presses: VecDeque<(u8, f64, Option<f64>)>,
+}
+
+impl Timeline {
+    pub fn update(&mut self, dt: f64, delay_timer: u8, sound_timer: u8) {
+        self.time += dt;
+        self.timers.push_back((self.time, delay_timer, sound_timer));
+        let start = self.time - TIMELINE_SECONDS;
+        // One sample before the start is kept, so that the plot reaches the left edge
+        while self.timers.len() > 1 && self.timers[1].0 <= start {
+            self.timers.pop_front();
+        }
+        self.key_presses
+            .retain(|&(_, _, released)| released.is_none_or(|released| released > start));
+    }
+
+    pub fn handle_key_event(&mut self, key: u8, pressed: bool) {
+        let held = self
+            .key_presses
+            .iter_mut()
+            .find(|(held_key, _, released)| *held_key == key && released.is_none());
+        match (held, pressed) {
+            (None, true) => self.key_presses.push_back((key, self.time, None)),
+            (Some((_, _, released)), false) => *released = Some(self.time),
+            _ => {}
+        }
+    }
+}
+
+impl App {
+    /// Plots the delay timer, the sound timer and the held keys over the last few seconds, in
+    /// place of the screen. The newest values are at the right edge.
+    pub(super) fn draw_timeline_view(&self, ctx: &mut Context) -> GameResult<()> {
+        let left = self.layout.screen_x;
+        let width = C8_WIDTH as f32 * self.layout.scaling;
+        let height = self.layout.c8_height as f32 * self.layout.scaling;
+        let lane_height = (height - MARGIN) / 3.0 - LABEL_HEIGHT;
+        let timeline = &self.timeline;
+        let start = timeline.time - TIMELINE_SECONDS;
+        let x_at = |time: f64| left + ((time - start) / TIMELINE_SECONDS).max(0.0) as f32 * width;
+
+        let mut mesh = MeshBuilder::new();
+        mesh.rectangle(
+            DrawMode::fill(),
+            Rect::new(left, self.layout.screen_y, width, height),
+            self.palette.background,
+        );
+        let lane_tops: Vec<f32> = (0..3)
+            .map(|lane| {
+                self.layout.screen_y
+                    + MARGIN
+                    + LABEL_HEIGHT
+                    + lane as f32 * (lane_height + LABEL_HEIGHT)
+            })
+            .collect();
+        let timer_lanes = [
+            (lane_tops[0], self.palette.highlight),
+            (lane_tops[1], self.palette.secondary_highlight),
+        ];
+        for (lane, &(top, color)) in timer_lanes.iter().enumerate() {
+            let points: Vec<Point2<f32>> = timeline
+                .timers
+                .iter()
+                .map(|&(time, delay_timer, sound_timer)| {
+                    let value = if lane == 0 { delay_timer } else { sound_timer };
+                    Point2 {
+                        x: x_at(time),
+                        y: top + lane_height * (1.0 - value as f32 / 255.0),
+                    }
+                })
+                .collect();
+            if points.len() >= 2 {
+                mesh.line(&points, 1.0, color)?;
+            }
+        }
+        let key_height = lane_height / 16.0;
+        for &(key, pressed, released) in &timeline.key_presses {
+            let x = x_at(pressed);
+            let bounds = Rect::new(
+                x,
+                lane_tops[2] + key as f32 * key_height,
+                (x_at(released.unwrap_or(timeline.time)) - x).max(1.0),
+                key_height.max(1.0),
+            );
+            mesh.rectangle(DrawMode::fill(), bounds, self.palette.foreground);
+        }
+        for &top in &lane_tops {
+            let bottom = top + lane_height;
+            mesh.line(
+                &[
+                    Point2 { x: left, y: bottom },
+                    Point2 {
+                        x: left + width,
+                        y: bottom,
+                    },
+                ],
+                1.0,
+                self.palette.text,
+            )?;
+        }
+        let mesh = mesh.build(ctx)?;
+        graphics::draw(ctx, &mesh, DrawParam::default())?;
+
+        let labels = [
+            format!("Delay timer: {:02X}", self.chip8.delay_timer),
+            format!("Sound timer: {:02X}", self.chip8.sound_timer),
+            format!("Keys 0-F (last {} seconds)", TIMELINE_SECONDS),
+        ];
+        for (label, &top) in labels.iter().zip(&lane_tops) {
+            self.draw_text(ctx, label, left + MARGIN, top - LABEL_HEIGHT + 3.0)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,150 @@
+use super::memory_view::{BYTES_PER_ROW, MEMORY_VIEW_ROWS, MEMORY_VIEW_X};
+use super::App;
+
+use ggez::event::KeyCode;
+use ggez::Context;
+use tracing::info;
+
+const LINE_HEIGHT: f32 = 15.0;
+const MARGIN: f32 = 10.0;
+const INFO_X: f32 = 80.0;
+
+/// Something in the machine that can be edited from the debugger
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) enum EditTarget {
+    Register(u8),
+    AddressRegister,
+    ProgramCounter,
+    Memory(u16),
+}
+
+impl EditTarget {
+    fn max_digits(self) -> usize {
+        match self {
+            EditTarget::Register(_) | EditTarget::Memory(_) => 2,
+            EditTarget::AddressRegister => 4,
+            EditTarget::ProgramCounter => 3,
+        }
+    }
+}
+
+/// A hex value that is being typed in, to be written to the machine when Enter is pressed
+pub(super) struct ValueEditor {
+    pub target: EditTarget,
+    input: String,
+}
+
+impl ValueEditor {
+    /// The input so far, as it should be shown in place of the edited value
+    pub fn text(&self) -> String {
+        format!("{:_<width$}", self.input, width = self.target.max_digits())
+    }
+}
+
+impl App {
+    /// Starts editing the register or memory byte at the given window position, if any
+    pub(super) fn begin_edit_at(&mut self, ctx: &mut Context, x: f32, y: f32) -> bool {
+        let target = match self.edit_target_at(ctx, x, y) {
+            Some(target) => target,
+            None => return false,
+        };
+        if !self.paused {
+            self.show_toast("Pause (Enter) to edit values".to_owned());
+            return true;
+        }
+        self.value_editor = Some(ValueEditor {
+            target,
+            input: String::new(),
+        });
+        true
+    }
+
+    fn edit_target_at(&self, ctx: &mut Context, x: f32, y: f32) -> Option<EditTarget> {
+        let row = (y - self.layout.debug_y - MARGIN) / LINE_HEIGHT;
+        if row < 0.0 {
+            return None;
+        }
+        let row = row as u16;
+        if (MARGIN..INFO_X).contains(&x) {
+            return if row < 16 {
+                Some(EditTarget::Register(row as u8))
+            } else {
+                None
+            };
+        }
+        if (INFO_X..INFO_X + 70.0).contains(&x) {
+            return match row {
+                0 => Some(EditTarget::AddressRegister),
+                1 => Some(EditTarget::ProgramCounter),
+                _ => None,
+            };
+        }
+        if x >= MEMORY_VIEW_X && row < MEMORY_VIEW_ROWS {
+            let row_width = self.text_width(ctx, "000:");
+            let byte_width = self.text_width(ctx, " 00");
+            let column = (x - MEMORY_VIEW_X - row_width) / byte_width;
+            if column >= 0.0 && column < BYTES_PER_ROW as f32 {
+                let address = self.memory_view_address + row * BYTES_PER_ROW + column as u16;
+                if (address as usize) < self.chip8.memory().len() {
+                    return Some(EditTarget::Memory(address));
+                }
+            }
+        }
+        None
+    }
+
+    /// Handles a key press while a value is being edited
+    pub(super) fn handle_value_editor_key(&mut self, keycode: KeyCode) {
+        match keycode {
+            KeyCode::Return => self.commit_edit(),
+            KeyCode::Escape => self.value_editor = None,
+            KeyCode::Back => {
+                if let Some(editor) = &mut self.value_editor {
+                    editor.input.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub(super) fn handle_value_editor_input(&mut self, character: char) {
+        if let Some(editor) = &mut self.value_editor {
+            if character.is_ascii_hexdigit() && editor.input.len() < editor.target.max_digits() {
+                editor.input.push(character.to_ascii_uppercase());
+            }
+        }
+    }
+
+    fn commit_edit(&mut self) {
+        let editor = match self.value_editor.take() {
+            Some(editor) => editor,
+            None => return,
+        };
+        let value = match u16::from_str_radix(&editor.input, 16) {
+            Ok(value) => value,
+            Err(_) => return,
+        };
+        self.stop_emulation_thread();
+        match editor.target {
+            EditTarget::Register(x) => self.chip8.registers[x as usize] = value as u8,
+            EditTarget::AddressRegister => self.chip8.address_register = value,
+            EditTarget::ProgramCounter => self.chip8.program_counter = value,
+            EditTarget::Memory(address) => {
+                if let Err(err) = self.chip8.write_memory(address, &[value as u8]) {
+                    self.show_toast(err);
+                    return;
+                }
+                self.refresh_disassembly(address);
+            }
+        }
+        info!("Set {:?} to {:X}", editor.target, value);
+    }
+
+    /// The text to show instead of the given value, if it's being edited
+    pub(super) fn edited_text(&self, target: EditTarget) -> Option<String> {
+        match &self.value_editor {
+            Some(editor) if editor.target == target => Some(editor.text()),
+            _ => None,
+        }
+    }
+}
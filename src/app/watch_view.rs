@@ -0,0 +1,112 @@
+use super::App;
+use crate::cheats::{Cheat, Cheats};
+use crate::watch::WatchExpression;
+
+use ggez::graphics::TextFragment;
+use ggez::{Context, GameResult};
+use std::path::Path;
+use tracing::{info, warn};
+
+const LINE_HEIGHT: f32 = 15.0;
+const MARGIN: f32 = 15.0;
+/// Below the call stack, which is at most 16 frames deep
+const WATCH_VIEW_Y: f32 = MARGIN + 20.0 * LINE_HEIGHT;
+
+impl App {
+    /// The watch expressions and their current values, followed by the frozen cheats and the memory
+    /// search
+    pub(super) fn draw_watch_view(&self, ctx: &mut Context) -> GameResult<()> {
+        let x = self.layout.call_stack_x + MARGIN;
+        let mut y = WATCH_VIEW_Y;
+        self.draw_text(ctx, "Watches:", x, y)?;
+        for watch in &self.watches {
+            y += LINE_HEIGHT;
+            let fragments = vec![
+                TextFragment::new(format!("{}: ", watch.source())).color(self.palette.text),
+                TextFragment::new(watch.evaluate(&self.chip8)).color(self.palette.highlight),
+            ];
+            self.draw_fragments(ctx, fragments, x, y)?;
+        }
+        if !self.cheats.frozen().is_empty() {
+            y += 2.0 * LINE_HEIGHT;
+            self.draw_text(ctx, "Cheats:", x, y)?;
+            for cheat in self.cheats.frozen() {
+                y += LINE_HEIGHT;
+                self.draw_text(ctx, &cheat.to_string(), x, y)?;
+            }
+        }
+        if self.memory_search.is_some() {
+            self.draw_search_view(ctx, x, y + 2.0 * LINE_HEIGHT)?;
+        }
+        Ok(())
+    }
+
+    pub(super) fn add_watch(&mut self, source: &str) -> String {
+        match WatchExpression::parse(source) {
+            Ok(watch) => {
+                let message = format!("Watching {}", watch.source());
+                self.watches.push(watch);
+                message
+            }
+            Err(err) => err,
+        }
+    }
+
+    pub(super) fn remove_watch(&mut self, source: &str) -> String {
+        let source = source.trim();
+        let count = self.watches.len();
+        self.watches
+            .retain(|watch| !watch.source().eq_ignore_ascii_case(source));
+        if self.watches.len() == count {
+            format!("Not watching {}", source)
+        } else {
+            format!("Stopped watching {}", source)
+        }
+    }
+
+    /// Replaces the frozen cheats with the ones in the ROM's cheats file, if it has one
+    pub(super) fn load_cheats(&mut self, rom_path: &Path) {
+        self.cheats.clear();
+        match Cheats::load(rom_path) {
+            Ok(cheats) if cheats.is_empty() => {}
+            Ok(cheats) => {
+                info!(
+                    "Loaded {} cheats from {}",
+                    cheats.len(),
+                    Cheats::path(rom_path).display()
+                );
+                for cheat in cheats {
+                    self.cheats.add(cheat, &mut self.chip8);
+                }
+            }
+            Err(err) => warn!("{}", err),
+        }
+    }
+
+    /// Applies a cheat like `freeze V3 = 5` or `poke mem[0x300] = 0x10`
+    pub(super) fn add_cheat(&mut self, source: &str) -> String {
+        match Cheat::parse(source) {
+            Ok(cheat) => {
+                // The thread is restarted with the new cheats on the next update
+                self.stop_emulation_thread();
+                let message = if cheat.is_frozen() {
+                    format!("Froze {}", cheat.target())
+                } else {
+                    format!("Poked {}", cheat.target())
+                };
+                self.cheats.add(cheat, &mut self.chip8);
+                message
+            }
+            Err(err) => err,
+        }
+    }
+
+    pub(super) fn unfreeze(&mut self, target: &str) -> String {
+        self.stop_emulation_thread();
+        match self.cheats.unfreeze(target) {
+            Ok(true) => format!("Unfroze {}", target.trim()),
+            Ok(false) => format!("Not frozen: {}", target.trim()),
+            Err(err) => err,
+        }
+    }
+}
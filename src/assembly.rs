@@ -1,21 +1,27 @@
 pub fn disassemble_rom(buffer: Vec<u8>) -> Vec<String> {
-    let mut disassembled = vec![String::new(); 0x1000];
+    disassemble_rom_at(buffer, 0x200)
+}
+
+/// Like `disassemble_rom`, for a ROM that is loaded at the given address
+pub fn disassemble_rom_at(buffer: Vec<u8>, start_address: u16) -> Vec<String> {
+    let mut disassembled = vec![String::new(); (start_address as usize + buffer.len()).max(0x1000)];
 
+    let start = start_address as usize;
     let mut visited = Vec::new();
     let mut return_addresses = Vec::new();
-    let mut pc = 0x200;
+    let mut pc = start;
     loop {
-        if pc < 0x200 || pc - 0x200 + 1 >= buffer.len() {
+        if pc < start || pc - start + 1 >= buffer.len() {
             break;
         }
-        let offset = pc - 0x200;
+        let offset = pc - start;
         let opcode = ((buffer[offset] as u16) << 8) | buffer[offset + 1] as u16;
         let text = match disassemble_opcode(opcode) {
-            Ok(s) => format!("{}", &s),
+            Ok(s) => s,
             Err(_err) => format!("DATA[{:#06X}]", opcode),
         };
 
-        disassembled[0x200 + offset] = text;
+        disassembled[start + offset] = text;
 
         if opcode & 0xF000 == 0x1000 {
             // We follow the jump instruction (it may point to an unaligned address)
@@ -38,7 +44,7 @@ pub fn disassemble_rom(buffer: Vec<u8>) -> Vec<String> {
             }
         } else if opcode == 0x00EE {
             // We follow the return instruction
-            if return_addresses.len() > 0 {
+            if !return_addresses.is_empty() {
                 pc = return_addresses.pop().expect("Popping return address");
             } else {
                 pc += 2;
@@ -50,6 +56,86 @@ pub fn disassemble_rom(buffer: Vec<u8>) -> Vec<String> {
     disassembled
 }
 
+/// Like `disassemble_rom_at`, but every other byte from the start is taken as an instruction,
+/// instead of only the ones that can be reached by following the program. Data shows up as
+/// instructions (or `DATA[...]`), but nothing is missed.
+pub fn disassemble_linear_at(buffer: Vec<u8>, start_address: u16) -> Vec<String> {
+    let mut disassembled = vec![String::new(); (start_address as usize + buffer.len()).max(0x1000)];
+    let start = start_address as usize;
+    for (i, pair) in buffer.chunks_exact(2).enumerate() {
+        let address = start + 2 * i;
+        if address >= disassembled.len() {
+            break;
+        }
+        let opcode = u16::from_be_bytes([pair[0], pair[1]]);
+        disassembled[address] =
+            disassemble_opcode(opcode).unwrap_or_else(|_| format!("DATA[{:#06X}]", opcode));
+    }
+    disassembled
+}
+
+/// The most bytes of data on a line of a listing
+const DATA_BYTES_PER_LINE: usize = 8;
+
+/// A line of a listing: an instruction, or a run of bytes that no instruction covers
+#[derive(Clone, Debug, PartialEq)]
+pub struct ListingLine {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub text: String,
+}
+
+impl ListingLine {
+    pub fn is_data(&self) -> bool {
+        self.text.starts_with("data:")
+    }
+}
+
+/// Every byte of the ROM in the order of the addresses, given its disassembly (see
+/// `disassemble_rom_at`): the instructions, and the bytes between them as lines of data (like
+/// `data: FF 00`), so that nothing in the ROM is left out
+pub fn listing(buffer: &[u8], start_address: u16, disassembled: &[String]) -> Vec<ListingLine> {
+    let start = start_address as usize;
+    let end = (start + buffer.len()).min(disassembled.len());
+    let mut lines = Vec::new();
+    let mut data: Vec<u8> = Vec::new();
+    let flush_data = |data: &mut Vec<u8>, lines: &mut Vec<ListingLine>, address: usize| {
+        if !data.is_empty() {
+            let text = data
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect::<Vec<String>>()
+                .join(" ");
+            lines.push(ListingLine {
+                address: (address - data.len()) as u16,
+                bytes: std::mem::take(data),
+                text: format!("data: {}", text),
+            });
+        }
+    };
+    let mut address = start;
+    while address < end {
+        let text = &disassembled[address];
+        if !text.is_empty() && address + 1 < end {
+            flush_data(&mut data, &mut lines, address);
+            lines.push(ListingLine {
+                address: address as u16,
+                bytes: buffer[address - start..address - start + 2].to_vec(),
+                text: text.clone(),
+            });
+            address += 2;
+        } else {
+            data.push(buffer[address - start]);
+            address += 1;
+            if data.len() == DATA_BYTES_PER_LINE {
+                flush_data(&mut data, &mut lines, address);
+            }
+        }
+    }
+    flush_data(&mut data, &mut lines, address);
+    lines
+}
+
 pub fn disassemble_opcode(opcode: u16) -> Result<String, String> {
     let s = match opcode & 0xF000 {
         0x0000 => match opcode {
@@ -199,6 +285,10 @@ pub fn disassemble_opcode(opcode: u16) -> Result<String, String> {
                 let a = ((opcode & 0x0F00) >> 8) as usize;
                 format!("I = sprite_addr(V{:X})", a)
             }
+            0x30 => {
+                let a = ((opcode & 0x0F00) >> 8) as usize;
+                format!("I = large_sprite_addr(V{:X})", a)
+            }
             0x33 => {
                 let a = ((opcode & 0x0F00) >> 8) as usize;
                 format!("BCD(V{:X})", a)
@@ -211,6 +301,14 @@ pub fn disassemble_opcode(opcode: u16) -> Result<String, String> {
                 let end_index = ((opcode & 0x0F00) >> 8) as usize;
                 format!("load(V{:X})", end_index)
             }
+            0x75 => {
+                let end_index = ((opcode & 0x0F00) >> 8) as usize;
+                format!("flags_dump(V{:X})", end_index)
+            }
+            0x85 => {
+                let end_index = ((opcode & 0x0F00) >> 8) as usize;
+                format!("flags_load(V{:X})", end_index)
+            }
             _ => return Err(format!("Unhandled op-code: {:#06X}", opcode)),
         },
         _ => return Err(format!("Unhandled op-code: {:#06X}", opcode)),
@@ -218,6 +316,168 @@ pub fn disassemble_opcode(opcode: u16) -> Result<String, String> {
     Ok(s)
 }
 
+const INSTRUCTION_PATTERNS: [(&str, u16); 39] = [
+    ("return", 0x00EE),
+    ("clear screen", 0x00E0),
+    ("call (machine): {nnn}", 0x0000),
+    ("jump: {nnn}", 0x1000),
+    ("call: {nnn}", 0x2000),
+    ("skip if {x} == {nn}", 0x3000),
+    ("skip if {x} != {nn}", 0x4000),
+    ("skip if {x} == {y}", 0x5000),
+    ("{x} = {nn}", 0x6000),
+    ("{x} += {nn}", 0x7000),
+    ("{x} = {y}", 0x8000),
+    ("{x} = {x} | {y}", 0x8001),
+    ("{x} = {x} & {y}", 0x8002),
+    ("{x} = {x} ^ {y}", 0x8003),
+    ("{x} = {x} + {y}", 0x8004),
+    ("{x} = {x} - {y}", 0x8005),
+    ("{x} >>= 1", 0x8006),
+    ("{x} = {y} - {x}", 0x8007),
+    ("{x} <<= 1", 0x800E),
+    ("skip if {x} != {y}", 0x9000),
+    ("I = {nnn}", 0xA000),
+    ("jump to V0 + {nnn}", 0xB000),
+    ("{x} = rand() & {nn}", 0xC000),
+    ("render({x}, {y}, {n})", 0xD000),
+    ("skip if {x} pressed", 0xE09E),
+    ("skip if {x} not pressed", 0xE0A1),
+    ("{x} = get_delay()", 0xF007),
+    ("{x} = get_key()", 0xF00A),
+    ("I = delay_timer({x})", 0xF015),
+    ("I = sound_timer({x})", 0xF018),
+    ("I += {x}", 0xF01E),
+    ("I = sprite_addr({x})", 0xF029),
+    ("I = large_sprite_addr({x})", 0xF030),
+    ("BCD({x})", 0xF033),
+    ("dump({x})", 0xF055),
+    ("load({x})", 0xF065),
+    ("flags_dump({x})", 0xF075),
+    ("flags_load({x})", 0xF085),
+    ("DATA[{nnnn}]", 0x0000),
+];
+
+/// Assembles a single instruction, written in the syntax produced by
+/// `disassemble_opcode`. Whitespace and letter case are ignored.
+pub fn assemble_opcode(instruction: &str) -> Result<u16, String> {
+    let input = normalize(instruction);
+    for (pattern, base) in INSTRUCTION_PATTERNS.iter() {
+        if let Some(opcode) = match_pattern(&normalize(pattern), &input) {
+            return Ok(base | opcode);
+        }
+    }
+    Err(format!("Unrecognized instruction: {}", instruction))
+}
+
+/// Assembles a line of the form "ADDR: instruction" (as in the disassembler's output) or
+/// just "instruction". Returns the address (if one was given) and the opcode.
+pub fn assemble_line(line: &str) -> Result<(Option<u16>, u16), String> {
+    if let Some(colon) = line.find(':') {
+        let prefix = line[..colon].trim();
+        let prefix = prefix.trim_start_matches("0x");
+        if !prefix.is_empty() && prefix.chars().all(|c| c.is_ascii_hexdigit()) {
+            let address = u16::from_str_radix(prefix, 16)
+                .map_err(|_| format!("Invalid address: {}", prefix))?;
+            let opcode = assemble_opcode(&line[colon + 1..])?;
+            return Ok((Some(address), opcode));
+        }
+    }
+    Ok((None, assemble_opcode(line)?))
+}
+
+fn normalize(s: &str) -> String {
+    s.chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_lowercase()
+}
+
+fn match_pattern(pattern: &str, input: &str) -> Option<u16> {
+    let mut x = None;
+    let mut y = None;
+    let mut constant = 0;
+    let mut pattern = pattern;
+    let mut input = input;
+    while !pattern.is_empty() {
+        if pattern.starts_with('{') {
+            let end = pattern.find('}')?;
+            let placeholder = &pattern[1..end];
+            pattern = &pattern[end + 1..];
+            match placeholder {
+                "x" | "y" => {
+                    let (register, rest) = parse_register(input)?;
+                    input = rest;
+                    let field = if placeholder == "x" { &mut x } else { &mut y };
+                    match field {
+                        Some(previous) if *previous != register => return None,
+                        _ => *field = Some(register),
+                    }
+                }
+                _ => {
+                    let max = match placeholder {
+                        "n" => 0xF,
+                        "nn" => 0xFF,
+                        "nnn" => 0xFFF,
+                        _ => 0xFFFF,
+                    };
+                    let (number, rest) = parse_number(input)?;
+                    if number > max {
+                        return None;
+                    }
+                    input = rest;
+                    constant = number as u16;
+                }
+            }
+        } else {
+            let c = pattern.chars().next()?;
+            if !input.starts_with(c) {
+                return None;
+            }
+            pattern = &pattern[c.len_utf8()..];
+            input = &input[c.len_utf8()..];
+        }
+    }
+    if !input.is_empty() {
+        return None;
+    }
+    Some(((x.unwrap_or(0) as u16) << 8) | ((y.unwrap_or(0) as u16) << 4) | constant)
+}
+
+/// Parses "vA", "v10" (the disassembler's decimal form) or "v0x0a"
+fn parse_register(input: &str) -> Option<(u8, &str)> {
+    let input = input.strip_prefix('v')?;
+    let (register, rest) = if input.starts_with("0x") {
+        parse_number(input)?
+    } else if input.starts_with(|c: char| c.is_ascii_digit()) {
+        let end = input
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(input.len());
+        (input[..end].parse().ok()?, &input[end..])
+    } else {
+        let digit = input.chars().next()?.to_digit(16)?;
+        (digit, &input[1..])
+    };
+    if register > 0xF {
+        return None;
+    }
+    Some((register as u8, rest))
+}
+
+fn parse_number(input: &str) -> Option<(u32, &str)> {
+    if let Some(hex) = input.strip_prefix("0x") {
+        let end = hex
+            .find(|c: char| !c.is_ascii_hexdigit())
+            .unwrap_or(hex.len());
+        Some((u32::from_str_radix(&hex[..end], 16).ok()?, &hex[end..]))
+    } else {
+        let end = input
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(input.len());
+        Some((input[..end].parse().ok()?, &input[end..]))
+    }
+}
+
 #[test]
 fn test_disassemble_opcode() {
     assert_eq!(disassemble_opcode(0xF70A).unwrap(), "V7 = get_key()");
@@ -236,6 +496,61 @@ fn test_disassemble_rom() {
     assert_eq!(result[0x202], "V3 = V6 - V3".to_owned());
 }
 
+#[test]
+fn test_disassemble_rom_at() {
+    // Jump to 0x604, which is the second instruction after the jump
+    let rom = vec![0x16, 0x04, 0xFF, 0xFF, 0x83, 0x67];
+
+    let result = disassemble_rom_at(rom, 0x600);
+
+    assert_eq!(result[0x600], "jump: 0x604".to_owned());
+    assert_eq!(result[0x602], "".to_owned());
+    assert_eq!(result[0x604], "V3 = V6 - V3".to_owned());
+}
+
+#[test]
+fn test_disassemble_linear_at() {
+    // Jump over a word of data
+    let rom = vec![0x12, 0x04, 0xFF, 0xFF, 0x83, 0x67, 0x00];
+
+    let result = disassemble_linear_at(rom, 0x200);
+
+    assert_eq!(result[0x200], "jump: 0x204".to_owned());
+    assert_eq!(result[0x202], "DATA[0xFFFF]".to_owned());
+    assert_eq!(result[0x204], "V3 = V6 - V3".to_owned());
+    assert_eq!(result[0x206], "".to_owned());
+}
+
+#[test]
+fn test_listing() {
+    // Jump over 10 bytes of data, then an odd byte at the end
+    let mut rom = vec![0x12, 0x0C];
+    rom.extend_from_slice(&[0xAA; 10]);
+    rom.extend_from_slice(&[0x83, 0x67, 0x01]);
+
+    let lines = listing(&rom, 0x200, &disassemble_rom(rom.clone()));
+
+    let summary: Vec<(u16, &str)> = lines
+        .iter()
+        .map(|line| (line.address, line.text.as_str()))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            (0x200, "jump: 0x20C"),
+            (0x202, "data: AA AA AA AA AA AA AA AA"),
+            (0x20A, "data: AA AA"),
+            (0x20C, "V3 = V6 - V3"),
+            (0x20E, "data: 01"),
+        ]
+    );
+    assert_eq!(lines[3].bytes, [0x83, 0x67]);
+    assert!(lines[4].is_data());
+    // Every byte is on exactly one line
+    let bytes: Vec<u8> = lines.iter().flat_map(|line| line.bytes.clone()).collect();
+    assert_eq!(bytes, rom);
+}
+
 #[test]
 fn test_disassemble_rom_jump_to_unaligned() {
     let rom = vec![
@@ -269,3 +584,37 @@ fn test_disassemble_rom_call_to_unaligned() {
     assert_eq!(result[0x202], "call: 0x205".to_owned());
     assert_eq!(result[0x205], "V7 = get_key()".to_owned());
 }
+
+#[test]
+fn test_assemble_opcode() {
+    assert_eq!(assemble_opcode("V7 = get_key()").unwrap(), 0xF70A);
+    assert_eq!(assemble_opcode("v3=v6-v3").unwrap(), 0x8367);
+    assert_eq!(assemble_opcode("render(V10, V2, 5)").unwrap(), 0xDA25);
+    assert_eq!(assemble_opcode("V0x03 = rand() & 0xF2").unwrap(), 0xC3F2);
+    assert_eq!(assemble_opcode("I = 200").unwrap(), 0xA0C8);
+    assert!(assemble_opcode("V3 = 0x100").is_err());
+    assert!(assemble_opcode("V3 == 0x10").is_err());
+}
+
+#[test]
+fn test_assemble_line() {
+    assert_eq!(
+        assemble_line("2A0: V3 = 0x10").unwrap(),
+        (Some(0x2A0), 0x6310)
+    );
+    assert_eq!(
+        assemble_line("call (machine): 0x234").unwrap(),
+        (None, 0x0234)
+    );
+    assert!(assemble_line("10000: return").is_err());
+}
+
+#[test]
+fn test_assemble_disassembled_opcodes() {
+    for opcode in 0..=0xFFFF {
+        if let Ok(text) = disassemble_opcode(opcode) {
+            let assembled = assemble_opcode(&text).unwrap();
+            assert_eq!(disassemble_opcode(assembled).unwrap(), text);
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! Autosaves, for continuing a long game where it was left: the state of the machine is kept in a
+//! file next to the ROM, `ROM.autosave`, which is written when the emulator closes or another ROM
+//! is loaded.
+
+use crate::chip8::Chip8;
+use crate::state_file::StateFile;
+
+use std::ffi::OsString;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// `ROM.autosave`, next to the ROM
+pub fn path(rom_path: &Path) -> PathBuf {
+    let mut name = OsString::from(rom_path.as_os_str());
+    name.push(".autosave");
+    PathBuf::from(name)
+}
+
+pub fn exists(rom_path: &Path) -> bool {
+    path(rom_path).is_file()
+}
+
+pub fn save(rom_path: &Path, chip8: &Chip8) -> Result<(), String> {
+    let path = path(rom_path);
+    fs::write(&path, StateFile::new(chip8).to_bytes(true))
+        .map_err(|err| format!("Couldn't write {}: {}", path.display(), err))
+}
+
+/// Loads the ROM's autosave into the machine, and returns false if there isn't one. It's an
+/// error if the ROM has changed since.
+pub fn resume(rom_path: &Path, chip8: &mut Chip8) -> Result<bool, String> {
+    let path = path(rom_path);
+    let state = match fs::read(&path) {
+        Ok(state) => state,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(format!("Couldn't read {}: {}", path.display(), err)),
+    };
+    StateFile::parse(&state)
+        .and_then(|file| file.load_into(chip8))
+        .map_err(|err| format!("{}: {}", path.display(), err))?;
+    Ok(true)
+}
+
+#[test]
+fn test_autosave() {
+    let dir = std::env::temp_dir().join(format!("chip8_autosave_test_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let rom_path = dir.join("game.ch8");
+
+    let mut chip8 = Chip8::from_rom(&[0x60, 0x2A, 0x12, 0x02]).unwrap();
+    assert!(!exists(&rom_path));
+    assert_eq!(resume(&rom_path, &mut chip8), Ok(false));
+    chip8.step_instruction().unwrap();
+    save(&rom_path, &chip8).unwrap();
+    assert_eq!(path(&rom_path), dir.join("game.ch8.autosave"));
+    assert!(exists(&rom_path));
+
+    let mut resumed = Chip8::from_rom(&[0x60, 0x2A, 0x12, 0x02]).unwrap();
+    assert_eq!(resume(&rom_path, &mut resumed), Ok(true));
+    assert_eq!(resumed.registers[0], 0x2A);
+    assert_eq!(resumed.program_counter, 0x202);
+    fs::remove_dir_all(&dir).unwrap();
+}
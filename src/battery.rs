@@ -0,0 +1,124 @@
+//! Battery-backed memory, for homebrew programs that want to keep high scores: a range of memory
+//! (by default the top 256 bytes, 0xF00 to 0xFFF) is kept in a file next to the ROM, `ROM.sav`.
+//!
+//! A program only needs to keep what it wants saved in the range. The file is loaded into memory
+//! before the program starts, and the range is written back when the emulator closes or another
+//! ROM is loaded.
+
+use crate::chip8::Chip8;
+
+use std::ffi::OsString;
+use std::fs;
+use std::io::ErrorKind;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+/// The top 256 bytes of memory
+pub const DEFAULT_BATTERY_RANGE: RangeInclusive<u16> = 0xF00..=0xFFF;
+
+/// Parses a range of addresses in hex, like `F00-FFF` or `0xE00-0xFFF` (both ends included)
+pub fn parse_range(source: &str) -> Result<RangeInclusive<u16>, String> {
+    let invalid = || format!("Expected a range of addresses like F00-FFF: {}", source);
+    let mut ends = source.trim().splitn(2, '-').map(|end| {
+        let end = end.trim();
+        u16::from_str_radix(end.strip_prefix("0x").unwrap_or(end), 16).ok()
+    });
+    match (ends.next().flatten(), ends.next().flatten()) {
+        (Some(start), Some(end)) if start <= end && end < 0x1000 => Ok(start..=end),
+        _ => Err(invalid()),
+    }
+}
+
+/// The save file of a ROM, and the memory that was last loaded from or saved to it
+pub struct BatteryFile {
+    path: PathBuf,
+    range: RangeInclusive<u16>,
+    saved: Vec<u8>,
+}
+
+impl BatteryFile {
+    /// `ROM.sav`, next to the ROM
+    pub fn path(rom_path: &Path) -> PathBuf {
+        let mut name = OsString::from(rom_path.as_os_str());
+        name.push(".sav");
+        PathBuf::from(name)
+    }
+
+    /// Loads the ROM's save file (if it has one yet) into the range of memory. A file that is
+    /// shorter than the range fills the start of it.
+    pub fn open(
+        rom_path: &Path,
+        range: RangeInclusive<u16>,
+        chip8: &mut Chip8,
+    ) -> Result<BatteryFile, String> {
+        let path = BatteryFile::path(rom_path);
+        let size = range.len();
+        match fs::read(&path) {
+            Ok(bytes) if bytes.len() <= size => chip8.write_memory(*range.start(), &bytes)?,
+            Ok(bytes) => {
+                return Err(format!(
+                    "{} holds {} bytes, but the battery-backed memory is only {} bytes",
+                    path.display(),
+                    bytes.len(),
+                    size
+                ))
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(format!("Couldn't read {}: {}", path.display(), err)),
+        }
+        let saved = memory(chip8, &range).to_vec();
+        Ok(BatteryFile { path, range, saved })
+    }
+
+    pub fn file_path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes the range of memory to the file, if it has changed since it was loaded or last
+    /// saved. Returns whether it was written.
+    pub fn save(&mut self, chip8: &Chip8) -> Result<bool, String> {
+        let memory = memory(chip8, &self.range);
+        if memory == self.saved.as_slice() {
+            return Ok(false);
+        }
+        fs::write(&self.path, memory)
+            .map_err(|err| format!("Couldn't write {}: {}", self.path.display(), err))?;
+        self.saved = memory.to_vec();
+        Ok(true)
+    }
+}
+
+fn memory<'a>(chip8: &'a Chip8, range: &RangeInclusive<u16>) -> &'a [u8] {
+    &chip8.memory()[*range.start() as usize..=*range.end() as usize]
+}
+
+#[test]
+fn test_parse_range() {
+    assert_eq!(parse_range("F00-FFF"), Ok(DEFAULT_BATTERY_RANGE));
+    assert_eq!(parse_range(" 0xE00 - 0xE0F "), Ok(0xE00..=0xE0F));
+    assert!(parse_range("FFF-F00").is_err());
+    assert!(parse_range("F00-1000").is_err());
+    assert!(parse_range("F00").is_err());
+}
+
+#[test]
+fn test_battery_file() {
+    let dir = std::env::temp_dir().join(format!("chip8_battery_test_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let rom_path = dir.join("game.ch8");
+
+    let mut chip8 = Chip8::new([0; 0x1000]);
+    let mut file = BatteryFile::open(&rom_path, 0xF00..=0xF03, &mut chip8).unwrap();
+    assert_eq!(file.file_path(), dir.join("game.ch8.sav"));
+    assert_eq!(file.save(&chip8), Ok(false));
+    chip8.write_memory(0xF01, &[42]).unwrap();
+    chip8.write_memory(0xF04, &[1]).unwrap();
+    assert_eq!(file.save(&chip8), Ok(true));
+    assert_eq!(fs::read(file.file_path()).unwrap(), [0, 42, 0, 0]);
+
+    let mut restarted = Chip8::new([0; 0x1000]);
+    BatteryFile::open(&rom_path, 0xF00..=0xF03, &mut restarted).unwrap();
+    assert_eq!(restarted.memory()[0xF00..0xF05], [0, 42, 0, 0, 0]);
+    assert!(BatteryFile::open(&rom_path, 0xF00..=0xF01, &mut restarted).is_err());
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,57 @@
+use chip_8_rs::chip8::Chip8;
+
+use std::env;
+use std::fs;
+use std::time::{Duration, Instant};
+
+/// Each ROM runs for at least this long
+const MEASUREMENT_TIME: Duration = Duration::from_secs(2);
+const BATCH_SIZE: u64 = 10_000;
+
+fn main() {
+    let mut files: Vec<String> = env::args().skip(1).collect();
+    if files.is_empty() {
+        files = fs::read_dir("programs")
+            .expect("Read programs directory")
+            .map(|entry| {
+                entry
+                    .expect("Read directory entry")
+                    .path()
+                    .display()
+                    .to_string()
+            })
+            .collect();
+        files.sort();
+    }
+
+    println!("{:>14}  ROM", "instructions/s");
+    for file in files {
+        let rom = fs::read(&file).unwrap_or_else(|_| panic!("Couldn't read ROM file: {}", file));
+        match measure(&rom) {
+            Ok(instructions_per_second) => {
+                println!("{:>14.0}  {}", instructions_per_second, file)
+            }
+            Err(err) => println!("{:>14}  {} ({})", "-", file, err),
+        }
+    }
+}
+
+/// Runs the ROM as fast as possible, starting over if it stops, and returns how many
+/// instructions were executed per second
+fn measure(rom: &[u8]) -> Result<f64, String> {
+    let mut chip8 = Chip8::from_rom(rom)?;
+    let mut executed = 0;
+    let start = Instant::now();
+    while start.elapsed() < MEASUREMENT_TIME {
+        for _ in 0..BATCH_SIZE {
+            if let Err(err) = chip8.step_instruction() {
+                if executed == 0 {
+                    return Err(err);
+                }
+                chip8 = Chip8::from_rom(rom)?;
+            }
+            executed += 1;
+        }
+    }
+    Ok(executed as f64 / start.elapsed().as_secs_f64())
+}
@@ -0,0 +1,128 @@
+use chip_8_rs::assembly;
+use chip_8_rs::builtin_roms;
+use chip_8_rs::chip8::Instruction;
+use chip_8_rs::patch;
+use chip_8_rs::rom_database::{self, Platform, RomDatabase};
+
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::process;
+
+use clap::{App, Arg};
+
+/// The space between the start of programs and the end of the 4KB memory
+const MAX_ROM_SIZE: usize = 0x1000 - 0x200;
+/// The number of instructions that are shown from the entry point
+const ENTRY_POINT_INSTRUCTIONS: usize = 8;
+
+fn main() {
+    let matches = App::new("Chip-8 ROM info")
+        .version("0.1.0")
+        .about("Prints what can be told about ROMs without running them: their size, checksums, which extensions of Chip-8 they use and how much of them is code.")
+        .arg(
+            Arg::with_name("ROM_FILES")
+                .multiple(true)
+                .required(true)
+                .help("The ROMs to describe (or builtin:NAME)"),
+        )
+        .get_matches();
+
+    let database = RomDatabase::builtin();
+    let mut failed = false;
+    for (i, filename) in matches.values_of("ROM_FILES").unwrap().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        match builtin_roms::read_rom(Path::new(filename)) {
+            Ok(rom) => print_info(filename, &rom, &database),
+            Err(err) => {
+                eprintln!("{}", err);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+fn print_info(filename: &str, rom: &[u8], database: &RomDatabase) {
+    println!("{}", filename);
+    let fits = if rom.len() <= MAX_ROM_SIZE {
+        "fits in memory".to_owned()
+    } else {
+        format!("too big: at most {} bytes fit in memory", MAX_ROM_SIZE)
+    };
+    println!("  Size:         {} bytes ({})", rom.len(), fits);
+    println!("  SHA-1:        {}", sha1_smol::Sha1::from(rom).digest());
+    println!("  CRC32:        {:08x}", patch::crc32(rom));
+    if let Some(info) = database.lookup(rom) {
+        println!("  Known as:     {} ({:?})", info.name(), info.platform);
+    }
+
+    // The addresses that the disassembler reached by following the program from the entry point
+    let disassembled = assembly::disassemble_rom(rom.to_vec());
+    let reached: Vec<usize> = (0x200..0x200 + rom.len().min(MAX_ROM_SIZE))
+        .filter(|&address| !disassembled[address].is_empty())
+        .collect();
+    let opcode =
+        |address: usize| u16::from_be_bytes([rom[address - 0x200], rom[address - 0x200 + 1]]);
+
+    let unknown = reached
+        .iter()
+        .filter(|&&address| Instruction::decode(opcode(address)).is_err())
+        .count();
+    let mut covered = vec![false; rom.len()];
+    for &address in &reached {
+        covered[address - 0x200] = true;
+        covered[address - 0x200 + 1] = true;
+    }
+    let data_bytes = covered.iter().filter(|&&covered| !covered).count();
+    println!(
+        "  Instructions: {} reached from the entry point ({} of them unknown), {} bytes of data",
+        reached.len(),
+        unknown,
+        data_bytes
+    );
+
+    let extensions: BTreeSet<(String, &str)> = reached
+        .iter()
+        .filter_map(|&address| rom_database::extension_opcode(opcode(address)))
+        .map(|(platform, name)| (platform_name(platform).to_owned(), name))
+        .collect();
+    if extensions.is_empty() {
+        println!("  Extensions:   none (plain Chip-8)");
+    } else {
+        println!("  Extensions:");
+        for (platform, name) in extensions {
+            println!("    {:<8} {}", platform, name);
+        }
+    }
+
+    // Jumps are followed, since many programs start by jumping over their data
+    println!("  Entry point:");
+    let mut address = 0x200;
+    for _ in 0..ENTRY_POINT_INSTRUCTIONS {
+        if address < 0x200 || address - 0x200 + 1 >= rom.len() {
+            break;
+        }
+        let opcode = opcode(address);
+        let text = assembly::disassemble_opcode(opcode).unwrap_or_else(|_| "?".to_owned());
+        println!("    {:03X}: {:04X}  {}", address, opcode, text);
+        address = match Instruction::decode(opcode) {
+            Ok(Instruction::Jump(destination)) if destination as usize != address => {
+                destination as usize
+            }
+            _ => address + 2,
+        };
+    }
+}
+
+fn platform_name(platform: Platform) -> &'static str {
+    match platform {
+        Platform::Chip8 => "Chip-8",
+        Platform::Schip => "SCHIP",
+        Platform::Xochip => "XO-CHIP",
+        Platform::Chip8x => "CHIP-8X",
+    }
+}
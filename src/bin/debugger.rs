@@ -0,0 +1,442 @@
+use chip_8_rs::assembly;
+use chip_8_rs::builtin_roms;
+use chip_8_rs::chip8::{
+    parse_clock_frequency, parse_memory_size, Chip8, Quirks, StateDiff, StepHistory, QUIRKS_PRESETS,
+};
+use chip_8_rs::logging;
+use chip_8_rs::state_file::StateFile;
+use chip_8_rs::watch::WatchExpression;
+
+use std::convert::TryFrom;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use clap::{App, Arg};
+
+/// `continue` gives up after this many instructions, unless given another limit
+const DEFAULT_CONTINUE_LIMIT: u64 = 1_000_000;
+const DEFAULT_DISASSEMBLY_LINES: u16 = 10;
+/// How many of the instructions that were stepped through `reverse-step` can go back over
+const STEP_HISTORY_LENGTH: usize = 10_000;
+
+const HELP: &str = "\
+Commands:
+  break ADDR (b)          Stop before executing the instruction at ADDR
+  delete ADDR (d)         Remove the breakpoint at ADDR
+  info breakpoints        List the breakpoints
+  info coverage           Show which forms of instructions have been executed
+  info modified           List the instructions that the program wrote over after executing them
+  step [N] (s)            Execute one (or N) instructions
+  reverse-step [N] (rs)   Go back over one (or N) of the instructions that were stepped through
+  continue [N] (c)        Run until a breakpoint or an error (or at most N instructions)
+  print EXPR (p)          Show a value, like V3, I, PC, delay, mem[I] or mem[0x300..0x310]
+  x/N ADDR                Show N bytes of memory, starting at ADDR
+  disas [ADDR] [N]        Disassemble N instructions from ADDR (by default around PC)
+  set TARGET VALUE        Change V0-VF, I, PC, delay, sound or mem[ADDR]
+  press KEY, release KEY  Change the state of a keypad key (0-F)
+  registers (r)           Show all registers
+  screen                  Show the screen as text, with the numbers of the rows and columns
+  display                 Toggle showing the screen whenever the program stops
+  quit (q)                Exit
+Numbers are decimal unless prefixed with 0x. An empty line repeats the last command.";
+
+fn main() {
+    logging::init();
+    let (filename, clock_frequency, quirks, memory_size, state_file) = parse_args();
+
+    let rom = builtin_roms::read_rom(Path::new(&filename)).unwrap_or_else(|err| panic!("{}", err));
+    let mut chip8 = Chip8::from_rom(&rom).unwrap_or_else(|err| panic!("{}: {}", filename, err));
+    if let Some(size) = memory_size {
+        chip8
+            .set_memory_size(size.max(chip8.memory().len()))
+            .unwrap();
+    }
+    if let Some(state_file) = state_file {
+        let state = fs::read(&state_file)
+            .unwrap_or_else(|_| panic!("Couldn't read state file: {}", state_file));
+        StateFile::parse(&state)
+            .and_then(|file| file.load_into(&mut chip8))
+            .unwrap_or_else(|err| panic!("Couldn't load state from {}: {}", state_file, err));
+        println!("Loaded state from {}", state_file);
+    }
+    if let Some(freq) = clock_frequency {
+        chip8.set_clock_frequency(freq);
+    }
+    chip8.set_quirks(quirks);
+
+    println!("Loaded {}. Type help for a list of commands.", filename);
+    let mut debugger = Debugger {
+        chip8,
+        show_display: false,
+        step_history: StepHistory::new(STEP_HISTORY_LENGTH),
+    };
+    debugger.print_location();
+
+    let stdin = io::stdin();
+    let mut last_command = String::new();
+    loop {
+        print!("(chip8) ");
+        io::stdout().flush().expect("Flush stdout");
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).expect("Read from stdin") == 0 {
+            break;
+        }
+        let line = line.trim();
+        let command = if line.is_empty() {
+            last_command.clone()
+        } else {
+            line.to_owned()
+        };
+        if command.is_empty() {
+            continue;
+        }
+        match debugger.execute(&command) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(err) => println!("{}", err),
+        }
+        last_command = command;
+    }
+}
+
+fn parse_args() -> (String, Option<f64>, Quirks, Option<usize>, Option<String>) {
+    let matches = App::new("Chip-8 debugger")
+        .version("0.1.0")
+        .about("Debugs a Chip-8 program from a gdb-style prompt, without a window.")
+        .arg(
+            Arg::with_name("ROM_FILE")
+                .short("f")
+                .long("file")
+                .visible_alias("rom")
+                .takes_value(true)
+                .help("A file containing the program that will be debugged (or builtin:NAME)"),
+        )
+        .arg(
+            Arg::with_name("CLOCK_FREQUENCY")
+                .short("c")
+                .long("clock")
+                .takes_value(true)
+                .help("The number of instructions to be executed by Chip-8 per second (which decides how fast the timers count down)"),
+        )
+        .arg(
+            Arg::with_name("QUIRKS")
+                .long("quirks")
+                .takes_value(true)
+                .possible_values(&QUIRKS_PRESETS)
+                .help("How to handle the instructions that differ between interpreters"),
+        )
+        .arg(
+            Arg::with_name("MEMORY_SIZE")
+                .long("memory-size")
+                .takes_value(true)
+                .help("The size of memory in hex, from 1000 to 10000 (XO-CHIP, which ROMs that don't fit in 4K get anyway)"),
+        )
+        .arg(
+            Arg::with_name("STATE_FILE")
+                .long("load-state")
+                .takes_value(true)
+                .help("A save state (or a crash dump) to start from"),
+        )
+        .get_matches();
+
+    let filename = builtin_roms::filename_or_default(matches.value_of("ROM_FILE"));
+
+    let clock_frequency = matches
+        .value_of("CLOCK_FREQUENCY")
+        .map(|freq| parse_clock_frequency(freq).unwrap_or_else(|err| panic!("{}", err)));
+
+    let quirks = match matches.value_of("QUIRKS") {
+        Some(name) => Quirks::preset(name).expect("Quirks preset"),
+        None => Quirks::default(),
+    };
+
+    let state_file = matches.value_of("STATE_FILE").map(|s| s.to_owned());
+
+    let memory_size = matches
+        .value_of("MEMORY_SIZE")
+        .map(|size| parse_memory_size(size).unwrap_or_else(|err| panic!("{}", err)));
+
+    (filename, clock_frequency, quirks, memory_size, state_file)
+}
+
+struct Debugger {
+    chip8: Chip8,
+    show_display: bool,
+    step_history: StepHistory,
+}
+
+impl Debugger {
+    /// Returns false if the user wants to quit
+    fn execute(&mut self, command: &str) -> Result<bool, String> {
+        let mut words = command.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let args: Vec<&str> = words.collect();
+        match name {
+            "break" | "b" => {
+                let address = parse_number(single_arg(&args)?)?;
+                if !self.chip8.breakpoints().contains(&address) {
+                    self.chip8.toggle_breakpoint(address);
+                }
+                println!("Breakpoint at {:#05X}", address);
+            }
+            "delete" | "d" => {
+                let address = parse_number(single_arg(&args)?)?;
+                if !self.chip8.breakpoints().contains(&address) {
+                    return Err(format!("No breakpoint at {:#05X}", address));
+                }
+                self.chip8.toggle_breakpoint(address);
+            }
+            "info"
+                if args
+                    .first()
+                    .is_some_and(|arg| "breakpoints".starts_with(arg)) =>
+            {
+                let mut breakpoints: Vec<u16> = self.chip8.breakpoints().iter().copied().collect();
+                breakpoints.sort_unstable();
+                if breakpoints.is_empty() {
+                    println!("No breakpoints");
+                }
+                for address in breakpoints {
+                    println!("{:#05X}: {}", address, self.disassemble_at(address));
+                }
+            }
+            "info" if args.first().is_some_and(|arg| "coverage".starts_with(arg)) => {
+                print!("{}", self.chip8.opcode_coverage().summary());
+            }
+            "info" if args.first().is_some_and(|arg| "modified".starts_with(arg)) => {
+                if self.chip8.modified_code().is_empty() {
+                    println!("No modified instructions");
+                }
+                for (&address, &writer) in self.chip8.modified_code() {
+                    println!(
+                        "{:#05X}: {} (written by {:#05X})",
+                        address,
+                        self.disassemble_at(address),
+                        writer
+                    );
+                }
+            }
+            "step" | "s" => {
+                let count = optional_count(&args, 1)?;
+                for _ in 0..count {
+                    let before = self.chip8.save_state();
+                    let step = self.step_history.step(&mut self.chip8)?;
+                    if count == 1 {
+                        println!("Executed {:#05X}: {}", step.pc, step.decoded);
+                        let diff = StateDiff::between_states(&before, &self.chip8.save_state())?;
+                        for line in diff.to_string().lines() {
+                            println!("  {}", line);
+                        }
+                    }
+                }
+                self.print_stop();
+            }
+            "reverse-step" | "rs" => {
+                let count = optional_count(&args, 1)?;
+                for stepped_back in 0..count {
+                    if !self.step_history.step_back(&mut self.chip8)? {
+                        println!("Went back {} instructions (no earlier steps)", stepped_back);
+                        break;
+                    }
+                }
+                self.print_stop();
+            }
+            "continue" | "c" => {
+                let limit = optional_count(&args, DEFAULT_CONTINUE_LIMIT)?;
+                self.continue_running(limit)?;
+            }
+            "print" | "p" => {
+                let watch = WatchExpression::parse(&args.join(" "))?;
+                println!("{} = {}", watch.source(), watch.evaluate(&self.chip8));
+            }
+            x if x == "x" || x.starts_with("x/") => {
+                let count = match x.strip_prefix("x/") {
+                    Some(count) => parse_number(count)?,
+                    None => 1,
+                };
+                let address = self.parse_address(single_arg(&args)?)?;
+                self.print_memory(address, count);
+            }
+            "disas" => {
+                let address = match args.first() {
+                    Some(address) => self.parse_address(address)?,
+                    None => self.chip8.program_counter.saturating_sub(4),
+                };
+                let lines = match args.get(1) {
+                    Some(lines) => parse_number(lines)?,
+                    None => DEFAULT_DISASSEMBLY_LINES,
+                };
+                for i in 0..lines {
+                    let address = address.wrapping_add(2 * i);
+                    if address as usize + 1 >= self.chip8.memory().len() {
+                        break;
+                    }
+                    let marker = if address == self.chip8.program_counter {
+                        "=>"
+                    } else {
+                        "  "
+                    };
+                    println!(
+                        "{} {:#05X}: {}",
+                        marker,
+                        address,
+                        self.disassemble_at(address)
+                    );
+                }
+            }
+            "set" => match args.as_slice() {
+                [target, value] => self.set(target, parse_number(value)?)?,
+                _ => return Err("Usage: set TARGET VALUE".to_owned()),
+            },
+            "press" | "release" => {
+                let key = u8::from_str_radix(single_arg(&args)?, 16)
+                    .ok()
+                    .filter(|&key| key < 16)
+                    .ok_or("Expected a keypad key 0-F")?;
+                self.chip8.handle_key_event(key, name == "press");
+            }
+            "registers" | "r" => self.print_registers(),
+            "screen" => print!("{}", self.chip8.display_buffer.to_text_grid()),
+            "display" => {
+                self.show_display = !self.show_display;
+                let state = if self.show_display { "on" } else { "off" };
+                println!("Showing the screen when stopping: {}", state);
+            }
+            "help" | "h" => println!("{}", HELP),
+            "quit" | "q" => return Ok(false),
+            _ => return Err(format!("Unknown command: {} (type help)", command)),
+        }
+        Ok(true)
+    }
+
+    fn continue_running(&mut self, limit: u64) -> Result<(), String> {
+        for executed in 0..limit {
+            let pc = self.chip8.program_counter;
+            if executed > 0 && self.chip8.breakpoints().contains(&pc) {
+                println!("Hit breakpoint after {} instructions", executed);
+                self.print_stop();
+                return Ok(());
+            }
+            if let Err(err) = self.chip8.step_instruction() {
+                self.print_stop();
+                return Err(err);
+            }
+        }
+        println!("Stopped after {} instructions", limit);
+        self.print_stop();
+        Ok(())
+    }
+
+    fn set(&mut self, target: &str, value: u16) -> Result<(), String> {
+        let target = target.to_lowercase();
+        let byte =
+            || u8::try_from(value).map_err(|_| format!("Too large for a byte: {:#X}", value));
+        match target.as_str() {
+            "i" => self.chip8.address_register = value,
+            "pc" => self.chip8.program_counter = value,
+            "delay" | "dt" => self.chip8.delay_timer = byte()?,
+            "sound" | "st" => self.chip8.sound_timer = byte()?,
+            t if t.len() == 2 && t.starts_with('v') => {
+                let register = u8::from_str_radix(&t[1..], 16)
+                    .map_err(|_| format!("Invalid register: {}", target))?;
+                self.chip8.registers[register as usize] = byte()?;
+            }
+            t if t.starts_with("mem[") && t.ends_with(']') => {
+                let address = self.parse_address(&t[4..t.len() - 1])?;
+                self.chip8.write_memory(address, &[byte()?])?;
+            }
+            _ => return Err(format!("Can't set {}", target)),
+        }
+        Ok(())
+    }
+
+    /// A number, or I or PC
+    fn parse_address(&self, s: &str) -> Result<u16, String> {
+        match s.to_lowercase().as_str() {
+            "i" => Ok(self.chip8.address_register),
+            "pc" => Ok(self.chip8.program_counter),
+            _ => parse_number(s),
+        }
+    }
+
+    fn disassemble_at(&self, address: u16) -> String {
+        let memory = self.chip8.memory();
+        let address = address as usize;
+        if address + 1 >= memory.len() {
+            return "-".to_owned();
+        }
+        let opcode = u16::from_be_bytes([memory[address], memory[address + 1]]);
+        assembly::disassemble_opcode(opcode).unwrap_or_else(|_| format!("(data: {:04X})", opcode))
+    }
+
+    fn print_memory(&self, address: u16, count: u16) {
+        let memory = self.chip8.memory();
+        let start = address as usize;
+        let end = (start + count as usize).min(memory.len());
+        for (row, bytes) in memory[start.min(end)..end].chunks(8).enumerate() {
+            let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+            println!("{:#05X}: {}", start + row * 8, bytes.join(" "));
+        }
+    }
+
+    fn print_registers(&self) {
+        for row in 0..4 {
+            let registers: Vec<String> = (0..4)
+                .map(|column| {
+                    let register = row * 4 + column;
+                    format!("V{:X}={:02X}", register, self.chip8.registers[register])
+                })
+                .collect();
+            println!("{}", registers.join(" "));
+        }
+        println!(
+            "I={:04X} PC={:03X} SP={:X} DT={:02X} ST={:02X}",
+            self.chip8.address_register,
+            self.chip8.program_counter,
+            self.chip8.stack_pointer,
+            self.chip8.delay_timer,
+            self.chip8.sound_timer
+        );
+    }
+
+    fn print_location(&self) {
+        let pc = self.chip8.program_counter;
+        println!("=> {:#05X}: {}", pc, self.disassemble_at(pc));
+    }
+
+    fn print_stop(&self) {
+        if self.show_display {
+            print!("{}", self.chip8.display_buffer.to_text_grid());
+        }
+        if let Some(register) = self.chip8.waiting_for_key() {
+            println!("Waiting for a key press (into V{:X})", register);
+        }
+        self.print_location();
+    }
+}
+
+fn single_arg<'a>(args: &[&'a str]) -> Result<&'a str, String> {
+    match args {
+        [arg] => Ok(arg),
+        _ => Err("Expected one argument".to_owned()),
+    }
+}
+
+fn optional_count(args: &[&str], default: u64) -> Result<u64, String> {
+    match args {
+        [] => Ok(default),
+        [count] => count
+            .parse()
+            .map_err(|_| format!("Invalid count: {}", count)),
+        _ => Err("Expected at most one argument".to_owned()),
+    }
+}
+
+fn parse_number(s: &str) -> Result<u16, String> {
+    let result = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    result.map_err(|_| format!("Invalid number: {}", s))
+}
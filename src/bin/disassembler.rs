@@ -1,45 +1,130 @@
 use chip_8_rs::assembly;
+use chip_8_rs::chip8::parse_start_address;
 
-use std::env;
-use std::fs::File;
-use std::io::Read;
-use std::io::Write;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::process;
+
+use clap::{App, Arg};
 
 fn main() {
-    let mut args: Vec<String> = env::args().collect();
-    let (rom_file, result_file) = match args.len() {
-        3 => (args.remove(1), args.remove(1)),
-        _ => {
-            println!("Usage: {} rom_filename result_file", args[0]);
-            std::process::exit(1);
-        }
-    };
+    let matches = App::new("Chip-8 disassembler")
+        .version("0.1.0")
+        .about("Disassembles a Chip-8 program, one instruction per line in the order of their addresses, with the bytes between the instructions as data")
+        .arg(
+            Arg::with_name("ROM_FILE")
+                .required(true)
+                .help("The program to disassemble"),
+        )
+        .arg(
+            Arg::with_name("OUTPUT_FILE")
+                .help("Where to write the disassembly (by default, or with -, to stdout)"),
+        )
+        .arg(
+            Arg::with_name("START_ADDRESS")
+                .long("start-address")
+                .takes_value(true)
+                .help("Where the ROM is loaded, in hex (by default 200, or eti660 for 600)"),
+        )
+        .arg(
+            Arg::with_name("FROM")
+                .long("from")
+                .takes_value(true)
+                .help("Only list instructions at this address (in hex) and after"),
+        )
+        .arg(
+            Arg::with_name("TO")
+                .long("to")
+                .takes_value(true)
+                .help("Only list instructions at this address (in hex) and before"),
+        )
+        .arg(
+            Arg::with_name("LINEAR")
+                .long("linear")
+                .help("Take every other byte as an instruction, instead of following jumps and calls from the entry point"),
+        )
+        .arg(
+            Arg::with_name("CODE_ONLY")
+                .long("code-only")
+                .help("Leave out the bytes between the instructions, instead of listing them as data"),
+        )
+        .arg(
+            Arg::with_name("BYTES")
+                .long("bytes")
+                .help("Show the op-code of each instruction"),
+        )
+        .get_matches();
 
-    disassemble(&rom_file, &result_file);
-}
+    let filename = matches.value_of("ROM_FILE").unwrap();
+    let start_address = matches
+        .value_of("START_ADDRESS")
+        .map(|address| parse_start_address(address).unwrap_or_else(|err| exit_with_error(&err)))
+        .unwrap_or(0x200);
+    let from = matches.value_of("FROM").map_or(0, parse_address);
+    let to = matches.value_of("TO").map_or(0xFFF, parse_address);
+    let rom = fs::read(filename)
+        .unwrap_or_else(|err| exit_with_error(&format!("Couldn't read {}: {}", filename, err)));
 
-fn disassemble(filename: &str, result_filename: &str) {
-    let mut f = File::open(filename).expect(&format!("Couldn't open ROM file: {}", filename));
-    let mut buffer = Vec::new();
-    f.read_to_end(&mut buffer)
-        .expect(&format!("Couldn't read from ROM file: {}", filename));
-    let mut memory = [0; 0x1000];
-    for i in 0..buffer.len() {
-        memory[0x200 + i] = buffer[i];
-    }
-    let disassembled_program = assembly::disassemble_rom(buffer);
+    let disassembled = if matches.is_present("LINEAR") {
+        assembly::disassemble_linear_at(rom.clone(), start_address)
+    } else {
+        assembly::disassemble_rom_at(rom.clone(), start_address)
+    };
+    let output_file = matches.value_of("OUTPUT_FILE").filter(|&file| file != "-");
+    let output: Box<dyn Write> =
+        match output_file {
+            Some(file) => Box::new(File::create(file).unwrap_or_else(|err| {
+                exit_with_error(&format!("Couldn't create {}: {}", file, err))
+            })),
+            None => Box::new(io::stdout()),
+        };
+    let mut output = BufWriter::new(output);
 
-    let mut output_file = File::create(&result_filename)
-        .expect(&format!("Couldn't create output file: {}", result_filename));
     let mut num_instructions = 0;
-    for (i, line) in disassembled_program.iter().enumerate() {
-        if !line.is_empty() {
-            writeln!(output_file, "{:03X}: {}", i, line).expect(&format!(
-                "Couldn't write disassembled program to file: {}",
-                result_filename
-            ));
+    for line in assembly::listing(&rom, start_address, &disassembled) {
+        if line.address < from
+            || line.address > to
+            || (line.is_data() && matches.is_present("CODE_ONLY"))
+        {
+            continue;
+        }
+        let result = if !matches.is_present("BYTES") {
+            writeln!(output, "{:03X}: {}", line.address, line.text)
+        } else if line.is_data() {
+            writeln!(output, "{:03X}:       {}", line.address, line.text)
+        } else {
+            let opcode = u16::from_be_bytes([line.bytes[0], line.bytes[1]]);
+            writeln!(
+                output,
+                "{:03X}: {:04X}  {}",
+                line.address, opcode, line.text
+            )
+        };
+        result.unwrap_or_else(|err| {
+            exit_with_error(&format!("Couldn't write the disassembly: {}", err))
+        });
+        if !line.is_data() {
             num_instructions += 1;
         }
     }
-    println!("Wrote {} instructions to {}", num_instructions, result_filename);
+    output
+        .flush()
+        .unwrap_or_else(|err| exit_with_error(&format!("Couldn't write the disassembly: {}", err)));
+    if let Some(file) = output_file {
+        println!("Wrote {} instructions to {}", num_instructions, file);
+    }
+}
+
+/// An address in hex, like `0x2A0` or `2A0`
+fn parse_address(source: &str) -> u16 {
+    let hex = source.strip_prefix("0x").unwrap_or(source);
+    u16::from_str_radix(hex, 16)
+        .ok()
+        .filter(|&address| address < 0x1000)
+        .unwrap_or_else(|| exit_with_error(&format!("Invalid address: {}", source)))
+}
+
+fn exit_with_error(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(2)
 }
@@ -1,27 +1,267 @@
-use chip_8_rs::chip8::{Chip8, FONT_SPRITES};
-use chip_8_rs::{app, assembly};
+use chip_8_rs::app::{
+    self, parse_color, GamepadMap, GgezFrontend, KeyMap, Palette, SecondMachine, PALETTE_PRESETS,
+};
+use chip_8_rs::assembly;
+use chip_8_rs::autosave;
+use chip_8_rs::battery;
+use chip_8_rs::builtin_roms;
+use chip_8_rs::chip8::{
+    parse_clock_frequency, parse_display_mode, parse_memory_size, parse_start_address, Chip8,
+    FontProtection, Quirks, Random, TraceFilter, Tracer, FONT_PROTECTION_MODES, PROGRAM_START,
+    QUIRKS_PRESETS, TRACE_FILTERS,
+};
+use chip_8_rs::config::{Config, CONFIG_FILE};
+use chip_8_rs::frontend::Frontend;
+use chip_8_rs::input_script::InputScript;
+use chip_8_rs::key_commands::KeyInjector;
+use chip_8_rs::logging;
+use chip_8_rs::netplay::{Netplay, NetplayClient, NetplayHost};
+use chip_8_rs::patch;
+use chip_8_rs::rom_database::{Platform, RomDatabase, RomInfo};
+use chip_8_rs::state_file::StateFile;
+use chip_8_rs::watch::WatchExpression;
 
-use std::fs::File;
-use std::io::Read;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
 
 use clap::{App, Arg};
 
+/// The settings from the command line, on top of the ones from the config file
+struct Args {
+    filename: String,
+    patch_file: Option<String>,
+    clock_frequency: Option<f64>,
+    tickrate: Option<u32>,
+    cycle_costs: bool,
+    sticky_keys: bool,
+    quirks: Option<Quirks>,
+    font_protection: Option<FontProtection>,
+    state_file: Option<String>,
+    /// Start from the ROM's autosave
+    resume: bool,
+    trace: Option<(String, TraceFilter)>,
+    repl: bool,
+    /// The port to wait for a second player on
+    netplay_host: Option<u16>,
+    /// The host to connect to, as the second player
+    netplay_connect: Option<String>,
+    /// stdin, or the port to accept key commands on
+    key_commands: Option<String>,
+    script_file: Option<String>,
+    /// Another ROM to run side by side with the first one
+    compare_rom: Option<String>,
+    /// The name of the quirks preset to run a second machine with, side by side with the first
+    compare_quirks: Option<String>,
+    options: app::Options,
+}
+
 fn main() {
-    let (filename, clock_frequency, debug) = parse_args();
+    logging::init();
+    let Args {
+        filename,
+        patch_file,
+        clock_frequency,
+        tickrate,
+        cycle_costs,
+        sticky_keys,
+        quirks,
+        font_protection,
+        state_file,
+        resume,
+        trace,
+        repl,
+        netplay_host,
+        netplay_connect,
+        key_commands,
+        script_file,
+        compare_rom,
+        compare_quirks,
+        mut options,
+    } = parse_args();
 
-    let (mut chip8, disassembled_program) = setup_chip8(&filename);
+    let (mut chip8, disassembled_program, rom_info) = setup_chip8(
+        &filename,
+        patch_file,
+        options.start_address,
+        options.rom_database.as_ref(),
+    );
+    if let Some(info) = &rom_info {
+        println!("Recognized {}", info.name());
+        if info.platform != Platform::Chip8 && info.platform != Platform::Chip8x {
+            println!(
+                "This is a {:?} ROM, which may use instructions that aren't supported",
+                info.platform
+            );
+        }
+    }
+    if let Some(height) = options.display_height {
+        chip8.set_display_height(height).unwrap();
+    }
+    if let Some(size) = options.memory_size {
+        chip8
+            .set_memory_size(size.max(chip8.memory().len()))
+            .unwrap();
+    }
+    if chip8.display_buffer.height() != 32 {
+        println!("Using the 64x{} display", chip8.display_buffer.height());
+    }
+    // Settings given by the user win over the ones recommended by the ROM database
+    let clock_frequency = clock_frequency.or_else(|| {
+        rom_info
+            .as_ref()
+            .and_then(|info| info.clock)
+            .map(|freq| freq as f64)
+    });
+    let given_quirks = quirks;
+    let quirks = quirks.or_else(|| rom_info.as_ref().and_then(RomInfo::recommended_quirks));
+    chip8.set_quirks(quirks.unwrap_or_default());
+    chip8.set_font_protection(font_protection);
+    if let Some(font) = &options.font {
+        chip8
+            .set_font(font)
+            .unwrap_or_else(|err| panic!("Invalid font: {}", err));
+    }
 
-    if let Some(freq) = clock_frequency {
+    let loading_state = state_file.is_some() || resume;
+    if let Some(state_file) = state_file {
+        let state = fs::read(&state_file)
+            .unwrap_or_else(|_| panic!("Couldn't read state file: {}", state_file));
+        StateFile::parse(&state)
+            .and_then(|file| file.load_into(&mut chip8))
+            .unwrap_or_else(|err| panic!("Couldn't load state from {}: {}", state_file, err));
+        println!("Loaded state from {}", state_file);
+    }
+    if resume {
+        match autosave::resume(Path::new(&filename), &mut chip8) {
+            Ok(true) => println!(
+                "Resumed from {}",
+                autosave::path(Path::new(&filename)).display()
+            ),
+            Ok(false) => panic!("There is no autosave of {}", filename),
+            Err(err) => panic!("{}", err),
+        }
+    } else if options.autosave && autosave::exists(Path::new(&filename)) {
+        println!("Continue from the autosave with --resume (or pick it with F4)");
+    }
+
+    if let Some(tickrate) = tickrate {
+        chip8.set_tickrate(tickrate);
+        println!(
+            "Running {} at {} instructions per frame",
+            filename, tickrate
+        );
+    } else if let Some(freq) = clock_frequency {
         chip8.set_clock_frequency(freq);
         println!("Running {} at {} Hz", filename, freq);
     } else {
         println!("Running {}", filename);
     }
+    chip8.set_cycle_costs(cycle_costs);
+    chip8.set_sticky_keys(sticky_keys);
+
+    let mut seed = None;
+    if let Some(script_file) = script_file {
+        let source = fs::read_to_string(&script_file)
+            .unwrap_or_else(|_| panic!("Couldn't read script: {}", script_file));
+        let script =
+            InputScript::parse(&source).unwrap_or_else(|err| panic!("{}: {}", script_file, err));
+        if let Some(script_seed) = script.seed {
+            chip8.set_random(Random::seeded(script_seed));
+        }
+        seed = script.seed;
+        options.script = script.events(chip8.cycles_per_frame() as u64);
+        println!("Playing the input script {}", script_file);
+    }
+    // After the script has seeded the random number generator, and unless a save state has
+    // replaced the memory
+    if options.random_ram && !loading_state {
+        chip8.randomize_free_memory();
+    }
+
+    if compare_rom.is_some() || compare_quirks.is_some() {
+        let second_machine = setup_second_machine(
+            &chip8,
+            &filename,
+            compare_rom,
+            compare_quirks,
+            given_quirks,
+            seed,
+            &options,
+        );
+        options.second_machine = Some(second_machine);
+    }
+
+    if let Some((trace_file, filter)) = trace {
+        let file = File::create(&trace_file)
+            .unwrap_or_else(|_| panic!("Couldn't create trace file: {}", trace_file));
+        chip8.set_tracer(Some(Tracer::new(Box::new(BufWriter::new(file)), filter)));
+        println!("Tracing instructions to {}", trace_file);
+    }
 
-    app::run(chip8, disassembled_program, filename, debug).expect("Run app");
+    if repl {
+        println!("Type an instruction to execute it, or ADDR: instruction to patch memory");
+        println!("Type watch EXPRESSION or unwatch EXPRESSION to change the watches");
+        println!("Type freeze TARGET = VALUE, poke TARGET = VALUE or unfreeze TARGET for cheats");
+        println!("Type search VALUE (or changed, unchanged, increased, decreased, reset) to find a value in memory");
+        options.repl = Some(spawn_repl());
+    }
+
+    if let Some(port) = netplay_host {
+        let host = NetplayHost::listen(("0.0.0.0", port)).unwrap_or_else(|err| panic!("{}", err));
+        println!("Waiting for a second player on port {}", port);
+        options.netplay = Some(Netplay::Host(host));
+    } else if let Some(address) = netplay_connect {
+        let client = NetplayClient::connect(address.as_str())
+            .unwrap_or_else(|err| panic!("{}: {}", address, err));
+        println!("Connected to {}, which runs the game", address);
+        options.netplay = Some(Netplay::Client(client));
+    }
+
+    match key_commands.as_deref() {
+        Some("stdin") => {
+            println!("Reading key commands (like press 5 100ms) from stdin");
+            options.key_injector = Some(KeyInjector::stdin());
+        }
+        Some(port) => {
+            let port = port
+                .parse::<u16>()
+                .unwrap_or_else(|err| panic!("Expected stdin or a port: {} ({})", port, err));
+            let injector =
+                KeyInjector::listen(("0.0.0.0", port)).unwrap_or_else(|err| panic!("{}", err));
+            println!(
+                "Accepting key commands (like press 5 100ms) on port {}",
+                port
+            );
+            options.key_injector = Some(injector);
+        }
+        None => {}
+    }
+
+    let frontend = GgezFrontend {
+        disassembled_program,
+        window_title: filename,
+        options,
+    };
+    frontend.run(chip8).expect("Run app");
+}
+
+fn spawn_repl() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let line = line.expect("Read from stdin");
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
 }
 
-fn parse_args() -> (String, Option<u32>, bool) {
+fn parse_args() -> Args {
     let matches = App::new("Chip-8 emulator")
         .version("0.1.0")
         .about("An emulator/debugger of the virtual machine Chip-8, programmed in Rust.")
@@ -29,8 +269,15 @@ fn parse_args() -> (String, Option<u32>, bool) {
             Arg::with_name("ROM_FILE")
                 .short("f")
                 .long("file")
+                .visible_alias("rom")
                 .takes_value(true)
-                .help("A file containing the program that will be run"),
+                .help("A file containing the program that will be run (or builtin:NAME)"),
+        )
+        .arg(
+            Arg::with_name("PATCH_FILE")
+                .long("patch")
+                .takes_value(true)
+                .help("An IPS or BPS patch to apply to the ROM (by default, ROM_FILE.ips or ROM_FILE.bps is applied if it exists)"),
         )
         .arg(
             Arg::with_name("CLOCK_FREQUENCY")
@@ -39,48 +286,571 @@ fn parse_args() -> (String, Option<u32>, bool) {
                 .takes_value(true)
                 .help("The number of instructions to be executed by Chip-8 per second"),
         )
+        .arg(
+            Arg::with_name("TICKRATE")
+                .long("tickrate")
+                .takes_value(true)
+                .conflicts_with("CLOCK_FREQUENCY")
+                .help("Execute exactly this many instructions in every 60Hz frame, like Octo, instead of spreading them out over time"),
+        )
+        .arg(
+            Arg::with_name("CYCLE_COSTS")
+                .long("cycle-costs")
+                .conflicts_with("TICKRATE")
+                .help("Make each instruction take about as long as it did on the COSMAC VIP (drawing takes longer than adding), relative to the clock frequency"),
+        )
+        .arg(
+            Arg::with_name("STICKY_KEYS")
+                .long("sticky-keys")
+                .help("Keep a quick key press until the program checks that key (with Ex9E or ExA1), so that taps aren't lost at low clock frequencies"),
+        )
+        .arg(
+            Arg::with_name("START_ADDRESS")
+                .long("start-address")
+                .takes_value(true)
+                .help("Where the ROM is loaded and starts running, in hex (by default 200). Programs for the ETI 660 need 600, which can also be given as eti660."),
+        )
+        .arg(
+            Arg::with_name("DISPLAY_MODE")
+                .long("display-mode")
+                .takes_value(true)
+                .help("The size of the display: 64x32, 64x48 (ETI 660) or 64x64 (hires). Hires programs are detected and get 64x64 anyway."),
+        )
+        .arg(
+            Arg::with_name("MEMORY_SIZE")
+                .long("memory-size")
+                .takes_value(true)
+                .help("The size of memory in hex, from 1000 (the default) to 10000 (XO-CHIP). ROMs that don't fit in 4K get 10000 anyway."),
+        )
+        .arg(
+            Arg::with_name("QUIRKS")
+                .long("quirks")
+                .takes_value(true)
+                .possible_values(&QUIRKS_PRESETS)
+                .help("How to handle the instructions that differ between interpreters (vip for the original COSMAC VIP, schip for SUPER-CHIP)"),
+        )
+        .arg(
+            Arg::with_name("COMPARE_ROM")
+                .long("compare-rom")
+                .takes_value(true)
+                .conflicts_with("NETPLAY_CONNECT")
+                .help("Run this ROM too, next to the first one and with the same keys, for comparing their behavior (like a patched ROM against the original)"),
+        )
+        .arg(
+            Arg::with_name("COMPARE_QUIRKS")
+                .long("compare-quirks")
+                .takes_value(true)
+                .possible_values(&QUIRKS_PRESETS)
+                .conflicts_with("NETPLAY_CONNECT")
+                .help("Run a second machine with these quirks next to the first one, with the same keys (and with --compare-rom, give that ROM these quirks)"),
+        )
         .arg(
             Arg::with_name("DEBUG")
                 .short("d")
                 .long("debug")
                 .help("Show debug information (like register contents and disassembled instructions) while running"),
         )
+        .arg(
+            Arg::with_name("KEYMAP")
+                .short("k")
+                .long("keymap")
+                .takes_value(true)
+                .help("The 16 keyboard keys that map to the Chip-8 keypad values 0 to F (in that order). Or a keyboard layout: qwerty (the default, \"x123qweasdzc4rfv\"), qwertz, azerty, dvorak or colemak."),
+        )
+        .arg(
+            Arg::with_name("GAMEPAD_MAP")
+                .long("gamepad-map")
+                .takes_value(true)
+                .help("Comma-separated button=value pairs mapping gamepad buttons to keypad values. Defaults to \"up=2,down=8,left=4,right=6,south=5,east=0\"."),
+        )
+        .arg(
+            Arg::with_name("PALETTE")
+                .long("palette")
+                .takes_value(true)
+                .possible_values(&PALETTE_PRESETS)
+                .help("The colors used for the screen and the debugger"),
+        )
+        .arg(
+            Arg::with_name("FOREGROUND_COLOR")
+                .long("fg")
+                .takes_value(true)
+                .help("The color of lit pixels, as RRGGBB (overrides the palette)"),
+        )
+        .arg(
+            Arg::with_name("BACKGROUND_COLOR")
+                .long("bg")
+                .takes_value(true)
+                .help("The color of unlit pixels, as RRGGBB (overrides the palette)"),
+        )
+        .arg(
+            Arg::with_name("GHOSTING")
+                .long("ghosting")
+                .help("Let pixels fade out over a few frames, like the phosphor of a CRT, to reduce flicker"),
+        )
+        .arg(
+            Arg::with_name("CRT")
+                .long("crt")
+                .help("Render the screen with a CRT effect (scanlines, curvature and vignette)"),
+        )
+        .arg(
+            Arg::with_name("STATS")
+                .long("stats")
+                .help("Show the instructions per second, frame times and fast-forwarded cycles on top of the screen (toggle with F3)"),
+        )
+        .arg(
+            Arg::with_name("SCALE")
+                .long("scale")
+                .takes_value(true)
+                .help("The initial size of a Chip-8 pixel, in window pixels. Defaults to 8."),
+        )
+        .arg(
+            Arg::with_name("NO_ROM_DATABASE")
+                .long("no-rom-database")
+                .help("Don't use the recommended settings of known ROMs"),
+        )
+        .arg(
+            Arg::with_name("CONFIG_FILE")
+                .long("config")
+                .takes_value(true)
+                .help("A TOML file with default values for these options. Defaults to chip8.toml, if it exists."),
+        )
+        .arg(
+            Arg::with_name("STATE_FILE")
+                .long("load-state")
+                .takes_value(true)
+                .help("A save state (written with F5-F8 in the app) to start from"),
+        )
+        .arg(
+            Arg::with_name("TRACE_FILE")
+                .long("trace")
+                .takes_value(true)
+                .help("Write a line for each executed instruction to this file: the cycle, address, op-code, instruction and the registers it changed"),
+        )
+        .arg(
+            Arg::with_name("TRACE_FILTER")
+                .long("trace-filter")
+                .takes_value(true)
+                .requires("TRACE_FILE")
+                .possible_values(&TRACE_FILTERS)
+                .help("Only trace jumps, calls and returns (jumps), or instructions that write to VF (vf)"),
+        )
+        .arg(
+            Arg::with_name("WATCH")
+                .long("watch")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("A value to show in the debugger, like V3, delay, mem[I] or mem[0x3A0..0x3A4] (can be given several times)"),
+        )
+        .arg(
+            Arg::with_name("COVERAGE")
+                .long("coverage")
+                .help("Print which forms of instructions were executed, and how often, on exit"),
+        )
+        .arg(
+            Arg::with_name("RANDOM_RAM")
+                .long("random-ram")
+                .help("Fill the memory after the program with random bytes instead of zeros, like real hardware at power-on"),
+        )
+        .arg(
+            Arg::with_name("PROTECT_FONT")
+                .long("protect-font")
+                .takes_value(true)
+                .possible_values(&FONT_PROTECTION_MODES)
+                .help("Warn about (or stop on) writes by the program below 0x200, where the font is, which garble the digits it draws later"),
+        )
+        .arg(
+            Arg::with_name("FONT")
+                .long("font")
+                .takes_value(true)
+                .help("Use the font in the given file instead of the built-in one: 80 bytes for the 16 small digits, optionally followed by 160 bytes for the 8x10 digits of Fx30"),
+        )
+        .arg(
+            Arg::with_name("THREADED")
+                .long("threaded")
+                .help("Run the emulation on its own thread, so that its timing doesn't depend on the frame rate"),
+        )
+        .arg(
+            Arg::with_name("PAUSE_ON_FOCUS_LOSS")
+                .long("pause-on-focus-loss")
+                .help("Pause while the window doesn't have focus, and resume when it gets it back"),
+        )
+        .arg(
+            Arg::with_name("BACKGROUND_THROTTLE")
+                .long("background-throttle")
+                .help("Update only 10 times per second while the window doesn't have focus, and stop drawing while it's minimized, to save power"),
+        )
+        .arg(
+            Arg::with_name("AUTOSAVE")
+                .long("autosave")
+                .help("Save the state next to the ROM, ROM.autosave, when the window is closed or another ROM is loaded"),
+        )
+        .arg(
+            Arg::with_name("RESUME")
+                .long("resume")
+                .conflicts_with("STATE_FILE")
+                .help("Start from the ROM's autosave (and keep autosaving)"),
+        )
+        .arg(
+            Arg::with_name("PERSIST_FLAGS")
+                .long("persist-flags")
+                .help("Keep the SUPER-CHIP flags (Fx75/Fx85, where games save high scores) in a file next to the ROM, ROM.flags, between runs"),
+        )
+        .arg(
+            Arg::with_name("BATTERY")
+                .long("battery")
+                .help("Keep the top 256 bytes of memory (F00-FFF) in a save file next to the ROM, ROM.sav, between runs"),
+        )
+        .arg(
+            Arg::with_name("BATTERY_RANGE")
+                .long("battery-range")
+                .takes_value(true)
+                .help("Like --battery, but for another range of memory, like E00-FFF"),
+        )
+        .arg(
+            Arg::with_name("NETPLAY_HOST")
+                .long("netplay-host")
+                .takes_value(true)
+                .conflicts_with("NETPLAY_CONNECT")
+                .help("Let a second player connect over TCP on the given port and share the keypad, for two-player games"),
+        )
+        .arg(
+            Arg::with_name("NETPLAY_CONNECT")
+                .long("netplay-connect")
+                .takes_value(true)
+                .help("Join a game started with --netplay-host, given as HOST:PORT. The keypad is sent there, and its screen is shown here (the ROM is not run)"),
+        )
+        .arg(
+            Arg::with_name("SCRIPT")
+                .long("script")
+                .takes_value(true)
+                .help("Press keys as told by an input script, with lines like: at cycle 5000 press 4 for 10 frames (and optionally seed N, for the random number generator)"),
+        )
+        .arg(
+            Arg::with_name("KEY_COMMANDS")
+                .long("key-commands")
+                .takes_value(true)
+                .help("Press keys on commands like press 5 100ms, hold 5 or release 5, one per line, from stdin or TCP connections on a port (given as stdin or the port number)"),
+        )
+        .arg(
+            Arg::with_name("REPL")
+                .long("repl")
+                .help("Read assembly instructions from stdin and execute them (or patch them into memory, given as ADDR: instruction)"),
+        )
         .get_matches();
 
-    let filename = matches
-        .value_of("ROM_FILE")
-        .unwrap_or("programs/Space Invaders [David Winter].ch8")
-        .to_owned();
+    let config = match matches.value_of("CONFIG_FILE") {
+        Some(path) => {
+            let toml = fs::read_to_string(path)
+                .unwrap_or_else(|_| panic!("Couldn't read config file: {}", path));
+            Config::parse(&toml).unwrap_or_else(|err| panic!("{}: {}", path, err))
+        }
+        None => {
+            Config::load_if_exists(Path::new(CONFIG_FILE)).unwrap_or_else(|err| panic!("{}", err))
+        }
+    };
+
+    let filename = builtin_roms::filename_or_default(matches.value_of("ROM_FILE"));
 
     let clock_frequency = match matches.value_of("CLOCK_FREQUENCY") {
-        Some(freq) => match freq.parse::<u32>() {
-            Ok(freq) => Some(freq),
-            Err(err) => {
-                panic!("Invalid non-integer clock frequency: {} ({})", freq, err);
+        Some(freq) => Some(parse_clock_frequency(freq).unwrap_or_else(|err| panic!("{}", err))),
+        None => config.clock.inspect(|&freq| {
+            if !(freq.is_finite() && freq > 0.0) {
+                panic!("Invalid clock frequency in the config file: {}", freq);
             }
+        }),
+    };
+    let tickrate = match matches.value_of("TICKRATE") {
+        Some(tickrate) => match tickrate.parse::<u32>() {
+            Ok(tickrate) if tickrate > 0 => Some(tickrate),
+            _ => panic!("Invalid tickrate: {}", tickrate),
         },
+        None if matches.occurrences_of("CLOCK_FREQUENCY") > 0 => None,
+        None => config.tickrate,
+    };
+    let cycle_costs = matches.occurrences_of("CYCLE_COSTS") > 0 || config.cycle_costs == Some(true);
+    let sticky_keys = matches.occurrences_of("STICKY_KEYS") > 0 || config.sticky_keys == Some(true);
+    let start_address = matches
+        .value_of("START_ADDRESS")
+        .or(config.start_address.as_deref())
+        .map(|address| parse_start_address(address).unwrap_or_else(|err| panic!("{}", err)))
+        .unwrap_or(PROGRAM_START);
+    let display_height = matches
+        .value_of("DISPLAY_MODE")
+        .or(config.display_mode.as_deref())
+        .map(|mode| parse_display_mode(mode).unwrap_or_else(|err| panic!("{}", err)));
+    let memory_size = matches
+        .value_of("MEMORY_SIZE")
+        .or(config.memory_size.as_deref())
+        .map(|size| parse_memory_size(size).unwrap_or_else(|err| panic!("{}", err)));
+
+    let quirks = matches
+        .value_of("QUIRKS")
+        .or(config.quirks.as_deref())
+        .map(|name| Quirks::preset(name).unwrap_or_else(|err| panic!("{}", err)));
+
+    let font_protection = matches
+        .value_of("PROTECT_FONT")
+        .or(config.protect_font.as_deref())
+        .map(|mode| FontProtection::parse(mode).unwrap_or_else(|err| panic!("{}", err)));
+    let font = matches
+        .value_of("FONT")
+        .or(config.font.as_deref())
+        .map(|path| fs::read(path).unwrap_or_else(|_| panic!("Couldn't read font file: {}", path)));
+
+    let debug = matches.occurrences_of("DEBUG") > 0 || config.debug == Some(true);
+    let keymap = match matches.value_of("KEYMAP").or(config.keymap.as_deref()) {
+        Some(keys) => KeyMap::parse(keys).unwrap_or_else(|err| panic!("Invalid keymap: {}", err)),
+        None => KeyMap::default(),
+    };
+
+    let gamepad_map = match matches
+        .value_of("GAMEPAD_MAP")
+        .or(config.gamepad_map.as_deref())
+    {
+        Some(mapping) => {
+            GamepadMap::parse(mapping).unwrap_or_else(|err| panic!("Invalid gamepad map: {}", err))
+        }
+        None => GamepadMap::default(),
+    };
+
+    let mut palette = match matches.value_of("PALETTE").or(config.palette.as_deref()) {
+        Some(name) => Palette::preset(name).unwrap_or_else(|| {
+            panic!(
+                "Unknown palette: {} (expected one of {})",
+                name,
+                PALETTE_PRESETS.join(", ")
+            )
+        }),
+        None => Palette::default(),
+    };
+    if let Some(color) = matches
+        .value_of("FOREGROUND_COLOR")
+        .or(config.fg.as_deref())
+    {
+        palette.foreground =
+            parse_color(color).unwrap_or_else(|err| panic!("Invalid foreground color: {}", err));
+    }
+    if let Some(color) = matches
+        .value_of("BACKGROUND_COLOR")
+        .or(config.bg.as_deref())
+    {
+        palette.background =
+            parse_color(color).unwrap_or_else(|err| panic!("Invalid background color: {}", err));
+    }
+
+    let ghosting = matches.occurrences_of("GHOSTING") > 0 || config.ghosting == Some(true);
+    let crt = matches.occurrences_of("CRT") > 0 || config.crt == Some(true);
+    let stats = matches.occurrences_of("STATS") > 0 || config.stats == Some(true);
+    let threaded = matches.occurrences_of("THREADED") > 0 || config.threaded == Some(true);
+    let pause_on_focus_loss = matches.occurrences_of("PAUSE_ON_FOCUS_LOSS") > 0
+        || config.pause_on_focus_loss == Some(true);
+    let background_throttle = matches.occurrences_of("BACKGROUND_THROTTLE") > 0
+        || config.background_throttle == Some(true);
+    let random_ram = matches.occurrences_of("RANDOM_RAM") > 0 || config.random_ram == Some(true);
+    let resume = matches.occurrences_of("RESUME") > 0;
+    let autosave =
+        resume || matches.occurrences_of("AUTOSAVE") > 0 || config.autosave == Some(true);
+    let persist_flags =
+        matches.occurrences_of("PERSIST_FLAGS") > 0 || config.persist_flags == Some(true);
+    let battery = match matches
+        .value_of("BATTERY_RANGE")
+        .or(config.battery_range.as_deref())
+    {
+        Some(range) => Some(battery::parse_range(range).unwrap_or_else(|err| panic!("{}", err))),
+        None if matches.occurrences_of("BATTERY") > 0 || config.battery == Some(true) => {
+            Some(battery::DEFAULT_BATTERY_RANGE)
+        }
         None => None,
     };
 
-    let debug = matches.occurrences_of("DEBUG") > 0;
+    let scale = match matches.value_of("SCALE") {
+        Some(scale) => Some(
+            scale
+                .parse::<f32>()
+                .unwrap_or_else(|err| panic!("Invalid scale: {} ({})", scale, err)),
+        ),
+        None => config.scale,
+    };
+
+    let patch_file = matches.value_of("PATCH_FILE").map(|s| s.to_owned());
+    let state_file = matches.value_of("STATE_FILE").map(|s| s.to_owned());
+    let trace = matches.value_of("TRACE_FILE").map(|trace_file| {
+        let filter = matches.value_of("TRACE_FILTER").unwrap_or("all");
+        let filter = TraceFilter::parse(filter).unwrap_or_else(|err| panic!("{}", err));
+        (trace_file.to_owned(), filter)
+    });
 
-    (filename, clock_frequency, debug)
+    let watch_sources: Vec<&str> = match matches.values_of("WATCH") {
+        Some(sources) => sources.collect(),
+        None => config.watches.iter().map(String::as_str).collect(),
+    };
+    let watches = watch_sources
+        .into_iter()
+        .map(|source| WatchExpression::parse(source).unwrap_or_else(|err| panic!("{}", err)))
+        .collect();
+
+    let repl = matches.occurrences_of("REPL") > 0;
+    let netplay_host = matches.value_of("NETPLAY_HOST").map(|port| {
+        port.parse::<u16>()
+            .unwrap_or_else(|err| panic!("Invalid port: {} ({})", port, err))
+    });
+    let netplay_connect = matches.value_of("NETPLAY_CONNECT").map(|s| s.to_owned());
+    let key_commands = matches.value_of("KEY_COMMANDS").map(|s| s.to_owned());
+    let script_file = matches.value_of("SCRIPT").map(|s| s.to_owned());
+    let compare_rom = matches.value_of("COMPARE_ROM").map(|s| s.to_owned());
+    let compare_quirks = matches.value_of("COMPARE_QUIRKS").map(|s| s.to_owned());
+    if repl && key_commands.as_deref() == Some("stdin") {
+        panic!("--repl and --key-commands stdin can't both read from stdin");
+    }
+    let coverage = matches.occurrences_of("COVERAGE") > 0;
+
+    let rom_database =
+        if matches.occurrences_of("NO_ROM_DATABASE") > 0 || config.rom_database == Some(false) {
+            None
+        } else {
+            Some(RomDatabase::builtin())
+        };
+
+    let options = app::Options {
+        debug,
+        keymap,
+        gamepad_map,
+        palette,
+        ghosting,
+        crt,
+        stats,
+        scale,
+        rom_database,
+        repl: None,
+        watches,
+        coverage,
+        threaded,
+        pause_on_focus_loss,
+        background_throttle,
+        random_ram,
+        autosave,
+        persist_flags,
+        battery,
+        start_address,
+        display_height,
+        memory_size,
+        font,
+        netplay: None,
+        key_injector: None,
+        script: Vec::new(),
+        second_machine: None,
+    };
+
+    Args {
+        filename,
+        patch_file,
+        clock_frequency,
+        tickrate,
+        cycle_costs,
+        sticky_keys,
+        quirks,
+        font_protection,
+        state_file,
+        resume,
+        trace,
+        repl,
+        netplay_host,
+        netplay_connect,
+        key_commands,
+        script_file,
+        compare_rom,
+        compare_quirks,
+        options,
+    }
 }
 
-fn setup_chip8(filename: &str) -> (Chip8, Vec<String>) {
-    let mut f = File::open(filename).expect(&format!("Couldn't open ROM file: {}", filename));
-    let mut buffer = Vec::new();
-    f.read_to_end(&mut buffer)
-        .expect(&format!("Couldn't read from ROM file: {}", filename));
-    let mut memory = [0; 0x1000];
-    for i in 0..buffer.len() {
-        memory[0x200 + i] = buffer[i];
+fn setup_chip8(
+    filename: &str,
+    patch_file: Option<String>,
+    start_address: u16,
+    rom_database: Option<&RomDatabase>,
+) -> (Chip8, Vec<String>, Option<RomInfo>) {
+    let mut buffer =
+        builtin_roms::read_rom(Path::new(filename)).unwrap_or_else(|err| panic!("{}", err));
+    // The ROM database knows the original ROM, and its recommendations still apply after patching
+    let rom_info = rom_database.and_then(|database| database.lookup(&buffer).cloned());
+    let patch_file = patch_file
+        .map(PathBuf::from)
+        .or_else(|| patch::sidecar(Path::new(filename)));
+    if let Some(patch_file) = patch_file {
+        buffer = patch::apply_file(&buffer, &patch_file).unwrap_or_else(|err| panic!("{}", err));
+        println!("Applied patch {}", patch_file.display());
     }
+    let chip8 = Chip8::from_rom_at(&buffer, start_address)
+        .unwrap_or_else(|err| panic!("{}: {}", filename, err));
+    let disassembled_program = assembly::disassemble_rom_at(buffer, start_address);
+    (chip8, disassembled_program, rom_info)
+}
 
-    let disassembled_program = assembly::disassemble_rom(buffer);
+/// The machine that runs next to the main one: another ROM (with its own recommended quirks,
+/// unless quirks were given), or the same ROM with other quirks, at the same speed and with the
+/// same random numbers
+fn setup_second_machine(
+    main: &Chip8,
+    filename: &str,
+    compare_rom: Option<String>,
+    compare_quirks: Option<String>,
+    given_quirks: Option<Quirks>,
+    seed: Option<u64>,
+    options: &app::Options,
+) -> SecondMachine {
+    let second_filename = compare_rom.unwrap_or_else(|| filename.to_owned());
+    let (mut chip8, _, rom_info) = setup_chip8(
+        &second_filename,
+        None,
+        options.start_address,
+        options.rom_database.as_ref(),
+    );
+    let quirks = match compare_quirks {
+        Some(name) => Quirks::preset(&name).unwrap_or_else(|err| panic!("{}", err)),
+        None => given_quirks
+            .or_else(|| rom_info.as_ref().and_then(RomInfo::recommended_quirks))
+            .unwrap_or_default(),
+    };
+    chip8.set_quirks(quirks);
+    chip8.set_font_protection(main.font_protection());
+    if let Some(font) = &options.font {
+        chip8.set_font(font).unwrap();
+    }
+    if let Some(height) = options.display_height {
+        chip8.set_display_height(height).unwrap();
+    }
+    if let Some(size) = options.memory_size {
+        chip8
+            .set_memory_size(size.max(chip8.memory().len()))
+            .unwrap();
+    }
+    chip8.set_clock_frequency(main.clock_frequency());
+    chip8.set_timing(main.timing());
+    chip8.set_cycle_costs(main.cycle_costs());
+    chip8.set_sticky_keys(main.sticky_keys());
+    if let Some(seed) = seed {
+        chip8.set_random(Random::seeded(seed));
+    }
+    if options.random_ram {
+        chip8.randomize_free_memory();
+    }
+    let labels = [
+        format!("{} ({})", filename, quirks_label(main.quirks())),
+        format!("{} ({})", second_filename, quirks_label(quirks)),
+    ];
+    println!("Running {} side by side", labels[1]);
+    SecondMachine::new(chip8, labels)
+}
 
-    for i in 0..FONT_SPRITES.len() {
-        memory[i] = FONT_SPRITES[i];
+/// The name of the preset that the quirks match, for labeling a machine
+fn quirks_label(quirks: Quirks) -> String {
+    match QUIRKS_PRESETS
+        .iter()
+        .find(|&&name| Quirks::preset(name) == Ok(quirks))
+    {
+        Some(name) => format!("{} quirks", name),
+        None => "custom quirks".to_owned(),
     }
-    (Chip8::new(memory), disassembled_program)
 }
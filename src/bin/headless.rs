@@ -0,0 +1,583 @@
+use chip_8_rs::assembly;
+use chip_8_rs::builtin_roms;
+use chip_8_rs::capture::RunCapture;
+use chip_8_rs::chip8::{
+    parse_clock_frequency, parse_display_mode, parse_memory_size, parse_start_address, Chip8,
+    FontProtection, Quirks, Random, FONT_PROTECTION_MODES, PROGRAM_START, QUIRKS_PRESETS,
+};
+use chip_8_rs::crash_dump;
+use chip_8_rs::input_script::InputScript;
+use chip_8_rs::lockstep::Lockstep;
+use chip_8_rs::logging;
+use chip_8_rs::patch;
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::process;
+
+use clap::{App, Arg};
+
+/// A key press or release that happens before the given cycle
+struct KeyEvent {
+    cycle: u64,
+    key: u8,
+    pressed: bool,
+}
+
+fn main() {
+    logging::init();
+    let matches = App::new("Chip-8 headless runner")
+        .version("0.1.0")
+        .about("Runs a Chip-8 program without a window, and reports the final display and a hash of the machine state.")
+        .arg(
+            Arg::with_name("ROM_FILE")
+                .short("f")
+                .long("file")
+                .visible_alias("rom")
+                .takes_value(true)
+                .required(true)
+                .help("A file containing the program that will be run (or builtin:NAME)"),
+        )
+        .arg(
+            Arg::with_name("PATCH_FILE")
+                .long("patch")
+                .takes_value(true)
+                .help("An IPS or BPS patch to apply to the ROM"),
+        )
+        .arg(
+            Arg::with_name("CLOCK_FREQUENCY")
+                .short("c")
+                .long("clock")
+                .takes_value(true)
+                .help("The number of instructions to be executed by Chip-8 per second"),
+        )
+        .arg(
+            Arg::with_name("TICKRATE")
+                .long("tickrate")
+                .takes_value(true)
+                .conflicts_with("CLOCK_FREQUENCY")
+                .help("Execute exactly this many instructions in every 60Hz frame, like Octo"),
+        )
+        .arg(
+            Arg::with_name("CYCLE_COSTS")
+                .long("cycle-costs")
+                .conflicts_with("TICKRATE")
+                .help("Make each instruction take about as long as it did on the COSMAC VIP"),
+        )
+        .arg(
+            Arg::with_name("STICKY_KEYS")
+                .long("sticky-keys")
+                .help("Keep a quick key press until the program checks that key (with Ex9E or ExA1)"),
+        )
+        .arg(
+            Arg::with_name("START_ADDRESS")
+                .long("start-address")
+                .takes_value(true)
+                .help("Where the ROM is loaded and starts running, in hex (by default 200, or eti660 for 600)"),
+        )
+        .arg(
+            Arg::with_name("DISPLAY_MODE")
+                .long("display-mode")
+                .takes_value(true)
+                .help("The size of the display: 64x32, 64x48 (ETI 660) or 64x64 (hires, which is picked automatically for hires programs)"),
+        )
+        .arg(
+            Arg::with_name("MEMORY_SIZE")
+                .long("memory-size")
+                .takes_value(true)
+                .help("The size of memory in hex, from 1000 to 10000 (XO-CHIP, which ROMs that don't fit in 4K get anyway)"),
+        )
+        .arg(
+            Arg::with_name("QUIRKS")
+                .long("quirks")
+                .takes_value(true)
+                .possible_values(&QUIRKS_PRESETS)
+                .help("How to handle the instructions that differ between interpreters"),
+        )
+        .arg(
+            Arg::with_name("COMPARE_QUIRKS")
+                .long("compare-quirks")
+                .takes_value(true)
+                .possible_values(&QUIRKS_PRESETS)
+                .help("Also run a machine with these quirks, and report the first instruction after which the two machines differ"),
+        )
+        .arg(
+            Arg::with_name("CYCLES")
+                .long("cycles")
+                .takes_value(true)
+                .conflicts_with("SECONDS")
+                .help("The number of instructions to execute"),
+        )
+        .arg(
+            Arg::with_name("SECONDS")
+                .long("seconds")
+                .takes_value(true)
+                .help("How long to run, in emulated seconds (the default is 1)"),
+        )
+        .arg(
+            Arg::with_name("KEY_EVENT")
+                .long("key-event")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("A key press or release, as CYCLE:KEY:down or CYCLE:KEY:up (e.g. 500:5:down)"),
+        )
+        .arg(
+            Arg::with_name("SCRIPT")
+                .long("script")
+                .takes_value(true)
+                .help("An input script, with lines like: at cycle 5000 press 4 for 10 frames (and optionally seed N, for the random number generator)"),
+        )
+        .arg(
+            Arg::with_name("RANDOM_RAM")
+                .long("random-ram")
+                .help("Fill the memory after the program with random bytes instead of zeros (seeded like the random number generator)"),
+        )
+        .arg(
+            Arg::with_name("PROTECT_FONT")
+                .long("protect-font")
+                .takes_value(true)
+                .possible_values(&FONT_PROTECTION_MODES)
+                .help("Warn about (or stop on) writes by the program below 0x200, where the font is"),
+        )
+        .arg(
+            Arg::with_name("FONT")
+                .long("font")
+                .takes_value(true)
+                .help("Use the font in the given file instead of the built-in one (80 bytes, or 240 with the 8x10 digits)"),
+        )
+        .arg(
+            Arg::with_name("TEXT")
+                .long("text")
+                .help("Print the final display as text"),
+        )
+        .arg(
+            Arg::with_name("PNG_FILE")
+                .long("png")
+                .takes_value(true)
+                .help("Save the final display to a PNG file"),
+        )
+        .arg(
+            Arg::with_name("PBM_FILE")
+                .long("pbm")
+                .takes_value(true)
+                .help("Save the final display to a PBM file (lit pixels are black)"),
+        )
+        .arg(
+            Arg::with_name("VIDEO_FILE")
+                .long("record-video")
+                .takes_value(true)
+                .help("Save every emulated frame (a 60th of a second) to an animated GIF"),
+        )
+        .arg(
+            Arg::with_name("AUDIO_FILE")
+                .long("record-audio")
+                .takes_value(true)
+                .help("Save the buzzer of every emulated frame to a WAV file"),
+        )
+        .arg(
+            Arg::with_name("COVERAGE")
+                .long("coverage")
+                .help("Print which forms of instructions were executed, and how often"),
+        )
+        .arg(
+            Arg::with_name("PROFILE_FILE")
+                .long("profile")
+                .takes_value(true)
+                .help("Write the instructions executed in each stack of subroutine calls to this file, as folded stacks for flamegraph.pl or inferno"),
+        )
+        .arg(
+            Arg::with_name("FRAME_DIRECTORY")
+                .long("dump-frames")
+                .takes_value(true)
+                .help("Save the display after every emulated frame (or every Nth, with --dump-every) to this directory, as cycle_CYCLE.png"),
+        )
+        .arg(
+            Arg::with_name("DUMP_EVERY")
+                .long("dump-every")
+                .takes_value(true)
+                .requires("FRAME_DIRECTORY")
+                .help("Only dump every Nth frame"),
+        )
+        .arg(
+            Arg::with_name("DUMP_FORMAT")
+                .long("dump-format")
+                .takes_value(true)
+                .possible_values(&["png", "pbm"])
+                .requires("FRAME_DIRECTORY")
+                .help("The format of the dumped frames (png if not given)"),
+        )
+        .arg(
+            Arg::with_name("UNINITIALIZED_READS")
+                .long("uninitialized-reads")
+                .help("Report reads of memory that the program was neither loaded into nor has written"),
+        )
+        .arg(
+            Arg::with_name("HASH_LOG")
+                .long("hash-log")
+                .takes_value(true)
+                .help("Write the state hash after every emulated frame (a 60th of a second) to this file"),
+        )
+        .arg(
+            Arg::with_name("PRINT_HASH")
+                .long("print-hash")
+                .conflicts_with("EXPECTED_HASH")
+                .help("Only print a hash of the final display and registers (see Chip8::screen_hash), for comparing runs of different builds"),
+        )
+        .arg(
+            Arg::with_name("EXPECTED_HASH")
+                .long("expect-hash")
+                .takes_value(true)
+                .help("Exit with status 1 unless the final state hash is this (in hex)"),
+        )
+        .get_matches();
+
+    let filename = matches.value_of("ROM_FILE").expect("ROM file");
+    let mut rom =
+        builtin_roms::read_rom(Path::new(filename)).unwrap_or_else(|err| exit_with_error(&err));
+    if let Some(patch_file) = matches.value_of("PATCH_FILE") {
+        rom = patch::apply_file(&rom, Path::new(patch_file))
+            .unwrap_or_else(|err| exit_with_error(&err));
+    }
+    let start_address = matches
+        .value_of("START_ADDRESS")
+        .map(|address| parse_start_address(address).unwrap_or_else(|err| exit_with_error(&err)))
+        .unwrap_or(PROGRAM_START);
+    let mut chip8 = Chip8::from_rom_at(&rom, start_address)
+        .unwrap_or_else(|err| exit_with_error(&format!("{}: {}", filename, err)));
+    if let Some(mode) = matches.value_of("DISPLAY_MODE") {
+        let height = parse_display_mode(mode).unwrap_or_else(|err| exit_with_error(&err));
+        chip8.set_display_height(height).unwrap();
+    }
+    if let Some(size) = matches.value_of("MEMORY_SIZE") {
+        let size = parse_memory_size(size).unwrap_or_else(|err| exit_with_error(&err));
+        chip8
+            .set_memory_size(size.max(chip8.memory().len()))
+            .unwrap();
+    }
+    if let Some(tickrate) = matches.value_of("TICKRATE") {
+        let tickrate = tickrate
+            .parse::<u32>()
+            .ok()
+            .filter(|&tickrate| tickrate > 0)
+            .unwrap_or_else(|| exit_with_error(&format!("Invalid tickrate: {}", tickrate)));
+        chip8.set_tickrate(tickrate);
+    }
+    if let Some(freq) = matches.value_of("CLOCK_FREQUENCY") {
+        let freq = parse_clock_frequency(freq).unwrap_or_else(|err| exit_with_error(&err));
+        chip8.set_clock_frequency(freq);
+    }
+    chip8.set_cycle_costs(matches.is_present("CYCLE_COSTS"));
+    chip8.set_sticky_keys(matches.is_present("STICKY_KEYS"));
+    if let Some(name) = matches.value_of("QUIRKS") {
+        chip8.set_quirks(Quirks::preset(name).unwrap_or_else(|err| exit_with_error(&err)));
+    }
+    if let Some(mode) = matches.value_of("PROTECT_FONT") {
+        let protection = FontProtection::parse(mode).unwrap_or_else(|err| exit_with_error(&err));
+        chip8.set_font_protection(Some(protection));
+    }
+    let font = matches.value_of("FONT").map(|path| {
+        let font = fs::read(path).unwrap_or_else(|err| {
+            exit_with_error(&format!("Couldn't read font {}: {}", path, err))
+        });
+        chip8
+            .set_font(&font)
+            .unwrap_or_else(|err| exit_with_error(&format!("{}: {}", path, err)));
+        font
+    });
+
+    let cycles = match (matches.value_of("CYCLES"), matches.value_of("SECONDS")) {
+        (Some(cycles), _) => cycles
+            .parse::<u64>()
+            .unwrap_or_else(|_| exit_with_error(&format!("Invalid number of cycles: {}", cycles))),
+        (None, seconds) => {
+            let seconds = seconds.unwrap_or("1");
+            let seconds = seconds.parse::<f64>().unwrap_or_else(|_| {
+                exit_with_error(&format!("Invalid number of seconds: {}", seconds))
+            });
+            (seconds * chip8.clock_frequency()) as u64
+        }
+    };
+
+    let mut key_events: Vec<KeyEvent> = matches
+        .values_of("KEY_EVENT")
+        .map(|events| {
+            events
+                .map(|event| parse_key_event(event).unwrap_or_else(|err| exit_with_error(&err)))
+                .collect()
+        })
+        .unwrap_or_default();
+    let cycles_per_frame = chip8.cycles_per_frame() as u64;
+    let mut seed = None;
+    if let Some(path) = matches.value_of("SCRIPT") {
+        let source = fs::read_to_string(path)
+            .unwrap_or_else(|err| exit_with_error(&format!("Couldn't read {}: {}", path, err)));
+        let script = InputScript::parse(&source)
+            .unwrap_or_else(|err| exit_with_error(&format!("{}: {}", path, err)));
+        key_events.extend(
+            script
+                .events(cycles_per_frame)
+                .into_iter()
+                .map(|event| KeyEvent {
+                    cycle: event.cycle,
+                    key: event.key,
+                    pressed: event.pressed,
+                }),
+        );
+        seed = script.seed;
+    }
+    if let Some(seed) = seed {
+        chip8.set_random(Random::seeded(seed));
+    }
+    if matches.is_present("RANDOM_RAM") {
+        chip8.randomize_free_memory();
+    }
+    key_events.sort_by_key(|event| event.cycle);
+
+    if let Some(name) = matches.value_of("COMPARE_QUIRKS") {
+        let quirks = Quirks::preset(name).unwrap_or_else(|err| exit_with_error(&err));
+        let mut lockstep = Lockstep::new_at(&rom, start_address, chip8.quirks(), quirks)
+            .unwrap_or_else(|err| exit_with_error(&format!("{}: {}", filename, err)));
+        for machine in [&mut lockstep.first, &mut lockstep.second] {
+            machine.set_clock_frequency(chip8.clock_frequency());
+            machine.set_timing(chip8.timing());
+            machine.set_cycle_costs(chip8.cycle_costs());
+            machine.set_sticky_keys(chip8.sticky_keys());
+            machine.set_font_protection(chip8.font_protection());
+            if let Some(font) = &font {
+                machine.set_font(font).unwrap();
+            }
+            machine
+                .set_display_height(chip8.display_buffer.height())
+                .unwrap();
+            if let Some(seed) = seed {
+                machine.set_random(Random::seeded(seed));
+            }
+            if matches.is_present("RANDOM_RAM") {
+                machine.randomize_free_memory();
+            }
+        }
+        compare(lockstep, cycles, key_events);
+    }
+
+    let mut hash_log = matches.value_of("HASH_LOG").map(|path| {
+        let file = File::create(path)
+            .unwrap_or_else(|err| exit_with_error(&format!("Couldn't create {}: {}", path, err)));
+        (path, BufWriter::new(file))
+    });
+
+    let video_file = matches.value_of("VIDEO_FILE");
+    if let Some(path) = video_file {
+        if !path.to_lowercase().ends_with(".gif") {
+            exit_with_error(&format!("Only GIF videos are supported: {}", path));
+        }
+    }
+    let mut capture =
+        (video_file.is_some() || matches.is_present("AUDIO_FILE")).then(RunCapture::default);
+
+    if matches.is_present("PROFILE_FILE") {
+        chip8.profile_calls(true);
+    }
+    if matches.is_present("UNINITIALIZED_READS") {
+        chip8.detect_uninitialized_reads(true);
+    }
+
+    let frame_dump = matches.value_of("FRAME_DIRECTORY").map(|directory| {
+        fs::create_dir_all(directory).unwrap_or_else(|err| {
+            exit_with_error(&format!("Couldn't create {}: {}", directory, err))
+        });
+        let every = matches.value_of("DUMP_EVERY").map_or(1, |every| {
+            every
+                .parse::<u64>()
+                .ok()
+                .filter(|&every| every > 0)
+                .unwrap_or_else(|| exit_with_error(&format!("Invalid frame interval: {}", every)))
+        });
+        let format = matches.value_of("DUMP_FORMAT").unwrap_or("png");
+        (Path::new(directory), every * cycles_per_frame, format)
+    });
+
+    let mut key_events = key_events.into_iter().peekable();
+    for cycle in 0..cycles {
+        while let Some(event) = key_events.next_if(|event| event.cycle <= cycle) {
+            chip8.handle_key_event(event.key, event.pressed);
+        }
+        if let Err(err) = chip8.step_instruction() {
+            match crash_dump::write(&chip8, Path::new(filename), &err) {
+                Ok(dump) => eprintln!("Wrote a crash dump to {}", dump.report_path.display()),
+                Err(dump_err) => eprintln!("{}", dump_err),
+            }
+            exit_with_error(&format!("Error after {} cycles: {}", cycle, err));
+        }
+        if let Some(capture) = &mut capture {
+            if (cycle + 1) % cycles_per_frame == 0 {
+                capture.capture_frame(&chip8);
+            }
+        }
+        if let Some((directory, interval, format)) = frame_dump {
+            if (cycle + 1) % interval == 0 {
+                let path = directory.join(format!("cycle_{:010}.{}", cycle + 1, format));
+                dump_frame(&chip8, &path, format).unwrap_or_else(|err| exit_with_error(&err));
+            }
+        }
+        if let Some((path, writer)) = &mut hash_log {
+            if (cycle + 1) % cycles_per_frame == 0 {
+                let frame = (cycle + 1) / cycles_per_frame;
+                writeln!(writer, "{} {:016x}", frame, chip8.state_hash()).unwrap_or_else(|err| {
+                    exit_with_error(&format!("Couldn't write {}: {}", path, err))
+                });
+            }
+        }
+    }
+    if let Some((path, mut writer)) = hash_log {
+        writer
+            .flush()
+            .unwrap_or_else(|err| exit_with_error(&format!("Couldn't write {}: {}", path, err)));
+    }
+
+    if matches.occurrences_of("TEXT") > 0 {
+        println!("{:?}", chip8.display_buffer);
+    }
+    if let Some(path) = matches.value_of("PNG_FILE") {
+        chip8
+            .display_buffer
+            .write_png(Path::new(path))
+            .unwrap_or_else(|err| exit_with_error(&err));
+    }
+    if let Some(path) = matches.value_of("PBM_FILE") {
+        File::create(path)
+            .and_then(|file| {
+                let mut writer = BufWriter::new(file);
+                chip8.display_buffer.write_pbm(&mut writer)?;
+                writer.flush()
+            })
+            .unwrap_or_else(|err| exit_with_error(&format!("Couldn't write {}: {}", path, err)));
+    }
+
+    if let Some(capture) = &capture {
+        if let Some(path) = video_file {
+            capture
+                .write_gif(Path::new(path))
+                .unwrap_or_else(|err| exit_with_error(&err));
+        }
+        if let Some(path) = matches.value_of("AUDIO_FILE") {
+            File::create(path)
+                .and_then(|file| {
+                    let mut writer = BufWriter::new(file);
+                    capture.write_wav(&mut writer)?;
+                    writer.flush()
+                })
+                .unwrap_or_else(|err| {
+                    exit_with_error(&format!("Couldn't write {}: {}", path, err))
+                });
+        }
+    }
+
+    if matches.occurrences_of("COVERAGE") > 0 {
+        print!("{}", chip8.opcode_coverage().summary());
+    }
+    for read in chip8.uninitialized_reads() {
+        println!(
+            "Read of uninitialized memory at {:#05X} by the instruction at {:#05X}",
+            read.address, read.program_counter
+        );
+    }
+    if let (Some(path), Some(profile)) = (matches.value_of("PROFILE_FILE"), chip8.call_profile()) {
+        File::create(path)
+            .and_then(|file| {
+                let mut writer = BufWriter::new(file);
+                profile.write_folded(&mut writer)?;
+                writer.flush()
+            })
+            .unwrap_or_else(|err| exit_with_error(&format!("Couldn't write {}: {}", path, err)));
+    }
+
+    if matches.is_present("PRINT_HASH") {
+        println!("{:016x}", chip8.screen_hash());
+        return;
+    }
+    let hash = chip8.state_hash();
+    println!("Ran {} cycles, state hash: {:016x}", cycles, hash);
+    if let Some(expected) = matches.value_of("EXPECTED_HASH") {
+        let expected = u64::from_str_radix(expected, 16)
+            .unwrap_or_else(|_| exit_with_error(&format!("Invalid hash: {}", expected)));
+        if hash != expected {
+            println!("Expected state hash: {:016x}", expected);
+            process::exit(1);
+        }
+    }
+}
+
+/// Saves the display as a PNG or PBM file
+fn dump_frame(chip8: &Chip8, path: &Path, format: &str) -> Result<(), String> {
+    if format == "png" {
+        return chip8.display_buffer.write_png(path);
+    }
+    File::create(path)
+        .and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            chip8.display_buffer.write_pbm(&mut writer)?;
+            writer.flush()
+        })
+        .map_err(|err| format!("Couldn't write {}: {}", path.display(), err))
+}
+
+/// Runs both machines and exits, with status 1 if they diverge
+fn compare(mut lockstep: Lockstep, cycles: u64, key_events: Vec<KeyEvent>) -> ! {
+    let mut key_events = key_events.into_iter().peekable();
+    for cycle in 0..cycles {
+        while let Some(event) = key_events.next_if(|event| event.cycle <= cycle) {
+            lockstep.handle_key_event(event.key, event.pressed);
+        }
+        match lockstep.step() {
+            Ok(None) => {}
+            Ok(Some(divergence)) => {
+                let memory = lockstep.first.memory();
+                let address = divergence.address as usize;
+                let opcode = u16::from_be_bytes([memory[address], memory[address + 1]]);
+                let instruction = assembly::disassemble_opcode(opcode)
+                    .unwrap_or_else(|_| format!("(data: {:04X})", opcode));
+                println!(
+                    "The machines diverged after {} cycles, at {:#05X}: {}",
+                    divergence.cycle, divergence.address, instruction
+                );
+                for difference in divergence.differences {
+                    println!("  {}", difference);
+                }
+                process::exit(1);
+            }
+            Err(err) => exit_with_error(&format!("Error after {} cycles: {}", cycle, err)),
+        }
+    }
+    println!("The machines didn't diverge in {} cycles", cycles);
+    process::exit(0)
+}
+
+fn parse_key_event(s: &str) -> Result<KeyEvent, String> {
+    let parts: Vec<&str> = s.split(':').collect();
+    let invalid = || format!("Expected a key event like 500:5:down, but got: {}", s);
+    if parts.len() != 3 {
+        return Err(invalid());
+    }
+    let cycle = parts[0].parse().map_err(|_| invalid())?;
+    let key = u8::from_str_radix(parts[1], 16)
+        .ok()
+        .filter(|&key| key < 16)
+        .ok_or_else(invalid)?;
+    let pressed = match parts[2] {
+        "down" => true,
+        "up" => false,
+        _ => return Err(invalid()),
+    };
+    Ok(KeyEvent {
+        cycle,
+        key,
+        pressed,
+    })
+}
+
+fn exit_with_error(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(2)
+}
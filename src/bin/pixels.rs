@@ -0,0 +1,82 @@
+use chip_8_rs::builtin_roms;
+use chip_8_rs::chip8::{parse_clock_frequency, Chip8};
+use chip_8_rs::frontend::{keymap_keys, Frontend, DEFAULT_KEYMAP};
+use chip_8_rs::logging;
+use chip_8_rs::pixels_frontend::PixelsFrontend;
+
+use std::path::Path;
+
+use clap::{App, Arg};
+
+fn main() {
+    logging::init();
+    let (filename, clock_frequency, keymap, debug_window) = parse_args();
+
+    let rom = builtin_roms::read_rom(Path::new(&filename)).unwrap_or_else(|err| panic!("{}", err));
+    let mut chip8 = Chip8::from_rom(&rom).unwrap_or_else(|err| panic!("{}: {}", filename, err));
+    if let Some(freq) = clock_frequency {
+        chip8.set_clock_frequency(freq);
+    }
+
+    let frontend = PixelsFrontend {
+        window_title: filename,
+        keymap,
+        foreground: [255, 255, 255, 255],
+        background: [0, 0, 0, 255],
+        debug_window,
+    };
+    frontend.run(chip8).expect("Run window");
+}
+
+fn parse_args() -> (String, Option<f64>, Vec<char>, bool) {
+    let matches = App::new("Chip-8 emulator (winit + pixels)")
+        .version("0.1.0")
+        .about("Runs a Chip-8 program in a plain window, without ggez.")
+        .arg(
+            Arg::with_name("ROM_FILE")
+                .short("f")
+                .long("file")
+                .visible_alias("rom")
+                .takes_value(true)
+                .help("A file containing the program that will be run (or builtin:NAME)"),
+        )
+        .arg(
+            Arg::with_name("CLOCK_FREQUENCY")
+                .short("c")
+                .long("clock")
+                .takes_value(true)
+                .help("The number of instructions to be executed by Chip-8 per second"),
+        )
+        .arg(
+            Arg::with_name("KEYMAP")
+                .short("k")
+                .long("keymap")
+                .takes_value(true)
+                .help("The 16 keyboard keys that map to the Chip-8 keypad values 0 to F (in that order). Or a keyboard layout: qwerty (the default, \"x123qweasdzc4rfv\"), qwertz, azerty, dvorak or colemak."),
+        )
+        .arg(
+            Arg::with_name("DEBUG")
+                .short("d")
+                .long("debug")
+                .help("Show the registers, the instructions around PC and the memory at I in a second window"),
+        )
+        .get_matches();
+
+    let filename = builtin_roms::filename_or_default(matches.value_of("ROM_FILE"));
+
+    let clock_frequency = matches
+        .value_of("CLOCK_FREQUENCY")
+        .map(|freq| parse_clock_frequency(freq).unwrap_or_else(|err| panic!("{}", err)));
+
+    let keymap: Vec<char> = keymap_keys(matches.value_of("KEYMAP").unwrap_or(DEFAULT_KEYMAP))
+        .to_lowercase()
+        .chars()
+        .collect();
+    if keymap.len() != 16 {
+        panic!("Invalid keymap: expected 16 keys, but got {}", keymap.len());
+    }
+
+    let debug_window = matches.occurrences_of("DEBUG") > 0;
+
+    (filename, clock_frequency, keymap, debug_window)
+}
@@ -0,0 +1,107 @@
+use chip_8_rs::chip8::Quirks;
+use chip_8_rs::snapshot;
+
+use serde::Deserialize;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use clap::{App, Arg};
+
+const DEFAULT_MANIFEST: &str = "resources/test_roms.toml";
+
+#[derive(Deserialize)]
+struct Manifest {
+    #[serde(rename = "test")]
+    tests: Vec<TestRom>,
+}
+
+/// A ROM that draws its results, and the screen it should end up with
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TestRom {
+    name: String,
+    rom: PathBuf,
+    cycles: u64,
+    /// A text (or PNG) file with the expected screen
+    screen: PathBuf,
+    quirks: Option<String>,
+}
+
+enum Outcome {
+    Pass,
+    Fail(String),
+    Skipped(String),
+}
+
+fn main() {
+    let matches = App::new("Chip-8 test runner")
+        .version("0.1.0")
+        .about("Runs test ROMs without a window, and compares their final screens against the expected ones.")
+        .arg(
+            Arg::with_name("MANIFEST")
+                .long("manifest")
+                .takes_value(true)
+                .help("A TOML file listing the tests. Defaults to resources/test_roms.toml."),
+        )
+        .arg(
+            Arg::with_name("BLESS")
+                .long("bless")
+                .help("Write the actual screens as the expected ones, instead of comparing them (also turned on by CHIP8_BLESS=1)"),
+        )
+        .arg(
+            Arg::with_name("FILTER")
+                .help("Only run the tests whose names contain this"),
+        )
+        .get_matches();
+
+    let manifest_path = matches.value_of("MANIFEST").unwrap_or(DEFAULT_MANIFEST);
+    let manifest = fs::read_to_string(manifest_path)
+        .unwrap_or_else(|_| panic!("Couldn't read test manifest: {}", manifest_path));
+    let manifest: Manifest =
+        toml::from_str(&manifest).unwrap_or_else(|err| panic!("{}: {}", manifest_path, err));
+    let bless = matches.occurrences_of("BLESS") > 0 || snapshot::bless_mode();
+    let filter = matches.value_of("FILTER").unwrap_or_default();
+
+    let mut failures = 0;
+    for test in manifest
+        .tests
+        .iter()
+        .filter(|test| test.name.contains(filter))
+    {
+        match run_test(test, bless) {
+            Outcome::Pass => println!("PASS  {}", test.name),
+            Outcome::Fail(reason) => {
+                failures += 1;
+                println!("FAIL  {}: {}", test.name, reason);
+            }
+            Outcome::Skipped(reason) => println!("SKIP  {}: {}", test.name, reason),
+        }
+    }
+    if failures > 0 {
+        println!("{} test(s) failed", failures);
+        process::exit(1);
+    }
+}
+
+fn run_test(test: &TestRom, bless: bool) -> Outcome {
+    let rom = match fs::read(&test.rom) {
+        Ok(rom) => rom,
+        Err(_) => return Outcome::Skipped(format!("{} not found", test.rom.display())),
+    };
+    let quirks = match &test.quirks {
+        Some(name) => match Quirks::preset(name) {
+            Ok(quirks) => quirks,
+            Err(err) => return Outcome::Fail(err),
+        },
+        None => Quirks::default(),
+    };
+    let chip8 = match snapshot::run_rom(&rom, test.cycles, quirks) {
+        Ok(chip8) => chip8,
+        Err(err) => return Outcome::Fail(err),
+    };
+    match snapshot::compare_screen(&chip8.display_buffer, &test.screen, bless) {
+        Ok(()) => Outcome::Pass,
+        Err(err) => Outcome::Fail(err),
+    }
+}
@@ -0,0 +1,359 @@
+use chip_8_rs::app::DEFAULT_KEYMAP;
+use chip_8_rs::assembly;
+use chip_8_rs::builtin_roms;
+use chip_8_rs::chip8::{parse_clock_frequency, Chip8};
+use chip_8_rs::frontend::keymap_keys;
+
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{cursor, execute, queue};
+use std::io::{self, Stdout, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use clap::{App, Arg};
+
+const SCREEN_WIDTH: u8 = 64;
+const FRAME_INTERVAL: Duration = Duration::from_millis(1000 / 60);
+/// Terminals that can't report key releases only tell us about presses, so a key is considered
+/// held for this long after each press (key repeat keeps it held)
+const KEY_HOLD_TIME: Duration = Duration::from_millis(150);
+const LISTING_Y: u16 = 14;
+const LISTING_LINES: usize = 12;
+const PANEL_X: u16 = SCREEN_WIDTH as u16 + 4;
+
+fn main() {
+    let (filename, clock_frequency, keymap) = parse_args();
+
+    let rom = builtin_roms::read_rom(Path::new(&filename)).unwrap_or_else(|err| panic!("{}", err));
+    let mut chip8 = Chip8::from_rom(&rom).unwrap_or_else(|err| panic!("{}: {}", filename, err));
+    if let Some(freq) = clock_frequency {
+        chip8.set_clock_frequency(freq);
+    }
+    let disassembled_program = assembly::disassemble_rom(rom);
+
+    let mut tui = Tui::start(chip8, disassembled_program, keymap).expect("Start terminal UI");
+    let result = tui.run();
+    tui.stop().expect("Restore terminal");
+    result.expect("Run terminal UI");
+}
+
+fn parse_args() -> (String, Option<f64>, Vec<char>) {
+    let matches = App::new("Chip-8 terminal emulator")
+        .version("0.1.0")
+        .about("Runs a Chip-8 program in the terminal, drawing the screen with half-block characters.")
+        .arg(
+            Arg::with_name("ROM_FILE")
+                .short("f")
+                .long("file")
+                .visible_alias("rom")
+                .takes_value(true)
+                .help("A file containing the program that will be run (or builtin:NAME)"),
+        )
+        .arg(
+            Arg::with_name("CLOCK_FREQUENCY")
+                .short("c")
+                .long("clock")
+                .takes_value(true)
+                .help("The number of instructions to be executed by Chip-8 per second"),
+        )
+        .arg(
+            Arg::with_name("KEYMAP")
+                .short("k")
+                .long("keymap")
+                .takes_value(true)
+                .help("The 16 keyboard keys that map to the Chip-8 keypad values 0 to F (in that order). Or a keyboard layout: qwerty (the default, \"x123qweasdzc4rfv\"), qwertz, azerty, dvorak or colemak."),
+        )
+        .get_matches();
+
+    let filename = builtin_roms::filename_or_default(matches.value_of("ROM_FILE"));
+
+    let clock_frequency = matches
+        .value_of("CLOCK_FREQUENCY")
+        .map(|freq| parse_clock_frequency(freq).unwrap_or_else(|err| panic!("{}", err)));
+
+    let keymap: Vec<char> = keymap_keys(matches.value_of("KEYMAP").unwrap_or(DEFAULT_KEYMAP))
+        .to_lowercase()
+        .chars()
+        .collect();
+    if keymap.len() != 16 {
+        panic!("Invalid keymap: expected 16 keys, but got {}", keymap.len());
+    }
+
+    (filename, clock_frequency, keymap)
+}
+
+struct Tui {
+    stdout: Stdout,
+    chip8: Chip8,
+    disassembled_program: Vec<String>,
+    keymap: Vec<char>,
+    key_releases: bool,
+    held_keys: [Option<Instant>; 16],
+    paused: bool,
+    error: Option<String>,
+    /// The display height that the terminal was last drawn for
+    screen_height: u8,
+}
+
+impl Tui {
+    fn start(
+        chip8: Chip8,
+        disassembled_program: Vec<String>,
+        keymap: Vec<char>,
+    ) -> io::Result<Tui> {
+        let mut stdout = io::stdout();
+        terminal::enable_raw_mode()?;
+        execute!(
+            stdout,
+            EnterAlternateScreen,
+            cursor::Hide,
+            Clear(ClearType::All)
+        )?;
+        let key_releases = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if key_releases {
+            execute!(
+                stdout,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+        let screen_height = chip8.display_buffer.height();
+        Ok(Tui {
+            stdout,
+            chip8,
+            disassembled_program,
+            keymap,
+            key_releases,
+            held_keys: [None; 16],
+            paused: false,
+            error: None,
+            screen_height,
+        })
+    }
+
+    fn stop(mut self) -> io::Result<()> {
+        if self.key_releases {
+            execute!(self.stdout, PopKeyboardEnhancementFlags)?;
+        }
+        execute!(self.stdout, cursor::Show, LeaveAlternateScreen)?;
+        terminal::disable_raw_mode()
+    }
+
+    fn run(&mut self) -> io::Result<()> {
+        let mut last_update = Instant::now();
+        loop {
+            let frame_end = last_update + FRAME_INTERVAL;
+            while let Some(timeout) = frame_end.checked_duration_since(Instant::now()) {
+                if !event::poll(timeout)? {
+                    break;
+                }
+                if let Event::Key(key_event) = event::read()? {
+                    if !self.handle_key_event(key_event) {
+                        return Ok(());
+                    }
+                }
+            }
+            self.release_expired_keys();
+
+            let now = Instant::now();
+            let elapsed_time = now.duration_since(last_update).as_secs_f64();
+            last_update = now;
+            if !self.paused {
+                if let Err(err) = self.chip8.update(elapsed_time) {
+                    self.error = Some(err);
+                    self.paused = true;
+                }
+            }
+            self.draw()?;
+        }
+    }
+
+    /// Returns false if the user wants to quit
+    fn handle_key_event(&mut self, key_event: KeyEvent) -> bool {
+        let pressed = key_event.kind != KeyEventKind::Release;
+        match key_event.code {
+            KeyCode::Esc => return false,
+            KeyCode::Char('c') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+                return false
+            }
+            KeyCode::Enter if pressed => {
+                self.paused = !self.paused;
+                self.error = None;
+            }
+            KeyCode::Char(c) => {
+                let c = c.to_ascii_lowercase();
+                if let Some(keypad_value) = self.keymap.iter().position(|&key| key == c) {
+                    self.chip8.handle_key_event(keypad_value as u8, pressed);
+                    self.held_keys[keypad_value] = if pressed && !self.key_releases {
+                        Some(Instant::now() + KEY_HOLD_TIME)
+                    } else {
+                        None
+                    };
+                } else if pressed {
+                    self.handle_hotkey(c);
+                }
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn handle_hotkey(&mut self, c: char) {
+        match c {
+            'p' => self.chip8.multiply_clock_frequency(1.25),
+            'o' => self.chip8.multiply_clock_frequency(0.8),
+            'n' if self.paused => {
+                if let Err(err) = self.chip8.step_instruction() {
+                    self.error = Some(err);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn release_expired_keys(&mut self) {
+        let now = Instant::now();
+        for (keypad_value, release_time) in self.held_keys.iter_mut().enumerate() {
+            if matches!(release_time, Some(time) if *time <= now) {
+                *release_time = None;
+                self.chip8.handle_key_event(keypad_value as u8, false);
+            }
+        }
+    }
+
+    fn draw(&mut self) -> io::Result<()> {
+        let screen_height = self.chip8.display_buffer.height();
+        if screen_height != self.screen_height {
+            // The program switched display mode, which moves the status lines
+            self.screen_height = screen_height;
+            queue!(self.stdout, Clear(ClearType::All))?;
+        }
+        self.draw_screen()?;
+        self.draw_registers()?;
+        self.draw_instruction_listing()?;
+
+        let status_y = (LISTING_Y + LISTING_LINES as u16 + 1).max(screen_height as u16 / 2 + 3);
+        let status = match &self.error {
+            Some(err) => format!("Error: {}", err),
+            None if self.paused => "PAUSED (N: step)".to_owned(),
+            None => "RUNNING".to_owned(),
+        };
+        queue!(
+            self.stdout,
+            cursor::MoveTo(0, status_y),
+            Clear(ClearType::CurrentLine),
+            Print(status),
+            cursor::MoveTo(0, status_y + 1),
+            Print("Esc: quit  Enter: pause  P/O: speed up/down"),
+        )?;
+        self.stdout.flush()
+    }
+
+    /// Draws two rows of pixels per line of text, with half-block characters
+    fn draw_screen(&mut self) -> io::Result<()> {
+        let border = "-".repeat(SCREEN_WIDTH as usize);
+        queue!(
+            self.stdout,
+            cursor::MoveTo(0, 0),
+            Print(format!("+{}+", border))
+        )?;
+        let height = self.chip8.display_buffer.height();
+        for row in 0..height / 2 {
+            let mut line = String::with_capacity(3 * SCREEN_WIDTH as usize + 2);
+            line.push('|');
+            for x in 0..SCREEN_WIDTH {
+                let display = &self.chip8.display_buffer;
+                let (top, bottom) = (display[(x, 2 * row)], display[(x, 2 * row + 1)]);
+                line.push(match (top, bottom) {
+                    (true, true) => '\u{2588}',
+                    (true, false) => '\u{2580}',
+                    (false, true) => '\u{2584}',
+                    (false, false) => ' ',
+                });
+            }
+            line.push('|');
+            queue!(self.stdout, cursor::MoveTo(0, row as u16 + 1), Print(line))?;
+        }
+        queue!(
+            self.stdout,
+            cursor::MoveTo(0, height as u16 / 2 + 1),
+            Print(format!("+{}+", border))
+        )
+    }
+
+    fn draw_registers(&mut self) -> io::Result<()> {
+        for i in 0..8 {
+            let line = format!(
+                "V{:X}: {:02X}   V{:X}: {:02X}",
+                i,
+                self.chip8.registers[i],
+                i + 8,
+                self.chip8.registers[i + 8]
+            );
+            queue!(self.stdout, cursor::MoveTo(PANEL_X, i as u16), Print(line))?;
+        }
+        let lines = [
+            format!(
+                "I: {:04X}   PC: {:03X}",
+                self.chip8.address_register, self.chip8.program_counter
+            ),
+            format!(
+                "DT: {:02X}    ST: {:02X}",
+                self.chip8.delay_timer, self.chip8.sound_timer
+            ),
+            format!("Stack depth: {}", self.chip8.stack_pointer),
+            format!("Clock: {:.0} Hz", self.chip8.clock_frequency()),
+        ];
+        for (i, line) in lines.iter().enumerate() {
+            queue!(
+                self.stdout,
+                cursor::MoveTo(PANEL_X, 9 + i as u16),
+                Clear(ClearType::UntilNewLine),
+                Print(line)
+            )?;
+        }
+        Ok(())
+    }
+
+    /// The instructions around PC, below the registers
+    fn draw_instruction_listing(&mut self) -> io::Result<()> {
+        let pc = self.chip8.program_counter as usize;
+        let before: Vec<usize> = (0..pc)
+            .rev()
+            .filter(|&address| !self.disassembled_program[address].is_empty())
+            .take(3)
+            .collect();
+        let start = before.last().copied().unwrap_or(pc);
+        let addresses: Vec<usize> = (start..self.disassembled_program.len())
+            .filter(|&address| !self.disassembled_program[address].is_empty() || address == pc)
+            .take(LISTING_LINES)
+            .collect();
+
+        let y = LISTING_Y;
+        for line in 0..LISTING_LINES {
+            queue!(
+                self.stdout,
+                cursor::MoveTo(PANEL_X, y + line as u16),
+                Clear(ClearType::UntilNewLine)
+            )?;
+            if let Some(&address) = addresses.get(line) {
+                let text = format!("{:03X}: {}", address, self.disassembled_program[address]);
+                if address == pc {
+                    queue!(
+                        self.stdout,
+                        SetAttribute(Attribute::Reverse),
+                        Print(text),
+                        SetAttribute(Attribute::Reset)
+                    )?;
+                } else {
+                    queue!(self.stdout, Print(text))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,102 @@
+//! Small demo programs that are compiled into the binaries, so that there's always something to
+//! run. They're picked with a file name like `builtin:maze`.
+
+use std::fs;
+use std::path::Path;
+
+/// What file names of built-in ROMs start with
+pub const BUILTIN_PREFIX: &str = "builtin:";
+/// What runs when no ROM file is given
+pub const DEFAULT_ROM: &str = "builtin:maze";
+
+pub struct BuiltinRom {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub rom: &'static [u8],
+}
+
+pub const BUILTIN_ROMS: [BuiltinRom; 2] = [
+    BuiltinRom {
+        name: "maze",
+        description: "Fills the screen with a random maze",
+        rom: include_bytes!("../resources/builtin/maze.ch8"),
+    },
+    BuiltinRom {
+        name: "keypad",
+        description: "Shows the last key that was pressed",
+        rom: include_bytes!("../resources/builtin/keypad.ch8"),
+    },
+];
+
+/// The built-in ROM with the given name (without the `builtin:` prefix)
+pub fn find(name: &str) -> Option<&'static BuiltinRom> {
+    BUILTIN_ROMS.iter().find(|rom| rom.name == name)
+}
+
+/// Whether the path names a built-in ROM (like `builtin:maze`), which has no directory to keep
+/// files next to
+pub fn is_builtin(path: &Path) -> bool {
+    path.to_str()
+        .is_some_and(|path| path.starts_with(BUILTIN_PREFIX))
+}
+
+/// Reads the ROM file, or looks up the built-in ROM if the name starts with `builtin:`
+pub fn read_rom(path: &Path) -> Result<Vec<u8>, String> {
+    if let Some(name) = path
+        .to_str()
+        .and_then(|path| path.strip_prefix(BUILTIN_PREFIX))
+    {
+        return find(name).map(|rom| rom.rom.to_vec()).ok_or_else(|| {
+            let names: Vec<&str> = BUILTIN_ROMS.iter().map(|rom| rom.name).collect();
+            format!(
+                "There's no built-in ROM called {} (try {})",
+                name,
+                names.join(", ")
+            )
+        });
+    }
+    fs::read(path).map_err(|err| format!("Couldn't read {}: {}", path.display(), err))
+}
+
+/// One line for each built-in ROM, with its file name and description
+pub fn list() -> String {
+    BUILTIN_ROMS
+        .iter()
+        .map(|rom| format!("  {}{:<10} {}\n", BUILTIN_PREFIX, rom.name, rom.description))
+        .collect()
+}
+
+/// The given file name, or (after listing the built-in ROMs) `DEFAULT_ROM`
+pub fn filename_or_default(filename: Option<&str>) -> String {
+    match filename {
+        Some(filename) => filename.to_owned(),
+        None => {
+            println!(
+                "No ROM file was given, so running {}. The built-in ROMs are:",
+                DEFAULT_ROM
+            );
+            print!("{}", list());
+            DEFAULT_ROM.to_owned()
+        }
+    }
+}
+
+#[test]
+fn test_builtin_roms() {
+    use crate::chip8::Chip8;
+
+    assert_eq!(
+        read_rom(Path::new("builtin:maze")).unwrap(),
+        find("maze").unwrap().rom
+    );
+    assert!(read_rom(Path::new("builtin:tetris")).is_err());
+    assert!(is_builtin(Path::new("builtin:maze")));
+    assert!(!is_builtin(Path::new("programs/maze.ch8")));
+    assert!(list().contains("builtin:keypad"));
+    for builtin in &BUILTIN_ROMS {
+        let mut chip8 = Chip8::from_rom(builtin.rom).unwrap();
+        for _ in 0..1000 {
+            chip8.step_instruction().unwrap();
+        }
+    }
+}
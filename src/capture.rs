@@ -0,0 +1,150 @@
+//! Captures every emulated frame of a run (not the frames that a window happened to render), for
+//! writing it as a GIF and the buzzer as a WAV file afterwards. Since nothing is encoded while the
+//! machine runs, the result is smooth even if the host couldn't keep up.
+
+use crate::chip8::{Chip8, DisplayBuffer};
+
+#[cfg(feature = "native")]
+use std::fs::File;
+#[cfg(feature = "native")]
+use std::io::BufWriter;
+use std::io::{self, Write};
+#[cfg(feature = "native")]
+use std::path::Path;
+
+const FRAMES_PER_SECOND: u32 = 60;
+#[cfg(feature = "native")]
+const GIF_SCALING: usize = 4;
+const SAMPLE_RATE: u32 = 44_100;
+const TONE_FREQUENCY: f64 = 440.0;
+const VOLUME: i16 = 0x1000;
+
+/// The display and whether the buzzer sounded, for each 60Hz frame
+#[derive(Clone, Default)]
+pub struct RunCapture {
+    frames: Vec<DisplayBuffer>,
+    sound: Vec<bool>,
+}
+
+impl RunCapture {
+    /// Keeps the current display and buzzer, which should be called at the end of each frame
+    pub fn capture_frame(&mut self, chip8: &Chip8) {
+        self.frames.push(chip8.display_buffer.clone());
+        self.sound.push(chip8.sound_timer > 0);
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Writes an animated GIF, 4x the size of the display, with white pixels on black. Identical
+    /// consecutive frames are merged, and the delays are rounded to centiseconds such that the
+    /// total duration stays accurate.
+    #[cfg(feature = "native")]
+    pub fn write_gif(&self, path: &Path) -> Result<(), String> {
+        use gif::{Encoder, Frame, Repeat, SetParameter};
+
+        let write_error = |err: io::Error| format!("Couldn't write {}: {}", path.display(), err);
+        let height = self
+            .frames
+            .iter()
+            .map(|frame| frame.height())
+            .max()
+            .unwrap_or(32);
+        let (width, height) = (64 * GIF_SCALING, height as usize * GIF_SCALING);
+        let file = File::create(path)
+            .map_err(|err| format!("Couldn't create {}: {}", path.display(), err))?;
+        let mut encoder = Encoder::new(
+            BufWriter::new(file),
+            width as u16,
+            height as u16,
+            &[0, 0, 0, 255, 255, 255],
+        )
+        .map_err(write_error)?;
+        encoder.set(Repeat::Infinite).map_err(write_error)?;
+
+        let mut written_centiseconds = 0;
+        let mut start = 0;
+        while start < self.frames.len() {
+            let end = (start..self.frames.len())
+                .find(|&i| self.frames[i] != self.frames[start])
+                .unwrap_or(self.frames.len());
+            let end_centiseconds = end as u64 * 100 / FRAMES_PER_SECOND as u64;
+            let display = &self.frames[start];
+            let mut pixels = Vec::with_capacity(width * height);
+            for y in 0..height {
+                for x in 0..width {
+                    let (x, y) = ((x / GIF_SCALING) as u8, (y / GIF_SCALING) as u8);
+                    pixels.push((y < display.height() && display.get_pixel(x, y)) as u8);
+                }
+            }
+            let mut frame = Frame::from_indexed_pixels(width as u16, height as u16, &pixels, None);
+            frame.delay = (end_centiseconds - written_centiseconds).min(u16::MAX as u64) as u16;
+            encoder.write_frame(&frame).map_err(write_error)?;
+            written_centiseconds = end_centiseconds;
+            start = end;
+        }
+        Ok(())
+    }
+
+    /// Writes the buzzer as a mono 16-bit WAV file: a square wave while the sound timer ran, and
+    /// silence otherwise
+    pub fn write_wav<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let samples_per_frame = SAMPLE_RATE / FRAMES_PER_SECOND;
+        let data_size = 2 * samples_per_frame * self.sound.len() as u32;
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(36 + data_size).to_le_bytes())?;
+        writer.write_all(b"WAVEfmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        // PCM, one channel
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&SAMPLE_RATE.to_le_bytes())?;
+        writer.write_all(&(2 * SAMPLE_RATE).to_le_bytes())?;
+        writer.write_all(&2u16.to_le_bytes())?;
+        writer.write_all(&16u16.to_le_bytes())?;
+        writer.write_all(b"data")?;
+        writer.write_all(&data_size.to_le_bytes())?;
+
+        let mut phase = 0.0;
+        for &playing in &self.sound {
+            for _ in 0..samples_per_frame {
+                let sample = if !playing {
+                    0
+                } else if phase < 0.5 {
+                    VOLUME
+                } else {
+                    -VOLUME
+                };
+                phase = (phase + TONE_FREQUENCY / SAMPLE_RATE as f64) % 1.0;
+                writer.write_all(&sample.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_write_wav() {
+    // set the sound timer to 1, then loop
+    let mut chip8 = Chip8::from_rom(&[0x60, 0x01, 0xF0, 0x18, 0x12, 0x04]).unwrap();
+    let mut capture = RunCapture::default();
+    for _ in 0..2 {
+        chip8.step_instruction().unwrap();
+    }
+    capture.capture_frame(&chip8);
+    chip8.update(1.0 / 60.0).unwrap();
+    capture.capture_frame(&chip8);
+    assert_eq!(capture.frame_count(), 2);
+
+    let mut wav = Vec::new();
+    capture.write_wav(&mut wav).unwrap();
+    let samples_per_frame = 735;
+    assert_eq!(wav.len(), 44 + 2 * 2 * samples_per_frame);
+    assert_eq!(&wav[0..4], b"RIFF");
+    assert_eq!(&wav[36..40], b"data");
+    let sample = |i: usize| i16::from_le_bytes([wav[44 + 2 * i], wav[45 + 2 * i]]);
+    assert_eq!(sample(0), VOLUME);
+    assert_eq!(sample(60), -VOLUME);
+    assert_eq!(sample(samples_per_frame), 0);
+}
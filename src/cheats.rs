@@ -0,0 +1,235 @@
+//! Cheats that write values into the machine: frozen values are written again after every update
+//! (to keep, say, the lives counter from going down), and pokes are written once.
+
+use crate::chip8::Chip8;
+
+use std::convert::TryFrom;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// A cheat such as `freeze V3 = 5`, `freeze mem[0x2F0] = 0x03` or `poke mem[0x300] = 0x10`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cheat {
+    target: Target,
+    value: u8,
+    frozen: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Target {
+    Register(u8),
+    Memory(u16),
+}
+
+impl Cheat {
+    pub fn parse(source: &str) -> Result<Cheat, String> {
+        let source = source.trim();
+        let (frozen, assignment) = if let Some(rest) = source.strip_prefix("freeze ") {
+            (true, rest)
+        } else if let Some(rest) = source.strip_prefix("poke ") {
+            (false, rest)
+        } else {
+            return Err(format!(
+                "Expected a cheat like \"freeze V3 = 5\" or \"poke mem[0x300] = 1\": {}",
+                source
+            ));
+        };
+        let mut sides = assignment.splitn(2, '=');
+        let target = parse_target(sides.next().unwrap_or_default())?;
+        let value = sides
+            .next()
+            .and_then(|value| parse_number(value.trim()))
+            .and_then(|value| u8::try_from(value).ok())
+            .ok_or_else(|| format!("Expected a byte value after '=': {}", source))?;
+        Ok(Cheat {
+            target,
+            value,
+            frozen,
+        })
+    }
+
+    /// Whether the cheat is written after every update, rather than once
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    /// The register or memory address, as in `V3` or `mem[0x2F0]`
+    pub fn target(&self) -> String {
+        match self.target {
+            Target::Register(x) => format!("V{:X}", x),
+            Target::Memory(address) => format!("mem[{:#05X}]", address),
+        }
+    }
+
+    pub fn apply(&self, chip8: &mut Chip8) {
+        match self.target {
+            Target::Register(x) => chip8.registers[x as usize] = self.value,
+            Target::Memory(address) => {
+                // Writing clears the decoded instructions at the address, so only write changes
+                if chip8.memory()[address as usize] != self.value {
+                    chip8
+                        .write_memory(address, &[self.value])
+                        .expect("The address was checked when parsing");
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Cheat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = if self.frozen { "freeze" } else { "poke" };
+        write!(f, "{} {} = {:#04X}", kind, self.target(), self.value)
+    }
+}
+
+/// The frozen values of a ROM
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Cheats {
+    frozen: Vec<Cheat>,
+}
+
+impl Cheats {
+    /// Parses a cheats file, with one cheat per line. Empty lines and lines starting with `#` are
+    /// skipped.
+    pub fn parse(text: &str) -> Result<Vec<Cheat>, String> {
+        text.lines()
+            .enumerate()
+            .map(|(i, line)| (i, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(i, line)| Cheat::parse(line).map_err(|err| format!("Line {}: {}", i + 1, err)))
+            .collect()
+    }
+
+    /// Reads the cheats file of the ROM (see `path`), if there is one
+    pub fn load(rom_path: &Path) -> Result<Vec<Cheat>, String> {
+        let path = Cheats::path(rom_path);
+        match fs::read_to_string(&path) {
+            Ok(text) => Cheats::parse(&text).map_err(|err| format!("{}: {}", path.display(), err)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+            Err(err) => Err(format!("Couldn't read {}: {}", path.display(), err)),
+        }
+    }
+
+    /// `ROM.cheats`, next to the ROM
+    pub fn path(rom_path: &Path) -> PathBuf {
+        let mut name = OsString::from(rom_path.as_os_str());
+        name.push(".cheats");
+        PathBuf::from(name)
+    }
+
+    /// Applies the cheat, and keeps it (instead of an earlier one for the same target) if it's
+    /// frozen
+    pub fn add(&mut self, cheat: Cheat, chip8: &mut Chip8) {
+        cheat.apply(chip8);
+        if cheat.frozen {
+            self.frozen.retain(|frozen| frozen.target != cheat.target);
+            self.frozen.push(cheat);
+        }
+    }
+
+    /// Stops freezing the given target (like `V3` or `mem[0x2F0]`), returning false if it wasn't
+    /// frozen
+    pub fn unfreeze(&mut self, target: &str) -> Result<bool, String> {
+        let target = parse_target(target)?;
+        let count = self.frozen.len();
+        self.frozen.retain(|frozen| frozen.target != target);
+        Ok(self.frozen.len() != count)
+    }
+
+    pub fn frozen(&self) -> &[Cheat] {
+        &self.frozen
+    }
+
+    pub fn clear(&mut self) {
+        self.frozen.clear();
+    }
+
+    /// Writes the frozen values again
+    pub fn apply(&self, chip8: &mut Chip8) {
+        for cheat in &self.frozen {
+            cheat.apply(chip8);
+        }
+    }
+}
+
+fn parse_target(source: &str) -> Result<Target, String> {
+    let normalized: String = source
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_lowercase();
+    if normalized.len() == 2 && normalized.starts_with('v') {
+        if let Some(x) = normalized[1..].chars().next().and_then(|c| c.to_digit(16)) {
+            return Ok(Target::Register(x as u8));
+        }
+    }
+    if let Some(address) = normalized
+        .strip_prefix("mem[")
+        .and_then(|rest| rest.strip_suffix(']'))
+    {
+        return match parse_number(address) {
+            Some(address) if address < 0x1000 => Ok(Target::Memory(address)),
+            _ => Err(format!("Invalid address: {}", source.trim())),
+        };
+    }
+    Err(format!(
+        "Expected a register (like V3) or an address (like mem[0x2F0]): {}",
+        source.trim()
+    ))
+}
+
+fn parse_number(s: &str) -> Option<u16> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+#[test]
+fn test_parse_cheat() {
+    assert_eq!(
+        Cheat::parse("freeze V3 = 5").unwrap().to_string(),
+        "freeze V3 = 0x05"
+    );
+    assert_eq!(
+        Cheat::parse("poke mem[ 0x2F0 ]=0xFF").unwrap().to_string(),
+        "poke mem[0x2F0] = 0xFF"
+    );
+    assert!(Cheat::parse("freeze VG = 5").is_err());
+    assert!(Cheat::parse("freeze V3 = 256").is_err());
+    assert!(Cheat::parse("freeze mem[0x1000] = 1").is_err());
+    assert!(Cheat::parse("set V3 = 1").is_err());
+    assert_eq!(
+        Cheats::parse("# lives\nfreeze mem[0x2F0] = 3\n\npoke V1\n"),
+        Err("Line 4: Expected a byte value after '=': poke V1".to_owned())
+    );
+}
+
+#[test]
+fn test_cheats() {
+    let mut chip8 = Chip8::new([0; 0x1000]);
+    let mut cheats = Cheats::default();
+    for cheat in Cheats::parse("freeze mem[0x2F0] = 3\nfreeze V1 = 1\npoke V2 = 2").unwrap() {
+        cheats.add(cheat, &mut chip8);
+    }
+    assert_eq!(chip8.registers[2], 2);
+    assert_eq!(cheats.frozen().len(), 2);
+
+    chip8.write_memory(0x2F0, &[0]).unwrap();
+    chip8.registers[1] = 0;
+    chip8.registers[2] = 0;
+    cheats.apply(&mut chip8);
+    assert_eq!(chip8.memory()[0x2F0], 3);
+    assert_eq!(chip8.registers[1], 1);
+    assert_eq!(chip8.registers[2], 0);
+
+    assert_eq!(cheats.unfreeze("v1"), Ok(true));
+    assert_eq!(cheats.unfreeze("V1"), Ok(false));
+    cheats.add(Cheat::parse("freeze mem[0x2F0] = 9").unwrap(), &mut chip8);
+    assert_eq!(cheats.frozen().len(), 1);
+    assert_eq!(chip8.memory()[0x2F0], 9);
+}
@@ -1,10 +1,54 @@
-use rand::rngs::StdRng;
-use rand::{Rng, RngCore, SeedableRng};
+use rand::{Rng, RngCore};
+use std::collections::{BTreeMap, HashSet, VecDeque};
 use std::fmt;
 use std::fmt::{Debug, Formatter};
+use std::io::{self, Write};
+use std::ops::Index;
+#[cfg(feature = "native")]
+use std::path::Path;
+use tracing::{debug, debug_span, trace, trace_span, warn};
+
+mod colors;
+mod coverage;
+mod diff;
+mod events;
+mod instruction;
+mod profile;
+mod quirks;
+mod random;
+mod shadow;
+mod state;
+mod step_history;
+mod trace;
+
+pub use colors::Colors;
+pub use coverage::{opcode_form, OpcodeCoverage, OPCODE_FORMS};
+pub use diff::{MemoryChange, RegisterChange, StateDiff};
+pub use events::Chip8Event;
+pub use instruction::Instruction;
+pub use profile::CallProfile;
+pub use quirks::{Quirks, QUIRKS_PRESETS};
+pub use random::Random;
+pub use shadow::UninitializedRead;
+pub use state::MAX_STATE_SIZE;
+pub use step_history::StepHistory;
+pub use trace::{TraceFilter, Tracer, TRACE_FILTERS};
+
+use shadow::ShadowMemory;
+use trace::Registers;
 
 const SCREEN_WIDTH: u8 = 64;
 const SCREEN_HEIGHT: u8 = 32;
+/// The heights of the screen in the different display modes: the normal 64x32, the 64x48 of the
+/// ETI 660, and the 64x64 of the two-page hires CHIP-8
+pub const DISPLAY_HEIGHTS: [u8; 3] = [32, 48, 64];
+pub const MAX_SCREEN_HEIGHT: usize = 64;
+/// What hires CHIP-8 programs start with: a jump over the interpreter patch that they were
+/// distributed with, to the program itself at 0x2C0
+const HIRES_SIGNATURE: [u8; 2] = [0x12, 0x60];
+const HIRES_START: u16 = 0x2C0;
+/// The op-code that clears the screen in hires CHIP-8 (the machine code routine that it calls)
+const HIRES_CLEAR_SCREEN: u16 = 0x230;
 
 pub const FONT_SPRITES: [u8; 5 * 16] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // "0"
@@ -25,44 +69,352 @@ pub const FONT_SPRITES: [u8; 5 * 16] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // "F"
 ];
 
+/// The 8x10 digits of SUPER-CHIP (with the A to F of Octo), which Fx30 points I at. They come
+/// right after `FONT_SPRITES` in memory.
+pub const LARGE_FONT_SPRITES: [u8; 10 * 16] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // "0"
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // "1"
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // "2"
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // "3"
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // "4"
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // "5"
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // "6"
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // "7"
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // "8"
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // "9"
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // "A"
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // "B"
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // "C"
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // "D"
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // "E"
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // "F"
+];
+/// Where `LARGE_FONT_SPRITES` are in memory
+const LARGE_FONT_START: usize = FONT_SPRITES.len();
+/// The end of the fonts in memory, before which programs can't be loaded
+const FONTS_END: usize = LARGE_FONT_START + LARGE_FONT_SPRITES.len();
+
+/// Where programs are loaded and start running
+pub const PROGRAM_START: u16 = 0x200;
+/// Where programs for the ETI 660 are loaded instead
+pub const ETI_660_START: u16 = 0x600;
+
+/// The number of flags that Fx75 and Fx85 use (SUPER-CHIP has 8, and XO-CHIP 16)
+pub const FLAG_COUNT: usize = 16;
+
+/// The memory of the COSMAC VIP (and SUPER-CHIP), which machines start out with
+pub const DEFAULT_MEMORY_SIZE: usize = 0x1000;
+/// The memory of XO-CHIP, the most that `set_memory_size` allows
+pub const MAX_MEMORY_SIZE: usize = 0x10000;
+/// The number of return addresses that the COSMAC VIP interpreter had room for, which machines
+/// start out with
+pub const DEFAULT_STACK_DEPTH: u8 = 16;
+
 const INTERVAL_60_HZ: f64 = 1.0 / 60.0;
-const DEFAULT_CLOCK_FREQUENCY: u32 = 500;
+/// The most time that one update runs the machine for (in seconds)
+pub const MAX_UPDATE_TIME: f64 = 1.0;
+const DEFAULT_CLOCK_FREQUENCY: f64 = 500.0;
+/// The range that clock frequencies are clamped to
+pub const MIN_CLOCK_FREQUENCY: f64 = 1.0;
+pub const MAX_CLOCK_FREQUENCY: f64 = 500_000.0;
+/// With cycle costs, an instruction that costs this many VIP machine cycles (about an average
+/// one) takes one clock interval
+const AVERAGE_VIP_COST: u32 = 100;
+const HISTORY_LENGTH: usize = 16;
+
+/// The 64-bit FNV-1a hash of the bytes
+fn fnv_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Parses a start address like `0x600` or `600` (which is hex, like other addresses), or `eti660`
+pub fn parse_start_address(source: &str) -> Result<u16, String> {
+    let source = source.trim();
+    if source.eq_ignore_ascii_case("eti660") {
+        return Ok(ETI_660_START);
+    }
+    let hex = source.strip_prefix("0x").unwrap_or(source);
+    u16::from_str_radix(hex, 16)
+        .ok()
+        .filter(|&address| address < 0x1000)
+        .ok_or_else(|| format!("Invalid start address: {}", source))
+}
+
+/// Parses a clock frequency in Hz, like `700` or `0.5`, which has to be positive
+pub fn parse_clock_frequency(source: &str) -> Result<f64, String> {
+    let source = source.trim();
+    source
+        .parse::<f64>()
+        .ok()
+        .filter(|frequency| frequency.is_finite() && *frequency > 0.0)
+        .ok_or_else(|| {
+            format!(
+                "Invalid clock frequency (expected a positive number): {}",
+                source
+            )
+        })
+}
+
+/// Parses a memory size in hex, like `10000` (or `0x10000`), from `DEFAULT_MEMORY_SIZE` to
+/// `MAX_MEMORY_SIZE`
+pub fn parse_memory_size(source: &str) -> Result<usize, String> {
+    let source = source.trim();
+    let hex = source.strip_prefix("0x").unwrap_or(source);
+    usize::from_str_radix(hex, 16)
+        .ok()
+        .filter(|size| (DEFAULT_MEMORY_SIZE..=MAX_MEMORY_SIZE).contains(size))
+        .ok_or_else(|| {
+            format!(
+                "Invalid memory size (try {:X} to {:X}): {}",
+                DEFAULT_MEMORY_SIZE, MAX_MEMORY_SIZE, source
+            )
+        })
+}
 
-fn debug(_message: &str) {
-    //println!("{}", message);
+/// Parses a display mode: `64x32`, `64x48` or `64x64`. Returns the height.
+pub fn parse_display_mode(source: &str) -> Result<u8, String> {
+    let source = source.trim();
+    source
+        .strip_prefix("64x")
+        .and_then(|height| height.parse().ok())
+        .filter(|height| DISPLAY_HEIGHTS.contains(height))
+        .ok_or_else(|| {
+            format!(
+                "Invalid display mode (try 64x32, 64x48 or 64x64): {}",
+                source
+            )
+        })
 }
 
-pub struct DisplayBuffer(pub [bool; SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize]);
+/// How the clock frequency is turned into executed instructions
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Timing {
+    /// Instructions are spread evenly over time
+    Clock,
+    /// A fixed number of instructions (the clock frequency divided by 60) are executed in each
+    /// 60Hz frame, like Octo's "tickrate". Some programs are tuned for this, and it doesn't drift
+    /// when frames take uneven amounts of time.
+    Tickrate,
+}
+
+/// The names that `FontProtection::parse` accepts
+pub const FONT_PROTECTION_MODES: [&str; 2] = ["warn", "error"];
+
+/// What happens when a program writes below 0x200, where the interpreter and the font are. A
+/// program that overwrites the font only shows it later, when Fx29 digits come out garbled.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FontProtection {
+    /// Log a warning (once for each instruction that does it), and write anyway
+    Warn,
+    /// Fail the instruction, like other errors
+    Error,
+}
+
+impl FontProtection {
+    pub fn parse(name: &str) -> Result<FontProtection, String> {
+        match name {
+            "warn" => Ok(FontProtection::Warn),
+            "error" => Ok(FontProtection::Error),
+            _ => Err(format!(
+                "Unknown font protection: {} (expected one of {})",
+                name,
+                FONT_PROTECTION_MODES.join(", ")
+            )),
+        }
+    }
+}
+
+/// The screen, which is 64 pixels wide and 32 (or, in the other display modes, 48 or 64) pixels
+/// tall, as one `u64` per row, with the leftmost pixel in the most significant bit
+#[derive(Clone, PartialEq)]
+pub struct DisplayBuffer {
+    rows: [u64; MAX_SCREEN_HEIGHT],
+    height: u8,
+    /// The CHIP-8X colors, once the program has set any
+    colors: Option<Colors>,
+}
 
 impl DisplayBuffer {
     fn new() -> DisplayBuffer {
-        DisplayBuffer([false; SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize])
+        DisplayBuffer::from_rows(&[0; SCREEN_HEIGHT as usize])
     }
 
+    /// Panics unless there are 32, 48 or 64 rows (see `DISPLAY_HEIGHTS`)
+    pub fn from_rows(rows: &[u64]) -> DisplayBuffer {
+        assert!(
+            DISPLAY_HEIGHTS.contains(&(rows.len() as u8)),
+            "Unsupported display height: {}",
+            rows.len()
+        );
+        let mut display = DisplayBuffer {
+            rows: [0; MAX_SCREEN_HEIGHT],
+            height: rows.len() as u8,
+            colors: None,
+        };
+        display.rows[..rows.len()].copy_from_slice(rows);
+        display
+    }
+
+    pub fn rows(&self) -> &[u64] {
+        &self.rows[..self.height as usize]
+    }
+
+    pub fn width(&self) -> u8 {
+        SCREEN_WIDTH
+    }
+
+    pub fn height(&self) -> u8 {
+        self.height
+    }
+
+    /// The CHIP-8X colors, if the program has set any (otherwise the display is monochrome)
+    pub fn colors(&self) -> Option<&Colors> {
+        self.colors.as_ref()
+    }
+
+    fn colors_mut(&mut self) -> &mut Colors {
+        self.colors.get_or_insert_with(Colors::new)
+    }
+
+    #[cfg(test)]
     fn flip_pixel(&mut self, x: u8, y: u8) {
         let x = x % SCREEN_WIDTH;
-        let y = y % SCREEN_HEIGHT;
-        let index = y as usize * SCREEN_WIDTH as usize + x as usize;
-        self.0[index] = !self.0[index];
+        let y = y % self.height;
+        self.rows[y as usize] ^= 1 << (63 - x);
     }
 
     pub fn get_pixel(&self, x: u8, y: u8) -> bool {
         let x = x % SCREEN_WIDTH;
-        let y = y % SCREEN_HEIGHT;
-        let index = y as usize * SCREEN_WIDTH as usize + x as usize;
-        self.0[index]
+        let y = y % self.height;
+        self.rows[y as usize] & (1 << (63 - x)) != 0
     }
 
     fn clear(&mut self) {
-        for i in 0..self.0.len() {
-            self.0[i] = false;
+        self.rows = [0; MAX_SCREEN_HEIGHT];
+    }
+
+    /// XORs a sprite row onto the screen, with its left edge at x, and returns the pixels that
+    /// were turned off. The sprite either wraps around to the left edge or is cut off.
+    fn draw_sprite_row(&mut self, x: u8, y: usize, sprite: u8, clip: bool) -> u64 {
+        let shifted = (sprite as u64) << 56;
+        let sprite = if clip {
+            shifted >> x
+        } else {
+            shifted.rotate_right(x as u32)
+        };
+        let collisions = self.rows[y] & sprite;
+        self.rows[y] ^= sprite;
+        collisions
+    }
+
+    /// Whether each pixel is lit, row by row from the top left
+    pub fn pixels(&self) -> impl Iterator<Item = bool> + '_ {
+        self.rows()
+            .iter()
+            .flat_map(|&row| (0..SCREEN_WIDTH).map(move |x| row & (1 << (63 - x)) != 0))
+    }
+
+    /// One bit per pixel, 8 bytes per row, with the leftmost pixel in the most significant bit
+    /// (like sprites in memory)
+    pub fn as_bytes(&self) -> Vec<u8> {
+        self.rows()
+            .iter()
+            .flat_map(|row| row.to_be_bytes())
+            .collect()
+    }
+
+    /// Converts the display to RGBA bytes, row by row, with the given colors (or the CHIP-8X
+    /// colors, if the program has set any)
+    pub fn to_rgba(&self, foreground: [u8; 4], background: [u8; 4]) -> Vec<u8> {
+        let mut rgba = Vec::with_capacity(4 * SCREEN_WIDTH as usize * self.height as usize);
+        for (i, lit) in self.pixels().enumerate() {
+            match &self.colors {
+                Some(colors) => {
+                    let (x, y) = (i % SCREEN_WIDTH as usize, i / SCREEN_WIDTH as usize);
+                    let [r, g, b] = if lit {
+                        colors.foreground(x as u8, y as u8)
+                    } else {
+                        colors.background()
+                    };
+                    rgba.extend_from_slice(&[r, g, b, 255]);
+                }
+                None => rgba.extend_from_slice(if lit { &foreground } else { &background }),
+            }
+        }
+        rgba
+    }
+
+    /// One line per row, with `#` for lit pixels and `.` for unlit ones
+    pub fn to_ascii(&self) -> String {
+        let mut ascii = String::with_capacity((SCREEN_WIDTH as usize + 1) * self.height as usize);
+        for (i, lit) in self.pixels().enumerate() {
+            ascii.push(if lit { '#' } else { '.' });
+            if i % SCREEN_WIDTH as usize == SCREEN_WIDTH as usize - 1 {
+                ascii.push('\n');
+            }
+        }
+        ascii
+    }
+
+    /// Like `to_ascii`, but with the numbers of the rows in front of them and the numbers of the
+    /// columns above them (in decimal, tens above ones), so that a pixel can be found (or read out
+    /// by a screen reader) by its position, and two screens can be diffed line by line
+    pub fn to_text_grid(&self) -> String {
+        let mut grid = String::new();
+        for digit in [10, 1] {
+            grid.push_str("   ");
+            for x in 0..SCREEN_WIDTH {
+                grid.push(char::from_digit((x / digit % 10) as u32, 10).unwrap());
+            }
+            grid.push('\n');
+        }
+        for (y, row) in self.to_ascii().lines().enumerate() {
+            grid.push_str(&format!("{:2} {}\n", y, row));
+        }
+        grid
+    }
+
+    /// Writes the display as a binary PBM image. PBM counts set bits as black, so lit pixels come
+    /// out black on white.
+    pub fn write_pbm<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write!(writer, "P4\n{} {}\n", SCREEN_WIDTH, self.height)?;
+        writer.write_all(&self.as_bytes())
+    }
+
+    /// Saves the display as a PNG (64x32, unless it's in another display mode), with white pixels
+    /// on black
+    #[cfg(feature = "native")]
+    pub fn write_png(&self, path: &Path) -> Result<(), String> {
+        let rgba = self.to_rgba([255, 255, 255, 255], [0, 0, 0, 255]);
+        image::save_buffer(
+            path,
+            &rgba,
+            SCREEN_WIDTH as u32,
+            self.height as u32,
+            image::ColorType::RGBA(8),
+        )
+        .map_err(|err| format!("Couldn't write {}: {}", path.display(), err))
+    }
+}
+
+/// `display[(x, y)]` is the same as `display.get_pixel(x, y)`
+impl Index<(u8, u8)> for DisplayBuffer {
+    type Output = bool;
+
+    fn index(&self, (x, y): (u8, u8)) -> &bool {
+        if self.get_pixel(x, y) {
+            &true
+        } else {
+            &false
         }
     }
 }
 
 impl Debug for DisplayBuffer {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        for y in 0..SCREEN_HEIGHT {
+        for y in 0..self.height {
             f.write_str("\n")?;
             for x in 0..SCREEN_WIDTH {
                 if self.get_pixel(x, y) {
@@ -76,49 +428,265 @@ impl Debug for DisplayBuffer {
     }
 }
 
+/// What `Chip8::step_instruction` executed, so that callers don't have to fetch and decode it
+/// again
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StepReport {
+    /// The address of the instruction
+    pub pc: u16,
+    pub opcode: u16,
+    /// While the machine waits for a key, this is the Fx0A that it's waiting on
+    pub decoded: Instruction,
+    /// Whether the instruction changed any pixels (or colors) of the display
+    pub display_changed: bool,
+    /// The cycles that the machine has run so far, including this one (see `Chip8::cycles`)
+    pub cycles: u64,
+}
+
+/// What `Chip8::update` did, for frontends that show how the emulation runs
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct UpdateReport {
+    /// The instructions that were executed (including cycles spent waiting for a key)
+    pub cycles: u32,
+    /// How many times the 60Hz timers were decremented
+    pub timer_ticks: u32,
+    /// Whether any of the instructions changed the display
+    pub display_changed: bool,
+    /// Whether the elapsed time was more than `MAX_UPDATE_TIME`, and the rest was skipped
+    pub clamped: bool,
+}
+
+impl UpdateReport {
+    fn add_step(&mut self, step: &StepReport) {
+        self.cycles += 1;
+        self.display_changed |= step.display_changed;
+    }
+}
+
+/// The emulated machine. It's the only one: the app, the command-line tools and the wasm, C and
+/// libretro frontends all run this, so that a feature only has to be implemented once.
 pub struct Chip8 {
-    memory: [u8; 0x1000],
+    memory: Vec<u8>,
     pub registers: [u8; 16],
     pub address_register: u16,
     pub program_counter: u16,
-    pub stack: [u16; 16],
+    /// Room for the return addresses (see `set_stack_depth`), of which the first
+    /// `stack_pointer` are in use
+    pub stack: Vec<u16>,
     pub stack_pointer: u8,
     pub display_buffer: DisplayBuffer,
+    /// The SUPER-CHIP flags (the "RPL user flags" of the HP48), where programs keep things like
+    /// high scores
+    flags: [u8; FLAG_COUNT],
     pub delay_timer: u8,
     pub sound_timer: u8,
     timer_cooldown: f64,
-    random: Box<dyn RngCore>,
+    random: Random,
     pressed_keys: [bool; 16],
+    /// See `set_sticky_keys`
+    sticky_keys: bool,
+    /// With sticky keys, a bit for each key that has been pressed since Ex9E or ExA1 last
+    /// checked it
+    unseen_presses: u16,
     cycle_cooldown: f64,
     register_blocking_on_key_press: Option<u8>,
-    clock_frequency: u32,
+    clock_frequency: f64,
     clock_frequency_interval: f64,
+    timing: Timing,
+    /// See `set_cycle_costs`
+    cycle_costs: bool,
+    /// With `Timing::Tickrate`, the instructions that remain to be executed in the current frame
+    cycles_left_in_frame: u32,
+    /// The cycles that have run, including the ones spent waiting for a key
+    cycles: u64,
+    breakpoints: HashSet<u16>,
+    stopped_at_breakpoint: Option<u16>,
+    run_to_address: Option<u16>,
+    history: VecDeque<u16>,
+    execution_counts: Vec<u32>,
+    /// Instructions are decoded the first time they are executed, and kept until their memory
+    /// is written to
+    decoded: Vec<Option<Instruction>>,
+    /// The instructions that the program wrote over after they had been executed, and the
+    /// instruction that last did
+    modified_code: BTreeMap<u16, u16>,
+    opcode_coverage: OpcodeCoverage,
+    quirks: Quirks,
+    tracer: Option<Tracer>,
+    call_profile: Option<CallProfile>,
+    /// Everything before this was loaded: the interpreter area and font, and then the program
+    loaded_end: usize,
+    rom_hash: u64,
+    shadow_memory: Option<ShadowMemory>,
+    font_protection: Option<FontProtection>,
+    /// The instructions that have been warned about for writing to the font area
+    font_writers: HashSet<u16>,
+    /// The events since the last `take_events`, while they are recorded
+    events: Option<Vec<Chip8Event>>,
 }
 
 impl Chip8 {
     pub fn new(memory: [u8; 0x1000]) -> Chip8 {
         Chip8 {
-            memory: memory,
+            memory: memory.to_vec(),
             registers: [0; 16],
             address_register: 0,
-            program_counter: 0x200,
-            stack: [0; 16],
+            program_counter: PROGRAM_START,
+            stack: vec![0; DEFAULT_STACK_DEPTH as usize],
             stack_pointer: 0,
             display_buffer: DisplayBuffer::new(),
+            flags: [0; FLAG_COUNT],
             delay_timer: 0,
             sound_timer: 0,
             timer_cooldown: 0.0,
-            random: Box::from(StdRng::seed_from_u64(222)),
+            random: Random::seeded(222),
             pressed_keys: [false; 16],
+            sticky_keys: false,
+            unseen_presses: 0,
             cycle_cooldown: 0.0,
             register_blocking_on_key_press: None,
             clock_frequency: DEFAULT_CLOCK_FREQUENCY,
-            clock_frequency_interval: 1.0 / DEFAULT_CLOCK_FREQUENCY as f64,
+            clock_frequency_interval: 1.0 / DEFAULT_CLOCK_FREQUENCY,
+            timing: Timing::Clock,
+            cycle_costs: false,
+            cycles_left_in_frame: 0,
+            cycles: 0,
+            breakpoints: HashSet::new(),
+            stopped_at_breakpoint: None,
+            run_to_address: None,
+            history: VecDeque::with_capacity(HISTORY_LENGTH),
+            execution_counts: vec![0; DEFAULT_MEMORY_SIZE],
+            decoded: vec![None; DEFAULT_MEMORY_SIZE],
+            modified_code: BTreeMap::new(),
+            opcode_coverage: OpcodeCoverage::default(),
+            quirks: Quirks::default(),
+            tracer: None,
+            call_profile: None,
+            loaded_end: memory.len(),
+            rom_hash: fnv_hash(&[]),
+            shadow_memory: None,
+            font_protection: None,
+            font_writers: HashSet::new(),
+            events: None,
         }
     }
 
+    /// Creates a machine with the font sprites in memory and the given program loaded at 0x200
+    pub fn from_rom(rom: &[u8]) -> Result<Chip8, String> {
+        Chip8::from_rom_at(rom, PROGRAM_START)
+    }
+
+    /// Like `from_rom`, but the program is loaded (and starts running) at the given address. A
+    /// program that doesn't fit in 4K (like an XO-CHIP one) gets `MAX_MEMORY_SIZE` bytes of memory.
+    pub fn from_rom_at(rom: &[u8], start_address: u16) -> Result<Chip8, String> {
+        let mut memory = [0; DEFAULT_MEMORY_SIZE];
+        let start = start_address as usize;
+        if start < FONTS_END || start >= memory.len() {
+            return Err(format!("Invalid start address: {:#05X}", start_address));
+        }
+        if rom.len() > MAX_MEMORY_SIZE - start {
+            return Err(format!("ROM is too large: {} bytes", rom.len()));
+        }
+        memory[..FONT_SPRITES.len()].copy_from_slice(&FONT_SPRITES);
+        memory[LARGE_FONT_START..FONTS_END].copy_from_slice(&LARGE_FONT_SPRITES);
+        let mut chip8 = Chip8::new(memory);
+        if start + rom.len() > DEFAULT_MEMORY_SIZE {
+            chip8.resize_memory(MAX_MEMORY_SIZE);
+        }
+        chip8.memory[start..start + rom.len()].copy_from_slice(rom);
+        chip8.program_counter = start_address;
+        chip8.loaded_end = start + rom.len();
+        chip8.rom_hash = fnv_hash(rom);
+        if start_address == PROGRAM_START && rom.starts_with(&HIRES_SIGNATURE) {
+            chip8.program_counter = HIRES_START;
+            chip8.display_buffer = DisplayBuffer::from_rows(&[0; 64]);
+        }
+        Ok(chip8)
+    }
+
+    /// Switches to the display mode with the given height (see `DISPLAY_HEIGHTS`), which clears
+    /// the screen. Hires CHIP-8 programs are switched to 64x64 by `from_rom`.
+    pub fn set_display_height(&mut self, height: u8) -> Result<(), String> {
+        if !DISPLAY_HEIGHTS.contains(&height) {
+            return Err(format!("Unsupported display height: {}", height));
+        }
+        self.display_buffer = DisplayBuffer::from_rows(&vec![0; height as usize]);
+        Ok(())
+    }
+
+    /// Grows (or shrinks) memory to the given size, between `DEFAULT_MEMORY_SIZE` and
+    /// `MAX_MEMORY_SIZE`. Added memory is zeroed. Instructions that reach past the end of memory
+    /// fail, rather than wrapping around.
+    pub fn set_memory_size(&mut self, size: usize) -> Result<(), String> {
+        if !(DEFAULT_MEMORY_SIZE..=MAX_MEMORY_SIZE).contains(&size) {
+            return Err(format!(
+                "Unsupported memory size: {:#X} bytes (expected {:#X} to {:#X})",
+                size, DEFAULT_MEMORY_SIZE, MAX_MEMORY_SIZE
+            ));
+        }
+        self.resize_memory(size);
+        Ok(())
+    }
+
+    fn resize_memory(&mut self, size: usize) {
+        self.memory.resize(size, 0);
+        self.execution_counts.resize(size, 0);
+        self.decoded.resize(size, None);
+        if let Some(shadow_memory) = &mut self.shadow_memory {
+            shadow_memory.resize(size);
+        }
+    }
+
+    /// Makes room for the given number of return addresses on the stack (from 1 to 255). Calls
+    /// beyond that, and returns from an empty stack, fail rather than wrapping around.
+    pub fn set_stack_depth(&mut self, depth: u8) -> Result<(), String> {
+        if depth == 0 {
+            return Err("The stack needs room for at least one return address".to_owned());
+        }
+        if depth < self.stack_pointer {
+            return Err(format!(
+                "The stack holds {} return addresses, more than a depth of {}",
+                self.stack_pointer, depth
+            ));
+        }
+        self.stack.resize(depth as usize, 0);
+        Ok(())
+    }
+
+    /// Replaces the font with the given one: 16 digits of 5 bytes each (like `FONT_SPRITES`),
+    /// optionally followed by 16 digits of 10 bytes for Fx30 (like `LARGE_FONT_SPRITES`)
+    pub fn set_font(&mut self, font: &[u8]) -> Result<(), String> {
+        if font.len() != FONT_SPRITES.len() && font.len() != FONTS_END {
+            return Err(format!(
+                "A font needs {} bytes, or {} with the large digits, not {}",
+                FONT_SPRITES.len(),
+                FONTS_END,
+                font.len()
+            ));
+        }
+        self.write_memory(0, font)
+    }
+
+    /// Replaces the random number generator, which is seeded with the same number for every
+    /// machine
+    pub fn set_random(&mut self, random: Random) {
+        self.random = random;
+    }
+
+    /// Fills the memory after the program with bytes from the random number generator (so call
+    /// `set_random` first), like the memory of real hardware at power-on. Programs that count on
+    /// memory being zeroed then fail in the emulator too.
+    pub fn randomize_free_memory(&mut self) {
+        let start = self.loaded_end;
+        self.random.fill_bytes(&mut self.memory[start..]);
+        self.invalidate_decoded(start, self.memory.len() - start);
+    }
+
     pub fn handle_key_event(&mut self, key: u8, pressed: bool) {
         self.pressed_keys[key as usize] = pressed;
+        if pressed && self.sticky_keys {
+            self.unseen_presses |= 1 << key;
+        }
         if let Some(blocking_register) = self.register_blocking_on_key_press {
             if pressed {
                 self.registers[blocking_register as usize] = key;
@@ -127,351 +695,939 @@ impl Chip8 {
         }
     }
 
-    pub fn set_clock_frequency(&mut self, frequency: u32) {
+    pub fn pressed_keys(&self) -> &[bool; 16] {
+        &self.pressed_keys
+    }
+
+    pub fn sticky_keys(&self) -> bool {
+        self.sticky_keys
+    }
+
+    /// With sticky keys, a key press is seen by the next Ex9E or ExA1 that checks the key, even if
+    /// the key was released before. At low clock frequencies, a quick tap can otherwise come and
+    /// go between two checks.
+    pub fn set_sticky_keys(&mut self, sticky_keys: bool) {
+        self.sticky_keys = sticky_keys;
+        self.unseen_presses = 0;
+    }
+
+    /// Whether Ex9E and ExA1 see the key as pressed, which uses up a sticky press. Only the low
+    /// nibble of the register picks the key, as on the COSMAC VIP.
+    fn check_key(&mut self, key: u8) -> bool {
+        let key = key & 0xF;
+        let bit = 1 << key;
+        let pressed = self.pressed_keys[key as usize] || self.unseen_presses & bit != 0;
+        self.unseen_presses &= !bit;
+        pressed
+    }
+
+    /// The register that will receive the next key press, if blocked on Fx0A
+    pub fn waiting_for_key(&self) -> Option<u8> {
+        self.register_blocking_on_key_press
+    }
+
+    /// Sets the number of instructions per second, clamped to `MIN_CLOCK_FREQUENCY` to
+    /// `MAX_CLOCK_FREQUENCY` (and NaN is taken as the minimum)
+    pub fn set_clock_frequency(&mut self, frequency: f64) {
+        let frequency = if frequency.is_nan() {
+            MIN_CLOCK_FREQUENCY
+        } else {
+            frequency.clamp(MIN_CLOCK_FREQUENCY, MAX_CLOCK_FREQUENCY)
+        };
         self.clock_frequency = frequency;
-        self.clock_frequency_interval = 1.0 / frequency as f64;
+        self.clock_frequency_interval = 1.0 / frequency;
+    }
+
+    /// Speeds the clock up or slows it down by the factor, within the same range as
+    /// `set_clock_frequency`
+    pub fn multiply_clock_frequency(&mut self, multiplier: f64) {
+        self.set_clock_frequency(self.clock_frequency * multiplier);
+    }
+
+    pub fn clock_frequency(&self) -> f64 {
+        self.clock_frequency
+    }
+
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+        self.cycles_left_in_frame = 0;
+    }
+
+    pub fn cycle_costs(&self) -> bool {
+        self.cycle_costs
+    }
+
+    /// With `Timing::Clock`, makes each instruction take time in proportion to how long it took
+    /// on the COSMAC VIP (see `Instruction::vip_cost`), instead of every instruction taking the
+    /// same time. The clock frequency stays the rate of instructions of average cost.
+    pub fn set_cycle_costs(&mut self, cycle_costs: bool) {
+        self.cycle_costs = cycle_costs;
+    }
+
+    /// The time that the instruction takes with `Timing::Clock`
+    fn cycle_interval(&self, instruction: &Instruction) -> f64 {
+        if self.cycle_costs {
+            self.clock_frequency_interval * instruction.vip_cost() as f64 / AVERAGE_VIP_COST as f64
+        } else {
+            self.clock_frequency_interval
+        }
+    }
+
+    /// The number of instructions per frame with `Timing::Tickrate`
+    pub fn tickrate(&self) -> u32 {
+        ((self.clock_frequency / 60.0).round() as u32).max(1)
+    }
+
+    /// The number of instructions in each 60Hz frame (the tickrate, or the clock frequency
+    /// divided by 60 and rounded down)
+    pub fn cycles_per_frame(&self) -> u32 {
+        match self.timing {
+            Timing::Clock => ((self.clock_frequency / 60.0) as u32).max(1),
+            Timing::Tickrate => self.tickrate(),
+        }
+    }
+
+    /// Sets the clock frequency to the given number of instructions per frame, and switches to
+    /// `Timing::Tickrate`
+    pub fn set_tickrate(&mut self, tickrate: u32) {
+        self.set_clock_frequency(tickrate as f64 * 60.0);
+        self.set_timing(Timing::Tickrate);
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        if quirks.chip8x != self.quirks.chip8x {
+            // The op-codes decode differently
+            self.decoded.iter_mut().for_each(|entry| *entry = None);
+        }
+        self.quirks = quirks;
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// Where the program that the machine was created with ends in memory (the end of memory,
+    /// for `new`)
+    pub fn program_end(&self) -> usize {
+        self.loaded_end
+    }
+
+    pub fn flags(&self) -> &[u8] {
+        &self.flags
+    }
+
+    /// Sets the first flags (as many as are given, up to `FLAG_COUNT`)
+    pub fn set_flags(&mut self, flags: &[u8]) {
+        let count = flags.len().min(FLAG_COUNT);
+        self.flags[..count].copy_from_slice(&flags[..count]);
+    }
+
+    pub fn write_memory(&mut self, address: u16, bytes: &[u8]) -> Result<(), String> {
+        let start = address as usize;
+        let end = start + bytes.len();
+        if end > self.memory.len() {
+            return Err(format!("Address out of range: {:#05X}", address));
+        }
+        self.memory[start..end].copy_from_slice(bytes);
+        self.invalidate_decoded(start, bytes.len());
+        self.mark_initialized(start, bytes.len());
+        Ok(())
     }
 
-    pub fn multiply_clock_frequency(&mut self, multiplier: f32) {
-        let freq = self.clock_frequency as f32 * multiplier;
-        let max_frequency = 500_000;
-        if freq < 1.0 {
-            self.set_clock_frequency(1);
-        } else if freq >= max_frequency as f32 {
-            self.set_clock_frequency(max_frequency);
+    /// Toggles a breakpoint at the given address, returning true if it was added
+    pub fn toggle_breakpoint(&mut self, address: u16) -> bool {
+        if self.breakpoints.remove(&address) {
+            false
         } else {
-            if freq as u32 != self.clock_frequency {
-                self.set_clock_frequency(freq as u32);
-            } else if multiplier < 1.0 {
-                self.set_clock_frequency(self.clock_frequency - 1);
-            } else if multiplier > 1.0 {
-                self.set_clock_frequency(self.clock_frequency + 1);
+            self.breakpoints.insert(address);
+            true
+        }
+    }
+
+    pub fn breakpoints(&self) -> &HashSet<u16> {
+        &self.breakpoints
+    }
+
+    /// If the last update stopped because a breakpoint was reached, this is its address. The
+    /// instruction at the breakpoint is executed on the next update.
+    pub fn stopped_at_breakpoint(&self) -> Option<u16> {
+        self.stopped_at_breakpoint
+    }
+
+    /// Makes the following updates stop once the given address is reached, as if there was a
+    /// breakpoint there that is removed when hit
+    pub fn run_to(&mut self, address: u16) {
+        self.run_to_address = Some(address);
+    }
+
+    /// Executes exactly one instruction, letting the timers run for the duration of one cycle
+    pub fn step_instruction(&mut self) -> Result<StepReport, String> {
+        self.stopped_at_breakpoint = None;
+        let report = self.step()?;
+        match self.timing {
+            Timing::Clock => {
+                self.update_timers(self.cycle_interval(&report.decoded));
+            }
+            Timing::Tickrate => {
+                if self.cycles_left_in_frame == 0 {
+                    self.cycles_left_in_frame = self.tickrate();
+                }
+                self.end_tickrate_cycle();
             }
         }
+        Ok(report)
     }
 
-    pub fn clock_frequency(&self) -> u32 {
-        self.clock_frequency
+    /// A 64-bit FNV-1a hash of the ROM that the machine was created from (see `from_rom_at`),
+    /// which tells the save states of different programs apart
+    pub fn rom_hash(&self) -> u64 {
+        self.rom_hash
+    }
+
+    /// The cycles that the machine has run, including the ones spent waiting for a key (which
+    /// aren't saved with the state)
+    pub fn cycles(&self) -> u64 {
+        self.cycles
     }
 
-    pub fn update(&mut self, elapsed_time: f64) -> Result<u32, String> {
+    /// Executes instructions until the display changes, and returns how many were executed
+    /// (including the one that changed the display). Gives up after `max_cycles`.
+    pub fn run_until_draw(&mut self, max_cycles: u32) -> Result<u32, String> {
+        self.run_until(max_cycles, "the display to change", |_, step| {
+            step.display_changed
+        })
+    }
+
+    /// Executes instructions until one sets the sound timer (to a non-zero value), and returns
+    /// how many were executed. Gives up after `max_cycles`.
+    pub fn run_until_sound(&mut self, max_cycles: u32) -> Result<u32, String> {
+        self.run_until(
+            max_cycles,
+            "the sound timer to be set",
+            |chip8, step| match step.decoded {
+                Instruction::SetSound(x) => chip8.registers[x as usize] > 0,
+                _ => false,
+            },
+        )
+    }
+
+    /// Steps until `done` returns true for the machine and the instruction that was just executed
+    fn run_until<F>(&mut self, max_cycles: u32, what: &str, done: F) -> Result<u32, String>
+    where
+        F: Fn(&Chip8, &StepReport) -> bool,
+    {
+        for cycles in 1..=max_cycles {
+            let address = self.program_counter;
+            let step = self.step_instruction()?;
+            // While it's blocked on Fx0A, the machine doesn't execute anything
+            if self.program_counter != address && done(self, &step) {
+                return Ok(cycles);
+            }
+        }
+        Err(format!(
+            "Gave up waiting for {} after {} cycles",
+            what, max_cycles
+        ))
+    }
+
+    /// Runs the machine for one 60Hz frame
+    pub fn advance_frame(&mut self) -> Result<UpdateReport, String> {
+        self.update(INTERVAL_60_HZ)
+    }
+
+    /// Runs the machine for the elapsed time (in seconds). More than `MAX_UPDATE_TIME` is cut
+    /// down to it, so that the machine doesn't run for a long time to catch up after a stall.
+    pub fn update(&mut self, elapsed_time: f64) -> Result<UpdateReport, String> {
+        let _frame = debug_span!("frame", elapsed_time).entered();
+        let mut report = UpdateReport {
+            clamped: elapsed_time > MAX_UPDATE_TIME,
+            ..UpdateReport::default()
+        };
+        let elapsed_time = elapsed_time.min(MAX_UPDATE_TIME);
+        if self.timing == Timing::Tickrate {
+            self.update_with_tickrate(elapsed_time, &mut report)?;
+            return Ok(report);
+        }
         self.cycle_cooldown -= elapsed_time;
-        let mut cycles = 0;
+        let mut resuming_from_breakpoint = self.stopped_at_breakpoint.take();
         while self.cycle_cooldown <= 0.0 {
-            self.cycle_cooldown += self.clock_frequency_interval;
-            self.step()?;
-            cycles += 1;
+            if self.stop_at_breakpoint(&mut resuming_from_breakpoint) {
+                self.cycle_cooldown = 0.0;
+                break;
+            }
+            let step = self.step()?;
+            self.cycle_cooldown += self.cycle_interval(&step.decoded);
+            report.add_step(&step);
+        }
+
+        report.timer_ticks = self.update_timers(elapsed_time);
+        Ok(report)
+    }
+
+    /// Runs the instructions of every 60Hz frame that has started, and decrements the timers
+    /// after the last instruction of each frame
+    fn update_with_tickrate(
+        &mut self,
+        elapsed_time: f64,
+        report: &mut UpdateReport,
+    ) -> Result<(), String> {
+        self.timer_cooldown -= elapsed_time;
+        let mut resuming_from_breakpoint = self.stopped_at_breakpoint.take();
+        loop {
+            if self.cycles_left_in_frame == 0 {
+                if self.timer_cooldown > 0.0 {
+                    break;
+                }
+                self.timer_cooldown += INTERVAL_60_HZ;
+                self.cycles_left_in_frame = self.tickrate();
+            }
+            if self.stop_at_breakpoint(&mut resuming_from_breakpoint) {
+                // The timers keep running, like with `Timing::Clock`
+                while self.timer_cooldown <= 0.0 {
+                    self.timer_cooldown += INTERVAL_60_HZ;
+                    self.decrement_timers();
+                    report.timer_ticks += 1;
+                }
+                break;
+            }
+            let step = self.step()?;
+            report.add_step(&step);
+            if self.end_tickrate_cycle() {
+                report.timer_ticks += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns whether the frame ended, and the timers were decremented
+    fn end_tickrate_cycle(&mut self) -> bool {
+        self.cycles_left_in_frame -= 1;
+        if self.cycles_left_in_frame == 0 {
+            self.decrement_timers();
+            return true;
         }
+        false
+    }
 
+    /// Whether there's a breakpoint (or the address given to `run_to`) at the next instruction,
+    /// unless the update is resuming from that breakpoint
+    fn stop_at_breakpoint(&mut self, resuming_from_breakpoint: &mut Option<u16>) -> bool {
+        let pc = self.program_counter;
+        if resuming_from_breakpoint.take() == Some(pc)
+            || self.register_blocking_on_key_press.is_some()
+            || !(self.breakpoints.contains(&pc) || self.run_to_address == Some(pc))
+        {
+            return false;
+        }
+        if self.run_to_address == Some(pc) {
+            self.run_to_address = None;
+        }
+        self.stopped_at_breakpoint = Some(pc);
+        true
+    }
+
+    /// Returns how many times the timers were decremented
+    fn update_timers(&mut self, elapsed_time: f64) -> u32 {
         self.timer_cooldown -= elapsed_time;
+        let mut ticks = 0;
         while self.timer_cooldown <= 0.0 {
             self.timer_cooldown += INTERVAL_60_HZ;
-            if self.delay_timer > 0 {
-                self.delay_timer -= 1;
-            }
-            if self.sound_timer > 0 {
-                // TODO Add sound support
-                self.sound_timer -= 1;
+            self.decrement_timers();
+            ticks += 1;
+        }
+        ticks
+    }
+
+    fn decrement_timers(&mut self) {
+        self.emit(Chip8Event::TimerTick);
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
+            if self.sound_timer == 0 {
+                self.emit(Chip8Event::SoundStopped);
             }
         }
-        Ok(cycles)
     }
 
-    fn step(&mut self) -> Result<(), String> {
-        if self.register_blocking_on_key_press.is_some() {
-            return Ok(());
+    fn step(&mut self) -> Result<StepReport, String> {
+        self.cycles += 1;
+        if let Some(x) = self.register_blocking_on_key_press {
+            let pc = self.program_counter.wrapping_sub(2);
+            let opcode = match self.memory.get(pc as usize..pc as usize + 2) {
+                Some(bytes) => u16::from_be_bytes([bytes[0], bytes[1]]),
+                None => 0xF00A | (x as u16) << 8,
+            };
+            return Ok(StepReport {
+                pc,
+                opcode,
+                decoded: Instruction::WaitForKey(x),
+                display_changed: false,
+                cycles: self.cycles,
+            });
         }
 
         let addr = self.program_counter as usize;
-        debug(&format!("{:#05X}", addr));
+        self.check_address(addr, 2)?;
         let opcode = ((self.memory[addr] as u16) << 8) | self.memory[addr + 1] as u16;
-        self.program_counter += 2;
-        self.execute_opcode(opcode)
+        let _instruction = trace_span!(
+            "instruction",
+            address = %format_args!("{:#05X}", addr),
+            opcode = %format_args!("{:04X}", opcode)
+        )
+        .entered();
+        if self.history.len() == HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+        self.history.push_back(addr as u16);
+        self.execution_counts[addr] = self.execution_counts[addr].saturating_add(1);
+        // At the end of a 64K memory, only an instruction that jumps away can be executed
+        let overflowed = self.program_counter == u16::MAX - 1;
+        self.program_counter = self.program_counter.wrapping_add(2);
+        self.check_initialized(addr, 2);
+        let before = self.tracer.as_ref().map(|_| self.traced_registers());
+        let stack_pointer = self.stack_pointer;
+        let instruction = match self.decoded[addr] {
+            Some(instruction) => Ok(instruction),
+            None => self.decode(opcode).inspect(|&instruction| {
+                self.decoded[addr] = Some(instruction);
+            }),
+        };
+        let mut display_changed = false;
+        let instruction = instruction
+            .and_then(|instruction| {
+                if overflowed && !instruction.jumps() {
+                    return Err("Program counter overflowed past 0xFFFF".to_owned());
+                }
+                let display = instruction
+                    .may_change_display()
+                    .then(|| self.display_buffer.clone());
+                self.execute(instruction)?;
+                display_changed = display.is_some_and(|display| display != self.display_buffer);
+                Ok(instruction)
+            })
+            .map_err(|err| {
+                // Leave the failing instruction as the next one, so that it can be inspected
+                self.program_counter = addr as u16;
+                format!("{} at {:#05X}", err, addr)
+            })?;
+        self.opcode_coverage.record(instruction);
+        if let Some(profile) = &mut self.call_profile {
+            profile.record(stack_pointer, self.stack_pointer, self.program_counter);
+        }
+        if let Some(before) = before {
+            let after = self.traced_registers();
+            let quirks = self.quirks;
+            if let Some(tracer) = &mut self.tracer {
+                tracer
+                    .record(addr as u16, opcode, &before, &after, quirks)
+                    .map_err(|err| format!("Couldn't write the trace: {}", err))?;
+            }
+        }
+        Ok(StepReport {
+            pc: addr as u16,
+            opcode,
+            decoded: instruction,
+            display_changed,
+            cycles: self.cycles,
+        })
+    }
+
+    fn traced_registers(&self) -> Registers {
+        Registers {
+            v: self.registers,
+            i: self.address_register,
+            stack_pointer: self.stack_pointer,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+        }
+    }
+
+    /// Starts (or with None, stops) writing a line for each executed instruction
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
     }
 
-    fn push_program_counter(&mut self) {
+    /// Stops tracing, and returns the tracer so that it can be moved to another machine
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    /// Starts (or stops) counting the instructions executed in each stack of subroutine calls
+    pub fn profile_calls(&mut self, profile: bool) {
+        self.call_profile = if profile {
+            Some(CallProfile::default())
+        } else {
+            None
+        };
+    }
+
+    /// The counts since `profile_calls(true)`
+    pub fn call_profile(&self) -> Option<&CallProfile> {
+        self.call_profile.as_ref()
+    }
+
+    /// Starts (or stops) keeping track of which memory the program has written, and reporting the
+    /// reads of memory that it has neither written nor been loaded into. Memory that is written
+    /// while this is stopped doesn't count as initialized.
+    pub fn detect_uninitialized_reads(&mut self, detect: bool) {
+        self.shadow_memory = if detect {
+            Some(ShadowMemory::new(self.memory.len(), self.loaded_end))
+        } else {
+            None
+        };
+    }
+
+    /// The reads of uninitialized memory since `detect_uninitialized_reads(true)`, once for each
+    /// address and instruction
+    pub fn uninitialized_reads(&self) -> &[UninitializedRead] {
+        self.shadow_memory
+            .as_ref()
+            .map_or(&[], |shadow_memory| shadow_memory.reads())
+    }
+
+    /// Reports reads of uninitialized memory by the instruction that is being executed
+    fn check_initialized(&mut self, start: usize, len: usize) {
+        if let Some(shadow_memory) = &mut self.shadow_memory {
+            // The program counter has already moved past the instruction
+            let program_counter = self.program_counter.wrapping_sub(2);
+            shadow_memory.read(start, len, program_counter);
+        }
+    }
+
+    fn mark_initialized(&mut self, start: usize, len: usize) {
+        if let Some(shadow_memory) = &mut self.shadow_memory {
+            shadow_memory.write(start, len);
+        }
+    }
+
+    /// Guards the interpreter and font area (below 0x200) against writes by the program (see
+    /// `FontProtection`), or stops guarding it. Writes through `write_memory` are always allowed.
+    pub fn set_font_protection(&mut self, protection: Option<FontProtection>) {
+        self.font_protection = protection;
+    }
+
+    pub fn font_protection(&self) -> Option<FontProtection> {
+        self.font_protection
+    }
+
+    /// Warns about (or fails) a write to the font area by the instruction that is being executed
+    fn check_font_write(&mut self, start: usize) -> Result<(), String> {
+        if start >= PROGRAM_START as usize {
+            return Ok(());
+        }
+        // The program counter has already moved past the instruction
+        let program_counter = self.program_counter.wrapping_sub(2);
+        match self.font_protection {
+            Some(FontProtection::Error) => Err(format!(
+                "Write to the interpreter and font area at {:#05X}",
+                start
+            )),
+            Some(FontProtection::Warn) => {
+                if self.font_writers.insert(program_counter) {
+                    warn!(
+                        "The instruction at {:#05X} wrote to the interpreter and font area at {:#05X}",
+                        program_counter, start
+                    );
+                }
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Starts (or stops) keeping the events of the emulation for `take_events`. Starting again
+    /// keeps the events that haven't been taken yet.
+    pub fn record_events(&mut self, record: bool) {
+        if record {
+            self.events.get_or_insert_with(Vec::new);
+        } else {
+            self.events = None;
+        }
+    }
+
+    /// The events since the last call, in the order that they happened
+    pub fn take_events(&mut self) -> Vec<Chip8Event> {
+        self.events.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    fn emit(&mut self, event: Chip8Event) {
+        if let Some(events) = &mut self.events {
+            events.push(event);
+        }
+    }
+
+    /// The number of times an instruction has been executed at each address
+    pub fn execution_counts(&self) -> &[u32] {
+        &self.execution_counts
+    }
+
+    pub fn reset_execution_counts(&mut self) {
+        self.execution_counts
+            .iter_mut()
+            .for_each(|count| *count = 0);
+    }
+
+    /// The instructions that the program has written over after executing them (self-modifying
+    /// code), and the address of the instruction that last wrote to each
+    pub fn modified_code(&self) -> &BTreeMap<u16, u16> {
+        &self.modified_code
+    }
+
+    /// Which forms of instructions have been executed, and how often
+    pub fn opcode_coverage(&self) -> &OpcodeCoverage {
+        &self.opcode_coverage
+    }
+
+    pub fn reset_opcode_coverage(&mut self) {
+        self.opcode_coverage = OpcodeCoverage::default();
+    }
+
+    /// The addresses of the most recently executed instructions, oldest first
+    pub fn history(&self) -> &VecDeque<u16> {
+        &self.history
+    }
+
+    fn push_program_counter(&mut self) -> Result<(), String> {
+        if self.stack_pointer as usize == self.stack.len() {
+            return Err(format!(
+                "Stack overflow: more than {} nested calls",
+                self.stack.len()
+            ));
+        }
         self.stack[self.stack_pointer as usize] = self.program_counter;
         self.stack_pointer += 1;
+        Ok(())
     }
 
-    fn pop_program_counter(&mut self) {
+    fn pop_program_counter(&mut self) -> Result<(), String> {
+        if self.stack_pointer == 0 {
+            return Err("Stack underflow: return without a call".to_owned());
+        }
         self.stack_pointer -= 1;
         self.program_counter = self.stack[self.stack_pointer as usize];
+        Ok(())
     }
 
-    fn execute_opcode(&mut self, opcode: u16) -> Result<(), String> {
-        match opcode & 0xF000 {
-            0x0000 => match opcode {
-                0x00ee => {
-                    debug(&format!("[{:#06X}] return", opcode));
-                    self.pop_program_counter();
-                }
-                0x00e0 => {
-                    debug(&format!("[{:#06X}] clear screen", opcode));
-                    self.display_buffer.clear();
-                }
-                _ => {
-                    let address = opcode & 0x0FFF;
-                    debug(&format!(
-                        "[{:#06X}] call (machine): {:#05X}",
-                        opcode, address
-                    ));
-                    self.push_program_counter();
-                    self.program_counter = address;
-                }
-            },
-            0x1000 => {
-                let address = opcode & 0x0FFF;
-                debug(&format!("[{:#06X}] jump: {:#05X}", opcode, address));
-                self.program_counter = address;
+    /// Fails if an instruction would reach past the end of memory
+    /// Moves the program counter past the next instruction, for the skip instructions
+    fn skip_instruction(&mut self) -> Result<(), String> {
+        self.program_counter = self
+            .program_counter
+            .checked_add(2)
+            .ok_or("Program counter overflowed past 0xFFFF")?;
+        Ok(())
+    }
+
+    fn check_address(&self, start: usize, len: usize) -> Result<(), String> {
+        if start + len > self.memory.len() {
+            return Err(format!(
+                "Memory access out of range: {:#05X} (memory ends at {:#05X})",
+                start,
+                self.memory.len()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Decodes and executes an op-code (without caching the decoded instruction)
+    pub fn execute_opcode(&mut self, opcode: u16) -> Result<(), String> {
+        self.execute(self.decode(opcode)?)
+    }
+
+    /// Decodes an op-code the way this machine executes it, which depends on the `chip8x` quirk
+    pub fn decode(&self, opcode: u16) -> Result<Instruction, String> {
+        if self.quirks.chip8x {
+            Instruction::decode_chip8x(opcode)
+        } else {
+            Instruction::decode(opcode)
+        }
+    }
+
+    pub fn execute(&mut self, instruction: Instruction) -> Result<(), String> {
+        trace!("{}", instruction);
+        match instruction {
+            Instruction::ClearScreen => {
+                self.display_buffer.clear();
+                self.emit(Chip8Event::DrawOccurred);
+            }
+            Instruction::CallMachineCode(HIRES_CLEAR_SCREEN)
+                if self.display_buffer.height() == 64 =>
+            {
+                self.display_buffer.clear();
+                self.emit(Chip8Event::DrawOccurred);
             }
-            0x2000 => {
-                let address = opcode & 0x0FFF;
-                debug(&format!("[{:#06X}] call: {:#05X}", opcode, address));
-                self.push_program_counter();
+            Instruction::Return => {
+                self.pop_program_counter()?;
+                self.emit(Chip8Event::Return);
+            }
+            Instruction::CallMachineCode(address) | Instruction::Call(address) => {
+                self.push_program_counter()?;
                 self.program_counter = address;
+                self.emit(Chip8Event::Call(address));
             }
-            0x3000 => {
-                let a = ((opcode & 0x0F00) >> 8) as usize;
-                let constant = (opcode & 0x00FF) as u8;
-                debug(&format!(
-                    "[{:#06X}] skip if V{:X} == {:#04X}",
-                    opcode, a, constant
-                ));
-                if self.registers[a] == constant {
-                    self.program_counter += 2;
+            Instruction::Jump(address) => self.program_counter = address,
+            Instruction::SkipIfEqual(x, nn) => {
+                if self.registers[x as usize] == nn {
+                    self.skip_instruction()?;
                 }
             }
-            0x4000 => {
-                let a = ((opcode & 0x0F00) >> 8) as usize;
-                let constant = (opcode & 0x00FF) as u8;
-                debug(&format!(
-                    "[{:#06X}] skip if V{:X} != {:#04X}",
-                    opcode, a, constant
-                ));
-                if self.registers[a] != constant {
-                    self.program_counter += 2;
+            Instruction::SkipIfNotEqual(x, nn) => {
+                if self.registers[x as usize] != nn {
+                    self.skip_instruction()?;
                 }
             }
-            0x5000 => {
-                let a = ((opcode & 0x0F00) >> 8) as usize;
-                let b = ((opcode & 0x00F0) >> 4) as usize;
-                debug(&format!("[{:#06X}] skip if V{:X} == V{:X}", opcode, a, b));
-                if self.registers[a] == self.registers[b] {
-                    self.program_counter += 2;
+            Instruction::SkipIfRegistersEqual(x, y) => {
+                if self.registers[x as usize] == self.registers[y as usize] {
+                    self.skip_instruction()?;
                 }
             }
-            0x6000 => {
-                let a = ((opcode & 0x0F00) >> 8) as usize;
-                let constant = (opcode & 0x00FF) as u8;
-                debug(&format!("[{:#06X}] V{:X} = {:#04X}", opcode, a, constant));
-                self.registers[a] = constant;
-            }
-            0x7000 => {
-                let a = ((opcode & 0x0F00) >> 8) as usize;
-                let constant = (opcode & 0x00FF) as u8;
-                debug(&format!("[{:#06X}] V{:X} += {:#04X}", opcode, a, constant));
-                let result = self.registers[a].wrapping_add(constant);
-                self.registers[a] = result;
+            Instruction::Set(x, nn) => self.registers[x as usize] = nn,
+            Instruction::Add(x, nn) => {
+                self.registers[x as usize] = self.registers[x as usize].wrapping_add(nn);
             }
-            0x8000 => match opcode & 0x000F {
-                0x0 => {
-                    let a = ((opcode & 0x0F00) >> 8) as usize;
-                    let b = ((opcode & 0x00F0) >> 4) as usize;
-                    debug(&format!("[{:#06X}] V{:X} = V{:X}", opcode, a, b));
-                    self.registers[a] = self.registers[b];
+            Instruction::Copy(x, y) => self.registers[x as usize] = self.registers[y as usize],
+            Instruction::Or(x, y) => {
+                self.registers[x as usize] |= self.registers[y as usize];
+                if self.quirks.vf_reset {
+                    self.registers[0xF] = 0;
                 }
-                0x1 => {
-                    let a = ((opcode & 0x0F00) >> 8) as usize;
-                    let b = ((opcode & 0x00F0) >> 4) as usize;
-                    debug(&format!("[{:#06X}] V{:X} = V{:X} | V{:X}", opcode, a, a, b));
-                    self.registers[a] = self.registers[a] | self.registers[b];
-                }
-                0x2 => {
-                    let a = ((opcode & 0x0F00) >> 8) as usize;
-                    let b = ((opcode & 0x00F0) >> 4) as usize;
-                    debug(&format!("[{:#06X}] V{:X} = V{:X} & V{:X}", opcode, a, a, b));
-                    self.registers[a] = self.registers[a] & self.registers[b];
-                }
-                0x3 => {
-                    let a = ((opcode & 0x0F00) >> 8) as usize;
-                    let b = ((opcode & 0x00F0) >> 4) as usize;
-                    debug(&format!("[{:#06X}] V{:X} = V{:X} ^ V{:X}", opcode, a, a, b));
-                    self.registers[a] = self.registers[a] ^ self.registers[b];
-                }
-                0x4 => {
-                    let a = ((opcode & 0x0F00) >> 8) as usize;
-                    let b = ((opcode & 0x00F0) >> 4) as usize;
-                    debug(&format!("[{:#06X}] V{:X} = V{:X} + V{:X}", opcode, a, a, b));
-                    let result = self.registers[a] as u16 + self.registers[b] as u16;
-                    self.registers[a] = (result & 0xFF) as u8;
-                    self.registers[0xF] = if result > 0xFF { 1 } else { 0 };
-                }
-                0x5 => {
-                    let a = ((opcode & 0x0F00) >> 8) as usize;
-                    let b = ((opcode & 0x00F0) >> 4) as usize;
-                    debug(&format!("[{:#06X}] V{:X} = V{:X} - V{:X}", opcode, a, a, b));
-                    let result = self.registers[a] as i16 - self.registers[b] as i16;
-                    self.registers[a] = (result % 0x100i16) as u8;
-                    self.registers[0xF] = if result < 0 { 0 } else { 1 };
-                }
-                0x6 => {
-                    let a = ((opcode & 0x0F00) >> 8) as usize;
-                    debug(&format!("[{:#06X}] V{:X} >>= 1", opcode, a));
-                    self.registers[0xF] = if self.registers[a] & 1 == 1 { 1 } else { 0 };
-                    self.registers[a] >>= 1;
+            }
+            Instruction::And(x, y) => {
+                self.registers[x as usize] &= self.registers[y as usize];
+                if self.quirks.vf_reset {
+                    self.registers[0xF] = 0;
                 }
-                0x7 => {
-                    let a = ((opcode & 0x0F00) >> 8) as usize;
-                    let b = ((opcode & 0x00F0) >> 4) as usize;
-                    debug(&format!("[{:#06X}] V{:X} = V{:X} - V{:X}", opcode, a, b, a));
-                    let result = self.registers[b] as i16 - self.registers[a] as i16;
-                    self.registers[a] = (result % 0x100i16) as u8;
-                    self.registers[0xF] = if result < 0 { 0 } else { 1 };
+            }
+            Instruction::Xor(x, y) => {
+                self.registers[x as usize] ^= self.registers[y as usize];
+                if self.quirks.vf_reset {
+                    self.registers[0xF] = 0;
                 }
-                0xE => {
-                    let a = ((opcode & 0x0F00) >> 8) as usize;
-                    debug(&format!("[{:#06X}] V{:X} <<= 1", opcode, a));
-                    self.registers[0xF] = if self.registers[a] & 0b1000_0000 == 0b1000_0000 {
-                        1
-                    } else {
-                        0
-                    };
-                    self.registers[a] <<= 1;
+            }
+            Instruction::AddRegisters(x, y) => {
+                let (x, y) = (x as usize, y as usize);
+                let result = self.registers[x] as u16 + self.registers[y] as u16;
+                self.registers[x] = (result & 0xFF) as u8;
+                self.registers[0xF] = if result > 0xFF { 1 } else { 0 };
+            }
+            Instruction::Subtract(x, y) => {
+                let (x, y) = (x as usize, y as usize);
+                let result = self.registers[x] as i16 - self.registers[y] as i16;
+                self.registers[x] = (result % 0x100i16) as u8;
+                self.registers[0xF] = if result < 0 { 0 } else { 1 };
+            }
+            Instruction::ShiftRight(x, y) => {
+                let x = x as usize;
+                if self.quirks.shift_vy {
+                    self.registers[x] = self.registers[y as usize];
                 }
-                _ => return Err(format!("Unhandled op-code: {:#06X}", opcode)),
-            },
-            0x9000 => {
-                let a = ((opcode & 0x0F00) >> 8) as usize;
-                let b = ((opcode & 0x00F0) >> 4) as usize;
-                debug(&format!("[{:#06X}] skip if V{:X} != V{:X}", opcode, a, b));
-                if self.registers[a] != self.registers[b] {
-                    self.program_counter += 2;
+                let shifted_out = self.registers[x] & 1;
+                self.registers[x] >>= 1;
+                self.registers[0xF] = shifted_out;
+            }
+            Instruction::SubtractReversed(x, y) => {
+                let (x, y) = (x as usize, y as usize);
+                let result = self.registers[y] as i16 - self.registers[x] as i16;
+                self.registers[x] = (result % 0x100i16) as u8;
+                self.registers[0xF] = if result < 0 { 0 } else { 1 };
+            }
+            Instruction::ShiftLeft(x, y) => {
+                let x = x as usize;
+                if self.quirks.shift_vy {
+                    self.registers[x] = self.registers[y as usize];
                 }
+                let shifted_out = self.registers[x] >> 7;
+                self.registers[x] <<= 1;
+                self.registers[0xF] = shifted_out;
             }
-            0xA000 => {
-                let address = opcode & 0x0FFF;
-                debug(&format!("[{:#06X}] I = {:#04X}", opcode, address));
-                self.address_register = address;
+            Instruction::SkipIfRegistersNotEqual(x, y) => {
+                if self.registers[x as usize] != self.registers[y as usize] {
+                    self.skip_instruction()?;
+                }
             }
-            0xB000 => {
-                let address = opcode & 0x0FFF;
-                debug(&format!("[{:#06X}] jump to V0 + {:#04X}", opcode, address));
-                self.program_counter = self.registers[0] as u16 + address;
+            Instruction::SetI(address) => self.address_register = address,
+            Instruction::JumpWithOffset(x, address) => {
+                let register = if self.quirks.jump_vx { x as usize } else { 0 };
+                self.program_counter = self.registers[register] as u16 + address;
             }
-            0xC000 => {
-                let a = ((opcode & 0x0F00) >> 8) as usize;
-                let constant = (opcode & 0x00FF) as u8;
-                debug(&format!(
-                    "[{:#06X}] V{:#04X} = rand() & {:#04X}",
-                    opcode, a, constant
-                ));
+            Instruction::Random(x, nn) => {
                 let rnd = self.random.gen::<u8>();
-                self.registers[a] = rnd & constant;
+                self.registers[x as usize] = rnd & nn;
             }
-            0xD000 => {
-                let vx = ((opcode & 0x0F00) >> 8) as usize;
-                let vy = ((opcode & 0x00F0) >> 4) as usize;
-                let height = (opcode & 0x000F) as u8;
-                debug(&format!(
-                    "[{:#06X}] render(V{}, V{}, {})",
-                    opcode, vx, vy, height
-                ));
-
-                let x = self.registers[vx];
-                let y = self.registers[vy];
-
-                let mut any_pixel_flip = false;
+            Instruction::Draw(vx, vy, height) => {
+                let screen_height = self.display_buffer.height();
+                let x = self.registers[vx as usize] % SCREEN_WIDTH;
+                let y = self.registers[vy as usize] % screen_height;
+
+                self.check_address(self.address_register as usize, height as usize)?;
+                let mut collisions = 0;
                 for dy in 0..height {
-                    let row_data = self.memory[(self.address_register + dy as u16) as usize];
-                    for dx in 0..8 {
-                        if row_data & (1 << (7 - dx)) != 0 {
-                            self.display_buffer.flip_pixel(x + dx, y + dy);
-                            if !self.display_buffer.get_pixel(x + dx, y + dy) {
-                                any_pixel_flip = true;
-                            }
-                        }
+                    let row = y as usize + dy as usize;
+                    if self.quirks.clipping && row >= screen_height as usize {
+                        break;
                     }
+                    self.check_initialized((self.address_register + dy as u16) as usize, 1);
+                    let sprite = self.memory[(self.address_register + dy as u16) as usize];
+                    collisions |= self.display_buffer.draw_sprite_row(
+                        x,
+                        row % screen_height as usize,
+                        sprite,
+                        self.quirks.clipping,
+                    );
                 }
-                self.registers[0xF] = if any_pixel_flip { 1 } else { 0 };
+                self.registers[0xF] = if collisions != 0 { 1 } else { 0 };
+                self.emit(Chip8Event::DrawOccurred);
             }
-            0xE000 => match opcode & 0x00FF {
-                0x9E => {
-                    let a = ((opcode & 0x0F00) >> 8) as usize;
-                    debug(&format!("[{:#06X}] skip if V{:X} pressed", opcode, a));
-                    let key = self.registers[a];
-                    if self.pressed_keys[key as usize] {
-                        self.program_counter += 2;
-                    }
-                }
-                0xA1 => {
-                    let a = ((opcode & 0x0F00) >> 8) as usize;
-                    debug(&format!("[{:#06X}] skip if V{:X} not pressed", opcode, a));
-                    let key = self.registers[a];
-                    if !self.pressed_keys[key as usize] {
-                        self.program_counter += 2;
-                    }
-                }
-                _ => return Err(format!("Unhandled op-code: {:#06X}", opcode)),
-            },
-            0xF000 => match opcode & 0x00FF {
-                0x07 => {
-                    let a = ((opcode & 0x0F00) >> 8) as usize;
-                    debug(&format!("[{:#06X}] V{:X} = get_delay()", opcode, a));
-                    self.registers[a] = self.delay_timer;
+            Instruction::SkipIfPressed(x) => {
+                let key = self.registers[x as usize];
+                if self.check_key(key) {
+                    self.skip_instruction()?;
                 }
-                0x0A => {
-                    let a = ((opcode & 0x0F00) >> 8) as u8;
-                    debug(&format!("[{:#06X}] V{:X} = get_key()", opcode, a));
-                    self.register_blocking_on_key_press = Some(a);
+            }
+            Instruction::SkipIfNotPressed(x) => {
+                let key = self.registers[x as usize];
+                if !self.check_key(key) {
+                    self.skip_instruction()?;
                 }
-                0x15 => {
-                    let a = ((opcode & 0x0F00) >> 8) as usize;
-                    debug(&format!("[{:#06X}] I = delay_timer(V{:X})", opcode, a));
-                    self.delay_timer = self.registers[a];
+            }
+            Instruction::GetDelay(x) => self.registers[x as usize] = self.delay_timer,
+            Instruction::WaitForKey(x) => {
+                self.register_blocking_on_key_press = Some(x);
+                self.emit(Chip8Event::KeyWaited);
+            }
+            Instruction::SetDelay(x) => self.delay_timer = self.registers[x as usize],
+            Instruction::SetSound(x) => {
+                let was_playing = self.sound_timer > 0;
+                self.sound_timer = self.registers[x as usize];
+                match (was_playing, self.sound_timer > 0) {
+                    (false, true) => self.emit(Chip8Event::SoundStarted),
+                    (true, false) => self.emit(Chip8Event::SoundStopped),
+                    _ => {}
                 }
-                0x18 => {
-                    let a = ((opcode & 0x0F00) >> 8) as usize;
-                    debug(&format!("[{:#06X}] I = sound_timer(V{:X})", opcode, a));
-                    self.sound_timer = self.registers[a];
+            }
+            Instruction::AddToI(x) => {
+                self.address_register = self
+                    .address_register
+                    .wrapping_add(self.registers[x as usize] as u16);
+            }
+            Instruction::FontSprite(x) => {
+                self.address_register = self.registers[x as usize] as u16 * 5;
+            }
+            Instruction::LargeFontSprite(x) => {
+                self.address_register =
+                    LARGE_FONT_START as u16 + (self.registers[x as usize] & 0xF) as u16 * 10;
+            }
+            Instruction::Bcd(x) => {
+                let value = self.registers[x as usize];
+                let address = self.address_register as usize;
+                self.check_address(address, 3)?;
+                self.check_font_write(address)?;
+                self.memory[address] = value / 100;
+                self.memory[address + 1] = (value / 10) % 10;
+                self.memory[address + 2] = value % 10;
+                self.record_modified_code(address, 3);
+                self.invalidate_decoded(address, 3);
+                self.mark_initialized(address, 3);
+            }
+            Instruction::Store(end_index) => {
+                let end_index = end_index as usize;
+                let address = self.address_register as usize;
+                self.check_address(address, end_index + 1)?;
+                self.check_font_write(address)?;
+                for i in 0..end_index + 1 {
+                    self.memory[address + i] = self.registers[i];
                 }
-                0x1E => {
-                    let a = ((opcode & 0x0F00) >> 8) as usize;
-                    debug(&format!("[{:#06X}] I += V{:X}", opcode, a));
+                self.record_modified_code(address, end_index + 1);
+                self.invalidate_decoded(address, end_index + 1);
+                self.mark_initialized(address, end_index + 1);
+                if self.quirks.increment_i {
                     self.address_register =
-                        self.address_register.wrapping_add(self.registers[a] as u16);
-                }
-                0x29 => {
-                    let a = ((opcode & 0x0F00) >> 8) as usize;
-                    debug(&format!("[{:#06X}] I = sprite_addr(V{:X})", opcode, a));
-                    self.address_register = self.registers[a] as u16 * 5;
+                        self.address_register.wrapping_add(end_index as u16 + 1);
                 }
-                0x33 => {
-                    let a = ((opcode & 0x0F00) >> 8) as usize;
-                    debug(&format!("[{:#06X}] BCD(V{:X})", opcode, a));
-                    self.memory[self.address_register as usize] = self.registers[a] / 100;
-                    self.memory[self.address_register as usize + 1] = (self.registers[a] / 10) % 10;
-                    self.memory[self.address_register as usize + 2] = self.registers[a] % 10;
-                }
-                0x55 => {
-                    let end_index = ((opcode & 0x0F00) >> 8) as usize;
-                    debug(&format!("[{:#06X}] dump(V{:X})", opcode, end_index));
-                    for i in 0..end_index + 1 {
-                        self.memory[self.address_register as usize + i] = self.registers[i];
-                    }
+            }
+            Instruction::Load(end_index) => {
+                let end_index = end_index as usize;
+                self.check_address(self.address_register as usize, end_index + 1)?;
+                self.check_initialized(self.address_register as usize, end_index + 1);
+                for i in 0..end_index + 1 {
+                    self.registers[i] = self.memory[self.address_register as usize + i];
                 }
-                0x65 => {
-                    let end_index = ((opcode & 0x0F00) >> 8) as usize;
-                    debug(&format!("[{:#06X}] load(V{:X})", opcode, end_index));
-                    for i in 0..end_index + 1 {
-                        self.registers[i] = self.memory[self.address_register as usize + i];
-                    }
+                if self.quirks.increment_i {
+                    self.address_register =
+                        self.address_register.wrapping_add(end_index as u16 + 1);
                 }
-                _ => return Err(format!("Unhandled op-code: {:#06X}", opcode)),
-            },
-            _ => return Err(format!("Unhandled op-code: {:#06X}", opcode)),
+            }
+            Instruction::StoreFlags(x) => {
+                let count = x as usize + 1;
+                self.flags[..count].copy_from_slice(&self.registers[..count]);
+            }
+            Instruction::LoadFlags(x) => {
+                let count = x as usize + 1;
+                self.registers[..count].copy_from_slice(&self.flags[..count]);
+            }
+            Instruction::NextBackgroundColor => self.display_buffer.colors_mut().next_background(),
+            Instruction::AddNibbles(x, y) => {
+                let (vx, vy) = (self.registers[x as usize], self.registers[y as usize]);
+                self.registers[x as usize] = ((vx & 0x77) + (vy & 0x77)) & 0x77;
+            }
+            Instruction::SetZoneColor(x, y) => {
+                let columns = self.registers[x as usize];
+                let rows = self.registers[(x as usize + 1) % 16];
+                let color = self.registers[y as usize];
+                let height = self.display_buffer.height();
+                self.display_buffer
+                    .colors_mut()
+                    .color_zones(columns, rows, color, height);
+            }
+            Instruction::SetRowColor(x, y, n) => {
+                let pixel_x = self.registers[x as usize];
+                let pixel_y = self.registers[(x as usize + 1) % 16];
+                let color = self.registers[y as usize];
+                let height = self.display_buffer.height();
+                self.display_buffer
+                    .colors_mut()
+                    .color_rows(pixel_x, pixel_y, n, color, height);
+            }
+        }
+        Ok(())
+    }
+
+    /// Remembers the executed (decoded) instructions that overlap the bytes that the program is
+    /// writing to
+    fn record_modified_code(&mut self, start: usize, len: usize) {
+        let first = start.saturating_sub(1);
+        let end = (start + len).min(self.decoded.len());
+        // The program counter has already moved past the instruction
+        let writer = self.program_counter.wrapping_sub(2);
+        for address in first..end {
+            if self.decoded[address].is_some()
+                && self.modified_code.insert(address as u16, writer).is_none()
+            {
+                debug!(
+                    "The instruction at {:#05X} was modified by the one at {:#05X}",
+                    address, writer
+                );
+            }
         }
-        return Ok(());
+    }
+
+    /// Forgets the decoded instructions that overlap the given (modified) bytes
+    fn invalidate_decoded(&mut self, start: usize, len: usize) {
+        let first = start.saturating_sub(1);
+        let end = (start + len).min(self.decoded.len());
+        self.decoded[first..end]
+            .iter_mut()
+            .for_each(|entry| *entry = None);
     }
 }
 
@@ -516,6 +1672,24 @@ fn test_00ee_return() {
     assert_eq!(m.stack_pointer, 0);
 }
 
+#[test]
+fn test_stack_overflow_and_underflow() {
+    let mut m = Chip8::new([0; 0x1000]);
+    assert!(m.execute_opcode(0x00EE).is_err());
+
+    m.set_stack_depth(2).unwrap();
+    m.execute_opcode(0x2300).unwrap();
+    m.execute_opcode(0x2400).unwrap();
+    assert!(m.execute_opcode(0x2500).is_err());
+    assert_eq!(m.stack_pointer, 2);
+    assert!(m.set_stack_depth(1).is_err());
+
+    m.set_stack_depth(40).unwrap();
+    m.execute_opcode(0x2500).unwrap();
+    assert_eq!(m.stack[2], 0x400);
+    assert!(m.set_stack_depth(0).is_err());
+}
+
 #[test]
 fn test_1nnn_jump() {
     let mut m = Chip8::new([0; 0x1000]);
@@ -837,7 +2011,7 @@ fn test_bnnn_jump_to_v0_plus_constant() {
 fn test_cxnn_set_vx_to_random() {
     let mut m = Chip8::new([0; 0x1000]);
     m.address_register = 100;
-    m.random = Box::from(StdRng::seed_from_u64(222));
+    m.set_random(Random::seeded(222));
 
     // V3 = rand() & 0b11110010
     m.execute_opcode(0xC3F2).unwrap();
@@ -911,6 +2085,176 @@ fn test_dxyn_draw_2_rows_no_carry() {
     assert_eq!(m.registers[0xF], 0)
 }
 
+#[test]
+fn test_dxyn_draw_wraps_around() {
+    let mut m = Chip8::new([0; 0x1000]);
+    m.address_register = 100;
+    m.memory[100] = 0b1111_0000;
+    m.memory[101] = 0b1000_0001;
+    m.registers[0x1] = 62;
+    m.registers[0x2] = 31;
+    m.display_buffer.flip_pixel(63, 31);
+
+    // draw(62, 31, 2), which wraps around to the left edge and to the top
+    m.execute_opcode(0xD122).unwrap();
+
+    assert!(m.display_buffer.get_pixel(62, 31));
+    assert!(!m.display_buffer.get_pixel(63, 31));
+    assert!(m.display_buffer.get_pixel(0, 31));
+    assert!(m.display_buffer.get_pixel(1, 31));
+    assert!(!m.display_buffer.get_pixel(2, 31));
+    assert!(m.display_buffer.get_pixel(62, 0));
+    assert!(m.display_buffer.get_pixel(5, 0));
+    assert_eq!(m.registers[0xF], 1)
+}
+
+#[test]
+fn test_run_until_draw_and_sound() {
+    // V0 = 0x05, V1 = 0x02, V1 -= 1 until it's 0, draw the "0" sprite, sound timer = V0, loop
+    let rom = [
+        0x60, 0x05, 0x61, 0x02, 0x71, 0xFF, 0x31, 0x00, 0x12, 0x04, 0xD2, 0x25, 0xF0, 0x18, 0x12,
+        0x0E,
+    ];
+    let mut m = Chip8::from_rom(&rom).unwrap();
+    assert_eq!(m.run_until_draw(100), Ok(8));
+    assert!(m.display_buffer.get_pixel(0, 0));
+    assert_eq!(m.run_until_sound(100), Ok(1));
+    assert!(m.sound_timer > 0);
+    assert_eq!(
+        m.run_until_draw(10),
+        Err("Gave up waiting for the display to change after 10 cycles".to_owned())
+    );
+}
+
+#[test]
+fn test_tickrate() {
+    // V0 += 1, jump to 0x200
+    let mut m = Chip8::from_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+    m.set_tickrate(5);
+    assert_eq!(m.clock_frequency(), 300.0);
+    m.delay_timer = 10;
+
+    // All the instructions of a frame run in the update where the frame starts
+    assert_eq!(m.update(INTERVAL_60_HZ * 0.5).unwrap().cycles, 5);
+    assert_eq!(m.delay_timer, 9);
+    assert_eq!(m.update(INTERVAL_60_HZ * 0.4).unwrap().cycles, 0);
+    assert_eq!(m.update(INTERVAL_60_HZ * 0.2).unwrap().cycles, 5);
+    assert_eq!(m.update(INTERVAL_60_HZ * 2.0).unwrap().cycles, 10);
+    assert_eq!(m.delay_timer, 6);
+
+    m.toggle_breakpoint(0x202);
+    assert_eq!(m.update(INTERVAL_60_HZ).unwrap().cycles, 1);
+    assert_eq!(m.stopped_at_breakpoint(), Some(0x202));
+    m.toggle_breakpoint(0x202);
+    assert_eq!(m.update(0.0).unwrap().cycles, 4);
+    assert_eq!(m.delay_timer, 5);
+}
+
+#[test]
+fn test_update_report() {
+    // Draw the digit 0, loop
+    let mut m = Chip8::from_rom(&[0xD0, 0x05, 0x12, 0x02]).unwrap();
+    m.set_clock_frequency(100.0);
+    assert_eq!(
+        m.update(0.015),
+        Ok(UpdateReport {
+            cycles: 2,
+            timer_ticks: 1,
+            display_changed: true,
+            clamped: false,
+        })
+    );
+    let report = m.update(0.012).unwrap();
+    assert_eq!((report.cycles, report.display_changed), (1, false));
+
+    // After a stall, only a second is caught up on
+    let report = m.update(10.0).unwrap();
+    assert!(report.clamped);
+    assert!(report.cycles <= 101);
+    assert!(report.timer_ticks <= 61);
+}
+
+#[test]
+fn test_clock_frequency() {
+    let mut m = Chip8::from_rom(&[]).unwrap();
+    m.set_clock_frequency(700.0);
+    m.multiply_clock_frequency(1.25);
+    assert_eq!(m.clock_frequency(), 875.0);
+    // Small factors still change the frequency
+    m.set_clock_frequency(2.0);
+    m.multiply_clock_frequency(1.25);
+    assert_eq!(m.clock_frequency(), 2.5);
+
+    m.multiply_clock_frequency(0.0);
+    assert_eq!(m.clock_frequency(), MIN_CLOCK_FREQUENCY);
+    m.set_clock_frequency(1e9);
+    assert_eq!(m.clock_frequency(), MAX_CLOCK_FREQUENCY);
+    m.set_clock_frequency(f64::NAN);
+    assert_eq!(m.clock_frequency(), MIN_CLOCK_FREQUENCY);
+}
+
+#[test]
+fn test_cycle_costs() {
+    // Clear the screen, then jump to the jump
+    let mut m = Chip8::from_rom(&[0x00, 0xE0, 0x12, 0x02]).unwrap();
+    m.set_clock_frequency(100.0);
+    m.set_cycle_costs(true);
+    // Clearing the screen takes 3118 VIP cycles, as long as 31 average instructions
+    assert_eq!(m.update(0.001).unwrap().cycles, 1);
+    assert_eq!(m.update(0.3).unwrap().cycles, 0);
+    // A jump takes 52 cycles, about half an average instruction
+    assert_eq!(m.update(0.1).unwrap().cycles, 18);
+
+    m.set_cycle_costs(false);
+    assert_eq!(m.update(0.1).unwrap().cycles, 10);
+    assert!(
+        Instruction::Draw(0, 0, 15).vip_cost() > 10 * Instruction::Draw(0, 0, 1).vip_cost() / 2
+    );
+}
+
+#[test]
+fn test_display_buffer_accessors() {
+    let mut rows = [0; 32];
+    rows[1] = 0x8000_0000_0000_0001;
+    let display = DisplayBuffer::from_rows(&rows);
+    assert!(display[(0, 1)]);
+    assert!(display[(63, 1)]);
+    assert!(!display[(1, 1)]);
+    assert!(display[(64, 33)]);
+
+    let lit: Vec<usize> = display
+        .pixels()
+        .enumerate()
+        .filter(|&(_, lit)| lit)
+        .map(|(i, _)| i)
+        .collect();
+    assert_eq!(lit, [64, 127]);
+
+    let bytes = display.as_bytes();
+    assert_eq!(bytes.len(), 256);
+    assert_eq!(bytes[8..16], [0x80, 0, 0, 0, 0, 0, 0, 0x01]);
+    assert_eq!(
+        display.to_rgba([1, 1, 1, 1], [0, 0, 0, 0])[4 * 64..4 * 66],
+        [1, 1, 1, 1, 0, 0, 0, 0]
+    );
+}
+
+#[test]
+fn test_display_buffer_export() {
+    let mut rows = [0; 32];
+    rows[0] = 0xC000_0000_0000_0000;
+    let display = DisplayBuffer::from_rows(&rows);
+
+    let mut pbm = Vec::new();
+    display.write_pbm(&mut pbm).unwrap();
+    assert!(pbm.starts_with(b"P4\n64 32\n\xC0\0"));
+    assert_eq!(pbm.len(), 9 + 256);
+
+    let ascii = display.to_ascii();
+    assert_eq!(ascii.lines().count(), 32);
+    assert!(ascii.starts_with("##...."));
+}
+
 #[test]
 fn test_ex9e_skip_if_vx_pressed_true() {
     let mut m = Chip8::new([0; 0x1000]);
@@ -937,6 +2281,53 @@ fn test_exa1_skip_if_vx_not_pressed_false() {
     assert_eq!(m.program_counter, 20);
 }
 
+#[test]
+fn test_ex9e_exa1_with_vx_over_f() {
+    let mut m = Chip8::new([0; 0x1000]);
+    m.program_counter = 20;
+    m.registers[0x7] = 0x10;
+    m.set_sticky_keys(true);
+
+    // Key 0 isn't pressed
+    m.execute_opcode(0xE79E).unwrap();
+    assert_eq!(m.program_counter, 20);
+
+    m.handle_key_event(0x0, true);
+    m.execute_opcode(0xE79E).unwrap();
+    assert_eq!(m.program_counter, 22);
+    m.execute_opcode(0xE7A1).unwrap();
+    assert_eq!(m.program_counter, 22);
+}
+
+#[test]
+fn test_sticky_keys() {
+    // Skip the next instruction if key V0 is pressed
+    let mut m = Chip8::new([0; 0x1000]);
+    m.program_counter = 20;
+    m.handle_key_event(0xB, true);
+    m.handle_key_event(0xB, false);
+    m.registers[0x0] = 0xB;
+    m.execute_opcode(0xE09E).unwrap();
+    assert_eq!(m.program_counter, 20);
+
+    // The tap is seen once
+    m.set_sticky_keys(true);
+    m.handle_key_event(0xB, true);
+    m.handle_key_event(0xB, false);
+    m.execute_opcode(0xE09E).unwrap();
+    assert_eq!(m.program_counter, 22);
+    m.execute_opcode(0xE09E).unwrap();
+    assert_eq!(m.program_counter, 22);
+
+    // ExA1 uses it up too
+    m.handle_key_event(0xB, true);
+    m.handle_key_event(0xB, false);
+    m.execute_opcode(0xE0A1).unwrap();
+    assert_eq!(m.program_counter, 22);
+    m.execute_opcode(0xE0A1).unwrap();
+    assert_eq!(m.program_counter, 24);
+}
+
 #[test]
 fn test_fx07_set_vx_to_delay_timer() {
     let mut m = Chip8::new([0; 0x1000]);
@@ -1002,7 +2393,7 @@ fn test_fx29_set_i_to_font_sprite_address_0() {
     // I = sprite_addr(VB)
     m.execute_opcode(0xFB29).unwrap();
 
-    assert_eq!(m.address_register, 5 * 0x0);
+    assert_eq!(m.address_register, 0);
 }
 
 #[test]
@@ -1085,11 +2476,385 @@ fn test_receiving_key_press_while_blocking() {
     assert_eq!(m.registers[0x3], 0x8);
 }
 
+#[test]
+fn test_display_buffer_to_rgba() {
+    let mut display_buffer = DisplayBuffer::new();
+    display_buffer.flip_pixel(1, 0);
+
+    let rgba = display_buffer.to_rgba([255, 255, 255, 255], [0, 0, 0, 255]);
+
+    assert_eq!(rgba.len(), 4 * 64 * 32);
+    assert_eq!(&rgba[0..8], [0, 0, 0, 255, 255, 255, 255, 255]);
+}
+
+#[test]
+fn test_write_memory() {
+    let mut m = Chip8::new([0; 0x1000]);
+
+    m.write_memory(0x2A0, &[0x63, 0x10]).unwrap();
+
+    assert_eq!(&m.memory()[0x2A0..0x2A2], [0x63, 0x10]);
+    assert!(m.write_memory(0xFFF, &[0x63, 0x10]).is_err());
+}
+
+#[test]
+fn test_step_report() {
+    // draw the digit 0, wait for a key into V1
+    let mut chip8 = Chip8::from_rom(&[0xD0, 0x05, 0xF1, 0x0A]).unwrap();
+    let step = chip8.step_instruction().unwrap();
+    assert_eq!(
+        step,
+        StepReport {
+            pc: 0x200,
+            opcode: 0xD005,
+            decoded: Instruction::Draw(0, 0, 5),
+            display_changed: true,
+            cycles: 1,
+        }
+    );
+    let step = chip8.step_instruction().unwrap();
+    assert_eq!(step.decoded, Instruction::WaitForKey(1));
+    assert!(!step.display_changed);
+    let step = chip8.step_instruction().unwrap();
+    assert_eq!((step.pc, step.opcode, step.cycles), (0x202, 0xF10A, 3));
+    assert_eq!(chip8.cycles(), 3);
+}
+
+#[test]
+fn test_set_font() {
+    // point I at the large digit 2, then at the small digit 2
+    let mut chip8 = Chip8::from_rom(&[0x60, 0x02, 0xF0, 0x30, 0xF0, 0x29]).unwrap();
+    assert!(chip8.set_font(&[0xFF; 100]).is_err());
+    let mut font = [FONT_SPRITES.as_slice(), &LARGE_FONT_SPRITES].concat();
+    font[10] = 0xAA;
+    font[0x64] = 0xBB;
+    chip8.set_font(&font).unwrap();
+    for _ in 0..2 {
+        chip8.step_instruction().unwrap();
+    }
+    assert_eq!(chip8.address_register, 0x64);
+    assert_eq!(chip8.memory[0x64], 0xBB);
+    chip8.step_instruction().unwrap();
+    assert_eq!(chip8.address_register, 10);
+    assert_eq!(chip8.memory[10], 0xAA);
+}
+
+#[test]
+fn test_font_protection() {
+    let mut m = Chip8::from_rom(&[0xF0, 0x55, 0xF0, 0x55]).unwrap();
+    m.address_register = 0x1F0;
+    m.registers[0] = 0xAA;
+    m.set_font_protection(Some(FontProtection::Error));
+    assert!(m.step_instruction().is_err());
+    assert_eq!(m.memory()[0x1F0], 0);
+    assert_eq!(m.program_counter, 0x200);
+
+    m.set_font_protection(Some(FontProtection::Warn));
+    m.step_instruction().unwrap();
+    assert_eq!(m.memory()[0x1F0], 0xAA);
+
+    m.set_font_protection(Some(FontProtection::Error));
+    m.address_register = 0x300;
+    m.step_instruction().unwrap();
+    m.write_memory(0x000, &[0xFF]).unwrap();
+    assert!(FontProtection::parse("ignore").is_err());
+}
+
+#[test]
+fn test_set_memory_size() {
+    let mut m = Chip8::new([0; 0x1000]);
+    m.address_register = 0xFFE;
+    m.registers[0] = 0x12;
+    // Fx55 and Fx33 past the end of memory
+    assert!(m.execute_opcode(0xF255).is_err());
+    assert!(m.execute_opcode(0xF033).is_err());
+
+    m.set_memory_size(0x10000).unwrap();
+    m.execute_opcode(0xF033).unwrap();
+    assert_eq!(&m.memory()[0xFFE..0x1001], [0, 1, 8]);
+    m.write_memory(0xFFFE, &[0x12, 0x00]).unwrap();
+    m.program_counter = 0xFFFE;
+    m.step_instruction().unwrap();
+    assert_eq!(m.program_counter, 0x200);
+    m.program_counter = 0xFFFF;
+    assert!(m.step_instruction().is_err());
+
+    // V0 = 1 at 0xFFFE, and a skip at 0xFFFC, would continue past the end of memory
+    m.write_memory(0xFFFC, &[0x30, 0x00, 0x60, 0x01]).unwrap();
+    m.program_counter = 0xFFFE;
+    assert!(m.step_instruction().is_err());
+    assert_eq!(m.program_counter, 0xFFFE);
+    assert_eq!(m.registers[0], 0x12);
+    m.registers[0] = 0;
+    m.program_counter = 0xFFFC;
+    assert!(m.step_instruction().is_err());
+    assert_eq!(m.program_counter, 0xFFFC);
+
+    assert!(m.set_memory_size(0x800).is_err());
+    assert!(m.set_memory_size(0x10001).is_err());
+}
+
+#[test]
+fn test_update_stops_at_breakpoint() {
+    let mut memory = [0; 0x1000];
+    // V0 += 1, jump to 0x200
+    memory[0x200..0x204].copy_from_slice(&[0x70, 0x01, 0x12, 0x00]);
+    let mut m = Chip8::new(memory);
+    m.set_clock_frequency(100.0);
+    m.toggle_breakpoint(0x202);
+
+    let cycles = m.update(1.0).unwrap().cycles;
+
+    assert_eq!(cycles, 1);
+    assert_eq!(m.program_counter, 0x202);
+    assert_eq!(m.stopped_at_breakpoint(), Some(0x202));
+}
+
+#[test]
+fn test_update_resumes_from_breakpoint() {
+    let mut memory = [0; 0x1000];
+    // V0 += 1, jump to 0x200
+    memory[0x200..0x204].copy_from_slice(&[0x70, 0x01, 0x12, 0x00]);
+    let mut m = Chip8::new(memory);
+    m.set_clock_frequency(100.0);
+    m.toggle_breakpoint(0x202);
+    m.update(1.0).unwrap();
+
+    let cycles = m.update(1.0).unwrap().cycles;
+
+    assert_eq!(cycles, 2);
+    assert_eq!(m.registers[0], 2);
+    assert_eq!(m.stopped_at_breakpoint(), Some(0x202));
+}
+
+#[test]
+fn test_step_instruction() {
+    let mut memory = [0; 0x1000];
+    // V0 += 1, jump to 0x200
+    memory[0x200..0x204].copy_from_slice(&[0x70, 0x01, 0x12, 0x00]);
+    let mut m = Chip8::new(memory);
+    m.toggle_breakpoint(0x200);
+
+    m.step_instruction().unwrap();
+
+    assert_eq!(m.registers[0], 1);
+    assert_eq!(m.program_counter, 0x202);
+}
+
+#[test]
+fn test_run_to() {
+    let mut memory = [0; 0x1000];
+    // V0 += 1, jump to 0x200
+    memory[0x200..0x204].copy_from_slice(&[0x70, 0x01, 0x12, 0x00]);
+    let mut m = Chip8::new(memory);
+    m.set_clock_frequency(100.0);
+    m.run_to(0x202);
+
+    assert_eq!(m.update(1.0).unwrap().cycles, 1);
+    assert_eq!(m.stopped_at_breakpoint(), Some(0x202));
+    assert!(m.update(1.0).unwrap().cycles > 1);
+    assert_eq!(m.stopped_at_breakpoint(), None);
+}
+
+#[test]
+fn test_from_rom() {
+    let m = Chip8::from_rom(&[0x12, 0x00]).unwrap();
+
+    assert_eq!(m.memory()[..FONT_SPRITES.len()], FONT_SPRITES[..]);
+    assert_eq!(m.memory()[0x200..0x202], [0x12, 0x00]);
+    assert_eq!(m.memory().len(), DEFAULT_MEMORY_SIZE);
+
+    // A ROM that doesn't fit in 4K gets all of the 64K
+    let mut rom = vec![0; 0x2000];
+    rom[0x1FFE..].copy_from_slice(&[0x12, 0x00]);
+    let m = Chip8::from_rom(&rom).unwrap();
+    assert_eq!(m.memory().len(), MAX_MEMORY_SIZE);
+    assert_eq!(m.memory()[0x21FE..0x2200], [0x12, 0x00]);
+    assert_eq!(m.memory()[..FONT_SPRITES.len()], FONT_SPRITES[..]);
+    assert!(Chip8::from_rom(&[0; 0xFE01]).is_err());
+
+    let m = Chip8::from_rom_at(&[0x16, 0x00], ETI_660_START).unwrap();
+    assert_eq!(m.program_counter, 0x600);
+    assert_eq!(m.memory()[0x600..0x602], [0x16, 0x00]);
+    assert!(Chip8::from_rom_at(&[0; 0xFA01], 0x600).is_err());
+    assert!(Chip8::from_rom_at(&[0x12, 0x00], 0x10).is_err());
+    assert!(Chip8::from_rom_at(&[0x12, 0x00], 0xA0).is_err());
+
+    assert_eq!(parse_start_address("eti660"), Ok(0x600));
+    assert_eq!(parse_start_address("0x300"), Ok(0x300));
+    assert_eq!(parse_start_address("2A0"), Ok(0x2A0));
+    assert!(parse_start_address("0x1000").is_err());
+
+    assert_eq!(parse_clock_frequency("700"), Ok(700.0));
+    assert_eq!(parse_clock_frequency(" 0.5 "), Ok(0.5));
+    for invalid in &["0", "-60", "NaN", "inf", "fast"] {
+        assert!(parse_clock_frequency(invalid).is_err());
+    }
+
+    assert_eq!(parse_memory_size("10000"), Ok(0x10000));
+    assert_eq!(parse_memory_size("0x2000"), Ok(0x2000));
+    assert!(parse_memory_size("800").is_err());
+    assert!(parse_memory_size("10001").is_err());
+}
+
+#[test]
+fn test_display_modes() {
+    // Hires programs jump over their interpreter patch, which isn't needed here
+    let mut m = Chip8::from_rom(&[0x12, 0x60]).unwrap();
+    assert_eq!(m.program_counter, 0x2C0);
+    assert_eq!(m.display_buffer.height(), 64);
+    m.display_buffer.flip_pixel(0, 63);
+    m.execute(Instruction::CallMachineCode(0x230)).unwrap();
+    assert!(!m.display_buffer.get_pixel(0, 63));
+
+    // A sprite at row 40 wraps around in 64x32, but not in 64x48
+    let mut m = Chip8::from_rom(&[0x00, 0xE0]).unwrap();
+    assert_eq!(m.display_buffer.height(), 32);
+    m.set_display_height(48).unwrap();
+    m.registers[1] = 40;
+    m.address_register = 0;
+    m.execute(Instruction::Draw(0, 1, 1)).unwrap();
+    assert!(m.display_buffer.get_pixel(0, 40));
+    assert!(!m.display_buffer.get_pixel(0, 8));
+    assert_eq!(m.display_buffer.rows().len(), 48);
+    assert!(m.set_display_height(40).is_err());
+
+    assert_eq!(parse_display_mode("64x48"), Ok(48));
+    assert!(parse_display_mode("128x64").is_err());
+}
+
+#[test]
+fn test_update_error_stops_at_failing_instruction() {
+    let mut memory = [0; 0x1000];
+    // V0 += 1, invalid
+    memory[0x200..0x204].copy_from_slice(&[0x70, 0x01, 0xF0, 0xFF]);
+    let mut m = Chip8::new(memory);
+    m.set_clock_frequency(100.0);
+
+    let err = m.update(1.0).unwrap_err();
+
+    assert_eq!(err, "Unhandled op-code: 0xF0FF at 0x202");
+    assert_eq!(m.program_counter, 0x202);
+    assert_eq!(
+        m.history().iter().copied().collect::<Vec<u16>>(),
+        [0x200, 0x202]
+    );
+}
+
+#[test]
+fn test_execution_counts() {
+    let mut memory = [0; 0x1000];
+    // V0 += 1, V0 += 1, jump to 0x202
+    memory[0x200..0x206].copy_from_slice(&[0x70, 0x01, 0x70, 0x01, 0x12, 0x02]);
+    let mut m = Chip8::new(memory);
+
+    for _ in 0..5 {
+        m.step_instruction().unwrap();
+    }
+
+    assert_eq!(m.execution_counts()[0x200..0x206], [1, 0, 2, 0, 2, 0]);
+    m.reset_execution_counts();
+    assert!(m.execution_counts().iter().all(|&count| count == 0));
+}
+
+#[test]
+fn test_opcode_coverage() {
+    let mut memory = [0; 0x1000];
+    // V0 += 1, V0 += 1, jump to 0x202, and an unhandled op-code at 0x206
+    memory[0x200..0x208].copy_from_slice(&[0x70, 0x01, 0x70, 0x01, 0x12, 0x02, 0xFF, 0xFF]);
+    let mut m = Chip8::new(memory);
+
+    for _ in 0..5 {
+        m.step_instruction().unwrap();
+    }
+    m.program_counter = 0x206;
+    assert!(m.step_instruction().is_err());
+
+    let coverage = m.opcode_coverage();
+    assert_eq!(coverage.counts()[opcode_form(0x7001).unwrap()], 3);
+    assert_eq!(coverage.counts()[opcode_form(0x1202).unwrap()], 2);
+    assert_eq!(coverage.missing().len(), OPCODE_FORMS.len() - 2);
+    assert!(coverage
+        .summary()
+        .starts_with("Executed 2 of 38 opcode forms"));
+    m.reset_opcode_coverage();
+    assert!(m.opcode_coverage().counts().iter().all(|&count| count == 0));
+}
+
+#[test]
+fn test_self_modifying_code() {
+    let rom = [
+        0x60, 0x72, // V0 = 0x72
+        0x61, 0x05, // V1 = 0x05
+        0xA2, 0x0E, // I = 0x20E
+        0x22, 0x0E, // call 0x20E
+        0xF1, 0x55, // overwrite the instruction at 0x20E with V0 and V1
+        0x22, 0x0E, // call 0x20E again
+        0x12, 0x0C, // loop forever
+        0x72, 0x01, // V2 += 1 (and then V2 += 5)
+        0x00, 0xEE, // return
+    ];
+    let mut m = Chip8::from_rom(&rom).unwrap();
+    for _ in 0..10 {
+        m.step_instruction().unwrap();
+    }
+    assert_eq!(m.registers[2], 6);
+
+    m.write_memory(0x20E, &[0x72, 0x10]).unwrap();
+    m.program_counter = 0x20E;
+    m.step_instruction().unwrap();
+    assert_eq!(m.registers[2], 0x16);
+}
+
+#[test]
+fn test_trace() {
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut memory = [0; 0x1000];
+    // VF = 0x01, V0 += 1, jump to 0x202
+    memory[0x200..0x206].copy_from_slice(&[0x6F, 0x01, 0x70, 0x01, 0x12, 0x02]);
+    let buffer = Arc::new(Mutex::new(Vec::new()));
+    let mut m = Chip8::new(memory);
+    let writer = Box::new(SharedBuffer(buffer.clone()));
+    m.set_tracer(Some(Tracer::new(writer, TraceFilter::All)));
+    for _ in 0..3 {
+        m.step_instruction().unwrap();
+    }
+    m.set_tracer(Some(Tracer::new(
+        Box::new(SharedBuffer(buffer.clone())),
+        TraceFilter::VfWrites,
+    )));
+    for _ in 0..4 {
+        m.step_instruction().unwrap();
+    }
+
+    let trace = String::from_utf8(buffer.lock().unwrap().clone()).unwrap();
+    let lines: Vec<&str> = trace.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("       0 200 6F01 "));
+    assert!(lines[0].ends_with(" VF=0x01"));
+    assert!(lines[1].ends_with(" V0=0x01"));
+    assert!(lines[2].starts_with("       2 204 1202 "));
+}
+
 #[test]
 fn test_rom() {
     use std::fs::File;
     use std::io::Read;
-    let mut f = File::open("test_opcode.ch8").expect("Open test file");
+    let mut f = File::open("programs/test_opcode.ch8").expect("Open test file");
     let mut buffer = Vec::new();
     f.read_to_end(&mut buffer).expect("Read from test file");
     let mut m = Chip8::new([0; 0x1000]);
@@ -1098,7 +2863,248 @@ fn test_rom() {
     }
     m.program_counter = 0x200;
 
-    for _ in 0..1000 {
+    for _ in 0..2000 {
+        m.step().unwrap();
+    }
+
+    // The screen of results (all OK), as checked by the test_runner binary
+    crate::assert_screen_eq!(m.display_buffer, "resources/golden/test_opcode.txt");
+}
+
+#[test]
+fn test_vip_quirks() {
+    let mut m = Chip8::new([0; 0x1000]);
+    m.set_quirks(Quirks::preset("vip").unwrap());
+    m.registers[0x2] = 0xFF;
+    m.registers[0x3] = 0b0000_0011;
+    m.registers[0xF] = 5;
+    m.address_register = 0x300;
+
+    // V2 = V3 >> 1
+    m.execute_opcode(0x8236).unwrap();
+    assert_eq!(m.registers[0x2], 0b0000_0001);
+    assert_eq!(m.registers[0xF], 1);
+
+    // V2 |= V3, which resets VF
+    m.execute_opcode(0x8231).unwrap();
+    assert_eq!(m.registers[0xF], 0);
+
+    // dump(V3)
+    m.execute_opcode(0xF355).unwrap();
+    assert_eq!(m.address_register, 0x304);
+}
+
+#[test]
+fn test_schip_quirks() {
+    let mut m = Chip8::new([0; 0x1000]);
+    m.set_quirks(Quirks::preset("schip").unwrap());
+    m.registers[0x3] = 0x10;
+    m.memory[0x300] = 0xFF;
+    m.address_register = 0x300;
+    m.registers[0x0] = 60;
+    m.registers[0x1] = 0;
+
+    // render(V0, V1, 1), which is cut off at the right edge
+    m.execute_opcode(0xD011).unwrap();
+    assert!(m.display_buffer.get_pixel(63, 0));
+    assert!(!m.display_buffer.get_pixel(0, 0));
+
+    // jump to V3 + 0x345
+    m.execute_opcode(0xB345).unwrap();
+    assert_eq!(m.program_counter, 0x355);
+
+    assert!(Quirks::preset("foo").is_err());
+}
+
+#[test]
+fn test_chip8x() {
+    // V0 = 0x08, V1 = 0x00, V2 = 6 (aqua), then the colors and a sprite
+    let rom = [
+        0x60, 0x08, 0x61, 0x00, 0x62, 0x06, 0xB0, 0x22, 0x02, 0xA0, 0xA0, 0x00, 0xD0, 0x11,
+    ];
+    let mut m = Chip8::from_rom(&rom).unwrap();
+    m.set_quirks(Quirks::preset("chip8x").unwrap());
+    for _ in 0..7 {
+        m.step_instruction().unwrap();
+    }
+    assert_eq!(m.program_counter, 0x20E);
+    let colors = m.display_buffer.colors().unwrap();
+    assert_eq!(colors.foreground(8, 1), [0x00, 0xFF, 0xFF]);
+    assert_eq!(colors.foreground(8, 2), [0xFF, 0x00, 0x00]);
+
+    // The top of the "0" sprite at (8, 0) is aqua, on the second background color (black)
+    let rgba = m.display_buffer.to_rgba([255; 4], [0; 4]);
+    assert_eq!(rgba[4 * 8..4 * 9], [0x00, 0xFF, 0xFF, 255]);
+    assert_eq!(rgba[4 * 12..4 * 13], [0x00, 0x00, 0x00, 255]);
+    assert_eq!(rgba[4 * 64..4 * 65], [0x00, 0x00, 0x00, 255]);
+
+    m.registers[3] = 0x35;
+    m.registers[4] = 0x4C;
+    m.execute_opcode(0x5341).unwrap();
+    assert_eq!(m.registers[3], 0x71);
+
+    // Without the quirk, Bnnn is a jump again
+    m.set_quirks(Quirks::default());
+    m.execute_opcode(0xB022).unwrap();
+    assert_eq!(m.program_counter, 0x02A);
+}
+
+#[test]
+fn test_display_to_ascii() {
+    let mut display = DisplayBuffer::new();
+    display.flip_pixel(1, 0);
+    display.flip_pixel(63, 31);
+
+    let ascii = display.to_ascii();
+    let lines: Vec<&str> = ascii.lines().collect();
+    assert_eq!(lines.len(), 32);
+    assert!(lines[0].starts_with(".#.."));
+    assert!(lines[31].ends_with(".#"));
+    assert_eq!(ascii.matches('#').count(), 2);
+}
+
+#[test]
+fn test_display_to_text_grid() {
+    let mut display = DisplayBuffer::new();
+    display.flip_pixel(12, 3);
+
+    let grid = display.to_text_grid();
+    let lines: Vec<&str> = grid.lines().collect();
+    assert_eq!(lines.len(), 2 + 32);
+    assert!(lines[0].starts_with("   0000000000111"));
+    assert!(lines[1].starts_with("   0123456789012"));
+    assert!(lines[1].ends_with("0123"));
+    assert_eq!(
+        lines[5],
+        format!(" 3 {}#{}", ".".repeat(12), ".".repeat(51))
+    );
+    assert!(lines[33].starts_with("31 ...."));
+}
+
+#[test]
+fn test_events() {
+    // V0 = 2, sound_timer(V0), call 0x20A, V1 = key(), clear screen, return
+    let rom = [
+        0x60, 0x02, 0xF0, 0x18, 0x22, 0x0A, 0xF1, 0x0A, 0x12, 0x08, 0x00, 0xE0, 0x00, 0xEE,
+    ];
+    let mut m = Chip8::from_rom(&rom).unwrap();
+    m.step_instruction().unwrap();
+    assert_eq!(m.take_events(), []);
+
+    m.record_events(true);
+    for _ in 0..5 {
+        m.step().unwrap();
+    }
+    assert_eq!(
+        m.take_events(),
+        [
+            Chip8Event::SoundStarted,
+            Chip8Event::Call(0x20A),
+            Chip8Event::DrawOccurred,
+            Chip8Event::Return,
+            Chip8Event::KeyWaited,
+        ]
+    );
+    m.decrement_timers();
+    m.decrement_timers();
+    assert_eq!(
+        m.take_events(),
+        [
+            Chip8Event::TimerTick,
+            Chip8Event::TimerTick,
+            Chip8Event::SoundStopped
+        ]
+    );
+
+    m.record_events(false);
+    m.decrement_timers();
+    m.record_events(true);
+    assert_eq!(m.take_events(), []);
+}
+
+#[test]
+fn test_call_profile() {
+    // call 0x206 twice, then loop; 0x206: call 0x20A, return; 0x20A: return
+    let rom = [
+        0x22, 0x06, 0x22, 0x06, 0x12, 0x04, 0x22, 0x0A, 0x00, 0xEE, 0x00, 0xEE,
+    ];
+    let mut m = Chip8::from_rom(&rom).unwrap();
+    assert_eq!(m.call_profile(), None);
+    m.profile_calls(true);
+    for _ in 0..11 {
+        m.step().unwrap();
+    }
+    let profile = m.call_profile().unwrap();
+    let cycles: Vec<(&[u16], u64)> = profile
+        .cycles()
+        .iter()
+        .map(|(stack, &cycles)| (stack.as_slice(), cycles))
+        .collect();
+    assert_eq!(
+        cycles,
+        [(&[][..], 5), (&[0x206][..], 4), (&[0x206, 0x20A][..], 2),]
+    );
+    let mut folded = Vec::new();
+    profile.write_folded(&mut folded).unwrap();
+    assert_eq!(
+        String::from_utf8(folded).unwrap(),
+        "main 5\nmain;0x206 4\nmain;0x206;0x20A 2\n"
+    );
+}
+
+#[test]
+fn test_uninitialized_reads() {
+    // store V0 at 0x300, load 0x300 and 0x301, then loop
+    let rom = [0xA3, 0x00, 0xF0, 0x55, 0xA3, 0x00, 0xF1, 0x65, 0x12, 0x08];
+    let mut m = Chip8::from_rom(&rom).unwrap();
+    m.detect_uninitialized_reads(true);
+    for _ in 0..6 {
+        m.step().unwrap();
+    }
+    assert_eq!(
+        m.uninitialized_reads(),
+        [UninitializedRead {
+            address: 0x301,
+            program_counter: 0x206
+        }]
+    );
+    m.detect_uninitialized_reads(false);
+    assert_eq!(m.uninitialized_reads(), []);
+}
+
+#[test]
+fn test_modified_code() {
+    // 0x20A: jump to 0x20C, which overwrites 0x20A with a jump to 0x208 and jumps back to it
+    let rom = [
+        0xA2, 0x0A, 0x60, 0x12, 0x61, 0x08, 0x12, 0x0A, 0x12, 0x08, 0x12, 0x0C, 0xF1, 0x55, 0x12,
+        0x0A,
+    ];
+    let mut m = Chip8::from_rom(&rom).unwrap();
+    for _ in 0..8 {
         m.step().unwrap();
     }
+    assert_eq!(m.program_counter, 0x208);
+    assert_eq!(
+        m.modified_code().iter().collect::<Vec<_>>(),
+        [(&0x20A, &0x20C)]
+    );
+    // Patches from outside the program don't count
+    m.write_memory(0x200, &[0xA2, 0x0C]).unwrap();
+    assert_eq!(m.modified_code().len(), 1);
+}
+
+#[test]
+fn test_randomize_free_memory() {
+    let rom = [0x12, 0x00];
+    let mut m = Chip8::from_rom(&rom).unwrap();
+    m.set_random(Random::seeded(1));
+    m.randomize_free_memory();
+    assert_eq!(m.memory()[..FONT_SPRITES.len()], FONT_SPRITES);
+    assert_eq!(m.memory()[0x200..0x202], rom);
+    assert!(m.memory()[0x202..].iter().any(|&byte| byte != 0));
+
+    let mut other = Chip8::from_rom(&rom).unwrap();
+    other.set_random(Random::seeded(1));
+    other.randomize_free_memory();
+    assert_eq!(other.memory(), m.memory());
 }
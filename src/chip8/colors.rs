@@ -0,0 +1,143 @@
+//! The colors of CHIP-8X, which ran on a COSMAC VIP with the VP-590 color board: one background
+//! color for the whole screen, and a foreground color for each zone of 8 pixels on a row.
+
+use super::{MAX_SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// The background colors, in the order that 02A0 steps through them: blue, black, green and red
+pub const BACKGROUND_COLORS: [[u8; 3]; 4] = [
+    [0x00, 0x00, 0x80],
+    [0x00, 0x00, 0x00],
+    [0x00, 0x80, 0x00],
+    [0x80, 0x00, 0x00],
+];
+/// The foreground colors: black, red, blue, violet, green, yellow, aqua and white
+pub const FOREGROUND_COLORS: [[u8; 3]; 8] = [
+    [0x00, 0x00, 0x00],
+    [0xFF, 0x00, 0x00],
+    [0x00, 0x00, 0xFF],
+    [0xFF, 0x00, 0xFF],
+    [0x00, 0xFF, 0x00],
+    [0xFF, 0xFF, 0x00],
+    [0x00, 0xFF, 0xFF],
+    [0xFF, 0xFF, 0xFF],
+];
+/// What the zones are before a program colors them (red)
+const DEFAULT_FOREGROUND: u8 = 1;
+const ZONE_WIDTH: u8 = 8;
+const ZONE_COLUMNS: usize = SCREEN_WIDTH as usize / ZONE_WIDTH as usize;
+/// The height of the zones that Bxy0 colors
+const ZONE_HEIGHT: u8 = 4;
+/// The length of `to_bytes`
+pub(super) const COLORS_SIZE: usize = 1 + ZONE_COLUMNS * MAX_SCREEN_HEIGHT;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Colors {
+    /// An index in `BACKGROUND_COLORS`
+    background: u8,
+    /// Indices in `FOREGROUND_COLORS`, for each zone of each row
+    zones: [u8; ZONE_COLUMNS * MAX_SCREEN_HEIGHT],
+}
+
+impl Colors {
+    pub fn new() -> Colors {
+        Colors {
+            background: 0,
+            zones: [DEFAULT_FOREGROUND; ZONE_COLUMNS * MAX_SCREEN_HEIGHT],
+        }
+    }
+
+    pub fn background(&self) -> [u8; 3] {
+        BACKGROUND_COLORS[self.background as usize]
+    }
+
+    /// The color of the pixel, if it's lit
+    pub fn foreground(&self, x: u8, y: u8) -> [u8; 3] {
+        let zone = y as usize % MAX_SCREEN_HEIGHT * ZONE_COLUMNS
+            + (x % SCREEN_WIDTH / ZONE_WIDTH) as usize;
+        FOREGROUND_COLORS[self.zones[zone] as usize]
+    }
+
+    pub(super) fn next_background(&mut self) {
+        self.background = (self.background + 1) % BACKGROUND_COLORS.len() as u8;
+    }
+
+    /// Bxy0: colors the zones in the columns from the low to the high nibble of `columns` (in
+    /// units of 8 pixels) and the rows from the low to the high nibble of `rows` (in units of 4)
+    pub(super) fn color_zones(&mut self, columns: u8, rows: u8, color: u8, height: u8) {
+        for column in columns & 0xF..=columns >> 4 {
+            for zone_row in rows & 0xF..=rows >> 4 {
+                for row in 0..ZONE_HEIGHT {
+                    let y = zone_row * ZONE_HEIGHT + row;
+                    self.color(column * ZONE_WIDTH, y % height, color);
+                }
+            }
+        }
+    }
+
+    /// Bxyn: colors the zone that the pixel is in, and the ones below it on the next rows
+    pub(super) fn color_rows(&mut self, x: u8, y: u8, rows: u8, color: u8, height: u8) {
+        for row in 0..rows {
+            self.color(x, y.wrapping_add(row) % height, color);
+        }
+    }
+
+    fn color(&mut self, x: u8, y: u8, color: u8) {
+        let zone = y as usize * ZONE_COLUMNS + (x % SCREEN_WIDTH / ZONE_WIDTH) as usize;
+        self.zones[zone] = color % FOREGROUND_COLORS.len() as u8;
+    }
+
+    /// The background followed by the zones, for save states
+    pub(super) fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(COLORS_SIZE);
+        bytes.push(self.background);
+        bytes.extend_from_slice(&self.zones);
+        bytes
+    }
+
+    pub(super) fn from_bytes(bytes: &[u8]) -> Result<Colors, String> {
+        let invalid = || "Invalid colors in save state".to_owned();
+        let (&background, zones) = bytes.split_first().ok_or_else(invalid)?;
+        if background as usize >= BACKGROUND_COLORS.len()
+            || zones.len() != ZONE_COLUMNS * MAX_SCREEN_HEIGHT
+            || zones
+                .iter()
+                .any(|&zone| zone as usize >= FOREGROUND_COLORS.len())
+        {
+            return Err(invalid());
+        }
+        let mut colors = Colors::new();
+        colors.background = background;
+        colors.zones.copy_from_slice(zones);
+        Ok(colors)
+    }
+}
+
+impl Default for Colors {
+    fn default() -> Colors {
+        Colors::new()
+    }
+}
+
+#[test]
+fn test_colors() {
+    let mut colors = Colors::new();
+    assert_eq!(colors.background(), [0x00, 0x00, 0x80]);
+    colors.next_background();
+    assert_eq!(colors.background(), [0x00, 0x00, 0x00]);
+
+    // Columns 1 to 2 and the second row of zones (rows 4 to 7) in green
+    colors.color_zones(0x21, 0x11, 4, 32);
+    assert_eq!(colors.foreground(8, 4), [0x00, 0xFF, 0x00]);
+    assert_eq!(colors.foreground(23, 7), [0x00, 0xFF, 0x00]);
+    assert_eq!(colors.foreground(24, 7), [0xFF, 0x00, 0x00]);
+    assert_eq!(colors.foreground(8, 8), [0xFF, 0x00, 0x00]);
+
+    // Two rows that wrap around to the top
+    colors.color_rows(60, 31, 2, 7, 32);
+    assert_eq!(colors.foreground(56, 31), [0xFF, 0xFF, 0xFF]);
+    assert_eq!(colors.foreground(63, 0), [0xFF, 0xFF, 0xFF]);
+    assert_eq!(colors.foreground(63, 1), [0xFF, 0x00, 0x00]);
+
+    assert_eq!(Colors::from_bytes(&colors.to_bytes()), Ok(colors));
+    assert!(Colors::from_bytes(&[4]).is_err());
+}
@@ -0,0 +1,91 @@
+use super::Instruction;
+
+/// The 38 forms of Chip-8 instructions (including the flags and the large font of SUPER-CHIP), in
+/// the order that `opcode_form` numbers them
+pub const OPCODE_FORMS: [&str; 38] = [
+    "00E0", "00EE", "0nnn", "1nnn", "2nnn", "3xnn", "4xnn", "5xy0", "6xnn", "7xnn", "8xy0", "8xy1",
+    "8xy2", "8xy3", "8xy4", "8xy5", "8xy6", "8xy7", "8xyE", "9xy0", "Annn", "Bnnn", "Cxnn", "Dxyn",
+    "Ex9E", "ExA1", "Fx07", "Fx0A", "Fx15", "Fx18", "Fx1E", "Fx29", "Fx33", "Fx55", "Fx65", "Fx75",
+    "Fx85", "Fx30",
+];
+
+/// The index in `OPCODE_FORMS` of the form that the op-code belongs to, or None if the
+/// interpreter doesn't handle it
+pub fn opcode_form(opcode: u16) -> Option<usize> {
+    Instruction::decode(opcode)
+        .ok()
+        .map(|instruction| instruction.form())
+}
+
+/// How many times each opcode form has been executed
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpcodeCoverage {
+    counts: [u32; OPCODE_FORMS.len()],
+}
+
+impl Default for OpcodeCoverage {
+    fn default() -> OpcodeCoverage {
+        OpcodeCoverage {
+            counts: [0; OPCODE_FORMS.len()],
+        }
+    }
+}
+
+impl OpcodeCoverage {
+    pub(super) fn record(&mut self, instruction: Instruction) {
+        let form = instruction.form();
+        self.counts[form] = self.counts[form].saturating_add(1);
+    }
+
+    /// The number of executions of each form, in the order of `OPCODE_FORMS`
+    pub fn counts(&self) -> &[u32] {
+        &self.counts
+    }
+
+    /// The names of the forms that haven't been executed
+    pub fn missing(&self) -> Vec<&'static str> {
+        OPCODE_FORMS
+            .iter()
+            .zip(self.counts.iter())
+            .filter(|(_, &count)| count == 0)
+            .map(|(&name, _)| name)
+            .collect()
+    }
+
+    /// A table of the executed forms and their counts, followed by the forms that were never
+    /// executed
+    pub fn summary(&self) -> String {
+        let missing = self.missing();
+        let mut summary = format!(
+            "Executed {} of {} opcode forms\n",
+            OPCODE_FORMS.len() - missing.len(),
+            OPCODE_FORMS.len()
+        );
+        for (name, &count) in OPCODE_FORMS.iter().zip(self.counts.iter()) {
+            if count > 0 {
+                summary.push_str(&format!("  {}  {:>10}\n", name, count));
+            }
+        }
+        if !missing.is_empty() {
+            summary.push_str(&format!("Never executed: {}\n", missing.join(", ")));
+        }
+        summary
+    }
+}
+
+#[test]
+fn test_opcode_form() {
+    assert_eq!(opcode_form(0x00E0), Some(0));
+    assert_eq!(opcode_form(0x0123), Some(2));
+    assert_eq!(opcode_form(0x8AB4), Some(14));
+    assert_eq!(opcode_form(0x8ABE), Some(18));
+    assert_eq!(opcode_form(0x8AB9), None);
+    assert_eq!(opcode_form(0xE39E), Some(24));
+    assert_eq!(opcode_form(0xF265), Some(34));
+    assert_eq!(opcode_form(0xF285), Some(36));
+    assert_eq!(opcode_form(0xF2FF), None);
+    for (form, name) in OPCODE_FORMS.iter().enumerate() {
+        let opcode = u16::from_str_radix(&name.replace(|c: char| c.is_lowercase(), "0"), 16);
+        assert_eq!(opcode_form(opcode.unwrap()), Some(form), "{}", name);
+    }
+}
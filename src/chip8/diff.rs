@@ -0,0 +1,219 @@
+use super::{Chip8, SCREEN_WIDTH};
+
+use std::fmt;
+
+/// A register, timer or stack entry that differs between two states
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegisterChange {
+    /// Like `V3`, `I` or `stack[2]`
+    pub name: String,
+    pub before: u16,
+    pub after: u16,
+}
+
+/// A run of consecutive bytes of memory that differ between two states
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemoryChange {
+    pub start: u16,
+    pub before: Vec<u8>,
+    pub after: Vec<u8>,
+}
+
+/// What differs between two states of a machine, for showing what an instruction changed or
+/// asserting on exactly that in tests
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StateDiff {
+    pub registers: Vec<RegisterChange>,
+    /// In the order of their addresses
+    pub memory: Vec<MemoryChange>,
+    /// The (x, y) of each pixel that was flipped, row by row. If the display mode changed, the
+    /// height is among the registers instead.
+    pub pixels: Vec<(u8, u8)>,
+}
+
+impl StateDiff {
+    pub fn between(before: &Chip8, after: &Chip8) -> StateDiff {
+        let mut registers = Vec::new();
+        let mut compare = |name: String, before: u16, after: u16| {
+            if before != after {
+                registers.push(RegisterChange {
+                    name,
+                    before,
+                    after,
+                });
+            }
+        };
+        for i in 0..16 {
+            compare(
+                format!("V{:X}", i),
+                before.registers[i] as u16,
+                after.registers[i] as u16,
+            );
+        }
+        compare(
+            "I".to_owned(),
+            before.address_register,
+            after.address_register,
+        );
+        compare(
+            "PC".to_owned(),
+            before.program_counter,
+            after.program_counter,
+        );
+        compare(
+            "SP".to_owned(),
+            before.stack_pointer as u16,
+            after.stack_pointer as u16,
+        );
+        for (i, (&a, &b)) in before.stack.iter().zip(&after.stack).enumerate() {
+            compare(format!("stack[{}]", i), a, b);
+        }
+        compare(
+            "delay".to_owned(),
+            before.delay_timer as u16,
+            after.delay_timer as u16,
+        );
+        compare(
+            "sound".to_owned(),
+            before.sound_timer as u16,
+            after.sound_timer as u16,
+        );
+        let (height_before, height_after) = (
+            before.display_buffer.height(),
+            after.display_buffer.height(),
+        );
+        compare(
+            "display height".to_owned(),
+            height_before as u16,
+            height_after as u16,
+        );
+
+        let mut memory: Vec<MemoryChange> = Vec::new();
+        for (address, (&a, &b)) in before.memory.iter().zip(&after.memory).enumerate() {
+            if a == b {
+                continue;
+            }
+            match memory.last_mut() {
+                Some(change) if change.start as usize + change.before.len() == address => {
+                    change.before.push(a);
+                    change.after.push(b);
+                }
+                _ => memory.push(MemoryChange {
+                    start: address as u16,
+                    before: vec![a],
+                    after: vec![b],
+                }),
+            }
+        }
+
+        let mut pixels = Vec::new();
+        if height_before == height_after {
+            for y in 0..height_before {
+                for x in 0..SCREEN_WIDTH {
+                    if before.display_buffer.get_pixel(x, y) != after.display_buffer.get_pixel(x, y)
+                    {
+                        pixels.push((x, y));
+                    }
+                }
+            }
+        }
+        StateDiff {
+            registers,
+            memory,
+            pixels,
+        }
+    }
+
+    /// Compares two save states (see `Chip8::save_state`)
+    pub fn between_states(before: &[u8], after: &[u8]) -> Result<StateDiff, String> {
+        let load = |state: &[u8]| -> Result<Chip8, String> {
+            let mut chip8 = Chip8::from_rom(&[])?;
+            chip8.load_state(state)?;
+            Ok(chip8)
+        };
+        Ok(StateDiff::between(&load(before)?, &load(after)?))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.registers.is_empty() && self.memory.is_empty() && self.pixels.is_empty()
+    }
+}
+
+/// One line for each register and run of memory, and one for all the pixels
+impl fmt::Display for StateDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for change in &self.registers {
+            writeln!(
+                f,
+                "{}: {:#04X} -> {:#04X}",
+                change.name, change.before, change.after
+            )?;
+        }
+        let hex = |bytes: &[u8]| {
+            bytes
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect::<Vec<String>>()
+                .join(" ")
+        };
+        for change in &self.memory {
+            let end = change.start as usize + change.before.len() - 1;
+            if end == change.start as usize {
+                write!(f, "mem[{:#05X}]", change.start)?;
+            } else {
+                write!(f, "mem[{:#05X}-{:#05X}]", change.start, end)?;
+            }
+            writeln!(f, ": {} -> {}", hex(&change.before), hex(&change.after))?;
+        }
+        if !self.pixels.is_empty() {
+            writeln!(f, "display: {} pixels flipped", self.pixels.len())?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_state_diff() {
+    // V0 = 0x12, I = 0x300, store V0 at I, draw 1 row of V0's sprite at (V0, V0)
+    let mut chip8 = Chip8::from_rom(&[0x60, 0x12, 0xA3, 0x00, 0xF0, 0x55, 0xD0, 0x01]).unwrap();
+    let start = chip8.save_state();
+    for _ in 0..4 {
+        chip8.step_instruction().unwrap();
+    }
+    let diff = StateDiff::between_states(&start, &chip8.save_state()).unwrap();
+    assert_eq!(
+        diff.registers,
+        [
+            RegisterChange {
+                name: "V0".to_owned(),
+                before: 0,
+                after: 0x12
+            },
+            RegisterChange {
+                name: "I".to_owned(),
+                before: 0,
+                after: 0x300
+            },
+            RegisterChange {
+                name: "PC".to_owned(),
+                before: 0x200,
+                after: 0x208
+            },
+        ]
+    );
+    assert_eq!(
+        diff.memory,
+        [MemoryChange {
+            start: 0x300,
+            before: vec![0],
+            after: vec![0x12]
+        }]
+    );
+    // 0x12 is 00010010
+    assert_eq!(diff.pixels, [(0x12 + 3, 0x12), (0x12 + 6, 0x12)]);
+    assert_eq!(
+        diff.to_string(),
+        "V0: 0x00 -> 0x12\nI: 0x00 -> 0x300\nPC: 0x200 -> 0x208\nmem[0x300]: 00 -> 12\ndisplay: 2 pixels flipped\n"
+    );
+    assert!(StateDiff::between(&chip8, &chip8).is_empty());
+}
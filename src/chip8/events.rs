@@ -0,0 +1,19 @@
+/// Something that happened while the machine ran, for frontends and tools that react to what the
+/// program does instead of comparing the state before and after each update.
+///
+/// The machine only keeps them after [`Chip8::record_events`](super::Chip8::record_events), and
+/// hands them over in [`Chip8::take_events`](super::Chip8::take_events).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Chip8Event {
+    /// The screen was drawn to or cleared
+    DrawOccurred,
+    SoundStarted,
+    SoundStopped,
+    /// The program started waiting for a key press
+    KeyWaited,
+    /// A subroutine was called at the address
+    Call(u16),
+    Return,
+    /// The timers counted down, 60 times a second
+    TimerTick,
+}
@@ -0,0 +1,390 @@
+use std::fmt;
+
+/// A decoded op-code. `x` and `y` are register indices.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Instruction {
+    /// 00E0
+    ClearScreen,
+    /// 00EE
+    Return,
+    /// 0nnn, which is treated like 2nnn
+    CallMachineCode(u16),
+    /// 1nnn
+    Jump(u16),
+    /// 2nnn
+    Call(u16),
+    /// 3xnn
+    SkipIfEqual(u8, u8),
+    /// 4xnn
+    SkipIfNotEqual(u8, u8),
+    /// 5xy0
+    SkipIfRegistersEqual(u8, u8),
+    /// 6xnn
+    Set(u8, u8),
+    /// 7xnn
+    Add(u8, u8),
+    /// 8xy0
+    Copy(u8, u8),
+    /// 8xy1
+    Or(u8, u8),
+    /// 8xy2
+    And(u8, u8),
+    /// 8xy3
+    Xor(u8, u8),
+    /// 8xy4
+    AddRegisters(u8, u8),
+    /// 8xy5
+    Subtract(u8, u8),
+    /// 8xy6
+    ShiftRight(u8, u8),
+    /// 8xy7
+    SubtractReversed(u8, u8),
+    /// 8xyE
+    ShiftLeft(u8, u8),
+    /// 9xy0
+    SkipIfRegistersNotEqual(u8, u8),
+    /// Annn
+    SetI(u16),
+    /// Bnnn. The x is only used with the `jump_vx` quirk.
+    JumpWithOffset(u8, u16),
+    /// Cxnn
+    Random(u8, u8),
+    /// Dxyn
+    Draw(u8, u8, u8),
+    /// Ex9E
+    SkipIfPressed(u8),
+    /// ExA1
+    SkipIfNotPressed(u8),
+    /// Fx07
+    GetDelay(u8),
+    /// Fx0A
+    WaitForKey(u8),
+    /// Fx15
+    SetDelay(u8),
+    /// Fx18
+    SetSound(u8),
+    /// Fx1E
+    AddToI(u8),
+    /// Fx29
+    FontSprite(u8),
+    /// Fx33
+    Bcd(u8),
+    /// Fx55
+    Store(u8),
+    /// Fx65
+    Load(u8),
+    /// Fx30 in SUPER-CHIP: points I at the 8x10 digit in Vx
+    LargeFontSprite(u8),
+    /// Fx75 in SUPER-CHIP: stores V0 to Vx in the flags
+    StoreFlags(u8),
+    /// Fx85 in SUPER-CHIP: loads V0 to Vx from the flags
+    LoadFlags(u8),
+    /// 02A0 in CHIP-8X: the next background color
+    NextBackgroundColor,
+    /// 5xy1 in CHIP-8X: Vx += Vy, with each nibble added separately (modulo 8)
+    AddNibbles(u8, u8),
+    /// Bxy0 in CHIP-8X: sets the color of the zones (8x4 pixels) in the columns given by the
+    /// nibbles of Vx and the rows given by the nibbles of Vx+1 to Vy
+    SetZoneColor(u8, u8),
+    /// Bxyn in CHIP-8X: sets the color of the n rows at (Vx, Vx+1), 8 pixels wide, to Vy
+    SetRowColor(u8, u8, u8),
+}
+
+impl Instruction {
+    pub fn decode(opcode: u16) -> Result<Instruction, String> {
+        let x = ((opcode & 0x0F00) >> 8) as u8;
+        let y = ((opcode & 0x00F0) >> 4) as u8;
+        let nn = (opcode & 0x00FF) as u8;
+        let nnn = opcode & 0x0FFF;
+        let instruction = match opcode & 0xF000 {
+            0x0000 => match opcode {
+                0x00E0 => Instruction::ClearScreen,
+                0x00EE => Instruction::Return,
+                _ => Instruction::CallMachineCode(nnn),
+            },
+            0x1000 => Instruction::Jump(nnn),
+            0x2000 => Instruction::Call(nnn),
+            0x3000 => Instruction::SkipIfEqual(x, nn),
+            0x4000 => Instruction::SkipIfNotEqual(x, nn),
+            0x5000 => Instruction::SkipIfRegistersEqual(x, y),
+            0x6000 => Instruction::Set(x, nn),
+            0x7000 => Instruction::Add(x, nn),
+            0x8000 => match opcode & 0x000F {
+                0x0 => Instruction::Copy(x, y),
+                0x1 => Instruction::Or(x, y),
+                0x2 => Instruction::And(x, y),
+                0x3 => Instruction::Xor(x, y),
+                0x4 => Instruction::AddRegisters(x, y),
+                0x5 => Instruction::Subtract(x, y),
+                0x6 => Instruction::ShiftRight(x, y),
+                0x7 => Instruction::SubtractReversed(x, y),
+                0xE => Instruction::ShiftLeft(x, y),
+                _ => return Err(unhandled(opcode)),
+            },
+            0x9000 => Instruction::SkipIfRegistersNotEqual(x, y),
+            0xA000 => Instruction::SetI(nnn),
+            0xB000 => Instruction::JumpWithOffset(x, nnn),
+            0xC000 => Instruction::Random(x, nn),
+            0xD000 => Instruction::Draw(x, y, (opcode & 0x000F) as u8),
+            0xE000 => match nn {
+                0x9E => Instruction::SkipIfPressed(x),
+                0xA1 => Instruction::SkipIfNotPressed(x),
+                _ => return Err(unhandled(opcode)),
+            },
+            _ => match nn {
+                0x07 => Instruction::GetDelay(x),
+                0x0A => Instruction::WaitForKey(x),
+                0x15 => Instruction::SetDelay(x),
+                0x18 => Instruction::SetSound(x),
+                0x1E => Instruction::AddToI(x),
+                0x29 => Instruction::FontSprite(x),
+                0x30 => Instruction::LargeFontSprite(x),
+                0x33 => Instruction::Bcd(x),
+                0x55 => Instruction::Store(x),
+                0x65 => Instruction::Load(x),
+                0x75 => Instruction::StoreFlags(x),
+                0x85 => Instruction::LoadFlags(x),
+                _ => return Err(unhandled(opcode)),
+            },
+        };
+        Ok(instruction)
+    }
+
+    /// Decodes the op-code as a CHIP-8X instruction, which only differs for the color
+    /// instructions (which take 02A0 and all of Bnnn) and 5xy1
+    pub fn decode_chip8x(opcode: u16) -> Result<Instruction, String> {
+        let x = ((opcode & 0x0F00) >> 8) as u8;
+        let y = ((opcode & 0x00F0) >> 4) as u8;
+        let n = (opcode & 0x000F) as u8;
+        match opcode & 0xF000 {
+            0x0000 if opcode == 0x02A0 => Ok(Instruction::NextBackgroundColor),
+            0x5000 if n == 1 => Ok(Instruction::AddNibbles(x, y)),
+            0xB000 if n == 0 => Ok(Instruction::SetZoneColor(x, y)),
+            0xB000 => Ok(Instruction::SetRowColor(x, y, n)),
+            _ => Instruction::decode(opcode),
+        }
+    }
+
+    /// The index of the instruction's form in `OPCODE_FORMS`
+    pub fn form(&self) -> usize {
+        match self {
+            Instruction::ClearScreen => 0,
+            Instruction::Return => 1,
+            Instruction::CallMachineCode(_) => 2,
+            Instruction::Jump(_) => 3,
+            Instruction::Call(_) => 4,
+            Instruction::SkipIfEqual(..) => 5,
+            Instruction::SkipIfNotEqual(..) => 6,
+            Instruction::SkipIfRegistersEqual(..) => 7,
+            Instruction::Set(..) => 8,
+            Instruction::Add(..) => 9,
+            Instruction::Copy(..) => 10,
+            Instruction::Or(..) => 11,
+            Instruction::And(..) => 12,
+            Instruction::Xor(..) => 13,
+            Instruction::AddRegisters(..) => 14,
+            Instruction::Subtract(..) => 15,
+            Instruction::ShiftRight(..) => 16,
+            Instruction::SubtractReversed(..) => 17,
+            Instruction::ShiftLeft(..) => 18,
+            Instruction::SkipIfRegistersNotEqual(..) => 19,
+            Instruction::SetI(_) => 20,
+            Instruction::JumpWithOffset(..) => 21,
+            Instruction::Random(..) => 22,
+            Instruction::Draw(..) => 23,
+            Instruction::SkipIfPressed(_) => 24,
+            Instruction::SkipIfNotPressed(_) => 25,
+            Instruction::GetDelay(_) => 26,
+            Instruction::WaitForKey(_) => 27,
+            Instruction::SetDelay(_) => 28,
+            Instruction::SetSound(_) => 29,
+            Instruction::AddToI(_) => 30,
+            Instruction::FontSprite(_) => 31,
+            Instruction::Bcd(_) => 32,
+            Instruction::Store(_) => 33,
+            Instruction::Load(_) => 34,
+            Instruction::StoreFlags(_) => 35,
+            Instruction::LoadFlags(_) => 36,
+            Instruction::LargeFontSprite(_) => 37,
+            // The CHIP-8X instructions count as the forms whose op-codes they take
+            Instruction::NextBackgroundColor => 2,
+            Instruction::AddNibbles(..) => 7,
+            Instruction::SetZoneColor(..) | Instruction::SetRowColor(..) => 21,
+        }
+    }
+
+    /// Whether the instruction always continues somewhere else than after itself
+    pub fn jumps(&self) -> bool {
+        matches!(
+            self,
+            Instruction::Jump(_) | Instruction::JumpWithOffset(..) | Instruction::Return
+        )
+    }
+
+    /// Whether executing the instruction can change the display (its pixels or colors)
+    pub fn may_change_display(&self) -> bool {
+        matches!(
+            self,
+            Instruction::ClearScreen
+                | Instruction::CallMachineCode(_)
+                | Instruction::Draw(..)
+                | Instruction::NextBackgroundColor
+                | Instruction::SetZoneColor(..)
+                | Instruction::SetRowColor(..)
+        )
+    }
+
+    /// Roughly how many machine cycles the COSMAC VIP interpreter spends on the instruction,
+    /// including the 40 that fetching and decoding it takes. Drawing takes longer the more rows
+    /// the sprite has, and clearing the screen is by far the slowest.
+    pub fn vip_cost(&self) -> u32 {
+        let cost = match *self {
+            Instruction::ClearScreen => 3078,
+            Instruction::Return => 10,
+            Instruction::CallMachineCode(_) | Instruction::Call(_) => 26,
+            Instruction::Jump(_) | Instruction::SetI(_) => 12,
+            Instruction::SkipIfEqual(..) | Instruction::SkipIfNotEqual(..) => 10,
+            Instruction::SkipIfRegistersEqual(..) | Instruction::SkipIfRegistersNotEqual(..) => 14,
+            Instruction::Set(..) => 6,
+            Instruction::Add(..) => 10,
+            Instruction::Copy(..) => 12,
+            Instruction::Or(..)
+            | Instruction::And(..)
+            | Instruction::Xor(..)
+            | Instruction::AddRegisters(..)
+            | Instruction::Subtract(..)
+            | Instruction::ShiftRight(..)
+            | Instruction::SubtractReversed(..)
+            | Instruction::ShiftLeft(..)
+            | Instruction::AddNibbles(..) => 44,
+            Instruction::JumpWithOffset(..) => 22,
+            Instruction::Random(..) => 36,
+            Instruction::Draw(_, _, rows) => 68 + 46 * rows as u32,
+            Instruction::SkipIfPressed(_) | Instruction::SkipIfNotPressed(_) => 14,
+            Instruction::GetDelay(_) | Instruction::SetDelay(_) | Instruction::SetSound(_) => 10,
+            Instruction::WaitForKey(_) => 19,
+            Instruction::AddToI(_) | Instruction::FontSprite(_) => 16,
+            Instruction::LargeFontSprite(_) => 16,
+            Instruction::Bcd(_) => 84,
+            Instruction::Store(x)
+            | Instruction::Load(x)
+            | Instruction::StoreFlags(x)
+            | Instruction::LoadFlags(x) => 14 + 14 * (x as u32 + 1),
+            Instruction::NextBackgroundColor => 24,
+            Instruction::SetZoneColor(..) => 200,
+            Instruction::SetRowColor(_, _, rows) => 68 + 20 * rows as u32,
+        };
+        40 + cost
+    }
+}
+
+fn unhandled(opcode: u16) -> String {
+    format!("Unhandled op-code: {:#06X}", opcode)
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::ClearScreen => write!(f, "clear screen"),
+            Instruction::Return => write!(f, "return"),
+            Instruction::CallMachineCode(address) => write!(f, "call (machine): {:#05X}", address),
+            Instruction::Jump(address) => write!(f, "jump: {:#05X}", address),
+            Instruction::Call(address) => write!(f, "call: {:#05X}", address),
+            Instruction::SkipIfEqual(x, nn) => write!(f, "skip if V{:X} == {:#04X}", x, nn),
+            Instruction::SkipIfNotEqual(x, nn) => write!(f, "skip if V{:X} != {:#04X}", x, nn),
+            Instruction::SkipIfRegistersEqual(x, y) => write!(f, "skip if V{:X} == V{:X}", x, y),
+            Instruction::Set(x, nn) => write!(f, "V{:X} = {:#04X}", x, nn),
+            Instruction::Add(x, nn) => write!(f, "V{:X} += {:#04X}", x, nn),
+            Instruction::Copy(x, y) => write!(f, "V{:X} = V{:X}", x, y),
+            Instruction::Or(x, y) => write!(f, "V{:X} = V{:X} | V{:X}", x, x, y),
+            Instruction::And(x, y) => write!(f, "V{:X} = V{:X} & V{:X}", x, x, y),
+            Instruction::Xor(x, y) => write!(f, "V{:X} = V{:X} ^ V{:X}", x, x, y),
+            Instruction::AddRegisters(x, y) => write!(f, "V{:X} = V{:X} + V{:X}", x, x, y),
+            Instruction::Subtract(x, y) => write!(f, "V{:X} = V{:X} - V{:X}", x, x, y),
+            Instruction::ShiftRight(x, _) => write!(f, "V{:X} >>= 1", x),
+            Instruction::SubtractReversed(x, y) => write!(f, "V{:X} = V{:X} - V{:X}", x, y, x),
+            Instruction::ShiftLeft(x, _) => write!(f, "V{:X} <<= 1", x),
+            Instruction::SkipIfRegistersNotEqual(x, y) => {
+                write!(f, "skip if V{:X} != V{:X}", x, y)
+            }
+            Instruction::SetI(address) => write!(f, "I = {:#05X}", address),
+            Instruction::JumpWithOffset(_, address) => write!(f, "jump to V0 + {:#05X}", address),
+            Instruction::Random(x, nn) => write!(f, "V{:X} = rand() & {:#04X}", x, nn),
+            Instruction::Draw(x, y, n) => write!(f, "render(V{:X}, V{:X}, {})", x, y, n),
+            Instruction::SkipIfPressed(x) => write!(f, "skip if V{:X} pressed", x),
+            Instruction::SkipIfNotPressed(x) => write!(f, "skip if V{:X} not pressed", x),
+            Instruction::GetDelay(x) => write!(f, "V{:X} = get_delay()", x),
+            Instruction::WaitForKey(x) => write!(f, "V{:X} = get_key()", x),
+            Instruction::SetDelay(x) => write!(f, "delay_timer(V{:X})", x),
+            Instruction::SetSound(x) => write!(f, "sound_timer(V{:X})", x),
+            Instruction::AddToI(x) => write!(f, "I += V{:X}", x),
+            Instruction::FontSprite(x) => write!(f, "I = sprite_addr(V{:X})", x),
+            Instruction::LargeFontSprite(x) => write!(f, "I = large_sprite_addr(V{:X})", x),
+            Instruction::Bcd(x) => write!(f, "BCD(V{:X})", x),
+            Instruction::Store(x) => write!(f, "dump(V{:X})", x),
+            Instruction::Load(x) => write!(f, "load(V{:X})", x),
+            Instruction::StoreFlags(x) => write!(f, "flags_dump(V{:X})", x),
+            Instruction::LoadFlags(x) => write!(f, "flags_load(V{:X})", x),
+            Instruction::NextBackgroundColor => write!(f, "next background color"),
+            Instruction::AddNibbles(x, y) => write!(f, "V{:X} = V{:X} +nibbles V{:X}", x, x, y),
+            Instruction::SetZoneColor(x, y) => write!(f, "zone_color(V{:X}, V{:X})", x, y),
+            Instruction::SetRowColor(x, y, n) => {
+                write!(f, "row_color(V{:X}, V{:X}, {})", x, y, n)
+            }
+        }
+    }
+}
+
+#[test]
+fn test_decode() {
+    assert_eq!(Instruction::decode(0x00E0), Ok(Instruction::ClearScreen));
+    assert_eq!(
+        Instruction::decode(0x8AB4),
+        Ok(Instruction::AddRegisters(0xA, 0xB))
+    );
+    assert_eq!(Instruction::decode(0xD125), Ok(Instruction::Draw(1, 2, 5)));
+    assert_eq!(
+        Instruction::decode(0xB3A0),
+        Ok(Instruction::JumpWithOffset(3, 0x3A0))
+    );
+    assert_eq!(Instruction::decode(0xF265), Ok(Instruction::Load(2)));
+    assert_eq!(Instruction::decode(0xF775), Ok(Instruction::StoreFlags(7)));
+    assert_eq!(
+        Instruction::decode(0xF430),
+        Ok(Instruction::LargeFontSprite(4))
+    );
+    assert!(Instruction::decode(0x8AB9).is_err());
+    assert!(Instruction::decode(0xE2FF).is_err());
+    assert_eq!(
+        Instruction::decode(0xF2FF),
+        Err("Unhandled op-code: 0xF2FF".to_owned())
+    );
+}
+
+#[test]
+fn test_decode_chip8x() {
+    assert_eq!(
+        Instruction::decode_chip8x(0x02A0),
+        Ok(Instruction::NextBackgroundColor)
+    );
+    assert_eq!(
+        Instruction::decode_chip8x(0x5121),
+        Ok(Instruction::AddNibbles(1, 2))
+    );
+    assert_eq!(
+        Instruction::decode_chip8x(0xB340),
+        Ok(Instruction::SetZoneColor(3, 4))
+    );
+    assert_eq!(
+        Instruction::decode_chip8x(0xB345),
+        Ok(Instruction::SetRowColor(3, 4, 5))
+    );
+    assert_eq!(
+        Instruction::decode_chip8x(0x5120),
+        Ok(Instruction::SkipIfRegistersEqual(1, 2))
+    );
+    assert_eq!(
+        Instruction::decode_chip8x(0x02A2),
+        Ok(Instruction::CallMachineCode(0x2A2))
+    );
+}
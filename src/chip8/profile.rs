@@ -0,0 +1,45 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+/// The instructions executed in each stack of subroutine calls, for finding out which
+/// subroutines take up the cycles. It's written as folded stacks, the input format of
+/// flamegraph.pl and inferno.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CallProfile {
+    /// The addresses of the subroutines that are being executed, outermost first
+    stack: Vec<u16>,
+    cycles: BTreeMap<Vec<u16>, u64>,
+}
+
+impl CallProfile {
+    /// Counts an instruction against the current stack, and then follows the call or return (if
+    /// the stack pointer changed) to the next instruction
+    pub(super) fn record(&mut self, stack_pointer_before: u8, stack_pointer: u8, next: u16) {
+        *self.cycles.entry(self.stack.clone()).or_insert(0) += 1;
+        if stack_pointer > stack_pointer_before {
+            self.stack.push(next);
+        } else if stack_pointer < stack_pointer_before {
+            // Returns from calls made before profiling started leave the stack empty
+            self.stack.pop();
+        }
+    }
+
+    /// The instructions executed in each stack (the addresses of the called subroutines,
+    /// outermost first) itself, not counting the subroutines that it called
+    pub fn cycles(&self) -> &BTreeMap<Vec<u16>, u64> {
+        &self.cycles
+    }
+
+    /// Writes a `main;0x2A0;0x2F6 1234` line for each stack, which flamegraph.pl and inferno
+    /// turn into a flame graph
+    pub fn write_folded(&self, writer: &mut impl Write) -> io::Result<()> {
+        for (stack, cycles) in &self.cycles {
+            write!(writer, "main")?;
+            for address in stack {
+                write!(writer, ";{:#05X}", address)?;
+            }
+            writeln!(writer, " {}", cycles)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,57 @@
+/// The names that `Quirks::preset` accepts
+pub const QUIRKS_PRESETS: [&str; 4] = ["default", "vip", "schip", "chip8x"];
+
+/// Behaviors that differ between Chip-8 interpreters, and that some programs depend on.
+///
+/// The default is what this emulator has always done, which suits most of the bundled programs.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Quirks {
+    /// 8xy6 and 8xyE shift Vy into Vx, like the COSMAC VIP, instead of shifting Vx in place
+    pub shift_vy: bool,
+    /// Fx55 and Fx65 leave I after the last register they stored or loaded
+    pub increment_i: bool,
+    /// Bxnn jumps to xnn + Vx, like SUPER-CHIP, instead of Bnnn jumping to nnn + V0
+    pub jump_vx: bool,
+    /// 8xy1, 8xy2 and 8xy3 set VF to 0
+    pub vf_reset: bool,
+    /// Sprites are cut off at the edges of the screen, instead of wrapping around
+    pub clipping: bool,
+    /// The CHIP-8X instructions: 02A0 and Bxyn set colors (so Bnnn isn't a jump) and 5xy1 adds
+    /// coordinates
+    pub chip8x: bool,
+}
+
+impl Quirks {
+    /// `default`, `vip` (the original COSMAC VIP interpreter), `schip` (SUPER-CHIP 1.1) or
+    /// `chip8x` (the VIP interpreter for the VP-590 color board)
+    pub fn preset(name: &str) -> Result<Quirks, String> {
+        match name {
+            "default" => Ok(Quirks::default()),
+            "vip" => Ok(Quirks {
+                shift_vy: true,
+                increment_i: true,
+                jump_vx: false,
+                vf_reset: true,
+                clipping: true,
+                chip8x: false,
+            }),
+            "schip" => Ok(Quirks {
+                shift_vy: false,
+                increment_i: false,
+                jump_vx: true,
+                vf_reset: false,
+                clipping: true,
+                chip8x: false,
+            }),
+            "chip8x" => Ok(Quirks {
+                chip8x: true,
+                ..Quirks::preset("vip")?
+            }),
+            _ => Err(format!(
+                "Unknown quirks preset: {} (expected one of {})",
+                name,
+                QUIRKS_PRESETS.join(", ")
+            )),
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use rand::rngs::{SmallRng, StdRng};
+use rand::{Error, RngCore, SeedableRng};
+
+/// The random number generator behind Cxnn. It's an enum rather than a boxed `RngCore`, so that
+/// generating a number doesn't go through a virtual call.
+// Boxing StdRng would bring back the allocation, and each machine only has one of these
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug)]
+pub enum Random {
+    Std(StdRng),
+    /// Faster, but not as good as `Std`
+    Small(SmallRng),
+}
+
+impl Random {
+    /// A generator that always produces the same numbers for the same seed
+    pub fn seeded(seed: u64) -> Random {
+        Random::Std(StdRng::seed_from_u64(seed))
+    }
+}
+
+impl RngCore for Random {
+    fn next_u32(&mut self) -> u32 {
+        match self {
+            Random::Std(rng) => rng.next_u32(),
+            Random::Small(rng) => rng.next_u32(),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match self {
+            Random::Std(rng) => rng.next_u64(),
+            Random::Small(rng) => rng.next_u64(),
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        match self {
+            Random::Std(rng) => rng.fill_bytes(dest),
+            Random::Small(rng) => rng.fill_bytes(dest),
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        match self {
+            Random::Std(rng) => rng.try_fill_bytes(dest),
+            Random::Small(rng) => rng.try_fill_bytes(dest),
+        }
+    }
+}
+
+#[test]
+fn test_random() {
+    let mut first = Random::seeded(7);
+    let mut second = Random::seeded(7);
+    assert_eq!(first.next_u64(), second.next_u64());
+
+    let mut first = Random::Small(SmallRng::seed_from_u64(7));
+    let mut second = first.clone();
+    let mut bytes = [0; 4];
+    first.fill_bytes(&mut bytes);
+    assert_eq!(u32::from_le_bytes(bytes), second.next_u32());
+}
@@ -0,0 +1,61 @@
+use std::collections::HashSet;
+
+/// A read of a byte of memory that was neither loaded with the program nor written by it, which
+/// on real hardware could hold anything
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct UninitializedRead {
+    pub address: u16,
+    /// The address of the instruction that read it
+    pub program_counter: u16,
+}
+
+/// Which bytes of memory have been initialized, for finding reads of the ones that haven't
+#[derive(Clone, Debug)]
+pub(super) struct ShadowMemory {
+    initialized: Vec<bool>,
+    /// Each read is only reported once for each instruction, in the order they happened
+    reads: Vec<UninitializedRead>,
+    reported: HashSet<UninitializedRead>,
+}
+
+impl ShadowMemory {
+    /// Memory up to `loaded_end` (the interpreter area, the font and the program) counts as
+    /// initialized
+    pub(super) fn new(size: usize, loaded_end: usize) -> ShadowMemory {
+        let mut initialized = vec![false; size];
+        initialized[..loaded_end.min(size)].fill(true);
+        ShadowMemory {
+            initialized,
+            reads: Vec::new(),
+            reported: HashSet::new(),
+        }
+    }
+
+    /// Memory that is added counts as uninitialized
+    pub(super) fn resize(&mut self, size: usize) {
+        self.initialized.resize(size, false);
+    }
+
+    pub(super) fn write(&mut self, start: usize, len: usize) {
+        let end = (start + len).min(self.initialized.len());
+        self.initialized[start.min(end)..end].fill(true);
+    }
+
+    pub(super) fn read(&mut self, start: usize, len: usize, program_counter: u16) {
+        for address in start..start + len {
+            if self.initialized.get(address) == Some(&false) {
+                let read = UninitializedRead {
+                    address: address as u16,
+                    program_counter,
+                };
+                if self.reported.insert(read) {
+                    self.reads.push(read);
+                }
+            }
+        }
+    }
+
+    pub(super) fn reads(&self) -> &[UninitializedRead] {
+        &self.reads
+    }
+}
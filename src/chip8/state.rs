@@ -0,0 +1,400 @@
+use super::colors::{Colors, COLORS_SIZE};
+use super::{
+    fnv_hash, Chip8, DisplayBuffer, DEFAULT_MEMORY_SIZE, DEFAULT_STACK_DEPTH, DISPLAY_HEIGHTS,
+    MAX_MEMORY_SIZE, MAX_SCREEN_HEIGHT,
+};
+
+const MAGIC: &[u8; 4] = b"C8ST";
+/// The number of rows of the display in a 64x32 display mode
+const NORMAL_ROWS: usize = 32;
+/// Comes before the CHIP-8X colors at the end of a state (it can't be mistaken for a height)
+const COLORS_TAG: u8 = b'C';
+/// Comes before the size of memory and the memory after the first 4K, for machines with more
+const MEMORY_TAG: u8 = b'M';
+/// Comes before the stack depth and the return addresses after the first 16, for machines with
+/// another depth
+const STACK_TAG: u8 = b'S';
+/// The size of a state with the 64x32 display and without colors
+const NORMAL_STATE_SIZE: usize = MAGIC.len()
+    + DEFAULT_MEMORY_SIZE
+    + 16
+    + 2
+    + 2
+    + 2 * DEFAULT_STACK_DEPTH as usize
+    + 1
+    + NORMAL_ROWS * 8
+    + 3
+    + 4
+    + 8
+    + 8;
+/// The size of the largest states, with 64 rows and the CHIP-8X colors, for frontends that
+/// need to reserve room for states up front (with the default memory size and stack depth)
+pub const MAX_STATE_SIZE: usize =
+    NORMAL_STATE_SIZE + 1 + (MAX_SCREEN_HEIGHT - NORMAL_ROWS) * 8 + 1 + COLORS_SIZE;
+
+impl Chip8 {
+    /// Serializes the machine state (memory, registers, stack, display and timers).
+    ///
+    /// Breakpoints, pressed keys and the state of the random number generator are not included.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(0x1200);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&self.memory[..DEFAULT_MEMORY_SIZE]);
+        bytes.extend_from_slice(&self.registers);
+        bytes.extend_from_slice(&self.address_register.to_be_bytes());
+        bytes.extend_from_slice(&self.program_counter.to_be_bytes());
+        // A shallower stack is padded, so that the first part of a state always has the same size
+        for i in 0..DEFAULT_STACK_DEPTH as usize {
+            let address = self.stack.get(i).copied().unwrap_or(0);
+            bytes.extend_from_slice(&address.to_be_bytes());
+        }
+        bytes.push(self.stack_pointer);
+        let display = self.display_buffer.as_bytes();
+        bytes.extend_from_slice(&display[..NORMAL_ROWS * 8]);
+        bytes.push(self.delay_timer);
+        bytes.push(self.sound_timer);
+        bytes.push(self.register_blocking_on_key_press.unwrap_or(0xFF));
+        // Whole Hz, as before clock frequencies could be fractional
+        bytes.extend_from_slice(&(self.clock_frequency.round() as u32).to_be_bytes());
+        bytes.extend_from_slice(&self.timer_cooldown.to_be_bytes());
+        bytes.extend_from_slice(&self.cycle_cooldown.to_be_bytes());
+        // The rows below the normal 32 are at the end, so that the states of 64x32 machines stay
+        // the same
+        let height = self.display_buffer.height();
+        if height as usize > NORMAL_ROWS {
+            bytes.push(height);
+            bytes.extend_from_slice(&display[NORMAL_ROWS * 8..]);
+        }
+        if let Some(colors) = self.display_buffer.colors() {
+            bytes.push(COLORS_TAG);
+            bytes.extend_from_slice(&colors.to_bytes());
+        }
+        if self.memory.len() != DEFAULT_MEMORY_SIZE {
+            bytes.push(MEMORY_TAG);
+            bytes.extend_from_slice(&(self.memory.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(&self.memory[DEFAULT_MEMORY_SIZE..]);
+        }
+        if self.stack.len() != DEFAULT_STACK_DEPTH as usize {
+            bytes.push(STACK_TAG);
+            bytes.push(self.stack.len() as u8);
+            for address in self.stack.iter().skip(DEFAULT_STACK_DEPTH as usize) {
+                bytes.extend_from_slice(&address.to_be_bytes());
+            }
+        }
+        bytes
+    }
+
+    /// A 64-bit FNV-1a hash of the save state, which covers memory, registers, display and timers.
+    /// Machines that ran the same program with the same inputs have the same hash.
+    pub fn state_hash(&self) -> u64 {
+        fnv_hash(&self.save_state())
+    }
+
+    /// A 64-bit FNV-1a hash of what the program shows and computes: the display (with its
+    /// height), V0-VF, I and PC. Unlike `state_hash`, it leaves out memory, timers and the clock,
+    /// so it only changes when the behavior of the emulator does (and not when the save state
+    /// format does).
+    pub fn screen_hash(&self) -> u64 {
+        let mut bytes = vec![self.display_buffer.height()];
+        bytes.extend_from_slice(&self.display_buffer.as_bytes());
+        bytes.extend_from_slice(&self.registers);
+        bytes.extend_from_slice(&self.address_register.to_be_bytes());
+        bytes.extend_from_slice(&self.program_counter.to_be_bytes());
+        fnv_hash(&bytes)
+    }
+
+    /// Restores a state that was created with `save_state`, along with the memory size and stack
+    /// depth that the machine had
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let mut reader = Reader { bytes };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err("Not a Chip-8 save state".to_owned());
+        }
+        let mut memory = reader.take(DEFAULT_MEMORY_SIZE)?.to_vec();
+        let mut registers = [0; 16];
+        registers.copy_from_slice(reader.take(16)?);
+        let address_register = reader.u16()?;
+        let program_counter = reader.u16()?;
+        let mut stack = vec![0; DEFAULT_STACK_DEPTH as usize];
+        for address in stack.iter_mut() {
+            *address = reader.u16()?;
+        }
+        let stack_pointer = reader.u8()?;
+        let mut rows = vec![0; NORMAL_ROWS];
+        for row in rows.iter_mut() {
+            *row = reader.u64()?;
+        }
+        let delay_timer = reader.u8()?;
+        let sound_timer = reader.u8()?;
+        let register_blocking_on_key_press = match reader.u8()? {
+            0xFF => None,
+            register if register <= 0xF => Some(register),
+            register => {
+                return Err(format!(
+                    "Invalid register waiting for a key in save state: {}",
+                    register
+                ))
+            }
+        };
+        let clock_frequency = reader.u32()?;
+        let timer_cooldown = reader.f64()?;
+        let cycle_cooldown = reader.f64()?;
+        if !reader.bytes.is_empty()
+            && ![COLORS_TAG, MEMORY_TAG, STACK_TAG].contains(&reader.bytes[0])
+        {
+            let height = reader.u8()?;
+            if !DISPLAY_HEIGHTS.contains(&height) || height as usize <= NORMAL_ROWS {
+                return Err(format!("Invalid display height in save state: {}", height));
+            }
+            for _ in NORMAL_ROWS..height as usize {
+                rows.push(reader.u64()?);
+            }
+        }
+        let mut display_buffer = DisplayBuffer::from_rows(&rows);
+        if !reader.bytes.is_empty() && reader.bytes[0] == COLORS_TAG {
+            reader.u8()?;
+            display_buffer.colors = Some(Colors::from_bytes(reader.take(COLORS_SIZE)?)?);
+        }
+        if !reader.bytes.is_empty() && reader.bytes[0] == MEMORY_TAG {
+            reader.u8()?;
+            let size = reader.u32()? as usize;
+            if !(DEFAULT_MEMORY_SIZE..=MAX_MEMORY_SIZE).contains(&size) {
+                return Err(format!("Invalid memory size in save state: {:#X}", size));
+            }
+            memory.extend_from_slice(reader.take(size - DEFAULT_MEMORY_SIZE)?);
+        }
+        if !reader.bytes.is_empty() && reader.bytes[0] == STACK_TAG {
+            reader.u8()?;
+            let depth = reader.u8()? as usize;
+            if depth == 0 {
+                return Err("Invalid stack depth in save state: 0".to_owned());
+            }
+            stack.resize(depth, 0);
+            for address in stack.iter_mut().skip(DEFAULT_STACK_DEPTH as usize) {
+                *address = reader.u16()?;
+            }
+        }
+        if stack_pointer as usize > stack.len() {
+            return Err(format!(
+                "Invalid stack pointer in save state: {}",
+                stack_pointer
+            ));
+        }
+        if !reader.bytes.is_empty() {
+            return Err("Unexpected data at the end of the save state".to_owned());
+        }
+
+        self.resize_memory(memory.len());
+        self.memory = memory;
+        self.decoded.iter_mut().for_each(|entry| *entry = None);
+        self.registers = registers;
+        self.address_register = address_register;
+        self.program_counter = program_counter;
+        self.stack = stack;
+        self.stack_pointer = stack_pointer;
+        self.display_buffer = display_buffer;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.register_blocking_on_key_press = register_blocking_on_key_press;
+        // Keeps a fractional frequency that the state was saved with
+        if clock_frequency != self.clock_frequency.round() as u32 {
+            self.set_clock_frequency(clock_frequency as f64);
+        }
+        self.timer_cooldown = timer_cooldown;
+        self.cycle_cooldown = cycle_cooldown;
+        self.cycles_left_in_frame = 0;
+        self.stopped_at_breakpoint = None;
+        self.run_to_address = None;
+        Ok(())
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() < n {
+            return Err("Save state is truncated".to_owned());
+        }
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let mut buf = [0; 2];
+        buf.copy_from_slice(self.take(2)?);
+        Ok(u16::from_be_bytes(buf))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(self.take(4)?);
+        Ok(u32::from_be_bytes(buf))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(self.take(8)?);
+        Ok(u64::from_be_bytes(buf))
+    }
+
+    fn f64(&mut self) -> Result<f64, String> {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(self.take(8)?);
+        Ok(f64::from_be_bytes(buf))
+    }
+}
+
+#[test]
+fn test_save_and_load_state() {
+    let mut memory = [0; 0x1000];
+    memory[0x200..0x206].copy_from_slice(&[0x60, 0x23, 0xA2, 0x00, 0xD0, 0x05]);
+    let mut m = Chip8::new(memory);
+    m.step_instruction().unwrap();
+    m.step_instruction().unwrap();
+    m.step_instruction().unwrap();
+    m.delay_timer = 0x10;
+    m.set_clock_frequency(700.0);
+    let state = m.save_state();
+
+    let mut restored = Chip8::new([0; 0x1000]);
+    restored.load_state(&state).unwrap();
+
+    assert_eq!(restored.memory(), m.memory());
+    assert_eq!(restored.registers, m.registers);
+    assert_eq!(restored.address_register, 0x200);
+    assert_eq!(restored.program_counter, 0x206);
+    assert_eq!(restored.display_buffer, m.display_buffer);
+    assert_eq!(restored.delay_timer, 0x10);
+    assert_eq!(restored.clock_frequency(), 700.0);
+    assert_eq!(restored.save_state(), state);
+}
+
+#[test]
+fn test_save_and_load_state_with_tall_display() {
+    let mut m = Chip8::new([0; 0x1000]);
+    m.set_display_height(48).unwrap();
+    m.display_buffer.flip_pixel(3, 40);
+    let state = m.save_state();
+    assert_eq!(
+        state.len(),
+        Chip8::new([0; 0x1000]).save_state().len() + 1 + 16 * 8
+    );
+
+    let mut restored = Chip8::new([0; 0x1000]);
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.display_buffer.height(), 48);
+    assert!(restored.display_buffer.get_pixel(3, 40));
+
+    restored
+        .load_state(&Chip8::new([0; 0x1000]).save_state())
+        .unwrap();
+    assert_eq!(restored.display_buffer.height(), 32);
+}
+
+#[test]
+fn test_save_and_load_state_with_colors() {
+    use super::Instruction;
+
+    let mut m = Chip8::new([0; 0x1000]);
+    m.set_display_height(64).unwrap();
+    m.execute(Instruction::NextBackgroundColor).unwrap();
+    let state = m.save_state();
+    assert_eq!(state.len(), MAX_STATE_SIZE);
+
+    let mut restored = Chip8::new([0; 0x1000]);
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.display_buffer.height(), 64);
+    assert_eq!(restored.display_buffer.colors(), m.display_buffer.colors());
+    assert!(restored.load_state(&state[..state.len() - 1]).is_err());
+}
+
+#[test]
+fn test_load_invalid_state() {
+    let mut m = Chip8::new([0; 0x1000]);
+    let state = m.save_state();
+    assert_eq!(state.len(), NORMAL_STATE_SIZE);
+
+    assert!(m.load_state(b"nope").is_err());
+    assert!(m.load_state(&state[..state.len() - 1]).is_err());
+
+    // The register that waits for a key, which comes before the clock and the two cooldowns
+    let mut state = state;
+    let register = state.len() - 8 - 8 - 4 - 1;
+    state[register] = 0x10;
+    assert!(m.load_state(&state).is_err());
+    state[register] = 0xF;
+    m.load_state(&state).unwrap();
+    assert_eq!(m.waiting_for_key(), Some(0xF));
+}
+
+#[test]
+fn test_state_hash() {
+    let rom = [0x60, 0x23, 0x70, 0x01, 0x12, 0x02];
+    let mut a = Chip8::from_rom(&rom).unwrap();
+    let mut b = Chip8::from_rom(&rom).unwrap();
+    assert_eq!(a.state_hash(), b.state_hash());
+
+    a.step_instruction().unwrap();
+    assert_ne!(a.state_hash(), b.state_hash());
+    b.step_instruction().unwrap();
+    assert_eq!(a.state_hash(), b.state_hash());
+}
+
+#[test]
+fn test_screen_hash() {
+    // V0 = 0x23, then count down the delay timer
+    let rom = [0x60, 0x23, 0xF0, 0x15, 0x12, 0x04];
+    let mut a = Chip8::from_rom(&rom).unwrap();
+    let mut b = Chip8::from_rom(&rom).unwrap();
+    a.step_instruction().unwrap();
+    assert_ne!(a.screen_hash(), b.screen_hash());
+    b.step_instruction().unwrap();
+    assert_eq!(a.screen_hash(), b.screen_hash());
+
+    // The timers and memory don't count
+    a.delay_timer = 5;
+    a.write_memory(0x300, &[1]).unwrap();
+    assert_eq!(a.screen_hash(), b.screen_hash());
+    assert_ne!(a.state_hash(), b.state_hash());
+    a.display_buffer = DisplayBuffer::from_rows(&[1; NORMAL_ROWS]);
+    assert_ne!(a.screen_hash(), b.screen_hash());
+}
+
+#[test]
+fn test_save_and_load_state_with_more_memory_and_stack() {
+    let mut m = Chip8::new([0; 0x1000]);
+    m.set_memory_size(0x2000).unwrap();
+    m.set_stack_depth(20).unwrap();
+    m.write_memory(0x1F00, &[0xAB]).unwrap();
+    m.stack[18] = 0x345;
+    m.stack_pointer = 19;
+    let state = m.save_state();
+    assert_eq!(
+        state.len(),
+        NORMAL_STATE_SIZE + 1 + 4 + 0x1000 + 1 + 1 + 2 * 4
+    );
+
+    let mut restored = Chip8::new([0; 0x1000]);
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.memory().len(), 0x2000);
+    assert_eq!(restored.memory()[0x1F00], 0xAB);
+    assert_eq!(restored.stack.len(), 20);
+    assert_eq!(restored.stack[18], 0x345);
+    assert_eq!(restored.save_state(), state);
+
+    m.set_stack_depth(8).unwrap_err();
+    m.stack_pointer = 8;
+    m.set_stack_depth(8).unwrap();
+    let state = m.save_state();
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.stack.len(), 8);
+    assert_eq!(restored.stack_pointer, 8);
+}
@@ -0,0 +1,130 @@
+use super::{Chip8, Random, StepReport};
+
+use std::collections::VecDeque;
+
+/// The machine from before one of the recorded steps, with what a save state leaves out, so that
+/// the step can be taken again with the same random numbers
+struct Snapshot {
+    state: Vec<u8>,
+    random: Random,
+    pressed_keys: [bool; 16],
+    unseen_presses: u16,
+    cycles_left_in_frame: u32,
+    cycles: u64,
+    /// The state hash right after the step, for noticing that the machine has since been run
+    /// (or loaded or reset) some other way
+    after: u64,
+}
+
+/// The last steps that were taken through `step`, for stepping backwards in a debugger
+pub struct StepHistory {
+    snapshots: VecDeque<Snapshot>,
+    capacity: usize,
+}
+
+impl StepHistory {
+    /// Remembers up to `capacity` steps, forgetting the oldest ones first
+    pub fn new(capacity: usize) -> StepHistory {
+        StepHistory {
+            snapshots: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /// Executes one instruction (see `Chip8::step_instruction`), remembering the machine from
+    /// before it
+    pub fn step(&mut self, chip8: &mut Chip8) -> Result<StepReport, String> {
+        let snapshot = Snapshot {
+            state: chip8.save_state(),
+            random: chip8.random.clone(),
+            pressed_keys: chip8.pressed_keys,
+            unseen_presses: chip8.unseen_presses,
+            cycles_left_in_frame: chip8.cycles_left_in_frame,
+            cycles: chip8.cycles,
+            after: 0,
+        };
+        let report = chip8.step_instruction()?;
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+        }
+        if self.capacity > 0 {
+            self.snapshots.push_back(Snapshot {
+                after: chip8.state_hash(),
+                ..snapshot
+            });
+        }
+        Ok(report)
+    }
+
+    /// Restores the machine to how it was before the last step, and returns false if there is
+    /// no such step. Steps that were followed by anything else than another step (like running
+    /// or loading a state) are forgotten.
+    pub fn step_back(&mut self, chip8: &mut Chip8) -> Result<bool, String> {
+        if self
+            .snapshots
+            .back()
+            .is_some_and(|snapshot| snapshot.after != chip8.state_hash())
+        {
+            self.snapshots.clear();
+        }
+        let snapshot = match self.snapshots.pop_back() {
+            Some(snapshot) => snapshot,
+            None => return Ok(false),
+        };
+        chip8.load_state(&snapshot.state)?;
+        chip8.random = snapshot.random;
+        chip8.pressed_keys = snapshot.pressed_keys;
+        chip8.unseen_presses = snapshot.unseen_presses;
+        chip8.cycles_left_in_frame = snapshot.cycles_left_in_frame;
+        chip8.cycles = snapshot.cycles;
+        chip8.stopped_at_breakpoint = None;
+        Ok(true)
+    }
+
+    /// The number of steps that can be taken back
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+}
+
+#[test]
+fn test_step_history() {
+    // V0 = random, V1 += 1, jump to start
+    let mut chip8 = Chip8::from_rom(&[0xC0, 0xFF, 0x71, 0x01, 0x12, 0x00]).unwrap();
+    let mut history = StepHistory::new(3);
+    assert!(!history.step_back(&mut chip8).unwrap());
+
+    for _ in 0..3 {
+        history.step(&mut chip8).unwrap();
+    }
+    let random = chip8.registers[0];
+    for _ in 0..3 {
+        assert!(history.step_back(&mut chip8).unwrap());
+    }
+    assert!(!history.step_back(&mut chip8).unwrap());
+    assert_eq!(chip8.program_counter, 0x200);
+    assert_eq!(chip8.registers, [0; 16]);
+    assert_eq!(chip8.cycles(), 0);
+    // Stepping again takes the same random number
+    history.step(&mut chip8).unwrap();
+    assert_eq!(chip8.registers[0], random);
+
+    // Only the last 3 steps are kept
+    for _ in 0..4 {
+        history.step(&mut chip8).unwrap();
+    }
+    assert_eq!(history.len(), 3);
+
+    // Running the machine otherwise forgets the steps
+    chip8.step_instruction().unwrap();
+    assert!(!history.step_back(&mut chip8).unwrap());
+    assert!(history.is_empty());
+}
@@ -0,0 +1,158 @@
+use super::Quirks;
+use crate::assembly;
+
+use std::io::{self, Write};
+
+/// The names that `TraceFilter::parse` accepts
+pub const TRACE_FILTERS: [&str; 3] = ["all", "jumps", "vf"];
+
+/// Which instructions end up in the trace
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TraceFilter {
+    All,
+    /// Jumps, calls and returns
+    Jumps,
+    /// Instructions that write to VF (even if they leave it unchanged)
+    VfWrites,
+}
+
+impl TraceFilter {
+    pub fn parse(name: &str) -> Result<TraceFilter, String> {
+        match name {
+            "all" => Ok(TraceFilter::All),
+            "jumps" => Ok(TraceFilter::Jumps),
+            "vf" => Ok(TraceFilter::VfWrites),
+            _ => Err(format!(
+                "Unknown trace filter: {} (expected one of {})",
+                name,
+                TRACE_FILTERS.join(", ")
+            )),
+        }
+    }
+
+    fn includes(self, opcode: u16, quirks: Quirks) -> bool {
+        match self {
+            TraceFilter::All => true,
+            TraceFilter::Jumps => is_jump(opcode) && !(quirks.chip8x && is_chip8x_color(opcode)),
+            TraceFilter::VfWrites => writes_vf(opcode, quirks),
+        }
+    }
+}
+
+fn is_jump(opcode: u16) -> bool {
+    matches!(opcode & 0xF000, 0x0000 | 0x1000 | 0x2000 | 0xB000) && opcode != 0x00E0
+}
+
+/// The CHIP-8X op-codes that take the place of 0nnn and Bnnn
+fn is_chip8x_color(opcode: u16) -> bool {
+    opcode == 0x02A0 || opcode & 0xF000 == 0xB000
+}
+
+fn writes_vf(opcode: u16, quirks: Quirks) -> bool {
+    let x = (opcode & 0x0F00) >> 8;
+    match opcode & 0xF000 {
+        0x5000 => quirks.chip8x && opcode & 0x000F == 1 && x == 0xF,
+        0x6000 | 0x7000 | 0xC000 => x == 0xF,
+        0x8000 => match opcode & 0x000F {
+            0x0 => x == 0xF,
+            0x1..=0x3 => x == 0xF || quirks.vf_reset,
+            _ => true,
+        },
+        0xD000 => true,
+        0xF000 => match opcode & 0x00FF {
+            0x07 | 0x0A | 0x65 | 0x85 => x == 0xF,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// The registers that an instruction may change
+#[derive(Clone, Copy, PartialEq)]
+pub(super) struct Registers {
+    pub(super) v: [u8; 16],
+    pub(super) i: u16,
+    pub(super) stack_pointer: u8,
+    pub(super) delay_timer: u8,
+    pub(super) sound_timer: u8,
+}
+
+/// Writes a line for each executed instruction: the cycle, the address, the op-code, the
+/// disassembled instruction and the registers that it changed
+pub struct Tracer {
+    writer: Box<dyn Write + Send>,
+    filter: TraceFilter,
+    cycle: u64,
+}
+
+impl Tracer {
+    pub fn new(writer: Box<dyn Write + Send>, filter: TraceFilter) -> Tracer {
+        Tracer {
+            writer,
+            filter,
+            cycle: 0,
+        }
+    }
+
+    pub(super) fn record(
+        &mut self,
+        address: u16,
+        opcode: u16,
+        before: &Registers,
+        after: &Registers,
+        quirks: Quirks,
+    ) -> io::Result<()> {
+        let cycle = self.cycle;
+        self.cycle += 1;
+        if !self.filter.includes(opcode, quirks) {
+            return Ok(());
+        }
+
+        let instruction = assembly::disassemble_opcode(opcode)
+            .unwrap_or_else(|_| format!("(data: {:04X})", opcode));
+        let mut changes = Vec::new();
+        for (i, (a, b)) in before.v.iter().zip(after.v.iter()).enumerate() {
+            if a != b {
+                changes.push(format!("V{:X}={:#04X}", i, b));
+            }
+        }
+        if before.i != after.i {
+            changes.push(format!("I={:#05X}", after.i));
+        }
+        if before.stack_pointer != after.stack_pointer {
+            changes.push(format!("SP={}", after.stack_pointer));
+        }
+        if before.delay_timer != after.delay_timer {
+            changes.push(format!("delay={:#04X}", after.delay_timer));
+        }
+        if before.sound_timer != after.sound_timer {
+            changes.push(format!("sound={:#04X}", after.sound_timer));
+        }
+        writeln!(
+            self.writer,
+            "{:>8} {:03X} {:04X} {:<28} {}",
+            cycle,
+            address,
+            opcode,
+            instruction,
+            changes.join(" ")
+        )
+    }
+}
+
+#[test]
+fn test_trace_filter() {
+    let quirks = Quirks::default();
+    assert!(TraceFilter::Jumps.includes(0x1200, quirks));
+    assert!(TraceFilter::Jumps.includes(0x00EE, quirks));
+    assert!(!TraceFilter::Jumps.includes(0x00E0, quirks));
+    assert!(!TraceFilter::Jumps.includes(0x6F01, quirks));
+
+    assert!(TraceFilter::VfWrites.includes(0x6F01, quirks));
+    assert!(TraceFilter::VfWrites.includes(0x8124, quirks));
+    assert!(TraceFilter::VfWrites.includes(0xD125, quirks));
+    assert!(!TraceFilter::VfWrites.includes(0x8121, quirks));
+    assert!(TraceFilter::VfWrites.includes(0x8121, Quirks::preset("vip").unwrap()));
+    assert!(!TraceFilter::VfWrites.includes(0x6E01, quirks));
+    assert!(TraceFilter::parse("calls").is_err());
+}
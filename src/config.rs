@@ -0,0 +1,111 @@
+use serde::Deserialize;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// The config file that the emulator reads from the current directory, if it exists
+pub const CONFIG_FILE: &str = "chip8.toml";
+
+/// Settings read from a TOML file, such as:
+///
+/// ```toml
+/// clock = 700
+/// quirks = "vip"
+/// keymap = "x123qweasdzc4rfv"
+/// palette = "amber"
+/// scale = 12
+/// debug = true
+/// watches = ["V3", "mem[I]"]
+/// ```
+///
+/// Everything is optional, and the flags given on the command line override these values.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub clock: Option<f64>,
+    /// Instructions per frame, which takes precedence over `clock`
+    pub tickrate: Option<u32>,
+    /// Make instructions take as long as they did on the COSMAC VIP, relative to each other
+    pub cycle_costs: Option<bool>,
+    /// Keep quick key presses until the program checks the key
+    pub sticky_keys: Option<bool>,
+    /// Where the ROM is loaded, like `"600"` or `"eti660"`
+    pub start_address: Option<String>,
+    /// `"64x32"`, `"64x48"` or `"64x64"`
+    pub display_mode: Option<String>,
+    /// The size of memory in hex, like `"10000"` for the 64K of XO-CHIP
+    pub memory_size: Option<String>,
+    pub quirks: Option<String>,
+    pub keymap: Option<String>,
+    pub gamepad_map: Option<String>,
+    pub palette: Option<String>,
+    pub fg: Option<String>,
+    pub bg: Option<String>,
+    /// The initial size of a Chip-8 pixel, in window pixels
+    pub scale: Option<f32>,
+    pub debug: Option<bool>,
+    pub ghosting: Option<bool>,
+    pub crt: Option<bool>,
+    pub stats: Option<bool>,
+    pub threaded: Option<bool>,
+    pub pause_on_focus_loss: Option<bool>,
+    /// Run slower while the window is in the background
+    pub background_throttle: Option<bool>,
+    /// Fill the memory after the program with random bytes
+    pub random_ram: Option<bool>,
+    /// `"warn"` or `"error"` on writes to the interpreter and font area
+    pub protect_font: Option<String>,
+    /// A file with a font to use instead of the built-in one
+    pub font: Option<String>,
+    /// Save the state next to the ROM on exit
+    pub autosave: Option<bool>,
+    pub persist_flags: Option<bool>,
+    /// Keep the top 256 bytes of memory in a save file
+    pub battery: Option<bool>,
+    /// Keep another range of memory in the save file instead, like `"E00-FFF"`
+    pub battery_range: Option<String>,
+    pub watches: Vec<String>,
+    /// Set to false to not use the recommended settings of known ROMs
+    pub rom_database: Option<bool>,
+}
+
+impl Config {
+    pub fn parse(toml: &str) -> Result<Config, String> {
+        toml::from_str(toml).map_err(|err| err.to_string())
+    }
+
+    /// Reads the config file, unless it doesn't exist (in which case everything is left unset)
+    pub fn load_if_exists(path: &Path) -> Result<Config, String> {
+        match fs::read_to_string(path) {
+            Ok(toml) => Config::parse(&toml).map_err(|err| format!("{}: {}", path.display(), err)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Config::default()),
+            Err(err) => Err(format!("Couldn't read {}: {}", path.display(), err)),
+        }
+    }
+}
+
+#[test]
+fn test_parse_config() {
+    let config = Config::parse(
+        r#"
+        clock = 700
+        quirks = "vip"
+        gamepad-map = "south=5"
+        scale = 12
+        debug = true
+        watches = ["V3"]
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(config.clock, Some(700.0));
+    assert_eq!(config.quirks.as_deref(), Some("vip"));
+    assert_eq!(config.gamepad_map.as_deref(), Some("south=5"));
+    assert_eq!(config.scale, Some(12.0));
+    assert_eq!(config.debug, Some(true));
+    assert_eq!(config.watches, vec!["V3"]);
+    assert_eq!(config.palette, None);
+    assert_eq!(Config::parse("").unwrap(), Config::default());
+    assert!(Config::parse("clock = \"fast\"").is_err());
+    assert!(Config::parse("colour = \"red\"").is_err());
+}
@@ -0,0 +1,113 @@
+//! Files that are written when a program fails, so that bug reports can come with something to
+//! reproduce the failure with: a save state from just before the failing instruction, and a report
+//! with the error and the instructions that led up to it.
+
+use crate::assembly;
+use crate::builtin_roms;
+use crate::chip8::Chip8;
+
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub struct CrashDump {
+    /// Can be loaded with `debugger --load-state` (or `emulator --load-state`)
+    pub state_path: PathBuf,
+    pub report_path: PathBuf,
+}
+
+/// Writes `ROM.crash` (the save state) and `ROM.crash.txt` (the report) next to the ROM. There's
+/// nowhere to write them for built-in ROMs.
+pub fn write(chip8: &Chip8, rom_path: &Path, error: &str) -> Result<CrashDump, String> {
+    if builtin_roms::is_builtin(rom_path) {
+        return Err(format!(
+            "No crash dump is written for the built-in ROM {}",
+            rom_path.display()
+        ));
+    }
+    let state_path = with_suffix(rom_path, ".crash");
+    let report_path = with_suffix(rom_path, ".crash.txt");
+    fs::write(&state_path, chip8.save_state())
+        .map_err(|err| format!("Couldn't write {}: {}", state_path.display(), err))?;
+    let report = format!(
+        "{}\n\nLoad the state in the debugger with:\n  cargo run --bin debugger -- -f \"{}\" --load-state \"{}\"\n",
+        report(chip8, error),
+        rom_path.display(),
+        state_path.display()
+    );
+    fs::write(&report_path, report)
+        .map_err(|err| format!("Couldn't write {}: {}", report_path.display(), err))?;
+    Ok(CrashDump {
+        state_path,
+        report_path,
+    })
+}
+
+/// The error, the most recently executed instructions and the registers
+pub fn report(chip8: &Chip8, error: &str) -> String {
+    let memory = chip8.memory();
+    let mut report = format!("{}\n\nRecent instructions, oldest first:\n", error);
+    for &address in chip8.history() {
+        let address = address as usize;
+        let opcode = u16::from_be_bytes([memory[address], memory[address + 1]]);
+        let instruction = assembly::disassemble_opcode(opcode)
+            .unwrap_or_else(|_| format!("(data: {:04X})", opcode));
+        report.push_str(&format!(
+            "  {:#05X}  {:04X}  {}\n",
+            address, opcode, instruction
+        ));
+    }
+
+    let registers: Vec<String> = chip8
+        .registers
+        .iter()
+        .map(|value| format!("{:02X}", value))
+        .collect();
+    report.push_str(&format!("\nV0-VF: {}\n", registers.join(" ")));
+    report.push_str(&format!(
+        "I: {:#05X}  PC: {:#05X}  delay: {:#04X}  sound: {:#04X}\n",
+        chip8.address_register, chip8.program_counter, chip8.delay_timer, chip8.sound_timer
+    ));
+    let stack: Vec<String> = chip8.stack[..chip8.stack_pointer as usize]
+        .iter()
+        .map(|address| format!("{:#05X}", address))
+        .collect();
+    report.push_str(&format!("Stack: [{}]", stack.join(", ")));
+    report
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[test]
+fn test_crash_dump() {
+    let dir = std::env::temp_dir().join(format!("chip8_crash_dump_test_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let rom_path = dir.join("game.ch8");
+    // V0 = 0x23, call 0x206, an unhandled op-code
+    let mut chip8 = Chip8::from_rom(&[0x60, 0x23, 0x22, 0x06, 0x00, 0x00, 0xFF, 0xFF]).unwrap();
+    chip8.step_instruction().unwrap();
+    chip8.step_instruction().unwrap();
+    let error = chip8.step_instruction().unwrap_err();
+
+    let dump = write(&chip8, &rom_path, &error).unwrap();
+    assert_eq!(dump.state_path, dir.join("game.ch8.crash"));
+    let report = fs::read_to_string(&dump.report_path).unwrap();
+    assert!(report.starts_with("Unhandled op-code: 0xFFFF at 0x206\n"));
+    assert!(report.contains("  0x202  2206  "));
+    assert!(report.contains("V0-VF: 23 00"));
+    assert!(report.contains("Stack: [0x204]"));
+
+    let mut restored = Chip8::new([0; 0x1000]);
+    restored
+        .load_state(&fs::read(&dump.state_path).unwrap())
+        .unwrap();
+    assert_eq!(restored.program_counter, 0x206);
+    fs::remove_dir_all(dir).unwrap();
+
+    assert!(write(&chip8, Path::new("builtin:maze"), &error).is_err());
+    assert!(!Path::new("builtin:maze.crash").exists());
+}
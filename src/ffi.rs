@@ -0,0 +1,296 @@
+//! A C interface, for embedding the interpreter in frontends written in other languages. The
+//! declarations are in `include/chip8.h`.
+//!
+//! Every function takes the pointer that `chip8_new` returned, which is only freed by
+//! `chip8_free`. Errors are reported by the return value, and `chip8_last_error` tells what went
+//! wrong.
+
+use crate::chip8::Chip8;
+
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+use std::slice;
+
+/// A machine and what the C side reads from it
+pub struct FfiChip8 {
+    chip8: Chip8,
+    /// One byte per pixel (1 if it's lit), row by row from the top left
+    display: Vec<u8>,
+    last_error: Option<CString>,
+}
+
+impl FfiChip8 {
+    fn update_display(&mut self) {
+        self.display.clear();
+        self.display
+            .extend(self.chip8.display_buffer.pixels().map(|lit| lit as u8));
+    }
+
+    fn fail(&mut self, err: String) -> c_int {
+        // An error can't contain a NUL byte unless it quotes one from somewhere
+        self.last_error = Some(CString::new(err.replace('\0', "")).unwrap());
+        -1
+    }
+}
+
+/// Creates a machine with nothing loaded, which has to be freed with `chip8_free`
+#[no_mangle]
+pub extern "C" fn chip8_new() -> *mut FfiChip8 {
+    let mut machine = FfiChip8 {
+        chip8: Chip8::new([0; 0x1000]),
+        display: Vec::new(),
+        last_error: None,
+    };
+    machine.update_display();
+    Box::into_raw(Box::new(machine))
+}
+
+/// Replaces the machine with a new one that runs the ROM. Returns 0, or -1 on errors.
+///
+/// # Safety
+///
+/// `machine` must come from `chip8_new`, and `rom` must point to `length` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_rom(
+    machine: *mut FfiChip8,
+    rom: *const u8,
+    length: usize,
+) -> c_int {
+    let machine = &mut *machine;
+    if rom.is_null() {
+        return machine.fail("The ROM is a null pointer".to_owned());
+    }
+    match Chip8::from_rom(slice::from_raw_parts(rom, length)) {
+        Ok(chip8) => {
+            machine.chip8 = chip8;
+            machine.update_display();
+            0
+        }
+        Err(err) => machine.fail(err),
+    }
+}
+
+/// Runs as many instructions as fit in the elapsed time (in seconds). Returns the number of
+/// executed instructions, or -1 on errors (like an unknown op-code).
+///
+/// # Safety
+///
+/// `machine` must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_update(machine: *mut FfiChip8, elapsed_time: f64) -> c_int {
+    let machine = &mut *machine;
+    let result = machine.chip8.update(elapsed_time);
+    machine.update_display();
+    match result {
+        Ok(report) => report.cycles.min(c_int::MAX as u32) as c_int,
+        Err(err) => machine.fail(err),
+    }
+}
+
+/// Executes one instruction (and counts down the timers by the time it takes). Returns 0, or -1
+/// on errors.
+///
+/// # Safety
+///
+/// `machine` must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_step(machine: *mut FfiChip8) -> c_int {
+    let machine = &mut *machine;
+    let result = machine.chip8.step_instruction();
+    machine.update_display();
+    match result {
+        Ok(_) => 0,
+        Err(err) => machine.fail(err),
+    }
+}
+
+/// Presses (if `pressed` is non-zero) or releases a keypad key, 0 to F. Other keys are ignored.
+///
+/// # Safety
+///
+/// `machine` must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_key(machine: *mut FfiChip8, key: u8, pressed: c_int) {
+    if key < 16 {
+        (*machine).chip8.handle_key_event(key, pressed != 0);
+    }
+}
+
+/// The display, as `chip8_display_width` times `chip8_display_height` bytes that are 1 for lit
+/// pixels and 0 for the others. The pointer is valid until the next call that takes the machine.
+///
+/// # Safety
+///
+/// `machine` must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_display_ptr(machine: *const FfiChip8) -> *const u8 {
+    (*machine).display.as_ptr()
+}
+
+/// Always 64
+///
+/// # Safety
+///
+/// `machine` must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_display_width(machine: *const FfiChip8) -> c_int {
+    (*machine).chip8.display_buffer.width() as c_int
+}
+
+/// 32, or 48 or 64 in the hires display modes
+///
+/// # Safety
+///
+/// `machine` must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_display_height(machine: *const FfiChip8) -> c_int {
+    (*machine).chip8.display_buffer.height() as c_int
+}
+
+/// The 4096 bytes of memory. The pointer is valid until the next call that takes the machine.
+///
+/// # Safety
+///
+/// `machine` must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_memory_ptr(machine: *const FfiChip8) -> *const u8 {
+    (*machine).chip8.memory().as_ptr()
+}
+
+/// The 16 registers, V0 to VF. The pointer is valid until the next call that takes the machine.
+///
+/// # Safety
+///
+/// `machine` must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_registers_ptr(machine: *const FfiChip8) -> *const u8 {
+    (*machine).chip8.registers.as_ptr()
+}
+
+/// # Safety
+///
+/// `machine` must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_program_counter(machine: *const FfiChip8) -> u16 {
+    (*machine).chip8.program_counter
+}
+
+/// The address register, I
+///
+/// # Safety
+///
+/// `machine` must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_address_register(machine: *const FfiChip8) -> u16 {
+    (*machine).chip8.address_register
+}
+
+/// # Safety
+///
+/// `machine` must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_delay_timer(machine: *const FfiChip8) -> u8 {
+    (*machine).chip8.delay_timer
+}
+
+/// # Safety
+///
+/// `machine` must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_sound_timer(machine: *const FfiChip8) -> u8 {
+    (*machine).chip8.sound_timer
+}
+
+/// Whether the beeper is on. Returns 1 or 0.
+///
+/// # Safety
+///
+/// `machine` must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_sound_playing(machine: *const FfiChip8) -> c_int {
+    ((*machine).chip8.sound_timer > 0) as c_int
+}
+
+/// The message of the last error, or null if there hasn't been one. The string is valid until
+/// the next call that takes the machine.
+///
+/// # Safety
+///
+/// `machine` must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_last_error(machine: *const FfiChip8) -> *const c_char {
+    match &(*machine).last_error {
+        Some(err) => err.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Frees the machine. Null is ignored.
+///
+/// # Safety
+///
+/// `machine` must come from `chip8_new`, and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_free(machine: *mut FfiChip8) {
+    if !machine.is_null() {
+        drop(Box::from_raw(machine));
+    }
+}
+
+#[test]
+fn test_ffi() {
+    use std::ffi::CStr;
+
+    unsafe {
+        let machine = chip8_new();
+        // Draws the top of the 0 in the font at (0, 0), then loops
+        let rom = [0x60, 0x00, 0xF0, 0x29, 0xD0, 0x01, 0x12, 0x06];
+        assert_eq!(chip8_load_rom(machine, rom.as_ptr(), rom.len()), 0);
+        chip8_key(machine, 5, 1);
+        assert!((*machine).chip8.pressed_keys()[5]);
+        assert!(chip8_update(machine, 0.02) > 3);
+        assert_eq!(chip8_display_width(machine), 64);
+        assert_eq!(chip8_display_height(machine), 32);
+        let display = slice::from_raw_parts(chip8_display_ptr(machine), 64 * 32);
+        assert_eq!(display[..5], [1, 1, 1, 1, 0]);
+        assert!(chip8_last_error(machine).is_null());
+        assert_eq!(chip8_program_counter(machine), 0x206);
+        assert_eq!(chip8_address_register(machine), 0);
+        assert_eq!(*chip8_memory_ptr(machine).add(0x200), 0x60);
+        assert_eq!(chip8_step(machine), 0);
+        assert_eq!(*chip8_registers_ptr(machine).add(0xF), 0);
+        assert_eq!(
+            (chip8_delay_timer(machine), chip8_sound_timer(machine)),
+            (0, 0)
+        );
+
+        let too_large = vec![0; 0x10000];
+        assert_eq!(
+            chip8_load_rom(machine, too_large.as_ptr(), too_large.len()),
+            -1
+        );
+        let err = CStr::from_ptr(chip8_last_error(machine));
+        assert_eq!(err.to_str(), Ok("ROM is too large: 65536 bytes"));
+        chip8_free(machine);
+    }
+}
+
+#[test]
+fn test_header_declares_every_function() {
+    let header = include_str!("../include/chip8.h");
+    let source = include_str!("ffi.rs");
+    for line in source.lines() {
+        let name = match line.split("extern \"C\" fn ").nth(1) {
+            Some(rest) => rest.split('(').next().unwrap(),
+            None => continue,
+        };
+        if name.starts_with("chip8_") {
+            assert!(
+                header.contains(&format!("{}(", name)),
+                "{} isn't declared in include/chip8.h",
+                name
+            );
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! Keeping the SUPER-CHIP flags (Fx75/Fx85) of a ROM in a file next to it, so that the high
+//! scores and settings that programs store there survive restarts, like they did on the HP48.
+
+use crate::chip8::{Chip8, FLAG_COUNT};
+
+use std::ffi::OsString;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// The flags file of a ROM, and the flags that it holds
+pub struct FlagsFile {
+    path: PathBuf,
+    saved: [u8; FLAG_COUNT],
+}
+
+impl FlagsFile {
+    /// `ROM.flags`, next to the ROM
+    pub fn path(rom_path: &Path) -> PathBuf {
+        let mut name = OsString::from(rom_path.as_os_str());
+        name.push(".flags");
+        PathBuf::from(name)
+    }
+
+    /// Loads the flags in the ROM's flags file (if it has one yet) into the machine
+    pub fn open(rom_path: &Path, chip8: &mut Chip8) -> Result<FlagsFile, String> {
+        let path = FlagsFile::path(rom_path);
+        let mut saved = [0; FLAG_COUNT];
+        match fs::read(&path) {
+            Ok(bytes) if bytes.len() <= FLAG_COUNT => saved[..bytes.len()].copy_from_slice(&bytes),
+            Ok(bytes) => {
+                return Err(format!(
+                    "{}: expected at most {} flags, but there are {}",
+                    path.display(),
+                    FLAG_COUNT,
+                    bytes.len()
+                ))
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(format!("Couldn't read {}: {}", path.display(), err)),
+        }
+        chip8.set_flags(&saved);
+        Ok(FlagsFile { path, saved })
+    }
+
+    pub fn file_path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes the machine's flags to the file, if they have changed since they were loaded or
+    /// last saved. Returns whether they were written.
+    pub fn save(&mut self, chip8: &Chip8) -> Result<bool, String> {
+        if chip8.flags() == self.saved {
+            return Ok(false);
+        }
+        fs::write(&self.path, chip8.flags())
+            .map_err(|err| format!("Couldn't write {}: {}", self.path.display(), err))?;
+        self.saved.copy_from_slice(chip8.flags());
+        Ok(true)
+    }
+}
+
+#[test]
+fn test_flags_file() {
+    use crate::chip8::Instruction;
+
+    let dir = std::env::temp_dir().join(format!("chip8_flags_test_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let rom_path = dir.join("game.ch8");
+
+    let mut chip8 = Chip8::new([0; 0x1000]);
+    let mut file = FlagsFile::open(&rom_path, &mut chip8).unwrap();
+    assert_eq!(file.file_path(), dir.join("game.ch8.flags"));
+    assert_eq!(file.save(&chip8), Ok(false));
+    chip8.registers[..3].copy_from_slice(&[7, 8, 9]);
+    chip8.execute(Instruction::StoreFlags(2)).unwrap();
+    assert_eq!(file.save(&chip8), Ok(true));
+    assert_eq!(file.save(&chip8), Ok(false));
+
+    let mut restarted = Chip8::new([0; 0x1000]);
+    FlagsFile::open(&rom_path, &mut restarted).unwrap();
+    restarted.execute(Instruction::LoadFlags(3)).unwrap();
+    assert_eq!(restarted.registers[..4], [7, 8, 9, 0]);
+
+    fs::write(FlagsFile::path(&rom_path), [0; FLAG_COUNT + 1]).unwrap();
+    assert!(FlagsFile::open(&rom_path, &mut restarted).is_err());
+    fs::remove_dir_all(&dir).unwrap();
+}
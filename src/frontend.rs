@@ -0,0 +1,168 @@
+use crate::chip8::{Chip8, Chip8Event, DisplayBuffer, UpdateReport};
+
+/// The conventional layout, mapping the keypad onto the left side of a QWERTY keyboard:
+///
+/// ```text
+/// 1 2 3 C        1 2 3 4
+/// 4 5 6 D   <=   Q W E R
+/// 7 8 9 E        A S D F
+/// A 0 B F        Z X C V
+/// ```
+pub const DEFAULT_KEYMAP: &str = "x123qweasdzc4rfv";
+
+pub const KEYMAP_PRESETS: [&str; 5] = ["qwerty", "qwertz", "azerty", "dvorak", "colemak"];
+
+/// The keys of a keymap that's given either as 16 keys (see `DEFAULT_KEYMAP`) or as the name of
+/// a keyboard layout (see `KEYMAP_PRESETS`), for which the keys are the ones in the same places as
+/// the default keys on a QWERTY keyboard. Only the names of the presets are checked here.
+pub fn keymap_keys(keymap: &str) -> &str {
+    match keymap.to_ascii_lowercase().as_str() {
+        "qwerty" => DEFAULT_KEYMAP,
+        "qwertz" => "x123qweasdyc4rfv",
+        "azerty" => "x123azeqsdwc4rfv",
+        "dvorak" => "q123',.aoe;j4puk",
+        "colemak" => "x123qwfarszc4ptv",
+        _ => keymap,
+    }
+}
+
+/// A window (or other user interface) that runs a Chip-8 machine until the user quits.
+///
+/// There is one for each backend: `app::GgezFrontend` (with the debugger) and
+/// `pixels_frontend::PixelsFrontend`, each behind its own cargo feature.
+pub trait Frontend {
+    fn run(self, chip8: Chip8) -> Result<(), String>;
+}
+
+/// Where a [`Driver`] sends the screen after each update
+pub trait DisplaySink {
+    fn show_frame(&mut self, display: &DisplayBuffer);
+}
+
+/// Where a [`Driver`] gets the state of the keypad from before each update
+pub trait InputSource {
+    /// Whether each of the keys 0 to F is held down
+    fn keypad_state(&mut self) -> [bool; 16];
+}
+
+/// Where a [`Driver`] sends the beeper, whenever it starts or stops
+pub trait AudioSink {
+    fn set_tone(&mut self, playing: bool);
+}
+
+/// The keys held down, for frontends that keep track of them from their own key events
+impl InputSource for [bool; 16] {
+    fn keypad_state(&mut self) -> [bool; 16] {
+        *self
+    }
+}
+
+/// For frontends without sound
+impl AudioSink for () {
+    fn set_tone(&mut self, _playing: bool) {}
+}
+
+/// Runs a machine and connects it to a frontend's display, input and audio, so that the
+/// frontend only has to call [`Driver::update`] regularly.
+pub struct Driver<D, I, A> {
+    pub chip8: Chip8,
+    pub display: D,
+    pub input: I,
+    pub audio: A,
+}
+
+impl<D: DisplaySink, I: InputSource, A: AudioSink> Driver<D, I, A> {
+    pub fn new(chip8: Chip8, display: D, input: I, audio: A) -> Driver<D, I, A> {
+        Driver {
+            chip8,
+            display,
+            input,
+            audio,
+        }
+    }
+
+    /// Reads the keypad, runs the machine for the elapsed time (in seconds) and shows the
+    /// resulting frame. Returns what the machine did (see `Chip8::update`).
+    pub fn update(&mut self, elapsed_time: f64) -> Result<UpdateReport, String> {
+        let keypad = self.input.keypad_state();
+        for (key, &pressed) in keypad.iter().enumerate() {
+            if self.chip8.pressed_keys()[key] != pressed {
+                self.chip8.handle_key_event(key as u8, pressed);
+            }
+        }
+
+        // The machine can have been replaced since the last update
+        self.chip8.record_events(true);
+        let result = self.chip8.update(elapsed_time);
+
+        for event in self.chip8.take_events() {
+            match event {
+                Chip8Event::SoundStarted => self.audio.set_tone(true),
+                Chip8Event::SoundStopped => self.audio.set_tone(false),
+                _ => {}
+            }
+        }
+        self.display.show_frame(&self.chip8.display_buffer);
+        result
+    }
+}
+
+#[cfg(test)]
+struct TestFrontend {
+    frames: u32,
+    tone_changes: Vec<bool>,
+}
+
+#[cfg(test)]
+impl DisplaySink for &mut TestFrontend {
+    fn show_frame(&mut self, _display: &DisplayBuffer) {
+        self.frames += 1;
+    }
+}
+
+#[cfg(test)]
+impl AudioSink for &mut TestFrontend {
+    fn set_tone(&mut self, playing: bool) {
+        self.tone_changes.push(playing);
+    }
+}
+
+#[test]
+fn test_keymap_keys() {
+    assert_eq!(keymap_keys("AZERTY"), "x123azeqsdwc4rfv");
+    assert_eq!(keymap_keys("0123456789abcdef"), "0123456789abcdef");
+    for preset in KEYMAP_PRESETS {
+        let keys = keymap_keys(preset);
+        assert_eq!(keys.len(), 16);
+        // Every preset keeps the digits of the top row
+        assert_eq!(&keys[1..4], "123");
+        assert_eq!(&keys[12..13], "4");
+    }
+}
+
+#[test]
+fn test_driver() {
+    // V0 = 2, sound_timer(V0), V1 = key(), jump to self
+    let rom = [0x60, 0x02, 0xF0, 0x18, 0xF1, 0x0A, 0x12, 0x06];
+    let chip8 = Chip8::from_rom(&rom).unwrap();
+    let mut display = TestFrontend {
+        frames: 0,
+        tone_changes: vec![],
+    };
+    let mut audio = TestFrontend {
+        frames: 0,
+        tone_changes: vec![],
+    };
+    let mut driver = Driver::new(chip8, &mut display, [false; 16], &mut audio);
+
+    driver.update(0.01).unwrap();
+    assert_eq!(driver.chip8.waiting_for_key(), Some(1));
+    driver.input[7] = true;
+    driver.update(0.01).unwrap();
+    assert_eq!(driver.chip8.registers[1], 7);
+    driver.update(0.1).unwrap();
+    drop(driver);
+
+    assert_eq!(display.frames, 3);
+    assert_eq!(audio.tone_changes, vec![true, false]);
+}
@@ -0,0 +1,179 @@
+//! Hand-written input for automated tests and demos: a script of key events at given cycles or
+//! frames, and optionally the seed of the random number generator, so that a run can be
+//! repeated exactly.
+//!
+//! ```text
+//! # Start the game, then move up for a while
+//! seed 1234
+//! at cycle 5000 press 4 for 10 frames
+//! at frame 400 hold 1
+//! at frame 460 release 1
+//! ```
+
+/// A key press or release, before the given cycle
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScriptEvent {
+    pub cycle: u64,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Time {
+    Cycles(u64),
+    Frames(u64),
+}
+
+impl Time {
+    fn cycles(self, cycles_per_frame: u64) -> u64 {
+        match self {
+            Time::Cycles(cycles) => cycles,
+            Time::Frames(frames) => frames * cycles_per_frame,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Action {
+    Press(u8, Time),
+    Hold(u8),
+    Release(u8),
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InputScript {
+    /// The seed for the random number generator, if the script gives one
+    pub seed: Option<u64>,
+    actions: Vec<(Time, Action)>,
+}
+
+impl InputScript {
+    pub fn parse(source: &str) -> Result<InputScript, String> {
+        let mut script = InputScript::default();
+        for (i, line) in source.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: String| format!("Line {}: {}", i + 1, message);
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                ["seed", seed] => {
+                    let seed = seed
+                        .parse()
+                        .map_err(|_| error(format!("Invalid seed: {}", seed)))?;
+                    script.seed = Some(seed);
+                }
+                ["at", unit, count, action @ ..] => {
+                    let time = parse_time(count, unit).map_err(error)?;
+                    let action = parse_action(action).map_err(error)?;
+                    script.actions.push((time, action));
+                }
+                _ => {
+                    return Err(error(format!(
+                        "Expected seed N or at cycle/frame N ACTION: {}",
+                        line
+                    )))
+                }
+            }
+        }
+        Ok(script)
+    }
+
+    /// The key events in the order that they happen, with frames counted as the given number of
+    /// cycles
+    pub fn events(&self, cycles_per_frame: u64) -> Vec<ScriptEvent> {
+        let mut events = Vec::new();
+        for (time, action) in &self.actions {
+            let cycle = time.cycles(cycles_per_frame);
+            let (key, pressed) = match *action {
+                Action::Press(key, duration) => {
+                    events.push(ScriptEvent {
+                        cycle: cycle + duration.cycles(cycles_per_frame),
+                        key,
+                        pressed: false,
+                    });
+                    (key, true)
+                }
+                Action::Hold(key) => (key, true),
+                Action::Release(key) => (key, false),
+            };
+            events.push(ScriptEvent {
+                cycle,
+                key,
+                pressed,
+            });
+        }
+        // A stable sort, so that events at the same cycle keep the order of the script
+        events.sort_by_key(|event| event.cycle);
+        events
+    }
+}
+
+fn parse_time(count: &str, unit: &str) -> Result<Time, String> {
+    let count = count
+        .parse::<u64>()
+        .map_err(|_| format!("Expected a number of {}s: {}", unit, count))?;
+    match unit {
+        "cycle" | "cycles" => Ok(Time::Cycles(count)),
+        "frame" | "frames" => Ok(Time::Frames(count)),
+        _ => Err(format!("Expected cycle or frame: {}", unit)),
+    }
+}
+
+fn parse_action(words: &[&str]) -> Result<Action, String> {
+    let key = |word: &str| match u8::from_str_radix(word, 16) {
+        Ok(key) if key < 16 => Ok(key),
+        _ => Err(format!("Expected a key from 0 to F: {}", word)),
+    };
+    match words {
+        ["press", k, "for", count, unit] => Ok(Action::Press(key(k)?, parse_time(count, unit)?)),
+        ["hold", k] => Ok(Action::Hold(key(k)?)),
+        ["release", k] => Ok(Action::Release(key(k)?)),
+        _ => Err(format!(
+            "Expected press KEY for N frames/cycles, hold KEY or release KEY: {}",
+            words.join(" ")
+        )),
+    }
+}
+
+#[test]
+fn test_input_script() {
+    let script = InputScript::parse(
+        "# A comment\n\
+         seed 1234\n\
+         at frame 10 hold 1   # up\n\
+         at cycle 5000 press 4 for 10 frames\n\
+         \n\
+         at frames 20 release 1\n\
+         at cycle 20 press a for 3 cycles\n",
+    )
+    .unwrap();
+    assert_eq!(script.seed, Some(1234));
+    let event = |cycle, key, pressed| ScriptEvent {
+        cycle,
+        key,
+        pressed,
+    };
+    assert_eq!(
+        script.events(8),
+        [
+            event(20, 0xA, true),
+            event(23, 0xA, false),
+            event(80, 1, true),
+            event(160, 1, false),
+            event(5000, 4, true),
+            event(5080, 4, false),
+        ]
+    );
+
+    assert_eq!(
+        InputScript::parse("at cycle 5 press 4").unwrap_err(),
+        "Line 1: Expected press KEY for N frames/cycles, hold KEY or release KEY: press 4"
+    );
+    assert!(InputScript::parse("at second 5 hold 4").is_err());
+    assert!(InputScript::parse("\nat cycle 5 hold 10")
+        .unwrap_err()
+        .starts_with("Line 2:"));
+    assert!(InputScript::parse("seed -1").is_err());
+}
@@ -0,0 +1,228 @@
+//! Driving the keypad with text commands, one per line, from stdin or TCP connections: for chat
+//! bots ("Twitch plays"), scripts and accessibility tools. The commands are:
+//!
+//! ```text
+//! press KEY [DURATION]   press the key and release it after the duration (100ms by default)
+//! hold KEY               press the key until it's released
+//! release KEY
+//! ```
+//!
+//! Keys are given in hex (0 to F) and durations like `250ms` or `1.5s`. Commands that can't be
+//! parsed are answered with an error, in the log or over the connection.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use tracing::warn;
+
+/// How long the key is held by `press KEY`, in seconds
+const DEFAULT_PRESS_DURATION: f64 = 0.1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyCommand {
+    /// A key and how long it's held, in seconds
+    Press(u8, f64),
+    Hold(u8),
+    Release(u8),
+}
+
+impl KeyCommand {
+    pub fn parse(source: &str) -> Result<KeyCommand, String> {
+        let words: Vec<&str> = source.split_whitespace().collect();
+        let key = |word: &str| match u8::from_str_radix(word, 16) {
+            Ok(key) if key < 16 => Ok(key),
+            _ => Err(format!("Expected a key from 0 to F: {}", word)),
+        };
+        match words.as_slice() {
+            ["press", k] => Ok(KeyCommand::Press(key(k)?, DEFAULT_PRESS_DURATION)),
+            ["press", k, duration] => Ok(KeyCommand::Press(key(k)?, parse_duration(duration)?)),
+            ["hold", k] => Ok(KeyCommand::Hold(key(k)?)),
+            ["release", k] => Ok(KeyCommand::Release(key(k)?)),
+            _ => Err(format!(
+                "Expected press KEY [DURATION], hold KEY or release KEY: {}",
+                source.trim()
+            )),
+        }
+    }
+}
+
+/// Parses a duration like `250ms` or `1.5s` into seconds
+fn parse_duration(source: &str) -> Result<f64, String> {
+    let seconds = match source.strip_suffix("ms") {
+        Some(millis) => millis.parse::<f64>().map(|millis| millis / 1000.0),
+        None => source.trim_end_matches('s').parse::<f64>(),
+    };
+    match seconds {
+        Ok(seconds) if seconds >= 0.0 && seconds.is_finite() => Ok(seconds),
+        _ => Err(format!(
+            "Expected a duration like 250ms or 1.5s: {}",
+            source
+        )),
+    }
+}
+
+/// Receives commands on other threads, and turns them into key events for the machine
+pub struct KeyInjector {
+    commands: Receiver<KeyCommand>,
+    /// The keys that were pressed with a duration, and the time left until they're released
+    releases: Vec<(u8, f64)>,
+}
+
+impl KeyInjector {
+    /// Reads commands from stdin
+    pub fn stdin() -> KeyInjector {
+        let (sender, commands) = mpsc::channel();
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => break,
+                };
+                match KeyCommand::parse(&line) {
+                    Ok(command) if sender.send(command).is_err() => break,
+                    Ok(_) => {}
+                    Err(err) => warn!("{}", err),
+                }
+            }
+        });
+        KeyInjector::new(commands)
+    }
+
+    /// Reads commands from any number of TCP connections
+    pub fn listen(address: impl ToSocketAddrs) -> Result<KeyInjector, String> {
+        let listener =
+            TcpListener::bind(address).map_err(|err| format!("Couldn't listen: {}", err))?;
+        let (sender, commands) = mpsc::channel::<KeyCommand>();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let sender = sender.clone();
+                thread::spawn(move || read_connection(stream, sender));
+            }
+        });
+        Ok(KeyInjector::new(commands))
+    }
+
+    fn new(commands: Receiver<KeyCommand>) -> KeyInjector {
+        KeyInjector {
+            commands,
+            releases: Vec::new(),
+        }
+    }
+
+    /// The key events (key and whether it's pressed) since the last update, given the elapsed
+    /// time in seconds. Keys pressed for a duration stay down for at least one update.
+    pub fn update(&mut self, elapsed_time: f64) -> Vec<(u8, bool)> {
+        let mut events = Vec::new();
+        for (key, remaining) in &mut self.releases {
+            *remaining -= elapsed_time;
+            if *remaining <= 0.0 {
+                events.push((*key, false));
+            }
+        }
+        self.releases.retain(|&(_, remaining)| remaining > 0.0);
+
+        for command in self.commands.try_iter() {
+            let key = match command {
+                KeyCommand::Press(key, _) | KeyCommand::Hold(key) | KeyCommand::Release(key) => key,
+            };
+            // A new command replaces the pending release of the key
+            self.releases.retain(|&(pending, _)| pending != key);
+            match command {
+                KeyCommand::Press(key, duration) => {
+                    events.push((key, true));
+                    self.releases.push((key, duration));
+                }
+                KeyCommand::Hold(key) => events.push((key, true)),
+                KeyCommand::Release(key) => events.push((key, false)),
+            }
+        }
+        events
+    }
+}
+
+fn read_connection(stream: TcpStream, sender: Sender<KeyCommand>) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => return,
+        };
+        match KeyCommand::parse(&line) {
+            Ok(command) if sender.send(command).is_err() => return,
+            Ok(_) => {}
+            Err(err) => {
+                if writeln!(writer, "{}", err).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_parse_key_command() {
+    assert_eq!(
+        KeyCommand::parse("press 5 250ms"),
+        Ok(KeyCommand::Press(5, 0.25))
+    );
+    assert_eq!(
+        KeyCommand::parse("press a 2s"),
+        Ok(KeyCommand::Press(0xA, 2.0))
+    );
+    assert_eq!(
+        KeyCommand::parse(" press F "),
+        Ok(KeyCommand::Press(0xF, 0.1))
+    );
+    assert_eq!(KeyCommand::parse("hold 0"), Ok(KeyCommand::Hold(0)));
+    assert_eq!(KeyCommand::parse("release 0"), Ok(KeyCommand::Release(0)));
+    assert!(KeyCommand::parse("press 10").is_err());
+    assert!(KeyCommand::parse("press 5 soon").is_err());
+    assert!(KeyCommand::parse("jump").is_err());
+}
+
+#[test]
+fn test_key_injector() {
+    let (sender, commands) = mpsc::channel();
+    let mut injector = KeyInjector::new(commands);
+    sender.send(KeyCommand::Press(5, 0.1)).unwrap();
+    sender.send(KeyCommand::Hold(6)).unwrap();
+    assert_eq!(injector.update(0.5), [(5, true), (6, true)]);
+    assert_eq!(injector.update(0.05), []);
+    assert_eq!(injector.update(0.05), [(5, false)]);
+
+    // Holding a pressed key cancels its release
+    sender.send(KeyCommand::Press(6, 0.0)).unwrap();
+    sender.send(KeyCommand::Hold(6)).unwrap();
+    sender.send(KeyCommand::Release(7)).unwrap();
+    assert_eq!(injector.update(0.0), [(6, true), (6, true), (7, false)]);
+    assert_eq!(injector.update(1.0), []);
+}
+
+#[test]
+fn test_key_injector_over_tcp() {
+    use std::time::Duration;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    drop(listener);
+    let mut injector = KeyInjector::listen(address).unwrap();
+    let mut stream = TcpStream::connect(address).unwrap();
+    stream.write_all(b"hold 1\nfly\n").unwrap();
+    let mut reply = String::new();
+    BufReader::new(&stream).read_line(&mut reply).unwrap();
+    assert!(reply.starts_with("Expected press KEY"));
+
+    for _ in 0..500 {
+        let events = injector.update(0.0);
+        if !events.is_empty() {
+            assert_eq!(events, [(1, true)]);
+            return;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    panic!("Timed out");
+}
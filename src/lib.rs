@@ -1,3 +1,37 @@
+#[cfg(feature = "native")]
 pub mod app;
 pub mod assembly;
+pub mod autosave;
+pub mod battery;
+pub mod builtin_roms;
+pub mod capture;
+pub mod cheats;
 pub mod chip8;
+#[cfg(feature = "native")]
+pub mod config;
+pub mod crash_dump;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod flags_file;
+pub mod frontend;
+pub mod input_script;
+pub mod key_commands;
+#[cfg(feature = "libretro")]
+pub mod libretro;
+pub mod lockstep;
+#[cfg(any(feature = "native", feature = "pixels-backend"))]
+pub mod logging;
+pub mod memory_map;
+pub mod memory_search;
+pub mod netplay;
+pub mod patch;
+#[cfg(feature = "pixels-backend")]
+pub mod pixels_frontend;
+#[cfg(feature = "native")]
+pub mod rom_database;
+pub mod snapshot;
+pub mod state_file;
+pub mod symbols;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod watch;
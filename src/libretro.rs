@@ -0,0 +1,589 @@
+//! A libretro core, so that the emulator can run inside RetroArch (and other libretro frontends)
+//! with their shaders, save states, rewinding and netplay.
+//!
+//! The libretro API is a set of C functions with global state, which is kept in the statics
+//! below. The keypad is mapped to the keyboard with the default keymap, and to the joypad like
+//! the default gamepad map of the ggez window: the D-pad is 2/8/4/6, B is 5 and A is 0.
+
+use crate::cheats::{Cheat, Cheats};
+use crate::chip8::{Chip8, MAX_SCREEN_HEIGHT, MAX_STATE_SIZE};
+use crate::frontend::DEFAULT_KEYMAP;
+
+use std::ffi::CString;
+use std::os::raw::{c_char, c_uint, c_void};
+use std::ptr;
+use std::slice;
+use std::sync::Mutex;
+
+use tracing::warn;
+
+const RETRO_API_VERSION: c_uint = 1;
+const RETRO_REGION_NTSC: c_uint = 0;
+const RETRO_DEVICE_JOYPAD: c_uint = 1;
+const RETRO_DEVICE_KEYBOARD: c_uint = 3;
+const RETRO_DEVICE_ID_JOYPAD_B: c_uint = 0;
+const RETRO_DEVICE_ID_JOYPAD_UP: c_uint = 4;
+const RETRO_DEVICE_ID_JOYPAD_DOWN: c_uint = 5;
+const RETRO_DEVICE_ID_JOYPAD_LEFT: c_uint = 6;
+const RETRO_DEVICE_ID_JOYPAD_RIGHT: c_uint = 7;
+const RETRO_DEVICE_ID_JOYPAD_A: c_uint = 8;
+const RETRO_ENVIRONMENT_SET_MESSAGE: c_uint = 6;
+const RETRO_ENVIRONMENT_SET_PIXEL_FORMAT: c_uint = 10;
+const RETRO_ENVIRONMENT_SET_GEOMETRY: c_uint = 37;
+const RETRO_PIXEL_FORMAT_XRGB8888: c_uint = 1;
+
+/// The keypad keys of the joypad buttons
+const JOYPAD_KEYS: [(c_uint, u8); 6] = [
+    (RETRO_DEVICE_ID_JOYPAD_UP, 0x2),
+    (RETRO_DEVICE_ID_JOYPAD_DOWN, 0x8),
+    (RETRO_DEVICE_ID_JOYPAD_LEFT, 0x4),
+    (RETRO_DEVICE_ID_JOYPAD_RIGHT, 0x6),
+    (RETRO_DEVICE_ID_JOYPAD_B, 0x5),
+    (RETRO_DEVICE_ID_JOYPAD_A, 0x0),
+];
+const FPS: f64 = 60.0;
+const SAMPLE_RATE: f64 = 44_100.0;
+const TONE_FREQUENCY: f64 = 440.0;
+const VOLUME: i16 = 0x1000;
+const FOREGROUND: [u8; 4] = [255, 255, 255, 255];
+const BACKGROUND: [u8; 4] = [0, 0, 0, 255];
+
+#[repr(C)]
+pub struct RetroSystemInfo {
+    library_name: *const c_char,
+    library_version: *const c_char,
+    valid_extensions: *const c_char,
+    need_fullpath: bool,
+    block_extract: bool,
+}
+
+#[repr(C)]
+pub struct RetroGameGeometry {
+    base_width: c_uint,
+    base_height: c_uint,
+    max_width: c_uint,
+    max_height: c_uint,
+    aspect_ratio: f32,
+}
+
+#[repr(C)]
+pub struct RetroSystemTiming {
+    fps: f64,
+    sample_rate: f64,
+}
+
+#[repr(C)]
+pub struct RetroSystemAvInfo {
+    geometry: RetroGameGeometry,
+    timing: RetroSystemTiming,
+}
+
+#[repr(C)]
+pub struct RetroGameInfo {
+    path: *const c_char,
+    data: *const c_void,
+    size: usize,
+    meta: *const c_char,
+}
+
+#[repr(C)]
+struct RetroMessage {
+    msg: *const c_char,
+    frames: c_uint,
+}
+
+pub type RetroEnvironment = unsafe extern "C" fn(cmd: c_uint, data: *mut c_void) -> bool;
+pub type RetroVideoRefresh =
+    unsafe extern "C" fn(data: *const c_void, width: c_uint, height: c_uint, pitch: usize);
+pub type RetroAudioSample = unsafe extern "C" fn(left: i16, right: i16);
+pub type RetroAudioSampleBatch = unsafe extern "C" fn(data: *const i16, frames: usize) -> usize;
+pub type RetroInputPoll = unsafe extern "C" fn();
+pub type RetroInputState =
+    unsafe extern "C" fn(port: c_uint, device: c_uint, index: c_uint, id: c_uint) -> i16;
+
+/// The callbacks that the frontend has given, which may come before `retro_init`
+#[derive(Clone, Copy)]
+struct Callbacks {
+    environment: Option<RetroEnvironment>,
+    video_refresh: Option<RetroVideoRefresh>,
+    audio_sample_batch: Option<RetroAudioSampleBatch>,
+    input_poll: Option<RetroInputPoll>,
+    input_state: Option<RetroInputState>,
+}
+
+static CALLBACKS: Mutex<Callbacks> = Mutex::new(Callbacks {
+    environment: None,
+    video_refresh: None,
+    audio_sample_batch: None,
+    input_poll: None,
+    input_state: None,
+});
+
+/// The loaded game
+static CORE: Mutex<Option<Core>> = Mutex::new(None);
+
+struct Core {
+    rom: Vec<u8>,
+    chip8: Chip8,
+    /// The cheats that the frontend has set, by index
+    cheat_codes: Vec<Option<Cheat>>,
+    cheats: Cheats,
+    /// Set when the program fails, after which the machine isn't run anymore
+    crashed: bool,
+    frame: Vec<u32>,
+    /// The height that the frontend was last told about
+    height: u8,
+    /// Where the beep's square wave is, in periods
+    tone_phase: f64,
+}
+
+impl Core {
+    fn new(rom: Vec<u8>) -> Result<Core, String> {
+        let chip8 = Chip8::from_rom(&rom)?;
+        let height = chip8.display_buffer.height();
+        Ok(Core {
+            rom,
+            chip8,
+            cheat_codes: Vec::new(),
+            cheats: Cheats::default(),
+            crashed: false,
+            frame: Vec::new(),
+            height,
+            tone_phase: 0.0,
+        })
+    }
+
+    fn run_frame(&mut self, callbacks: &Callbacks) {
+        if let Some(input_state) = callbacks.input_state {
+            let pressed = keypad_state(input_state);
+            for (key, &pressed) in pressed.iter().enumerate() {
+                if self.chip8.pressed_keys()[key] != pressed {
+                    self.chip8.handle_key_event(key as u8, pressed);
+                }
+            }
+        }
+        if !self.crashed {
+            if let Err(err) = self.chip8.update(1.0 / FPS) {
+                warn!("{}", err);
+                show_message(callbacks, &err);
+                self.crashed = true;
+            }
+            self.cheats.apply(&mut self.chip8);
+        }
+
+        let height = self.chip8.display_buffer.height();
+        if height != self.height {
+            self.height = height;
+            if let Some(environment) = callbacks.environment {
+                let mut geometry = geometry(height);
+                let data = &mut geometry as *mut RetroGameGeometry as *mut c_void;
+                unsafe { environment(RETRO_ENVIRONMENT_SET_GEOMETRY, data) };
+            }
+        }
+        if let Some(video_refresh) = callbacks.video_refresh {
+            self.frame.clear();
+            let rgba = self.chip8.display_buffer.to_rgba(FOREGROUND, BACKGROUND);
+            self.frame.extend(
+                rgba.chunks(4).map(|pixel| {
+                    (pixel[0] as u32) << 16 | (pixel[1] as u32) << 8 | pixel[2] as u32
+                }),
+            );
+            let width = self.chip8.display_buffer.width() as usize;
+            unsafe {
+                video_refresh(
+                    self.frame.as_ptr() as *const c_void,
+                    width as c_uint,
+                    height as c_uint,
+                    width * 4,
+                )
+            };
+        }
+        if let Some(audio_sample_batch) = callbacks.audio_sample_batch {
+            let samples = self.audio_frame();
+            unsafe { audio_sample_batch(samples.as_ptr(), samples.len() / 2) };
+        }
+    }
+
+    /// A frame of stereo samples: a square wave while the sound timer runs, silence otherwise
+    fn audio_frame(&mut self) -> Vec<i16> {
+        let frames = (SAMPLE_RATE / FPS) as usize;
+        let playing = self.chip8.sound_timer > 0;
+        let mut samples = Vec::with_capacity(2 * frames);
+        for _ in 0..frames {
+            let sample = if !playing {
+                0
+            } else if self.tone_phase < 0.5 {
+                VOLUME
+            } else {
+                -VOLUME
+            };
+            self.tone_phase = (self.tone_phase + TONE_FREQUENCY / SAMPLE_RATE) % 1.0;
+            samples.push(sample);
+            samples.push(sample);
+        }
+        samples
+    }
+
+    fn update_cheats(&mut self) {
+        self.cheats.clear();
+        for cheat in self.cheat_codes.iter().flatten() {
+            self.cheats.add(cheat.clone(), &mut self.chip8);
+        }
+    }
+}
+
+fn keypad_state(input_state: RetroInputState) -> [bool; 16] {
+    let mut pressed = [false; 16];
+    for (key, keycode) in DEFAULT_KEYMAP.bytes().enumerate() {
+        // The libretro key codes of letters and digits are their ASCII codes
+        pressed[key] = unsafe { input_state(0, RETRO_DEVICE_KEYBOARD, 0, keycode as c_uint) } != 0;
+    }
+    for &(button, key) in &JOYPAD_KEYS {
+        if unsafe { input_state(0, RETRO_DEVICE_JOYPAD, 0, button) } != 0 {
+            pressed[key as usize] = true;
+        }
+    }
+    pressed
+}
+
+fn show_message(callbacks: &Callbacks, message: &str) {
+    if let (Some(environment), Ok(text)) = (callbacks.environment, CString::new(message)) {
+        let mut message = RetroMessage {
+            msg: text.as_ptr(),
+            frames: 5 * FPS as c_uint,
+        };
+        let data = &mut message as *mut RetroMessage as *mut c_void;
+        unsafe { environment(RETRO_ENVIRONMENT_SET_MESSAGE, data) };
+    }
+}
+
+fn geometry(height: u8) -> RetroGameGeometry {
+    RetroGameGeometry {
+        base_width: 64,
+        base_height: height as c_uint,
+        max_width: 64,
+        max_height: MAX_SCREEN_HEIGHT as c_uint,
+        aspect_ratio: 64.0 / height as f32,
+    }
+}
+
+fn callbacks() -> Callbacks {
+    *CALLBACKS.lock().unwrap()
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    RETRO_API_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(environment: RetroEnvironment) {
+    CALLBACKS.lock().unwrap().environment = Some(environment);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(video_refresh: RetroVideoRefresh) {
+    CALLBACKS.lock().unwrap().video_refresh = Some(video_refresh);
+}
+
+/// The samples are sent a frame at a time, with `retro_set_audio_sample_batch`
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_audio_sample: RetroAudioSample) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(audio_sample_batch: RetroAudioSampleBatch) {
+    CALLBACKS.lock().unwrap().audio_sample_batch = Some(audio_sample_batch);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(input_poll: RetroInputPoll) {
+    CALLBACKS.lock().unwrap().input_poll = Some(input_poll);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(input_state: RetroInputState) {
+    CALLBACKS.lock().unwrap().input_state = Some(input_state);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    *CORE.lock().unwrap() = None;
+}
+
+/// # Safety
+///
+/// `info` must point to a `retro_system_info`.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut RetroSystemInfo) {
+    *info = RetroSystemInfo {
+        library_name: b"chip-8-rs\0".as_ptr() as *const c_char,
+        library_version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char,
+        valid_extensions: b"ch8|c8|rom\0".as_ptr() as *const c_char,
+        need_fullpath: false,
+        block_extract: false,
+    };
+}
+
+/// # Safety
+///
+/// `info` must point to a `retro_system_av_info`.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut RetroSystemAvInfo) {
+    let height = match &*CORE.lock().unwrap() {
+        Some(core) => core.chip8.display_buffer.height(),
+        None => 32,
+    };
+    *info = RetroSystemAvInfo {
+        geometry: geometry(height),
+        timing: RetroSystemTiming {
+            fps: FPS,
+            sample_rate: SAMPLE_RATE,
+        },
+    };
+}
+
+/// There is only the keypad, whichever device is plugged in
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+/// Restarts the ROM, keeping the cheats
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    if let Some(core) = &mut *CORE.lock().unwrap() {
+        core.chip8 = Chip8::from_rom(&core.rom).expect("Reload ROM");
+        core.crashed = false;
+        core.update_cheats();
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    let callbacks = callbacks();
+    if let Some(input_poll) = callbacks.input_poll {
+        unsafe { input_poll() };
+    }
+    if let Some(core) = &mut *CORE.lock().unwrap() {
+        core.run_frame(&callbacks);
+    }
+}
+
+/// Room for the largest save states, and the length of the state in front of it
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    4 + MAX_STATE_SIZE
+}
+
+/// # Safety
+///
+/// `data` must point to `size` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    let state = match &*CORE.lock().unwrap() {
+        Some(core) => core.chip8.save_state(),
+        None => return false,
+    };
+    if data.is_null() || size < 4 + state.len() {
+        return false;
+    }
+    let buffer = slice::from_raw_parts_mut(data as *mut u8, size);
+    buffer[..4].copy_from_slice(&(state.len() as u32).to_be_bytes());
+    buffer[4..4 + state.len()].copy_from_slice(&state);
+    buffer[4 + state.len()..]
+        .iter_mut()
+        .for_each(|byte| *byte = 0);
+    true
+}
+
+/// # Safety
+///
+/// `data` must point to `size` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    if data.is_null() || size < 4 {
+        return false;
+    }
+    let buffer = slice::from_raw_parts(data as *const u8, size);
+    let length = u32::from_be_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as usize;
+    match (&mut *CORE.lock().unwrap(), buffer[4..].get(..length)) {
+        (Some(core), Some(state)) => match core.chip8.load_state(state) {
+            Ok(()) => {
+                core.crashed = false;
+                true
+            }
+            Err(err) => {
+                warn!("{}", err);
+                false
+            }
+        },
+        _ => false,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {
+    if let Some(core) = &mut *CORE.lock().unwrap() {
+        core.cheat_codes.clear();
+        core.cheats.clear();
+    }
+}
+
+/// Cheats are written like in `.cheats` files, such as `freeze V3 = 5`
+///
+/// # Safety
+///
+/// `code` must be a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn retro_cheat_set(index: c_uint, enabled: bool, code: *const c_char) {
+    if code.is_null() {
+        return;
+    }
+    let code = std::ffi::CStr::from_ptr(code).to_string_lossy();
+    let cheat = match Cheat::parse(&code) {
+        Ok(cheat) => cheat,
+        Err(err) => {
+            show_message(&callbacks(), &err);
+            return;
+        }
+    };
+    if let Some(core) = &mut *CORE.lock().unwrap() {
+        let index = index as usize;
+        if core.cheat_codes.len() <= index {
+            core.cheat_codes.resize(index + 1, None);
+        }
+        core.cheat_codes[index] = Some(cheat).filter(|_| enabled);
+        core.update_cheats();
+    }
+}
+
+/// # Safety
+///
+/// `game` must point to a `retro_game_info`, with `size` bytes of data.
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const RetroGameInfo) -> bool {
+    if game.is_null() || (*game).data.is_null() {
+        return false;
+    }
+    let callbacks = callbacks();
+    if let Some(environment) = callbacks.environment {
+        let mut format = RETRO_PIXEL_FORMAT_XRGB8888;
+        let data = &mut format as *mut c_uint as *mut c_void;
+        if !environment(RETRO_ENVIRONMENT_SET_PIXEL_FORMAT, data) {
+            warn!("The frontend doesn't support XRGB8888");
+            return false;
+        }
+    }
+    let rom = slice::from_raw_parts((*game).data as *const u8, (*game).size).to_vec();
+    match Core::new(rom) {
+        Ok(core) => {
+            *CORE.lock().unwrap() = Some(core);
+            true
+        }
+        Err(err) => {
+            warn!("{}", err);
+            show_message(&callbacks, &err);
+            false
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(
+    _game_type: c_uint,
+    _info: *const RetroGameInfo,
+    _num_info: usize,
+) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    *CORE.lock().unwrap() = None;
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    RETRO_REGION_NTSC
+}
+
+/// Memory isn't exposed, since writing to it behind the machine's back would bypass its cache
+/// of decoded instructions
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(_id: c_uint) -> *mut c_void {
+    ptr::null_mut()
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(_id: c_uint) -> usize {
+    0
+}
+
+#[test]
+fn test_libretro_core() {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    static REFRESHED_HEIGHT: AtomicU32 = AtomicU32::new(0);
+    static LIT_PIXEL: AtomicU32 = AtomicU32::new(0);
+    static AUDIO_FRAMES: AtomicU32 = AtomicU32::new(0);
+
+    unsafe extern "C" fn environment(_cmd: c_uint, _data: *mut c_void) -> bool {
+        true
+    }
+    unsafe extern "C" fn video_refresh(
+        data: *const c_void,
+        _width: c_uint,
+        height: c_uint,
+        _pitch: usize,
+    ) {
+        REFRESHED_HEIGHT.store(height, Ordering::SeqCst);
+        LIT_PIXEL.store(*(data as *const u32), Ordering::SeqCst);
+    }
+    unsafe extern "C" fn audio_sample_batch(_data: *const i16, frames: usize) -> usize {
+        AUDIO_FRAMES.store(frames as u32, Ordering::SeqCst);
+        frames
+    }
+    unsafe extern "C" fn input_state(_: c_uint, _: c_uint, _: c_uint, _: c_uint) -> i16 {
+        0
+    }
+
+    retro_set_environment(environment);
+    retro_set_video_refresh(video_refresh);
+    retro_set_audio_sample_batch(audio_sample_batch);
+    retro_set_input_state(input_state);
+    retro_init();
+
+    // Draws the top of the 0 in the font at (0, 0), then loops
+    let rom: [u8; 8] = [0x60, 0x00, 0xF0, 0x29, 0xD0, 0x01, 0x12, 0x06];
+    let game = RetroGameInfo {
+        path: ptr::null(),
+        data: rom.as_ptr() as *const c_void,
+        size: rom.len(),
+        meta: ptr::null(),
+    };
+    unsafe {
+        assert!(retro_load_game(&game));
+        retro_run();
+        assert_eq!(REFRESHED_HEIGHT.load(Ordering::SeqCst), 32);
+        assert_eq!(LIT_PIXEL.load(Ordering::SeqCst), 0xFF_FF_FF);
+        assert_eq!(AUDIO_FRAMES.load(Ordering::SeqCst), 735);
+
+        let mut state = vec![0u8; retro_serialize_size()];
+        assert!(retro_serialize(
+            state.as_mut_ptr() as *mut c_void,
+            state.len()
+        ));
+        let code = CString::new("freeze V0 = 7").unwrap();
+        retro_cheat_set(0, true, code.as_ptr());
+        assert_eq!(CORE.lock().unwrap().as_ref().unwrap().chip8.registers[0], 7);
+        retro_cheat_reset();
+        assert!(retro_unserialize(
+            state.as_ptr() as *const c_void,
+            state.len()
+        ));
+        assert_eq!(CORE.lock().unwrap().as_ref().unwrap().chip8.registers[0], 0);
+    }
+    retro_unload_game();
+    retro_deinit();
+}
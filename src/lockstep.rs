@@ -0,0 +1,179 @@
+//! Running a ROM on two machines with different quirks side by side, to find the first instruction
+//! where the quirks make a difference.
+
+use crate::chip8::{Chip8, Quirks, PROGRAM_START};
+
+/// Two machines that execute the same ROM, one instruction at a time, with the same key presses
+pub struct Lockstep {
+    pub first: Chip8,
+    pub second: Chip8,
+    cycle: u64,
+}
+
+/// Where the machines stopped agreeing
+#[derive(Debug)]
+pub struct Divergence {
+    /// The number of instructions that had been executed before the diverging one
+    pub cycle: u64,
+    /// The address of the diverging instruction (in the first machine)
+    pub address: u16,
+    /// Descriptions like "V3: 0x10 vs 0x11"
+    pub differences: Vec<String>,
+}
+
+impl Lockstep {
+    pub fn new(
+        rom: &[u8],
+        first_quirks: Quirks,
+        second_quirks: Quirks,
+    ) -> Result<Lockstep, String> {
+        Lockstep::new_at(rom, PROGRAM_START, first_quirks, second_quirks)
+    }
+
+    /// Like `new`, with the ROM loaded at the given address (see `Chip8::from_rom_at`)
+    pub fn new_at(
+        rom: &[u8],
+        start_address: u16,
+        first_quirks: Quirks,
+        second_quirks: Quirks,
+    ) -> Result<Lockstep, String> {
+        let mut first = Chip8::from_rom_at(rom, start_address)?;
+        first.set_quirks(first_quirks);
+        let mut second = Chip8::from_rom_at(rom, start_address)?;
+        second.set_quirks(second_quirks);
+        Ok(Lockstep {
+            first,
+            second,
+            cycle: 0,
+        })
+    }
+
+    /// The number of instructions that both machines have executed
+    pub fn cycle(&self) -> u64 {
+        self.cycle
+    }
+
+    pub fn handle_key_event(&mut self, key: u8, pressed: bool) {
+        self.first.handle_key_event(key, pressed);
+        self.second.handle_key_event(key, pressed);
+    }
+
+    /// Executes one instruction on both machines, and returns how they differ afterwards (if they
+    /// do). It's an error if both machines fail, while only one failing counts as a divergence.
+    pub fn step(&mut self) -> Result<Option<Divergence>, String> {
+        let address = self.first.program_counter;
+        let results = (
+            self.first.step_instruction(),
+            self.second.step_instruction(),
+        );
+        let differences = match results {
+            (Ok(_), Ok(_)) => differences(&self.first, &self.second),
+            (Err(err), Err(_)) => return Err(err),
+            (Err(err), Ok(_)) => vec![format!("The first machine failed: {}", err)],
+            (Ok(_), Err(err)) => vec![format!("The second machine failed: {}", err)],
+        };
+        let cycle = self.cycle;
+        self.cycle += 1;
+        if differences.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Divergence {
+            cycle,
+            address,
+            differences,
+        }))
+    }
+
+    /// Runs until the machines diverge, or for at most the given number of instructions
+    pub fn run(&mut self, cycles: u64) -> Result<Option<Divergence>, String> {
+        for _ in 0..cycles {
+            if let Some(divergence) = self.step()? {
+                return Ok(Some(divergence));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// How the registers, timers, stack, memory and display of two machines differ
+pub fn differences(first: &Chip8, second: &Chip8) -> Vec<String> {
+    let mut differences = Vec::new();
+    let mut compare = |name: String, a: u16, b: u16| {
+        if a != b {
+            differences.push(format!("{}: {:#04X} vs {:#04X}", name, a, b));
+        }
+    };
+    for (i, (&a, &b)) in first
+        .registers
+        .iter()
+        .zip(second.registers.iter())
+        .enumerate()
+    {
+        compare(format!("V{:X}", i), a as u16, b as u16);
+    }
+    compare(
+        "I".to_owned(),
+        first.address_register,
+        second.address_register,
+    );
+    compare(
+        "PC".to_owned(),
+        first.program_counter,
+        second.program_counter,
+    );
+    compare(
+        "SP".to_owned(),
+        first.stack_pointer as u16,
+        second.stack_pointer as u16,
+    );
+    for (i, (&a, &b)) in first.stack.iter().zip(second.stack.iter()).enumerate() {
+        compare(format!("stack[{}]", i), a, b);
+    }
+    compare(
+        "delay".to_owned(),
+        first.delay_timer as u16,
+        second.delay_timer as u16,
+    );
+    compare(
+        "sound".to_owned(),
+        first.sound_timer as u16,
+        second.sound_timer as u16,
+    );
+    for (address, (&a, &b)) in first
+        .memory()
+        .iter()
+        .zip(second.memory().iter())
+        .enumerate()
+    {
+        compare(format!("mem[{:#05X}]", address), a as u16, b as u16);
+    }
+
+    let pixels: u32 = first
+        .display_buffer
+        .rows()
+        .iter()
+        .zip(second.display_buffer.rows().iter())
+        .map(|(a, b)| (a ^ b).count_ones())
+        .sum();
+    if pixels > 0 {
+        differences.push(format!("display: {} pixels differ", pixels));
+    }
+    differences
+}
+
+#[test]
+fn test_lockstep() {
+    // V1 = 0x81, V2 = 0x03, V1 >>= 1 (or V1 = V2 >> 1 with the VIP quirk), jump to self
+    let rom = [0x61, 0x81, 0x62, 0x03, 0x81, 0x26, 0x12, 0x06];
+    let vip = Quirks::preset("vip").unwrap();
+
+    let mut same = Lockstep::new(&rom, vip, vip).unwrap();
+    assert!(same.run(100).unwrap().is_none());
+    assert_eq!(same.cycle(), 100);
+
+    let mut lockstep = Lockstep::new(&rom, Quirks::default(), vip).unwrap();
+    let divergence = lockstep.run(100).unwrap().unwrap();
+    assert_eq!(divergence.cycle, 2);
+    assert_eq!(divergence.address, 0x204);
+    assert_eq!(divergence.differences, ["V1: 0x40 vs 0x01"]);
+}
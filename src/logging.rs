@@ -0,0 +1,18 @@
+//! Log output for the binaries. The library only emits `tracing` events (every executed instruction
+//! at trace level, inside a debug-level span per frame, and errors at warn level), so programs
+//! that embed it can install whichever subscriber they like instead.
+
+use std::io::{self, IsTerminal};
+use tracing_subscriber::EnvFilter;
+
+/// Prints events to stderr, filtered by the `RUST_LOG` environment variable (`info` if not set)
+pub fn init() {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_target(false)
+        .without_time()
+        .with_ansi(io::stderr().is_terminal())
+        .with_writer(io::stderr)
+        .init();
+}
@@ -0,0 +1,100 @@
+//! What each part of memory is used for, as far as can be told from the ROM and the disassembly,
+//! for annotating the debugger's views.
+
+use std::fmt;
+use std::ops::Range;
+
+/// Where the COSMAC VIP interpreter kept its stack, its variables and the display buffer, which
+/// programs written for it had to stay clear of
+pub const WORK_AREA_START: u16 = 0xEA0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryRegion {
+    /// Everything before the program: the interpreter itself, and the font
+    Interpreter,
+    /// Instructions of the program that the disassembler reached from the entry point
+    Code,
+    /// The rest of the program, like sprites and tables
+    Data,
+    /// Memory after the program, which it can use freely
+    Free,
+    /// The top of memory (from `WORK_AREA_START`), which was the interpreter's stack and work area
+    WorkArea,
+}
+
+impl fmt::Display for MemoryRegion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            MemoryRegion::Interpreter => "interpreter and font",
+            MemoryRegion::Code => "code",
+            MemoryRegion::Data => "data",
+            MemoryRegion::Free => "free",
+            MemoryRegion::WorkArea => "stack and work area",
+        })
+    }
+}
+
+/// The region of each address in memory
+pub struct MemoryMap {
+    regions: Vec<MemoryRegion>,
+}
+
+impl MemoryMap {
+    /// `disassembled` is the disassembly of the program (see `assembly::disassemble_rom_at`),
+    /// which is empty for the addresses that weren't reached as code, and `program` is where it
+    /// was loaded. A program that reaches into the work area keeps it as code and data.
+    pub fn new(disassembled: &[String], program: Range<usize>) -> MemoryMap {
+        let mut regions: Vec<MemoryRegion> = (0..disassembled.len())
+            .map(|address| {
+                if address < program.start {
+                    MemoryRegion::Interpreter
+                } else if program.contains(&address) {
+                    MemoryRegion::Data
+                } else if address >= WORK_AREA_START as usize {
+                    MemoryRegion::WorkArea
+                } else {
+                    MemoryRegion::Free
+                }
+            })
+            .collect();
+        for (address, text) in disassembled.iter().enumerate() {
+            if !text.is_empty() {
+                let end = (address + 2).min(regions.len());
+                regions[address..end].fill(MemoryRegion::Code);
+            }
+        }
+        MemoryMap { regions }
+    }
+
+    pub fn region(&self, address: u16) -> MemoryRegion {
+        self.regions
+            .get(address as usize)
+            .copied()
+            .unwrap_or(MemoryRegion::Free)
+    }
+}
+
+#[test]
+fn test_memory_map() {
+    use crate::assembly;
+
+    // jump over a byte of data, then loop
+    let rom = vec![0x12, 0x04, 0xFF, 0xFF, 0x12, 0x04];
+    let disassembled = assembly::disassemble_rom(rom.clone());
+    let map = MemoryMap::new(&disassembled, 0x200..0x200 + rom.len());
+    let regions: Vec<MemoryRegion> = (0x1FF..0x207).map(|address| map.region(address)).collect();
+    assert_eq!(
+        regions,
+        [
+            MemoryRegion::Interpreter,
+            MemoryRegion::Code,
+            MemoryRegion::Code,
+            MemoryRegion::Data,
+            MemoryRegion::Data,
+            MemoryRegion::Code,
+            MemoryRegion::Code,
+            MemoryRegion::Free,
+        ]
+    );
+    assert_eq!(map.region(0xEA0), MemoryRegion::WorkArea);
+}
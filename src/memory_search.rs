@@ -0,0 +1,132 @@
+//! Finding where a program keeps a value like the score or the lives, the way game trainers do it:
+//! search memory for the value, and then narrow the matches down by how they change between
+//! searches.
+
+use crate::chip8::Chip8;
+
+use std::convert::TryFrom;
+
+/// What the value at a matching address must be, compared to the previous search
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    Equal(u8),
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+}
+
+impl Filter {
+    /// Parses a value (like `3` or `0x03`), `changed`, `unchanged`, `increased` or `decreased`
+    pub fn parse(source: &str) -> Result<Filter, String> {
+        let source = source.trim();
+        let filter = match source.to_lowercase().as_str() {
+            "changed" => Filter::Changed,
+            "unchanged" => Filter::Unchanged,
+            "increased" => Filter::Increased,
+            "decreased" => Filter::Decreased,
+            value => {
+                let value = match value.strip_prefix("0x") {
+                    Some(hex) => u16::from_str_radix(hex, 16).ok(),
+                    None => value.parse().ok(),
+                };
+                let value = value.and_then(|value| u8::try_from(value).ok());
+                Filter::Equal(value.ok_or_else(|| {
+                    format!(
+                        "Expected a byte value, changed, unchanged, increased or decreased: {}",
+                        source
+                    )
+                })?)
+            }
+        };
+        Ok(filter)
+    }
+
+    fn matches(&self, previous: u8, current: u8) -> bool {
+        match *self {
+            Filter::Equal(value) => current == value,
+            Filter::Changed => current != previous,
+            Filter::Unchanged => current == previous,
+            Filter::Increased => current > previous,
+            Filter::Decreased => current < previous,
+        }
+    }
+}
+
+/// The addresses that have matched every search so far
+#[derive(Clone, Debug)]
+pub struct MemorySearch {
+    matches: Vec<u16>,
+    /// The memory at the time of the previous search
+    previous: Vec<u8>,
+}
+
+impl MemorySearch {
+    /// Starts a search where every address matches
+    pub fn new(chip8: &Chip8) -> MemorySearch {
+        MemorySearch {
+            // Up to 0x10000 addresses, so the range can't be of u16
+            matches: (0..chip8.memory().len())
+                .map(|address| address as u16)
+                .collect(),
+            previous: chip8.memory().to_vec(),
+        }
+    }
+
+    /// Keeps the matches that pass the filter, and returns how many are left
+    pub fn filter(&mut self, chip8: &Chip8, filter: Filter) -> usize {
+        let memory = chip8.memory();
+        let previous = &self.previous;
+        self.matches.retain(|&address| {
+            filter.matches(previous[address as usize], memory[address as usize])
+        });
+        self.previous = memory.to_vec();
+        self.matches.len()
+    }
+
+    /// The matching addresses, in increasing order
+    pub fn matches(&self) -> &[u16] {
+        &self.matches
+    }
+
+    /// The value at the address at the time of the previous search
+    pub fn previous_value(&self, address: u16) -> u8 {
+        self.previous[address as usize]
+    }
+}
+
+#[test]
+fn test_parse_filter() {
+    assert_eq!(Filter::parse("3"), Ok(Filter::Equal(3)));
+    assert_eq!(Filter::parse(" 0xFF "), Ok(Filter::Equal(0xFF)));
+    assert_eq!(Filter::parse("Increased"), Ok(Filter::Increased));
+    assert!(Filter::parse("256").is_err());
+    assert!(Filter::parse("bigger").is_err());
+}
+
+#[test]
+fn test_memory_search() {
+    let mut chip8 = Chip8::new([0; 0x1000]);
+    chip8.write_memory(0x300, &[3, 3, 5]).unwrap();
+    let mut search = MemorySearch::new(&chip8);
+    assert_eq!(search.filter(&chip8, Filter::Equal(3)), 2);
+    assert_eq!(search.matches(), [0x300, 0x301]);
+
+    chip8.write_memory(0x300, &[2]).unwrap();
+    assert_eq!(search.filter(&chip8, Filter::Changed), 1);
+    assert_eq!(search.matches(), [0x300]);
+    assert_eq!(search.previous_value(0x300), 2);
+
+    chip8.write_memory(0x300, &[4]).unwrap();
+    assert_eq!(search.filter(&chip8, Filter::Decreased), 0);
+}
+
+#[test]
+fn test_memory_search_with_64k_memory() {
+    let mut chip8 = Chip8::new([0; 0x1000]);
+    chip8.set_memory_size(0x10000).unwrap();
+    chip8.write_memory(0xFFFF, &[0x2A]).unwrap();
+    let mut search = MemorySearch::new(&chip8);
+    assert_eq!(search.filter(&chip8, Filter::Equal(0x2A)), 1);
+    assert_eq!(search.matches(), [0xFFFF]);
+}
@@ -0,0 +1,281 @@
+//! Two players on one keypad, over TCP: a second instance connects to the one that runs the
+//! machine (the host), sends it its key events, and gets each new frame back.
+//!
+//! The protocol is as simple as it gets. The client sends two bytes per key event, the key (0 to
+//! F) and whether it was pressed (1) or released (0). The host sends the height of the screen
+//! followed by the screen in `DisplayBuffer::as_bytes` form, whenever it changes. The CHIP-8X
+//! colors are not sent.
+
+use crate::chip8::{DisplayBuffer, DISPLAY_HEIGHTS};
+
+use std::convert::TryInto;
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Either end of a connection, for frontends that can be either
+pub enum Netplay {
+    Host(NetplayHost),
+    Client(NetplayClient),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NetplayEvent {
+    Connected(SocketAddr),
+    /// A key that the other player pressed or released
+    Key(u8, bool),
+    Disconnected,
+}
+
+/// Runs the machine, and lets one other player at a time connect to it
+pub struct NetplayHost {
+    listener: TcpListener,
+    peer: Option<Peer>,
+    /// Events that haven't been returned by `poll` yet
+    pending: Vec<NetplayEvent>,
+}
+
+struct Peer {
+    stream: TcpStream,
+    /// The key events, until the connection is closed
+    keys: Receiver<(u8, bool)>,
+    /// The keys that the other player holds down, which are released if they disconnect
+    held: [bool; 16],
+    /// The last message that was sent with the screen
+    last_frame: Vec<u8>,
+}
+
+impl NetplayHost {
+    pub fn listen(address: impl ToSocketAddrs) -> Result<NetplayHost, String> {
+        let listener =
+            TcpListener::bind(address).map_err(|err| format!("Couldn't listen: {}", err))?;
+        listener
+            .set_nonblocking(true)
+            .map_err(|err| err.to_string())?;
+        Ok(NetplayHost {
+            listener,
+            peer: None,
+            pending: Vec::new(),
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, String> {
+        self.listener.local_addr().map_err(|err| err.to_string())
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.peer.is_some()
+    }
+
+    /// Accepts a player if none is connected, and returns what happened since the last poll
+    pub fn poll(&mut self) -> Vec<NetplayEvent> {
+        if self.peer.is_none() {
+            if let Ok((stream, address)) = self.listener.accept() {
+                if let Ok(peer) = Peer::new(stream) {
+                    self.peer = Some(peer);
+                    self.pending.push(NetplayEvent::Connected(address));
+                }
+            }
+        }
+        if let Some(peer) = &mut self.peer {
+            loop {
+                match peer.keys.try_recv() {
+                    Ok((key, pressed)) => {
+                        peer.held[key as usize] = pressed;
+                        self.pending.push(NetplayEvent::Key(key, pressed));
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        self.disconnect();
+                        break;
+                    }
+                }
+            }
+        }
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Sends the screen to the other player, if one is connected and it has changed since the
+    /// last frame that was sent
+    pub fn send_frame(&mut self, display: &DisplayBuffer) {
+        let peer = match &mut self.peer {
+            Some(peer) => peer,
+            None => return,
+        };
+        let mut message = vec![display.height()];
+        message.extend(display.as_bytes());
+        if message == peer.last_frame {
+            return;
+        }
+        if peer.stream.write_all(&message).is_err() {
+            self.disconnect();
+            return;
+        }
+        peer.last_frame = message;
+    }
+
+    fn disconnect(&mut self) {
+        if let Some(peer) = self.peer.take() {
+            for (key, &held) in peer.held.iter().enumerate() {
+                if held {
+                    self.pending.push(NetplayEvent::Key(key as u8, false));
+                }
+            }
+            self.pending.push(NetplayEvent::Disconnected);
+        }
+    }
+}
+
+impl Peer {
+    fn new(stream: TcpStream) -> std::io::Result<Peer> {
+        // Accepted streams may inherit the listener's non-blocking mode
+        stream.set_nonblocking(false)?;
+        stream.set_nodelay(true)?;
+        // A player whose connection stalls is dropped, rather than stalling the host
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        let mut reader = stream.try_clone()?;
+        let (sender, keys) = mpsc::channel();
+        thread::spawn(move || {
+            let mut event = [0; 2];
+            while reader.read_exact(&mut event).is_ok() {
+                let [key, pressed] = event;
+                if key >= 16 || pressed > 1 || sender.send((key, pressed == 1)).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Peer {
+            stream,
+            keys,
+            held: [false; 16],
+            last_frame: Vec::new(),
+        })
+    }
+}
+
+/// The reading thread has its own handle to the socket, so it has to be shut down explicitly
+impl Drop for Peer {
+    fn drop(&mut self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+/// Sends key events to a host, and receives its screen
+pub struct NetplayClient {
+    stream: TcpStream,
+    frames: Receiver<DisplayBuffer>,
+}
+
+impl NetplayClient {
+    pub fn connect(address: impl ToSocketAddrs) -> Result<NetplayClient, String> {
+        let stream =
+            TcpStream::connect(address).map_err(|err| format!("Couldn't connect: {}", err))?;
+        stream.set_nodelay(true).map_err(|err| err.to_string())?;
+        let mut reader = stream.try_clone().map_err(|err| err.to_string())?;
+        let (sender, frames) = mpsc::channel();
+        thread::spawn(move || {
+            let mut height = [0];
+            while reader.read_exact(&mut height).is_ok() && DISPLAY_HEIGHTS.contains(&height[0]) {
+                let mut bytes = vec![0; 8 * height[0] as usize];
+                if reader.read_exact(&mut bytes).is_err() {
+                    break;
+                }
+                let rows: Vec<u64> = bytes
+                    .chunks(8)
+                    .map(|row| u64::from_be_bytes(row.try_into().unwrap()))
+                    .collect();
+                if sender.send(DisplayBuffer::from_rows(&rows)).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(NetplayClient { stream, frames })
+    }
+
+    pub fn send_key(&mut self, key: u8, pressed: bool) -> Result<(), String> {
+        self.stream
+            .write_all(&[key, pressed as u8])
+            .map_err(|err| format!("Lost the connection to the host: {}", err))
+    }
+
+    /// The newest of the frames that have arrived since the last call, if any. Fails once the
+    /// host has closed the connection.
+    pub fn latest_frame(&mut self) -> Result<Option<DisplayBuffer>, String> {
+        let mut latest = None;
+        loop {
+            match self.frames.try_recv() {
+                Ok(frame) => latest = Some(frame),
+                Err(TryRecvError::Empty) => return Ok(latest),
+                Err(TryRecvError::Disconnected) if latest.is_some() => return Ok(latest),
+                Err(TryRecvError::Disconnected) => {
+                    return Err("The host closed the connection".to_owned())
+                }
+            }
+        }
+    }
+}
+
+impl Drop for NetplayClient {
+    fn drop(&mut self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+#[test]
+fn test_netplay() {
+    fn wait_for<T>(mut poll: impl FnMut() -> Option<T>) -> T {
+        for _ in 0..500 {
+            if let Some(value) = poll() {
+                return value;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("Timed out");
+    }
+
+    let mut host = NetplayHost::listen("127.0.0.1:0").unwrap();
+    let mut client = NetplayClient::connect(host.local_addr().unwrap()).unwrap();
+    let connected = wait_for(|| host.poll().pop());
+    assert!(matches!(connected, NetplayEvent::Connected(_)));
+
+    client.send_key(5, true).unwrap();
+    client.send_key(0xA, true).unwrap();
+    client.send_key(5, false).unwrap();
+    let mut events = Vec::new();
+    wait_for(|| {
+        events.extend(host.poll());
+        Some(()).filter(|_| events.len() == 3)
+    });
+    assert_eq!(
+        events,
+        [
+            NetplayEvent::Key(5, true),
+            NetplayEvent::Key(0xA, true),
+            NetplayEvent::Key(5, false)
+        ]
+    );
+
+    let mut rows = [0; 64];
+    rows[63] = 0x8000_0000_0000_0001;
+    let display = DisplayBuffer::from_rows(&rows);
+    host.send_frame(&display);
+    let frame = wait_for(|| client.latest_frame().unwrap());
+    assert!(frame == display);
+
+    // The key that the client still holds is released when it disconnects
+    drop(client);
+    let mut events = Vec::new();
+    wait_for(|| {
+        events.extend(host.poll());
+        Some(()).filter(|_| events.contains(&NetplayEvent::Disconnected))
+    });
+    assert_eq!(
+        events,
+        [NetplayEvent::Key(0xA, false), NetplayEvent::Disconnected]
+    );
+    assert!(!host.is_connected());
+}
@@ -0,0 +1,252 @@
+//! Applying IPS and BPS patches (like community bug fixes) to a ROM before it's loaded.
+
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Applies the patch, which may be in either format (they're told apart by their headers)
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if patch.starts_with(b"PATCH") {
+        apply_ips(rom, patch)
+    } else if patch.starts_with(b"BPS1") {
+        apply_bps(rom, patch)
+    } else {
+        Err("Not an IPS or BPS patch".to_owned())
+    }
+}
+
+/// Reads the patch file and applies it
+pub fn apply_file(rom: &[u8], path: &Path) -> Result<Vec<u8>, String> {
+    let patch =
+        fs::read(path).map_err(|err| format!("Couldn't read {}: {}", path.display(), err))?;
+    apply(rom, &patch).map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+/// `ROM.ips` or `ROM.bps` next to the ROM, if there is one
+pub fn sidecar(rom_path: &Path) -> Option<PathBuf> {
+    ["ips", "bps"]
+        .iter()
+        .map(|extension| {
+            let mut name = OsString::from(rom_path.as_os_str());
+            name.push(".");
+            name.push(extension);
+            PathBuf::from(name)
+        })
+        .find(|path| path.is_file())
+}
+
+/// A cursor over the bytes of a patch
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn read(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position + count)
+            .ok_or("The patch ended unexpectedly")?;
+        self.position += count;
+        Ok(bytes)
+    }
+
+    /// A big-endian number
+    fn read_number(&mut self, count: usize) -> Result<usize, String> {
+        Ok(self
+            .read(count)?
+            .iter()
+            .fold(0, |number, &byte| number << 8 | byte as usize))
+    }
+
+    /// BPS's variable-length number encoding
+    fn read_varint(&mut self) -> Result<usize, String> {
+        let mut number = 0usize;
+        let mut shift = 1usize;
+        loop {
+            let byte = self.read(1)?[0] as usize;
+            number = number
+                .checked_add((byte & 0x7F) * shift)
+                .ok_or("Invalid number in the patch")?;
+            if byte & 0x80 != 0 {
+                return Ok(number);
+            }
+            shift = shift.checked_shl(7).ok_or("Invalid number in the patch")?;
+            number += shift;
+        }
+    }
+}
+
+fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    let mut reader = Reader {
+        bytes: patch,
+        position: 5,
+    };
+    let mut patched = rom.to_vec();
+    loop {
+        let offset = reader.read(3)?;
+        if offset == b"EOF" {
+            break;
+        }
+        let offset = offset
+            .iter()
+            .fold(0, |number, &byte| number << 8 | byte as usize);
+        let size = reader.read_number(2)?;
+        let bytes = if size == 0 {
+            // Run-length encoded
+            let size = reader.read_number(2)?;
+            vec![reader.read(1)?[0]; size]
+        } else {
+            reader.read(size)?.to_vec()
+        };
+        if patched.len() < offset + bytes.len() {
+            patched.resize(offset + bytes.len(), 0);
+        }
+        patched[offset..offset + bytes.len()].copy_from_slice(&bytes);
+    }
+    // Some patches end with the size to truncate the ROM to
+    if let Ok(size) = reader.read_number(3) {
+        patched.truncate(size);
+    }
+    Ok(patched)
+}
+
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if patch.len() < 16 {
+        return Err("The patch ended unexpectedly".to_owned());
+    }
+    let footer = patch.len() - 12;
+    let checksum =
+        |i: usize| u32::from_le_bytes([patch[i], patch[i + 1], patch[i + 2], patch[i + 3]]);
+    if crc32(&patch[..footer + 8]) != checksum(footer + 8) {
+        return Err("The patch is corrupt".to_owned());
+    }
+    if crc32(rom) != checksum(footer) {
+        return Err("The patch is for a different ROM".to_owned());
+    }
+
+    let mut reader = Reader {
+        bytes: &patch[..footer],
+        position: 4,
+    };
+    let source_size = reader.read_varint()?;
+    let target_size = reader.read_varint()?;
+    let metadata_size = reader.read_varint()?;
+    reader.read(metadata_size)?;
+    if source_size != rom.len() {
+        return Err("The patch is for a different ROM".to_owned());
+    }
+
+    let invalid = || "Invalid copy in the patch".to_owned();
+    let mut target = Vec::with_capacity(target_size);
+    let mut source_offset = 0usize;
+    let mut target_offset = 0usize;
+    while reader.position < footer {
+        let action = reader.read_varint()?;
+        let length = (action >> 2) + 1;
+        match action & 3 {
+            // SourceRead: the bytes at the same position in the ROM
+            0 => {
+                let start = target.len();
+                target.extend_from_slice(rom.get(start..start + length).ok_or_else(invalid)?);
+            }
+            // TargetRead: bytes from the patch
+            1 => target.extend_from_slice(reader.read(length)?),
+            // SourceCopy and TargetCopy: bytes from a (relative) position in the ROM or the
+            // output so far
+            command => {
+                let data = reader.read_varint()?;
+                let offset = if command == 2 {
+                    &mut source_offset
+                } else {
+                    &mut target_offset
+                };
+                *offset = if data & 1 == 0 {
+                    offset.checked_add(data >> 1)
+                } else {
+                    offset.checked_sub(data >> 1)
+                }
+                .ok_or_else(invalid)?;
+                for _ in 0..length {
+                    // A TargetCopy may read the bytes it's writing, so this goes byte by byte
+                    let byte = if command == 2 {
+                        rom.get(*offset)
+                    } else {
+                        target.get(*offset)
+                    };
+                    target.push(*byte.ok_or_else(invalid)?);
+                    *offset += 1;
+                }
+            }
+        }
+    }
+    if target.len() != target_size || crc32(&target) != checksum(footer + 4) {
+        return Err("Patching produced the wrong result".to_owned());
+    }
+    Ok(target)
+}
+
+/// The CRC-32 (as used by zip and PNG) that BPS patches use to check the ROMs
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[test]
+fn test_ips() {
+    let rom = [1, 2, 3, 4];
+    let mut patch = b"PATCH".to_vec();
+    // Write 9 at 1, and 7 7 7 at 3 (growing the ROM)
+    patch.extend_from_slice(&[0, 0, 1, 0, 1, 9]);
+    patch.extend_from_slice(&[0, 0, 3, 0, 0, 0, 3, 7]);
+    patch.extend_from_slice(b"EOF");
+    assert_eq!(apply(&rom, &patch), Ok(vec![1, 9, 3, 7, 7, 7]));
+    // ... and truncate it to 5 bytes
+    patch.extend_from_slice(&[0, 0, 5]);
+    assert_eq!(apply(&rom, &patch), Ok(vec![1, 9, 3, 7, 7]));
+
+    assert!(apply(&rom, b"PATCH\0\0\x01\0\x05\x09EOF").is_err());
+    assert_eq!(
+        apply(&rom, b"IPS?"),
+        Err("Not an IPS or BPS patch".to_owned())
+    );
+}
+
+#[test]
+fn test_bps() {
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+    let rom = [1, 2, 3, 4];
+    let target = [1, 2, 9, 9, 9, 4, 1, 2];
+    let mut patch = b"BPS1".to_vec();
+    // The sizes (4, 8, no metadata) as varints
+    patch.extend_from_slice(&[0x84, 0x88, 0x80]);
+    // SourceRead 2, TargetRead [9], TargetCopy 2 from 2, SourceCopy 1 from 3, SourceCopy 2 from 0
+    patch.extend_from_slice(&[0x80 | (1 << 2), 0x80 | 1, 9]);
+    patch.extend_from_slice(&[0x80 | (1 << 2) | 3, 0x80 | (2 << 1)]);
+    patch.extend_from_slice(&[0x80 | 2, 0x80 | (3 << 1)]);
+    patch.extend_from_slice(&[0x80 | (1 << 2) | 2, 0x80 | (4 << 1) | 1]);
+    patch.extend_from_slice(&crc32(&rom).to_le_bytes());
+    patch.extend_from_slice(&crc32(&target).to_le_bytes());
+    let patch_crc = crc32(&patch);
+    patch.extend_from_slice(&patch_crc.to_le_bytes());
+    assert_eq!(apply(&rom, &patch), Ok(target.to_vec()));
+
+    assert_eq!(
+        apply(&[1, 2, 3, 5], &patch),
+        Err("The patch is for a different ROM".to_owned())
+    );
+    let last = patch.len() - 1;
+    patch[last] ^= 1;
+    assert_eq!(apply(&rom, &patch), Err("The patch is corrupt".to_owned()));
+}
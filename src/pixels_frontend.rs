@@ -0,0 +1,230 @@
+use crate::chip8::{Chip8, DisplayBuffer};
+use crate::frontend::{DisplaySink, Driver, Frontend};
+
+mod debug_window;
+mod text;
+
+use debug_window::DebugWindow;
+use pixels::{Pixels, SurfaceTexture};
+use std::time::{Duration, Instant};
+use tracing::warn;
+use winit::dpi::LogicalSize;
+use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+use winit::event_loop::{ControlFlow, EventLoop};
+use winit::window::{Fullscreen, WindowBuilder};
+
+const C8_WIDTH: u32 = 64;
+/// The height of the normal display mode, which the window is sized for
+const C8_HEIGHT: u32 = 32;
+const FRAME_INTERVAL: Duration = Duration::from_micros(1_000_000 / 60);
+
+/// A plain window that shows the Chip-8 screen, drawn with winit + pixels, optionally with a
+/// second window for the debugger.
+///
+/// Escape quits, Enter pauses and F11 toggles fullscreen.
+pub struct PixelsFrontend {
+    pub window_title: String,
+    /// The 16 keyboard keys (letters or digits) that map to the keypad values 0 to F
+    pub keymap: Vec<char>,
+    pub foreground: [u8; 4],
+    pub background: [u8; 4],
+    /// Show the registers, the instructions around PC and the memory at I in a second window
+    pub debug_window: bool,
+}
+
+/// Draws frames into the pixels buffer, which is rendered on the next redraw
+struct PixelsDisplay {
+    pixels: Pixels,
+    /// The height of the pixels buffer, which follows the display mode
+    height: u32,
+    foreground: [u8; 4],
+    background: [u8; 4],
+}
+
+impl DisplaySink for PixelsDisplay {
+    fn show_frame(&mut self, display: &DisplayBuffer) {
+        let height = display.height() as u32;
+        if height != self.height {
+            if let Err(err) = self.pixels.resize_buffer(C8_WIDTH, height) {
+                warn!("Couldn't switch display mode: {}", err);
+                return;
+            }
+            self.height = height;
+        }
+        let rgba = display.to_rgba(self.foreground, self.background);
+        self.pixels.frame_mut().copy_from_slice(&rgba);
+    }
+}
+
+impl Frontend for PixelsFrontend {
+    fn run(self, chip8: Chip8) -> Result<(), String> {
+        let event_loop = EventLoop::new();
+        let window = WindowBuilder::new()
+            .with_title(&self.window_title)
+            .with_inner_size(LogicalSize::new(C8_WIDTH * 10, C8_HEIGHT * 10))
+            .with_min_inner_size(LogicalSize::new(C8_WIDTH, C8_HEIGHT))
+            .build(&event_loop)
+            .map_err(|err| format!("Couldn't create window: {}", err))?;
+        let size = window.inner_size();
+        let surface = SurfaceTexture::new(size.width, size.height, &window);
+        let pixels = Pixels::new(C8_WIDTH, C8_HEIGHT, surface)
+            .map_err(|err| format!("Couldn't set up rendering: {}", err))?;
+        let display = PixelsDisplay {
+            pixels,
+            height: C8_HEIGHT,
+            foreground: self.foreground,
+            background: self.background,
+        };
+        let mut debug_window = if self.debug_window {
+            Some(DebugWindow::new(
+                &event_loop,
+                &self.window_title,
+                self.foreground,
+                self.background,
+            )?)
+        } else {
+            None
+        };
+        let mut driver = Driver::new(chip8, display, [false; 16], ());
+
+        let mut paused = false;
+        let mut last_update = Instant::now();
+        event_loop.run(move |event, _, control_flow| match event {
+            Event::WindowEvent { window_id, event } => match event {
+                // Closing the debugger leaves the game running
+                WindowEvent::CloseRequested
+                    if debug_window.as_ref().map(DebugWindow::id) == Some(window_id) =>
+                {
+                    debug_window = None;
+                }
+                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+                WindowEvent::Resized(size) if window_id != window.id() => {
+                    if let Some(debug_window) = &mut debug_window {
+                        if let Err(err) = debug_window.resize(size) {
+                            warn!("{}", err);
+                        }
+                    }
+                }
+                WindowEvent::Resized(size) => {
+                    if let Err(err) = driver
+                        .display
+                        .pixels
+                        .resize_surface(size.width, size.height)
+                    {
+                        warn!("Couldn't resize: {}", err);
+                        *control_flow = ControlFlow::Exit;
+                    }
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(keycode),
+                            state,
+                            ..
+                        },
+                    ..
+                } => {
+                    let pressed = state == ElementState::Pressed;
+                    match keycode {
+                        VirtualKeyCode::Escape => *control_flow = ControlFlow::Exit,
+                        VirtualKeyCode::Return if pressed => paused = !paused,
+                        VirtualKeyCode::F11 if pressed => {
+                            let fullscreen = match window.fullscreen() {
+                                Some(_) => None,
+                                None => Some(Fullscreen::Borderless(None)),
+                            };
+                            window.set_fullscreen(fullscreen);
+                        }
+                        _ => {
+                            let keypad_value = key_to_char(keycode)
+                                .and_then(|c| self.keymap.iter().position(|&key| key == c));
+                            if let Some(keypad_value) = keypad_value {
+                                driver.input[keypad_value] = pressed;
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            },
+            Event::MainEventsCleared => {
+                let now = Instant::now();
+                if now >= last_update + FRAME_INTERVAL {
+                    let elapsed_time = now.duration_since(last_update).as_secs_f64();
+                    last_update = now;
+                    if !paused {
+                        if let Err(err) = driver.update(elapsed_time) {
+                            warn!("{}", err);
+                            paused = true;
+                        }
+                    }
+                    window.request_redraw();
+                    if let Some(debug_window) = &debug_window {
+                        debug_window.request_redraw();
+                    }
+                }
+                if *control_flow != ControlFlow::Exit {
+                    *control_flow = ControlFlow::WaitUntil(last_update + FRAME_INTERVAL);
+                }
+            }
+            Event::RedrawRequested(window_id) if window_id != window.id() => {
+                if let Some(debug_window) = &mut debug_window {
+                    if let Err(err) = debug_window.draw(&driver.chip8, paused) {
+                        warn!("{}", err);
+                        *control_flow = ControlFlow::Exit;
+                    }
+                }
+            }
+            Event::RedrawRequested(_) => {
+                if let Err(err) = driver.display.pixels.render() {
+                    warn!("Couldn't render: {}", err);
+                    *control_flow = ControlFlow::Exit;
+                }
+            }
+            _ => {}
+        })
+    }
+}
+
+fn key_to_char(keycode: VirtualKeyCode) -> Option<char> {
+    use VirtualKeyCode::*;
+    let c = match keycode {
+        Key0 | Numpad0 => '0',
+        Key1 | Numpad1 => '1',
+        Key2 | Numpad2 => '2',
+        Key3 | Numpad3 => '3',
+        Key4 | Numpad4 => '4',
+        Key5 | Numpad5 => '5',
+        Key6 | Numpad6 => '6',
+        Key7 | Numpad7 => '7',
+        Key8 | Numpad8 => '8',
+        Key9 | Numpad9 => '9',
+        A => 'a',
+        B => 'b',
+        C => 'c',
+        D => 'd',
+        E => 'e',
+        F => 'f',
+        G => 'g',
+        H => 'h',
+        I => 'i',
+        J => 'j',
+        K => 'k',
+        L => 'l',
+        M => 'm',
+        N => 'n',
+        O => 'o',
+        P => 'p',
+        Q => 'q',
+        R => 'r',
+        S => 's',
+        T => 't',
+        U => 'u',
+        V => 'v',
+        W => 'w',
+        X => 'x',
+        Y => 'y',
+        Z => 'z',
+        _ => return None,
+    };
+    Some(c)
+}
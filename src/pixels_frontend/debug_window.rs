@@ -0,0 +1,163 @@
+use super::text::{self, CELL_HEIGHT, CELL_WIDTH};
+use crate::assembly;
+use crate::chip8::Chip8;
+
+use pixels::{Pixels, SurfaceTexture};
+use winit::dpi::{LogicalSize, PhysicalSize};
+use winit::event_loop::EventLoop;
+use winit::window::{Window, WindowBuilder, WindowId};
+
+/// The size of the panels, in characters
+const COLUMNS: u32 = 40;
+const LINES: u32 = 34;
+/// The instructions that are listed before and after the one at PC
+const LISTING_CONTEXT: u16 = 5;
+/// The rows of 8 bytes that are shown, starting at the row of I
+const MEMORY_ROWS: u16 = 8;
+
+/// A second window with the registers, the instructions around PC and the memory at I, so that
+/// the game's window can be fullscreened or scaled on its own
+pub(super) struct DebugWindow {
+    window: Window,
+    pixels: Pixels,
+    foreground: [u8; 4],
+    background: [u8; 4],
+}
+
+impl DebugWindow {
+    pub fn new(
+        event_loop: &EventLoop<()>,
+        title: &str,
+        foreground: [u8; 4],
+        background: [u8; 4],
+    ) -> Result<DebugWindow, String> {
+        let (width, height) = (COLUMNS * CELL_WIDTH, LINES * CELL_HEIGHT);
+        let window = WindowBuilder::new()
+            .with_title(format!("Debugger - {}", title))
+            .with_inner_size(LogicalSize::new(width * 3, height * 3))
+            .with_min_inner_size(LogicalSize::new(width, height))
+            .build(event_loop)
+            .map_err(|err| format!("Couldn't create the debugger window: {}", err))?;
+        let size = window.inner_size();
+        let surface = SurfaceTexture::new(size.width, size.height, &window);
+        let pixels = Pixels::new(width, height, surface)
+            .map_err(|err| format!("Couldn't set up rendering for the debugger: {}", err))?;
+        Ok(DebugWindow {
+            window,
+            pixels,
+            foreground,
+            background,
+        })
+    }
+
+    pub fn id(&self) -> WindowId {
+        self.window.id()
+    }
+
+    pub fn request_redraw(&self) {
+        self.window.request_redraw();
+    }
+
+    pub fn resize(&mut self, size: PhysicalSize<u32>) -> Result<(), String> {
+        self.pixels
+            .resize_surface(size.width, size.height)
+            .map_err(|err| format!("Couldn't resize the debugger: {}", err))
+    }
+
+    pub fn draw(&mut self, chip8: &Chip8, paused: bool) -> Result<(), String> {
+        let frame = self.pixels.frame_mut();
+        for pixel in frame.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&self.background);
+        }
+        for (line, text) in panel_lines(chip8, paused).iter().enumerate() {
+            let width = COLUMNS * CELL_WIDTH;
+            text::draw_text(frame, width, 0, line as u32, text, self.foreground);
+        }
+        self.pixels
+            .render()
+            .map_err(|err| format!("Couldn't render the debugger: {}", err))
+    }
+}
+
+/// The lines of text in the debugger: the registers, the stack, the instructions around PC
+/// (marked with >) and the memory from the row of I
+fn panel_lines(chip8: &Chip8, paused: bool) -> Vec<String> {
+    let mut lines = vec![
+        if paused { "PAUSED" } else { "RUNNING" }.to_owned(),
+        format!(
+            "PC {:03X}  I {:03X}  DT {:02X}  ST {:02X}",
+            chip8.program_counter, chip8.address_register, chip8.delay_timer, chip8.sound_timer
+        ),
+    ];
+    for (row, registers) in chip8.registers.chunks(4).enumerate() {
+        let text = registers
+            .iter()
+            .enumerate()
+            .map(|(i, value)| format!("V{:X} {:02X}", 4 * row + i, value))
+            .collect::<Vec<String>>()
+            .join("  ");
+        lines.push(text);
+    }
+    let stack = chip8.stack[..chip8.stack_pointer as usize]
+        .iter()
+        .map(|address| format!("{:03X}", address))
+        .collect::<Vec<String>>();
+    lines.push(format!("STACK {}", stack.join(" ")));
+
+    lines.push(String::new());
+    let memory = chip8.memory();
+    let pc = chip8.program_counter;
+    let first = pc.saturating_sub(2 * LISTING_CONTEXT);
+    for address in (first..=pc.saturating_add(2 * LISTING_CONTEXT)).step_by(2) {
+        let marker = if address == pc { ">" } else { " " };
+        let text = match memory.get(address as usize..address as usize + 2) {
+            Some(bytes) => {
+                let opcode = u16::from_be_bytes([bytes[0], bytes[1]]);
+                assembly::disassemble_opcode(opcode)
+                    .unwrap_or_else(|_| format!("DATA[{:#06X}]", opcode))
+            }
+            None => continue,
+        };
+        lines.push(format!("{}{:03X}: {}", marker, address, text));
+    }
+
+    lines.push(String::new());
+    let start = chip8.address_register & !7;
+    for row in 0..MEMORY_ROWS {
+        let address = start as usize + 8 * row as usize;
+        let bytes = match memory.get(address..(address + 8).min(memory.len())) {
+            Some(bytes) if !bytes.is_empty() => bytes,
+            _ => break,
+        };
+        let bytes = bytes
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<Vec<String>>();
+        lines.push(format!("{:03X}: {}", address, bytes.join(" ")));
+    }
+    lines
+}
+
+#[test]
+fn test_panel_lines() {
+    // V0 = 5, I = 0x208, call 0x20A; at 0x20A: return
+    let mut chip8 = Chip8::from_rom(&[
+        0x60, 0x05, 0xA2, 0x08, 0x22, 0x0A, 0x00, 0x00, 0xAB, 0xCD, 0x00, 0xEE,
+    ])
+    .unwrap();
+    for _ in 0..3 {
+        chip8.step_instruction().unwrap();
+    }
+
+    let lines = panel_lines(&chip8, true);
+
+    assert_eq!(lines[0], "PAUSED");
+    assert_eq!(lines[1], "PC 20A  I 208  DT 00  ST 00");
+    assert_eq!(lines[2], "V0 05  V1 00  V2 00  V3 00");
+    assert_eq!(lines[6], "STACK 206");
+    assert!(lines.contains(&">20A: return".to_owned()));
+    assert!(lines.contains(&" 200: V0 = 0x05".to_owned()));
+    assert!(lines.contains(&"208: AB CD 00 EE 00 00 00 00".to_owned()));
+    assert!(lines.len() as u32 <= LINES);
+    assert!(lines.iter().all(|line| line.len() as u32 <= COLUMNS));
+}
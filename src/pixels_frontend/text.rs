@@ -0,0 +1,128 @@
+/// The width of a glyph, in pixels of the frame
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+/// The room that each character takes, including the space to the next one
+pub(super) const CELL_WIDTH: u32 = 4;
+pub(super) const CELL_HEIGHT: u32 = 6;
+
+/// A 3x5 font for the characters that the debugger shows (letters are drawn in upper case). Each
+/// row is 3 bits, with the leftmost pixel in the highest bit.
+const FONT: [(char, [u8; GLYPH_HEIGHT]); 58] = [
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b111, 0b001, 0b111, 0b100, 0b111]),
+    ('3', [0b111, 0b001, 0b111, 0b001, 0b111]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', [0b111, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b001, 0b010, 0b010]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b111]),
+    ('A', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('B', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('C', [0b011, 0b100, 0b100, 0b100, 0b011]),
+    ('D', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('E', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('F', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('G', [0b011, 0b100, 0b101, 0b101, 0b011]),
+    ('H', [0b101, 0b101, 0b111, 0b101, 0b101]),
+    ('I', [0b111, 0b010, 0b010, 0b010, 0b111]),
+    ('J', [0b001, 0b001, 0b001, 0b101, 0b010]),
+    ('K', [0b101, 0b101, 0b110, 0b101, 0b101]),
+    ('L', [0b100, 0b100, 0b100, 0b100, 0b111]),
+    ('M', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('N', [0b110, 0b101, 0b101, 0b101, 0b101]),
+    ('O', [0b010, 0b101, 0b101, 0b101, 0b010]),
+    ('P', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('Q', [0b010, 0b101, 0b101, 0b110, 0b011]),
+    ('R', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('S', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('T', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('U', [0b101, 0b101, 0b101, 0b101, 0b111]),
+    ('V', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('W', [0b101, 0b101, 0b111, 0b111, 0b101]),
+    ('X', [0b101, 0b101, 0b010, 0b101, 0b101]),
+    ('Y', [0b101, 0b101, 0b010, 0b010, 0b010]),
+    ('Z', [0b111, 0b001, 0b010, 0b100, 0b111]),
+    (' ', [0b000, 0b000, 0b000, 0b000, 0b000]),
+    (':', [0b000, 0b010, 0b000, 0b010, 0b000]),
+    ('=', [0b000, 0b111, 0b000, 0b111, 0b000]),
+    ('+', [0b000, 0b010, 0b111, 0b010, 0b000]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    ('&', [0b010, 0b101, 0b010, 0b101, 0b011]),
+    ('|', [0b010, 0b010, 0b010, 0b010, 0b010]),
+    ('^', [0b010, 0b101, 0b000, 0b000, 0b000]),
+    ('<', [0b001, 0b010, 0b100, 0b010, 0b001]),
+    ('>', [0b100, 0b010, 0b001, 0b010, 0b100]),
+    ('(', [0b001, 0b010, 0b010, 0b010, 0b001]),
+    (')', [0b100, 0b010, 0b010, 0b010, 0b100]),
+    ('[', [0b011, 0b010, 0b010, 0b010, 0b011]),
+    (']', [0b110, 0b010, 0b010, 0b010, 0b110]),
+    (',', [0b000, 0b000, 0b000, 0b010, 0b100]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    ('_', [0b000, 0b000, 0b000, 0b000, 0b111]),
+    ('!', [0b010, 0b010, 0b010, 0b000, 0b010]),
+    ('?', [0b110, 0b001, 0b010, 0b000, 0b010]),
+    ('/', [0b001, 0b001, 0b010, 0b100, 0b100]),
+    ('*', [0b000, 0b101, 0b010, 0b101, 0b000]),
+    ('#', [0b101, 0b111, 0b101, 0b111, 0b101]),
+];
+
+/// The rows of the character's glyph, or of '?' for a character that the font doesn't have
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    let find = |c: char| FONT.iter().find(|(glyph_char, _)| *glyph_char == c);
+    find(c.to_ascii_uppercase())
+        .or_else(|| find('?'))
+        .map(|&(_, rows)| rows)
+        .unwrap()
+}
+
+/// Draws a line of text into an RGBA frame that is `frame_width` pixels wide, with its top left
+/// corner at the given character cell. Characters that don't fit are left out.
+pub(super) fn draw_text(
+    frame: &mut [u8],
+    frame_width: u32,
+    column: u32,
+    line: u32,
+    text: &str,
+    color: [u8; 4],
+) {
+    let frame_width = frame_width as usize;
+    let frame_height = frame.len() / 4 / frame_width;
+    let top = (line * CELL_HEIGHT) as usize;
+    for (i, c) in text.chars().enumerate() {
+        let left = (column as usize + i) * CELL_WIDTH as usize;
+        if left + GLYPH_WIDTH > frame_width || top + GLYPH_HEIGHT > frame_height {
+            break;
+        }
+        for (dy, row) in glyph(c).iter().enumerate() {
+            for dx in 0..GLYPH_WIDTH {
+                if row & (0b100 >> dx) != 0 {
+                    let offset = 4 * ((top + dy) * frame_width + left + dx);
+                    frame[offset..offset + 4].copy_from_slice(&color);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_draw_text() {
+    // Two cells wide and one high
+    let mut frame = vec![0; 4 * 8 * 6];
+    draw_text(&mut frame, 8, 0, 0, "1a~", [255; 4]);
+
+    let lit: Vec<String> = frame
+        .chunks_exact(4 * 8)
+        .map(|row| {
+            row.chunks_exact(4)
+                .map(|pixel| if pixel[0] == 255 { '#' } else { '.' })
+                .collect()
+        })
+        .collect();
+    assert_eq!(
+        lit,
+        [".#...#..", "##..#.#.", ".#..###.", ".#..#.#.", "###.#.#.", "........"]
+    );
+    assert_eq!(glyph('~'), glyph('?'));
+}
@@ -0,0 +1,132 @@
+use crate::chip8::Quirks;
+
+use serde::Deserialize;
+
+/// The database that is compiled into the emulator
+const BUILTIN_DATABASE: &str = include_str!("../resources/rom_database.toml");
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+    Chip8,
+    Schip,
+    Xochip,
+    Chip8x,
+}
+
+/// What is known about a ROM, and the settings it should be run with
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RomInfo {
+    pub sha1: String,
+    pub title: String,
+    pub author: Option<String>,
+    pub platform: Platform,
+    /// The recommended number of instructions per second
+    pub clock: Option<u32>,
+    /// The name of a quirks preset (see `Quirks::preset`)
+    pub quirks: Option<String>,
+}
+
+impl RomInfo {
+    /// The title, and the author if known
+    pub fn name(&self) -> String {
+        match &self.author {
+            Some(author) => format!("{} by {}", self.title, author),
+            None => self.title.clone(),
+        }
+    }
+
+    pub fn recommended_quirks(&self) -> Option<Quirks> {
+        self.quirks
+            .as_ref()
+            .map(|name| Quirks::preset(name).expect("Quirks preset checked when parsing"))
+    }
+}
+
+/// ROMs keyed by the SHA-1 of their contents
+#[derive(Debug, Deserialize)]
+pub struct RomDatabase {
+    #[serde(rename = "rom")]
+    roms: Vec<RomInfo>,
+}
+
+impl RomDatabase {
+    pub fn parse(toml: &str) -> Result<RomDatabase, String> {
+        let database: RomDatabase = toml::from_str(toml).map_err(|err| err.to_string())?;
+        for info in &database.roms {
+            if let Some(name) = &info.quirks {
+                Quirks::preset(name).map_err(|err| format!("{}: {}", info.title, err))?;
+            }
+        }
+        Ok(database)
+    }
+
+    pub fn builtin() -> RomDatabase {
+        RomDatabase::parse(BUILTIN_DATABASE).expect("Parse builtin ROM database")
+    }
+
+    pub fn lookup(&self, rom: &[u8]) -> Option<&RomInfo> {
+        let sha1 = sha1_smol::Sha1::from(rom).digest().to_string();
+        self.roms.iter().find(|info| info.sha1 == sha1)
+    }
+}
+
+/// The platform that introduced the op-code, and what it does, if it's a SUPER-CHIP or XO-CHIP
+/// extension
+pub fn extension_opcode(opcode: u16) -> Option<(Platform, &'static str)> {
+    let extension = match opcode {
+        0x00C0..=0x00CF => (Platform::Schip, "00Cn scroll down"),
+        0x00FB => (Platform::Schip, "00FB scroll right"),
+        0x00FC => (Platform::Schip, "00FC scroll left"),
+        0x00FD => (Platform::Schip, "00FD exit"),
+        0x00FE => (Platform::Schip, "00FE low resolution"),
+        0x00FF => (Platform::Schip, "00FF high resolution"),
+        0x00D0..=0x00DF => (Platform::Xochip, "00Dn scroll up"),
+        0xF000 => (Platform::Xochip, "F000 long I"),
+        0xF002 => (Platform::Xochip, "F002 audio pattern"),
+        0x02A0 => (Platform::Chip8x, "02A0 background color"),
+        _ => match (opcode & 0xF000, opcode & 0xF00F, opcode & 0xF0FF) {
+            (0xD000, 0xD000, _) => (Platform::Schip, "Dxy0 16x16 sprite"),
+            (_, 0x5002, _) => (Platform::Xochip, "5xy2 store range"),
+            (_, 0x5003, _) => (Platform::Xochip, "5xy3 load range"),
+            (_, 0x5001, _) => (Platform::Chip8x, "5xy1 add nibbles"),
+            (_, _, 0xF030) => (Platform::Schip, "Fx30 big font sprite"),
+            (_, _, 0xF075) => (Platform::Schip, "Fx75 store flags"),
+            (_, _, 0xF085) => (Platform::Schip, "Fx85 load flags"),
+            (_, _, 0xF001) => (Platform::Xochip, "Fn01 select planes"),
+            (_, _, 0xF03A) => (Platform::Xochip, "Fx3A pitch"),
+            _ => return None,
+        },
+    };
+    Some(extension)
+}
+
+#[test]
+fn test_extension_opcode() {
+    assert_eq!(
+        extension_opcode(0x00FF),
+        Some((Platform::Schip, "00FF high resolution"))
+    );
+    assert_eq!(extension_opcode(0xD120).unwrap().0, Platform::Schip);
+    assert_eq!(extension_opcode(0x5123).unwrap().0, Platform::Xochip);
+    assert_eq!(extension_opcode(0xF201).unwrap().0, Platform::Xochip);
+    assert_eq!(extension_opcode(0x5121).unwrap().0, Platform::Chip8x);
+    assert_eq!(extension_opcode(0xD125), None);
+    assert_eq!(extension_opcode(0x00E0), None);
+    assert_eq!(extension_opcode(0xF265), None);
+}
+
+#[test]
+fn test_rom_database() {
+    let database = RomDatabase::builtin();
+    let rom = std::fs::read("programs/Space Invaders [David Winter].ch8").unwrap();
+
+    let info = database.lookup(&rom).unwrap();
+    assert_eq!(info.title, "Space Invaders");
+    assert_eq!(info.platform, Platform::Chip8);
+    assert!(database.lookup(&[0x12, 0x00]).is_none());
+
+    let invalid = "[[rom]]\nsha1 = \"00\"\ntitle = \"x\"\nplatform = \"chip8\"\nquirks = \"foo\"";
+    assert!(RomDatabase::parse(invalid).is_err());
+}
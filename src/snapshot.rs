@@ -0,0 +1,191 @@
+//! Helpers for regression tests that compare the screen against a stored snapshot.
+//!
+//! ```no_run
+//! use chip_8_rs::assert_screen_eq;
+//! use chip_8_rs::chip8::Quirks;
+//! use chip_8_rs::snapshot;
+//!
+//! let rom = std::fs::read("programs/test_opcode.ch8").unwrap();
+//! let chip8 = snapshot::run_rom(&rom, 2000, Quirks::default()).unwrap();
+//! assert_screen_eq!(chip8.display_buffer, "resources/golden/test_opcode.txt");
+//! ```
+//!
+//! Snapshots are text (see `DisplayBuffer::to_ascii`), or PNG files if the path ends with
+//! `.png` (with the `native` feature). Set the environment variable `CHIP8_BLESS=1` to write the
+//! actual screens as the new snapshots instead of comparing them.
+
+#[cfg(feature = "native")]
+use crate::chip8::DISPLAY_HEIGHTS;
+use crate::chip8::{Chip8, DisplayBuffer, Quirks};
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+/// The environment variable that turns on bless mode
+pub const BLESS_VARIABLE: &str = "CHIP8_BLESS";
+
+/// Asserts that the display matches the snapshot at the given path (or writes the snapshot, in
+/// bless mode).
+#[macro_export]
+macro_rules! assert_screen_eq {
+    ($display:expr, $path:expr) => {
+        if let Err(err) = $crate::snapshot::compare_screen(
+            &$display,
+            ::std::path::Path::new($path),
+            $crate::snapshot::bless_mode(),
+        ) {
+            panic!("{}", err);
+        }
+    };
+}
+
+/// Runs a ROM for the given number of instructions. The result only depends on the arguments,
+/// since no keys are pressed and the random number generator always has the same seed.
+pub fn run_rom(rom: &[u8], cycles: u64, quirks: Quirks) -> Result<Chip8, String> {
+    let mut chip8 = Chip8::from_rom(rom)?;
+    chip8.set_quirks(quirks);
+    for _ in 0..cycles {
+        chip8.step_instruction()?;
+    }
+    Ok(chip8)
+}
+
+/// Whether snapshots should be written instead of compared
+pub fn bless_mode() -> bool {
+    env::var(BLESS_VARIABLE).is_ok_and(|value| !value.is_empty() && value != "0")
+}
+
+/// Compares the display against the snapshot at the given path, or writes it there if `bless`
+pub fn compare_screen(display: &DisplayBuffer, path: &Path, bless: bool) -> Result<(), String> {
+    if bless {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|err| format!("Couldn't create {}: {}", dir.display(), err))?;
+        }
+        return write_snapshot(display, path);
+    }
+
+    let expected = match read_snapshot(path)? {
+        Some(expected) => expected,
+        None => {
+            return Err(format!(
+                "Missing snapshot {} (set {}=1 to create it)",
+                path.display(),
+                BLESS_VARIABLE
+            ))
+        }
+    };
+    let actual = display.to_ascii();
+    if actual == expected {
+        return Ok(());
+    }
+
+    let mut message = format!("The screen differs from {}:\n", path.display());
+    for (row, (actual_row, expected_row)) in actual.lines().zip(expected.lines()).enumerate() {
+        if actual_row != expected_row {
+            message.push_str(&format!(
+                "row {:2} expected {}\n       actual   {}\n",
+                row, expected_row, actual_row
+            ));
+        }
+    }
+    message.push_str(&format!(
+        "(set {}=1 to accept the new screen)",
+        BLESS_VARIABLE
+    ));
+    Err(message)
+}
+
+fn is_png(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "png")
+}
+
+fn write_snapshot(display: &DisplayBuffer, path: &Path) -> Result<(), String> {
+    if is_png(path) {
+        return write_png(display, path);
+    }
+    fs::write(path, display.to_ascii())
+        .map_err(|err| format!("Couldn't write {}: {}", path.display(), err))
+}
+
+/// The snapshot as text, or None if there is no snapshot
+fn read_snapshot(path: &Path) -> Result<Option<String>, String> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    if is_png(path) {
+        return read_png(path).map(Some);
+    }
+    fs::read_to_string(path)
+        .map(Some)
+        .map_err(|err| format!("Couldn't read {}: {}", path.display(), err))
+}
+
+#[cfg(feature = "native")]
+fn write_png(display: &DisplayBuffer, path: &Path) -> Result<(), String> {
+    display.write_png(path)
+}
+
+/// Reads a 64x32 PNG (or 64x48 or 64x64, for the other display modes) as text, counting any
+/// bright pixel as lit
+#[cfg(feature = "native")]
+fn read_png(path: &Path) -> Result<String, String> {
+    let image = image::open(path)
+        .map_err(|err| format!("Couldn't read {}: {}", path.display(), err))?
+        .to_luma();
+    let (width, height) = image.dimensions();
+    if width != 64 || !DISPLAY_HEIGHTS.iter().any(|&h| h as u32 == height) {
+        return Err(format!(
+            "{} isn't 64x32, 64x48 or 64x64 pixels",
+            path.display()
+        ));
+    }
+    let mut rows = vec![0; height as usize];
+    for (x, y, pixel) in image.enumerate_pixels() {
+        if pixel[0] >= 128 {
+            rows[y as usize] |= 1 << (63 - x);
+        }
+    }
+    Ok(DisplayBuffer::from_rows(&rows).to_ascii())
+}
+
+#[cfg(not(feature = "native"))]
+fn write_png(_display: &DisplayBuffer, path: &Path) -> Result<(), String> {
+    Err(format!(
+        "Can't write {} without the native feature",
+        path.display()
+    ))
+}
+
+#[cfg(not(feature = "native"))]
+fn read_png(path: &Path) -> Result<String, String> {
+    Err(format!(
+        "Can't read {} without the native feature",
+        path.display()
+    ))
+}
+
+#[test]
+fn test_compare_screen() {
+    let dir = env::temp_dir().join(format!("chip8_snapshot_test_{}", std::process::id()));
+    let rom = [0x60, 0x05, 0xF0, 0x29, 0xD1, 0x15, 0x12, 0x06]; // draw the "5" sprite
+    let chip8 = run_rom(&rom, 3, Quirks::default()).unwrap();
+    let other = run_rom(&rom, 0, Quirks::default()).unwrap();
+
+    // PNGs are only written and read with the native feature
+    let names: &[&str] = if cfg!(feature = "native") {
+        &["screen.txt", "screen.png"]
+    } else {
+        &["screen.txt"]
+    };
+    for name in names {
+        let path = dir.join(name);
+        assert!(compare_screen(&chip8.display_buffer, &path, false).is_err());
+        compare_screen(&chip8.display_buffer, &path, true).unwrap();
+        compare_screen(&chip8.display_buffer, &path, false).unwrap();
+        let err = compare_screen(&other.display_buffer, &path, false).unwrap_err();
+        assert!(err.contains("row  0"));
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,168 @@
+//! The files that save states are kept in (the slots of the app, autosaves and `--load-state`).
+//!
+//! A file starts with a header that can be read without loading the state: the format version,
+//! a hash of the ROM (so that a state isn't loaded into another program) and a thumbnail of the
+//! display. The state itself (see `Chip8::save_state`) follows, optionally compressed with
+//! deflate. Plain save states, as written by older versions and crash dumps, are read too.
+
+use crate::chip8::{Chip8, DisplayBuffer, DISPLAY_HEIGHTS};
+
+use std::convert::TryInto;
+
+const MAGIC: &[u8; 4] = b"C8SF";
+/// What plain save states start with
+const STATE_MAGIC: &[u8; 4] = b"C8ST";
+pub const VERSION: u8 = 1;
+const COMPRESSED: u8 = 1;
+/// Larger than any state, to not inflate a corrupt file without end
+const MAX_STATE_SIZE: usize = 0x20000;
+
+pub struct StateFile {
+    /// See `Chip8::rom_hash`. Plain save states don't have one, and can be loaded into any machine.
+    pub rom_hash: Option<u64>,
+    /// The display when the state was saved
+    pub thumbnail: Option<DisplayBuffer>,
+    pub state: Vec<u8>,
+}
+
+impl StateFile {
+    pub fn new(chip8: &Chip8) -> StateFile {
+        StateFile {
+            rom_hash: Some(chip8.rom_hash()),
+            thumbnail: Some(chip8.display_buffer.clone()),
+            state: chip8.save_state(),
+        }
+    }
+
+    pub fn to_bytes(&self, compress: bool) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.push(if compress { COMPRESSED } else { 0 });
+        bytes.extend_from_slice(&self.rom_hash.unwrap_or(0).to_be_bytes());
+        match &self.thumbnail {
+            Some(thumbnail) => {
+                bytes.push(thumbnail.height());
+                bytes.extend_from_slice(&thumbnail.as_bytes());
+            }
+            None => bytes.push(0),
+        }
+        bytes.extend_from_slice(&(self.state.len() as u32).to_be_bytes());
+        if compress {
+            bytes.extend_from_slice(&miniz_oxide::deflate::compress_to_vec(&self.state, 6));
+        } else {
+            bytes.extend_from_slice(&self.state);
+        }
+        bytes
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<StateFile, String> {
+        if bytes.starts_with(STATE_MAGIC) {
+            return Ok(StateFile {
+                rom_hash: None,
+                thumbnail: None,
+                state: bytes.to_vec(),
+            });
+        }
+        let truncated = || "The save state file is truncated".to_owned();
+        let take = |bytes: &mut &[u8], n: usize| -> Result<Vec<u8>, String> {
+            if bytes.len() < n {
+                return Err(truncated());
+            }
+            let (taken, rest) = bytes.split_at(n);
+            *bytes = rest;
+            Ok(taken.to_vec())
+        };
+        let mut bytes = bytes;
+        if take(&mut bytes, MAGIC.len())? != MAGIC {
+            return Err("Not a Chip-8 save state".to_owned());
+        }
+        let header = take(&mut bytes, 2)?;
+        let (version, flags) = (header[0], header[1]);
+        if version > VERSION {
+            return Err(format!(
+                "The save state has format version {}, but this emulator only reads up to {}",
+                version, VERSION
+            ));
+        }
+        let mut hash = [0; 8];
+        hash.copy_from_slice(&take(&mut bytes, 8)?);
+        let height = take(&mut bytes, 1)?[0];
+        let thumbnail = match height {
+            0 => None,
+            height if DISPLAY_HEIGHTS.contains(&height) => {
+                let rows: Vec<u64> = take(&mut bytes, 8 * height as usize)?
+                    .chunks(8)
+                    .map(|row| u64::from_be_bytes(row.try_into().unwrap()))
+                    .collect();
+                Some(DisplayBuffer::from_rows(&rows))
+            }
+            height => return Err(format!("Invalid thumbnail height: {}", height)),
+        };
+        let mut size = [0; 4];
+        size.copy_from_slice(&take(&mut bytes, 4)?);
+        let size = u32::from_be_bytes(size) as usize;
+        let state = if flags & COMPRESSED != 0 {
+            miniz_oxide::inflate::decompress_to_vec_with_limit(bytes, MAX_STATE_SIZE)
+                .map_err(|err| format!("Couldn't decompress the save state: {}", err))?
+        } else {
+            bytes.to_vec()
+        };
+        if state.len() != size {
+            return Err(truncated());
+        }
+        Ok(StateFile {
+            rom_hash: Some(u64::from_be_bytes(hash)),
+            thumbnail,
+            state,
+        })
+    }
+
+    /// Whether the state can be loaded into the machine: it was saved from the same ROM
+    pub fn matches(&self, chip8: &Chip8) -> bool {
+        self.rom_hash.is_none_or(|hash| hash == chip8.rom_hash())
+    }
+
+    /// Loads the state into the machine, unless it was saved from another ROM
+    pub fn load_into(&self, chip8: &mut Chip8) -> Result<(), String> {
+        if !self.matches(chip8) {
+            return Err("The save state is from another ROM".to_owned());
+        }
+        chip8.load_state(&self.state)
+    }
+}
+
+#[test]
+fn test_state_file() {
+    // V0 = 0x2A, draw the digit 0, loop
+    let rom = [0x60, 0x2A, 0xD0, 0x05, 0x12, 0x04];
+    let mut chip8 = Chip8::from_rom(&rom).unwrap();
+    for _ in 0..2 {
+        chip8.step_instruction().unwrap();
+    }
+    for compress in [false, true] {
+        let bytes = StateFile::new(&chip8).to_bytes(compress);
+        let file = StateFile::parse(&bytes).unwrap();
+        assert_eq!(file.thumbnail.as_ref(), Some(&chip8.display_buffer));
+        let mut loaded = Chip8::from_rom(&rom).unwrap();
+        file.load_into(&mut loaded).unwrap();
+        assert_eq!(loaded.registers[0], 0x2A);
+        assert_eq!(loaded.state_hash(), chip8.state_hash());
+
+        let mut other = Chip8::from_rom(&[0x12, 0x00]).unwrap();
+        assert!(!file.matches(&other));
+        assert!(file.load_into(&mut other).is_err());
+        assert!(StateFile::parse(&bytes[..bytes.len() - 1]).is_err());
+    }
+    assert!(StateFile::new(&chip8).to_bytes(true).len() < chip8.save_state().len() / 2);
+
+    // A plain save state loads into any machine
+    let file = StateFile::parse(&chip8.save_state()).unwrap();
+    assert!(file.rom_hash.is_none() && file.thumbnail.is_none());
+    file.load_into(&mut Chip8::from_rom(&[0x12, 0x00]).unwrap())
+        .unwrap();
+
+    let mut newer = StateFile::new(&chip8).to_bytes(false);
+    newer[4] = VERSION + 1;
+    assert!(StateFile::parse(&newer).is_err());
+}
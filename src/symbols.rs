@@ -0,0 +1,113 @@
+//! Symbol files, which give names to the addresses of a program (like the labels of its source),
+//! for showing them in the debugger: `ROM.sym` next to the ROM, with one label on each line, like
+//! `2A0 draw_player` (the address in hex).
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Symbols {
+    names: BTreeMap<u16, String>,
+}
+
+impl Symbols {
+    /// Parses a symbol file. Empty lines and lines starting with `#` are skipped.
+    pub fn parse(text: &str) -> Result<Symbols, String> {
+        let mut names = BTreeMap::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (address, name) =
+                parse_line(line).map_err(|err| format!("Line {}: {}", i + 1, err))?;
+            names.insert(address, name.to_owned());
+        }
+        Ok(Symbols { names })
+    }
+
+    /// Reads the symbol file of the ROM (see `path`), or returns no symbols if there isn't one
+    pub fn load(rom_path: &Path) -> Result<Symbols, String> {
+        let path = Symbols::path(rom_path);
+        match fs::read_to_string(&path) {
+            Ok(text) => Symbols::parse(&text).map_err(|err| format!("{}: {}", path.display(), err)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Symbols::default()),
+            Err(err) => Err(format!("Couldn't read {}: {}", path.display(), err)),
+        }
+    }
+
+    /// `ROM.sym`, next to the ROM
+    pub fn path(rom_path: &Path) -> PathBuf {
+        let mut name = OsString::from(rom_path.as_os_str());
+        name.push(".sym");
+        PathBuf::from(name)
+    }
+
+    /// The label at the address, if there is one
+    pub fn name(&self, address: u16) -> Option<&str> {
+        self.names.get(&address).map(String::as_str)
+    }
+
+    /// The address of the label (which is matched without regard to case)
+    pub fn address(&self, name: &str) -> Option<u16> {
+        self.names
+            .iter()
+            .find(|(_, label)| label.eq_ignore_ascii_case(name))
+            .map(|(&address, _)| address)
+    }
+
+    /// The labels in the order of their addresses
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &str)> {
+        self.names
+            .iter()
+            .map(|(&address, name)| (address, name.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+fn parse_line(line: &str) -> Result<(u16, &str), String> {
+    let mut parts = line.split_whitespace();
+    let (address, name) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(address), Some(name), None) => (address, name),
+        _ => {
+            return Err(format!(
+                "Expected an address and a label, like \"2A0 draw_player\": {}",
+                line
+            ))
+        }
+    };
+    let hex = address.strip_prefix("0x").unwrap_or(address);
+    match u16::from_str_radix(hex, 16) {
+        Ok(address) if address < 0x1000 => Ok((address, name.trim_end_matches(':'))),
+        _ => Err(format!("Invalid address: {}", address)),
+    }
+}
+
+#[test]
+fn test_parse_symbols() {
+    let symbols = Symbols::parse("# Sprites\n2F0 ball\n\n0x200 main:\n 2a4  draw_ball \n").unwrap();
+    assert_eq!(symbols.len(), 3);
+    assert_eq!(symbols.name(0x200), Some("main"));
+    assert_eq!(symbols.name(0x2A4), Some("draw_ball"));
+    assert_eq!(symbols.name(0x202), None);
+    assert_eq!(symbols.address("Draw_Ball"), Some(0x2A4));
+    assert_eq!(symbols.address("paddle"), None);
+    let names: Vec<&str> = symbols.iter().map(|(_, name)| name).collect();
+    assert_eq!(names, ["main", "draw_ball", "ball"]);
+
+    assert_eq!(
+        Symbols::parse("200 main\n1000 end"),
+        Err("Line 2: Invalid address: 1000".to_owned())
+    );
+    assert!(Symbols::parse("main").is_err());
+}
@@ -0,0 +1,63 @@
+use crate::chip8::Chip8;
+
+use wasm_bindgen::prelude::*;
+
+const FOREGROUND: [u8; 4] = [255, 255, 255, 255];
+const BACKGROUND: [u8; 4] = [0, 0, 0, 255];
+
+/// A Chip-8 machine that can be driven from JavaScript (see web/index.js).
+#[wasm_bindgen]
+pub struct WebChip8 {
+    chip8: Chip8,
+}
+
+#[wasm_bindgen]
+impl WebChip8 {
+    #[wasm_bindgen(constructor)]
+    pub fn new(rom: &[u8]) -> Result<WebChip8, JsValue> {
+        let chip8 = Chip8::from_rom(rom).map_err(JsValue::from)?;
+        Ok(WebChip8 { chip8 })
+    }
+
+    /// Runs as many instructions as fit in the elapsed time (in seconds)
+    pub fn update(&mut self, elapsed_time: f64) -> Result<(), JsValue> {
+        self.chip8.update(elapsed_time).map_err(JsValue::from)?;
+        Ok(())
+    }
+
+    pub fn step(&mut self) -> Result<(), JsValue> {
+        self.chip8.step_instruction().map_err(JsValue::from)?;
+        Ok(())
+    }
+
+    /// The display as RGBA bytes, ready to be put in an `ImageData`
+    pub fn display_rgba(&self) -> Vec<u8> {
+        self.chip8.display_buffer.to_rgba(FOREGROUND, BACKGROUND)
+    }
+
+    /// The height of the display: 32, or 48 or 64 in the hires display modes
+    pub fn display_height(&self) -> u8 {
+        self.chip8.display_buffer.height()
+    }
+
+    /// Presses a keypad key (0-F; other values are ignored)
+    pub fn key_down(&mut self, key: u8) {
+        if key < 16 {
+            self.chip8.handle_key_event(key, true);
+        }
+    }
+
+    pub fn key_up(&mut self, key: u8) {
+        if key < 16 {
+            self.chip8.handle_key_event(key, false);
+        }
+    }
+
+    pub fn sound_playing(&self) -> bool {
+        self.chip8.sound_timer > 0
+    }
+
+    pub fn set_clock_frequency(&mut self, frequency: f64) {
+        self.chip8.set_clock_frequency(frequency);
+    }
+}
@@ -0,0 +1,162 @@
+use crate::chip8::Chip8;
+
+/// The most bytes that a memory range watch shows
+const MAX_WATCHED_BYTES: usize = 16;
+
+/// A value in the machine that is evaluated and shown by the debugger, such as `V3`, `delay`,
+/// `mem[I]`, `mem[I+1..I+4]` or `mem[0x3A0..0x3A4]` (the end of a range is exclusive).
+#[derive(Clone, Debug, PartialEq)]
+pub struct WatchExpression {
+    source: String,
+    value: Value,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Register(u8),
+    AddressRegister,
+    ProgramCounter,
+    StackPointer,
+    DelayTimer,
+    SoundTimer,
+    Memory(Address, Option<Address>),
+}
+
+/// An address, given either as a number or relative to I
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Address {
+    Constant(u16),
+    AddressRegister(u16),
+}
+
+impl WatchExpression {
+    pub fn parse(source: &str) -> Result<WatchExpression, String> {
+        let normalized: String = source
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .to_lowercase();
+        let value = match normalized.as_str() {
+            "i" => Value::AddressRegister,
+            "pc" => Value::ProgramCounter,
+            "sp" => Value::StackPointer,
+            "delay" | "dt" => Value::DelayTimer,
+            "sound" | "st" => Value::SoundTimer,
+            s if s.len() == 2 && s.starts_with('v') => {
+                let register = s[1..]
+                    .chars()
+                    .next()
+                    .and_then(|c| c.to_digit(16))
+                    .ok_or_else(|| format!("Invalid register: {}", source))?;
+                Value::Register(register as u8)
+            }
+            s if s.starts_with("mem[") && s.ends_with(']') => {
+                let range = &s[4..s.len() - 1];
+                let mut bounds = range.splitn(2, "..");
+                let start = parse_address(bounds.next().unwrap_or_default())
+                    .ok_or_else(|| format!("Invalid address in watch: {}", source))?;
+                let end = match bounds.next() {
+                    Some(end) => Some(
+                        parse_address(end)
+                            .ok_or_else(|| format!("Invalid address in watch: {}", source))?,
+                    ),
+                    None => None,
+                };
+                Value::Memory(start, end)
+            }
+            _ => return Err(format!("Unsupported watch expression: {}", source)),
+        };
+        Ok(WatchExpression {
+            source: source.trim().to_owned(),
+            value,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The current value, formatted as hex
+    pub fn evaluate(&self, chip8: &Chip8) -> String {
+        match self.value {
+            Value::Register(x) => format!("{:02X}", chip8.registers[x as usize]),
+            Value::AddressRegister => format!("{:04X}", chip8.address_register),
+            Value::ProgramCounter => format!("{:03X}", chip8.program_counter),
+            Value::StackPointer => format!("{:X}", chip8.stack_pointer),
+            Value::DelayTimer => format!("{:02X}", chip8.delay_timer),
+            Value::SoundTimer => format!("{:02X}", chip8.sound_timer),
+            Value::Memory(start, end) => {
+                let memory = chip8.memory();
+                let start = start.resolve(chip8) as usize;
+                let end = match end {
+                    Some(end) => end.resolve(chip8) as usize,
+                    None => start + 1,
+                };
+                let end = end.min(memory.len()).min(start + MAX_WATCHED_BYTES);
+                if start >= end {
+                    return "-".to_owned();
+                }
+                let bytes: Vec<String> = memory[start..end]
+                    .iter()
+                    .map(|byte| format!("{:02X}", byte))
+                    .collect();
+                bytes.join(" ")
+            }
+        }
+    }
+}
+
+impl Address {
+    fn resolve(self, chip8: &Chip8) -> u16 {
+        match self {
+            Address::Constant(address) => address,
+            Address::AddressRegister(offset) => chip8.address_register.wrapping_add(offset),
+        }
+    }
+}
+
+fn parse_address(s: &str) -> Option<Address> {
+    if s == "i" {
+        Some(Address::AddressRegister(0))
+    } else if let Some(offset) = s.strip_prefix("i+") {
+        Some(Address::AddressRegister(parse_number(offset)?))
+    } else {
+        Some(Address::Constant(parse_number(s)?))
+    }
+}
+
+fn parse_number(s: &str) -> Option<u16> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+#[test]
+fn test_parse_watch_expression() {
+    assert!(WatchExpression::parse("V3").is_ok());
+    assert!(WatchExpression::parse("delay").is_ok());
+    assert!(WatchExpression::parse("mem[ I ]").is_ok());
+    assert!(WatchExpression::parse("mem[0x3A0..0x3A4]").is_ok());
+    assert!(WatchExpression::parse("mem[I+1..I+3]").is_ok());
+    assert!(WatchExpression::parse("VG").is_err());
+    assert!(WatchExpression::parse("mem[J]").is_err());
+    assert!(WatchExpression::parse("foo").is_err());
+}
+
+#[test]
+fn test_evaluate_watch_expression() {
+    let mut memory = [0; 0x1000];
+    memory[0x3A0..0x3A4].copy_from_slice(&[1, 2, 3, 4]);
+    let mut chip8 = Chip8::new(memory);
+    chip8.registers[3] = 0x2B;
+    chip8.address_register = 0x3A1;
+    let evaluate = |source| WatchExpression::parse(source).unwrap().evaluate(&chip8);
+
+    assert_eq!(evaluate("v3"), "2B");
+    assert_eq!(evaluate("I"), "03A1");
+    assert_eq!(evaluate("mem[I]"), "02");
+    assert_eq!(evaluate("mem[I+1..I+3]"), "03 04");
+    assert_eq!(evaluate("mem[0x3A0..0x3A4]"), "01 02 03 04");
+    assert_eq!(evaluate("mem[0x3A4..0x3A0]"), "-");
+}
@@ -0,0 +1,68 @@
+import init, { WebChip8 } from "./pkg/chip_8_rs.js";
+
+// The keyboard keys for the keypad values 0 to F (the same as the native emulator)
+const KEYMAP = "x123qweasdzc4rfv";
+
+const canvas = document.getElementById("screen");
+const context = canvas.getContext("2d");
+const status = document.getElementById("status");
+
+let chip8 = null;
+let lastTime = null;
+
+function frame(time) {
+    if (chip8 !== null) {
+        const elapsed = lastTime === null ? 0 : (time - lastTime) / 1000;
+        try {
+            chip8.update(elapsed);
+        } catch (err) {
+            status.textContent = "Error: " + err;
+            chip8 = null;
+        }
+        if (chip8 !== null) {
+            const height = chip8.display_height();
+            if (canvas.height !== height) {
+                canvas.height = height;
+                canvas.style.height = 10 * height + "px";
+            }
+            const pixels = new Uint8ClampedArray(chip8.display_rgba());
+            context.putImageData(new ImageData(pixels, 64, height), 0, 0);
+        }
+    }
+    lastTime = time;
+    requestAnimationFrame(frame);
+}
+
+function keypadValue(event) {
+    const value = KEYMAP.indexOf(event.key.toLowerCase());
+    return value === -1 || event.key.length !== 1 ? null : value;
+}
+
+document.addEventListener("keydown", (event) => {
+    const value = keypadValue(event);
+    if (chip8 !== null && value !== null) {
+        chip8.key_down(value);
+    }
+});
+
+document.addEventListener("keyup", (event) => {
+    const value = keypadValue(event);
+    if (chip8 !== null && value !== null) {
+        chip8.key_up(value);
+    }
+});
+
+document.getElementById("rom-file").addEventListener("change", async (event) => {
+    const file = event.target.files[0];
+    const rom = new Uint8Array(await file.arrayBuffer());
+    try {
+        chip8 = new WebChip8(rom);
+        status.textContent = "Running " + file.name;
+    } catch (err) {
+        status.textContent = "Error: " + err;
+        chip8 = null;
+    }
+});
+
+await init();
+requestAnimationFrame(frame);